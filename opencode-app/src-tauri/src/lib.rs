@@ -1,13 +1,17 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use opencode_cli::logging::{log_redactor, RedactingMakeWriter};
 use opencode_cli::message_processor::{process_message_async, LogLevel, SessionUpdate};
-use opencode_cli::{config::AppConfig, load_session, save_session};
+use opencode_cli::search::{self, SearchHit, SearchQuery};
+use opencode_cli::{config::AppConfig, CheckpointStore, PermissionResponder, Restore, SessionStore, Turn};
 use opencode_core::ids::SessionId;
+use opencode_core::permission::PermissionReply;
 use opencode_core::session::Session;
-use opencode_core::CommandDef;
+use opencode_core::{CommandDef, SessionStatus};
+use opencode_provider::ProviderFactory;
+use std::collections::HashMap;
 use std::path::PathBuf;
-use std::str::FromStr;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use tauri::{AppHandle, Emitter, State};
 use tokio::sync::mpsc;
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
@@ -21,10 +25,14 @@ fn init_tracing() {
         .append(true)
         .open("logs/opencode.log")
     {
-        let file_layer = fmt::Layer::new().with_writer(file).with_ansi(false);
+        let redactor = log_redactor();
+        let file_layer = fmt::Layer::new()
+            .with_writer(RedactingMakeWriter::new(file, redactor.clone()))
+            .with_ansi(false);
         let registry = tracing_subscriber::registry().with(filter).with(file_layer);
         #[cfg(debug_assertions)]
-        let registry = registry.with(fmt::Layer::new().with_writer(std::io::stderr));
+        let registry = registry
+            .with(fmt::Layer::new().with_writer(RedactingMakeWriter::new(std::io::stderr, redactor)));
         let _ = registry.try_init();
     }
 }
@@ -32,6 +40,52 @@ fn init_tracing() {
 struct AppState {
     workspace_path: Mutex<Option<PathBuf>>,
     current_agent: Mutex<String>,
+    /// Resolves the configured provider for each message; register custom provider types here.
+    provider_factory: ProviderFactory,
+    /// Permission requests of running turns awaiting `respond_permission`, by request id.
+    pending_permissions: Arc<Mutex<HashMap<String, PermissionResponder>>>,
+}
+
+/// Permission requests of one `send_message` turn. The turn's `TurnPermissionsGuard` removes its
+/// unanswered requests from `AppState.pending_permissions` when the turn ends, fails or is
+/// cancelled; dropping their responders denies the calls.
+struct TurnPermissions {
+    pending: Arc<Mutex<HashMap<String, PermissionResponder>>>,
+    /// Ids of the requests this turn added; None once the turn is over.
+    ids: Mutex<Option<Vec<String>>>,
+}
+
+impl TurnPermissions {
+    /// Registers a request for `respond_permission`; false when the turn is already over.
+    fn insert(&self, id: &str, responder: PermissionResponder) -> bool {
+        let Ok(mut ids) = self.ids.lock() else {
+            return false;
+        };
+        let (Some(ids), Ok(mut map)) = (ids.as_mut(), self.pending.lock()) else {
+            return false;
+        };
+        map.insert(id.to_string(), responder);
+        ids.push(id.to_string());
+        true
+    }
+}
+
+struct TurnPermissionsGuard(Arc<TurnPermissions>);
+
+impl Drop for TurnPermissionsGuard {
+    fn drop(&mut self) {
+        let ids = self
+            .0
+            .ids
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+            .unwrap_or_default();
+        let mut map = self.0.pending.lock().unwrap_or_else(|e| e.into_inner());
+        for id in ids {
+            map.remove(&id);
+        }
+    }
 }
 
 fn effective_workspace(state: &AppState) -> Option<PathBuf> {
@@ -78,7 +132,14 @@ fn write_last_workspace(path: Option<&PathBuf>) {
 #[derive(serde::Serialize)]
 struct SessionListItem {
     id: String,
+    /// Stored title, or one derived from the first message.
+    title: String,
+    tags: Vec<String>,
+    pinned: bool,
     updated_at: String,
+    /// Set for forked sessions.
+    parent_session_id: Option<String>,
+    forked_at_index: Option<usize>,
 }
 
 #[derive(serde::Serialize)]
@@ -97,48 +158,53 @@ struct ProviderListItemDto {
     base_url: Option<String>,
 }
 
+/// Sessions, pinned first and then newest first. `tag` and `pinned` narrow the list.
 #[tauri::command]
-fn list_sessions(state: State<AppState>) -> Result<Vec<SessionListItem>, String> {
+fn list_sessions(
+    tag: Option<String>,
+    pinned: Option<bool>,
+    state: State<AppState>,
+) -> Result<Vec<SessionListItem>, String> {
     let workspace = effective_workspace(&state).ok_or_else(|| "No workspace path".to_string())?;
     let config = AppConfig::load_from_workspace(&workspace).map_err(|e| e.to_string())?;
     sync_current_agent_from_config(&state, &config);
-    let session_dir = config.session_dir();
-    if !session_dir.exists() {
-        return Ok(Vec::new());
-    }
-    let mut items = Vec::new();
-    for entry in std::fs::read_dir(&session_dir).map_err(|e| e.to_string())? {
-        let entry = entry.map_err(|e| e.to_string())?;
-        let path = entry.path();
-        if path.is_dir() {
-            let session_file = path.join("session.json");
-            if session_file.exists() {
-                let id = path
-                    .file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("")
-                    .to_string();
-                if id.is_empty() {
-                    continue;
+    let filtered = tag.is_some() || pinned == Some(true);
+    // Unreadable sessions are listed without a date, unless a filter is set.
+    let items = config
+        .session_store()
+        .list()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter_map(|(id, summary)| match summary {
+            Ok(s) => {
+                if pinned == Some(true) && !s.pinned {
+                    return None;
+                }
+                if tag.as_ref().is_some_and(|t| !s.tags.contains(t)) {
+                    return None;
                 }
-                let updated_at = std::fs::metadata(&session_file)
-                    .and_then(|m| m.modified())
-                    .map(|t| {
-                        chrono::DateTime::from_timestamp(
-                            t.duration_since(std::time::UNIX_EPOCH)
-                                .unwrap()
-                                .as_secs() as i64,
-                            0,
-                        )
-                        .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
-                        .unwrap_or_else(|| "".to_string())
-                    })
-                    .unwrap_or_else(|_| "".to_string());
-                items.push(SessionListItem { id, updated_at });
+                Some(SessionListItem {
+                    id,
+                    title: s.display_title(),
+                    tags: s.tags,
+                    pinned: s.pinned,
+                    updated_at: s.updated_at.format("%Y-%m-%d %H:%M").to_string(),
+                    parent_session_id: s.parent_session_id,
+                    forked_at_index: s.forked_at_index,
+                })
             }
-        }
-    }
-    items.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+            Err(_) if filtered => None,
+            Err(_) => Some(SessionListItem {
+                id,
+                title: "Unreadable session".to_string(),
+                tags: Vec::new(),
+                pinned: false,
+                updated_at: String::new(),
+                parent_session_id: None,
+                forked_at_index: None,
+            }),
+        })
+        .collect();
     Ok(items)
 }
 
@@ -147,11 +213,15 @@ fn get_session(session_id: String, state: State<AppState>) -> Result<Session, St
     let workspace = effective_workspace(&state).ok_or_else(|| "No workspace path".to_string())?;
     let config = AppConfig::load_from_workspace(&workspace).map_err(|e| e.to_string())?;
     sync_current_agent_from_config(&state, &config);
-    let session_file = config.session_dir().join(&session_id).join("session.json");
-    if !session_file.exists() {
-        return Err("Session not found".to_string());
-    }
-    load_session(&session_file).map_err(|e| e.to_string())
+    config.session_store().load(&session_id).map_err(|e| e.to_string())
+}
+
+/// Turn state last recorded for the session (None before its first turn).
+#[tauri::command]
+fn get_session_status(session_id: String, state: State<AppState>) -> Result<Option<SessionStatus>, String> {
+    let workspace = effective_workspace(&state).ok_or_else(|| "No workspace path".to_string())?;
+    let config = AppConfig::load_from_workspace(&workspace).map_err(|e| e.to_string())?;
+    config.session_store().load_status(&session_id).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -159,13 +229,152 @@ fn create_session(state: State<AppState>) -> Result<String, String> {
     let workspace = effective_workspace(&state).ok_or_else(|| "No workspace path".to_string())?;
     let config = AppConfig::load_from_workspace(&workspace).map_err(|e| e.to_string())?;
     sync_current_agent_from_config(&state, &config);
-    let id = uuid::Uuid::new_v4().to_string();
-    let path = config.session_dir().join(&id).join("session.json");
-    let session = Session::with_id(
-        SessionId::from_str(&id).unwrap_or_else(|_| SessionId::new()),
-    );
-    save_session(&path, &session).map_err(|e| e.to_string())?;
-    Ok(id)
+    let session = Session::with_id(SessionId::new());
+    config.session_store().save(&session).map_err(|e| e.to_string())?;
+    Ok(session.id.to_string())
+}
+
+/// Messages matching `query` across all sessions, newest first.
+#[tauri::command]
+fn search_sessions(
+    query: String,
+    role: Option<String>,
+    since: Option<String>,
+    limit: Option<usize>,
+    state: State<AppState>,
+) -> Result<Vec<SearchHit>, String> {
+    let workspace = effective_workspace(&state).ok_or_else(|| "No workspace path".to_string())?;
+    let config = AppConfig::load_from_workspace(&workspace).map_err(|e| e.to_string())?;
+    let query = SearchQuery {
+        text: query,
+        role: role.as_deref().map(search::parse_role).transpose()?,
+        since: since.as_deref().map(search::parse_since).transpose()?,
+        limit: limit.unwrap_or(50),
+    };
+    search::search(&config.session_store(), &query).map_err(|e| e.to_string())
+}
+
+/// Creates a session with the first `at` messages of `session_id` and returns its id.
+#[tauri::command]
+fn fork_session(session_id: String, at: usize, state: State<AppState>) -> Result<String, String> {
+    let workspace = effective_workspace(&state).ok_or_else(|| "No workspace path".to_string())?;
+    let config = AppConfig::load_from_workspace(&workspace).map_err(|e| e.to_string())?;
+    sync_current_agent_from_config(&state, &config);
+    let store = config.session_store();
+    let session = store.load(&session_id).map_err(|e| e.to_string())?;
+    let fork = session
+        .fork(at)
+        .ok_or_else(|| format!("Session has only {} messages", session.messages.len()))?;
+    store.save(&fork).map_err(|e| e.to_string())?;
+    Ok(fork.id.to_string())
+}
+
+/// Loads `session_id`, applies `edit` and saves it.
+fn update_session(
+    session_id: &str,
+    state: &State<AppState>,
+    edit: impl FnOnce(&mut Session),
+) -> Result<(), String> {
+    let workspace = effective_workspace(state).ok_or_else(|| "No workspace path".to_string())?;
+    let config = AppConfig::load_from_workspace(&workspace).map_err(|e| e.to_string())?;
+    let store = config.session_store();
+    let mut session = store.load(session_id).map_err(|e| e.to_string())?;
+    edit(&mut session);
+    store.save(&session).map_err(|e| e.to_string())
+}
+
+/// Sets the session title; an empty title clears it.
+#[tauri::command]
+fn rename_session(session_id: String, title: String, state: State<AppState>) -> Result<(), String> {
+    let title = title.trim().to_string();
+    update_session(&session_id, &state, |s| {
+        s.title = (!title.is_empty()).then_some(title);
+    })
+}
+
+/// Replaces the session tags.
+#[tauri::command]
+fn set_session_tags(session_id: String, tags: Vec<String>, state: State<AppState>) -> Result<(), String> {
+    update_session(&session_id, &state, |s| {
+        s.tags.clear();
+        for tag in &tags {
+            s.add_tag(tag);
+        }
+    })
+}
+
+#[tauri::command]
+fn set_session_pinned(session_id: String, pinned: bool, state: State<AppState>) -> Result<(), String> {
+    update_session(&session_id, &state, |s| s.pinned = pinned)
+}
+
+#[derive(serde::Serialize)]
+struct CheckpointItem {
+    turn: u64,
+    session_id: String,
+    created_at: String,
+    files: Vec<String>,
+    undone: bool,
+}
+
+impl From<Turn> for CheckpointItem {
+    fn from(turn: Turn) -> Self {
+        Self {
+            turn: turn.id,
+            session_id: turn.session_id,
+            created_at: turn.created_at.to_rfc3339(),
+            files: turn.files,
+            undone: turn.undone,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct RestoreResult {
+    turn: u64,
+    /// Workspace-relative paths written or deleted.
+    files: Vec<String>,
+}
+
+impl From<Restore> for RestoreResult {
+    fn from(restore: Restore) -> Self {
+        Self {
+            turn: restore.turn.id,
+            files: restore.files,
+        }
+    }
+}
+
+fn checkpoint_store(state: &AppState) -> Result<CheckpointStore, String> {
+    let workspace = effective_workspace(state).ok_or_else(|| "No workspace path".to_string())?;
+    Ok(CheckpointStore::new(workspace))
+}
+
+/// File checkpoints recorded for agent turns, oldest first (only this session's when given).
+#[tauri::command]
+fn list_checkpoints(session_id: Option<String>, state: State<AppState>) -> Result<Vec<CheckpointItem>, String> {
+    let turns = checkpoint_store(&state)?
+        .turns(session_id.as_deref())
+        .map_err(|e| e.to_string())?;
+    Ok(turns.into_iter().map(CheckpointItem::from).collect())
+}
+
+/// Restores workspace files to the state before `turn` (default: the session's latest turn).
+#[tauri::command]
+fn undo_turn(session_id: Option<String>, turn: Option<u64>, state: State<AppState>) -> Result<RestoreResult, String> {
+    checkpoint_store(&state)?
+        .undo(session_id.as_deref(), turn)
+        .map(RestoreResult::from)
+        .map_err(|e| e.to_string())
+}
+
+/// Restores the files replaced by the latest undo, or the state right after `turn`.
+#[tauri::command]
+fn redo_turn(session_id: Option<String>, turn: Option<u64>, state: State<AppState>) -> Result<RestoreResult, String> {
+    checkpoint_store(&state)?
+        .redo(session_id.as_deref(), turn)
+        .map(RestoreResult::from)
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -173,9 +382,9 @@ fn delete_session(session_id: String, state: State<AppState>) -> Result<(), Stri
     let workspace = effective_workspace(&state).ok_or_else(|| "No workspace path".to_string())?;
     let config = AppConfig::load_from_workspace(&workspace).map_err(|e| e.to_string())?;
     sync_current_agent_from_config(&state, &config);
-    let dir = config.session_dir().join(&session_id);
-    if dir.exists() {
-        std::fs::remove_dir_all(&dir).map_err(|e| e.to_string())?;
+    let store = config.session_store();
+    if store.exists(&session_id) {
+        store.delete(&session_id).map_err(|e| e.to_string())?;
     }
     Ok(())
 }
@@ -228,6 +437,12 @@ async fn send_message(
     let (tx, mut rx) = mpsc::unbounded_channel::<(String, SessionUpdate)>();
 
     let app_handle = app.clone();
+    let turn_permissions = Arc::new(TurnPermissions {
+        pending: state.pending_permissions.clone(),
+        ids: Mutex::new(Some(Vec::new())),
+    });
+    // Dropped on every way out of this command, including cancellation.
+    let _permissions_guard = TurnPermissionsGuard(turn_permissions.clone());
     tokio::spawn(async move {
         while let Some((sid, update)) = rx.recv().await {
             match &update {
@@ -248,6 +463,21 @@ async fn send_message(
                         serde_json::json!({ "session_id": sid, "level": level, "message": entry.message }),
                     );
                 }
+                SessionUpdate::State(status) => {
+                    let _ = app_handle.emit(
+                        "session-state",
+                        serde_json::json!({ "session_id": sid, "status": status }),
+                    );
+                }
+                SessionUpdate::PermissionRequest(pending) => {
+                    if !turn_permissions.insert(&pending.request.id, pending.reply.clone()) {
+                        continue;
+                    }
+                    let _ = app_handle.emit(
+                        "permission-request",
+                        serde_json::json!({ "session_id": sid, "request": pending.request }),
+                    );
+                }
             }
         }
     });
@@ -260,11 +490,33 @@ async fn send_message(
         workspace_path,
         tx,
         command,
+        &state.provider_factory,
     )
     .await
     .map_err(|e| e.to_string())
 }
 
+/// Answers a `permission-request` event; `reply` is "once", "always" or "deny". Fails for ids
+/// that are unknown, already answered or of a turn that has ended.
+#[tauri::command]
+fn respond_permission(
+    request_id: String,
+    reply: PermissionReply,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let responder = state
+        .pending_permissions
+        .lock()
+        .map_err(|e| e.to_string())?
+        .remove(&request_id)
+        .ok_or_else(|| format!("Unknown permission request: {}", request_id))?;
+    if responder.respond(reply) {
+        Ok(())
+    } else {
+        Err("The turn is no longer waiting for this permission".to_string())
+    }
+}
+
 #[tauri::command]
 fn get_config(state: State<AppState>) -> Result<serde_json::Value, String> {
     let workspace = effective_workspace(&state).ok_or_else(|| "No workspace path".to_string())?;
@@ -295,6 +547,42 @@ fn get_providers(state: State<AppState>) -> Result<Vec<ProviderListItemDto>, Str
         .collect())
 }
 
+#[derive(serde::Serialize)]
+struct ModelInfoDto {
+    id: String,
+    name: String,
+    /// From the capability registry; None when the model is not recognized.
+    capabilities: Option<opencode_core::ModelCapabilities>,
+}
+
+/// Models offered by the provider with `provider_id` (default provider when None). Lists are
+/// cached by the provider factory.
+#[tauri::command]
+async fn list_models(
+    provider_id: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Vec<ModelInfoDto>, String> {
+    let workspace = effective_workspace(&state).ok_or_else(|| "No workspace path".to_string())?;
+    let config = AppConfig::load_from_workspace(&workspace).map_err(|e| e.to_string())?;
+    let provider_config = config
+        .provider_config(provider_id.as_deref())
+        .ok_or_else(|| format!("Unknown provider: {}", provider_id.unwrap_or_default()))?;
+    let models = state
+        .provider_factory
+        .list_models(&provider_config)
+        .await
+        .map_err(|e| e.to_string())?;
+    let registry = config.core_config().model_registry();
+    Ok(models
+        .into_iter()
+        .map(|m| ModelInfoDto {
+            capabilities: registry.find(&m.id).cloned(),
+            id: m.id,
+            name: m.name,
+        })
+        .collect())
+}
+
 #[tauri::command]
 fn set_provider_config(
     provider_id: String,
@@ -371,18 +659,31 @@ pub fn run() {
         .manage(AppState {
             workspace_path: Mutex::new(initial_workspace),
             current_agent: Mutex::new("build".to_string()),
+            provider_factory: ProviderFactory::default(),
+            pending_permissions: Arc::new(Mutex::new(HashMap::new())),
         })
         .invoke_handler(tauri::generate_handler![
             list_sessions,
             get_session,
+            get_session_status,
             create_session,
+            fork_session,
+            rename_session,
+            set_session_tags,
+            set_session_pinned,
+            list_checkpoints,
+            undo_turn,
+            redo_turn,
+            search_sessions,
             delete_session,
             get_workspace_path,
             set_workspace_path,
             list_commands,
             send_message,
+            respond_permission,
             get_config,
             get_providers,
+            list_models,
             set_provider_config,
             set_default_provider,
             list_agents,
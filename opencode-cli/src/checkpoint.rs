@@ -0,0 +1,460 @@
+//! File checkpoints for real undo/redo. The workspace is snapshotted into a shadow git repository
+//! under `.opencode/checkpoints/git` (its own git dir and index; the project's `.git` is never
+//! touched) before and after every agent turn. A turn that changed files is recorded with both
+//! tree ids, so whatever `write`, `edit`, `multiedit`, `patch` or `bash` did can be restored
+//! without knowing which tool touched which file. Ignore rules of the workspace apply: ignored
+//! files (e.g. build output or `.env`) are not snapshotted, so changes to them cannot be undone
+//! (see `IGNORED_NOTE`).
+//!
+//! Undoing a turn restores the files it changed to the state before it, which also reverts every
+//! later turn; the state it replaced is kept so `redo` can return to it. Only the files the
+//! undone (or redone) turns changed are restored, so edits the user made to other files in the
+//! meantime are kept. Recording a new turn clears the redo stack.
+//!
+//! Only project directories are snapshotted: the workspace needs a `.git` or a project config
+//! (`.opencode/config.json`), and the home directory never counts, so a workspace that falls back
+//! to `$HOME` does not copy the whole home directory into the shadow repository.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use thiserror::Error;
+
+/// Checkpoint directory, relative to the workspace root.
+pub const CHECKPOINT_DIR: &str = ".opencode/checkpoints";
+
+/// Ids of the built-in commands handled by the caller instead of the model.
+pub const UNDO_COMMAND_ID: &str = "undo";
+pub const REDO_COMMAND_ID: &str = "redo";
+
+/// Workspace paths never snapshotted (opencode's own state).
+const EXCLUDES: &[&str] = &["/.opencode/checkpoints/", "/.opencode/sessions/"];
+
+/// Shown with undo/redo results: what checkpoints do not cover.
+pub const IGNORED_NOTE: &str =
+    "Files ignored by the workspace's ignore rules (e.g. .env) are not snapshotted and were left as they are.";
+
+/// Files or directories that mark a workspace as a project.
+const PROJECT_MARKERS: &[&str] = &[".git", ".opencode/config.json"];
+
+#[derive(Error, Debug)]
+pub enum CheckpointError {
+    #[error("Checkpoints need git: {0}")]
+    GitUnavailable(io::Error),
+
+    #[error("git {command} failed: {stderr}")]
+    Git { command: String, stderr: String },
+
+    #[error("{0} is not a project directory (no .git or .opencode/config.json); checkpoints are off")]
+    NotProject(PathBuf),
+
+    #[error("Turn {0} not found")]
+    TurnNotFound(u64),
+
+    #[error("Nothing to undo")]
+    NothingToUndo,
+
+    #[error("Nothing to redo")]
+    NothingToRedo,
+
+    #[error("Checkpoint I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Checkpoint state error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, CheckpointError>;
+
+/// One agent turn that changed workspace files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Turn {
+    pub id: u64,
+    pub session_id: String,
+    pub created_at: DateTime<Utc>,
+    /// Tree of the workspace before the turn.
+    pub before: String,
+    /// Tree of the workspace after the turn.
+    pub after: String,
+    /// Workspace-relative paths the turn added, changed or deleted.
+    pub files: Vec<String>,
+    /// Reverted by `undo` (and not redone).
+    #[serde(default)]
+    pub undone: bool,
+}
+
+/// What `undo` replaced, for `redo`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RedoEntry {
+    /// The undone turn.
+    turn: u64,
+    /// Workspace tree at the time of the undo.
+    tree: String,
+    /// Turns marked undone by it.
+    undone: Vec<u64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    #[serde(default)]
+    turns: Vec<Turn>,
+    #[serde(default)]
+    redo: Vec<RedoEntry>,
+}
+
+/// Outcome of `undo` or `redo`.
+#[derive(Debug, Clone)]
+pub struct Restore {
+    /// The turn undone or redone.
+    pub turn: Turn,
+    /// Workspace-relative paths written or deleted.
+    pub files: Vec<String>,
+}
+
+/// Checkpoints of one workspace.
+#[derive(Debug, Clone)]
+pub struct CheckpointStore {
+    workspace: PathBuf,
+    dir: PathBuf,
+}
+
+impl CheckpointStore {
+    pub fn new(workspace: impl Into<PathBuf>) -> Self {
+        let workspace = workspace.into();
+        let dir = workspace.join(CHECKPOINT_DIR);
+        Self { workspace, dir }
+    }
+
+    /// Snapshots the workspace and returns its tree id. Call before a turn and pass the id to
+    /// `record` after it. Fails with `NotProject` outside a project directory.
+    pub fn snapshot(&self) -> Result<String> {
+        if !self.is_project() {
+            return Err(CheckpointError::NotProject(self.workspace.clone()));
+        }
+        let _lock = self.lock()?;
+        self.write_tree()
+    }
+
+    /// True when the workspace has a project marker and is not the home directory.
+    pub fn is_project(&self) -> bool {
+        let home = dirs::home_dir();
+        let is_home = home.is_some_and(|home| {
+            fs::canonicalize(&home).unwrap_or(home)
+                == fs::canonicalize(&self.workspace).unwrap_or_else(|_| self.workspace.clone())
+        });
+        !is_home && PROJECT_MARKERS.iter().any(|m| self.workspace.join(m).exists())
+    }
+
+    /// Records the turn that started at snapshot `before`. Returns None when it changed no files.
+    pub fn record(&self, session_id: &str, before: &str) -> Result<Option<Turn>> {
+        let _lock = self.lock()?;
+        let after = self.write_tree()?;
+        let files: Vec<String> = self
+            .diff(before, &after)?
+            .into_iter()
+            .map(|(_, path)| path)
+            .collect();
+        if files.is_empty() {
+            return Ok(None);
+        }
+        let mut state = self.read_state()?;
+        let turn = Turn {
+            id: state.turns.last().map_or(1, |t| t.id + 1),
+            session_id: session_id.to_string(),
+            created_at: Utc::now(),
+            before: before.to_string(),
+            after,
+            files,
+            undone: false,
+        };
+        state.turns.push(turn.clone());
+        state.redo.clear();
+        self.write_state(&state)?;
+        Ok(Some(turn))
+    }
+
+    /// Recorded turns, oldest first; only those of `session_id` when given.
+    pub fn turns(&self, session_id: Option<&str>) -> Result<Vec<Turn>> {
+        let _lock = self.lock()?;
+        let state = self.read_state()?;
+        Ok(state
+            .turns
+            .into_iter()
+            .filter(|t| session_id.is_none_or(|id| t.session_id == id))
+            .collect())
+    }
+
+    /// Restores the files changed by `turn` (default: the latest turn not undone, of `session_id`
+    /// when given) to the state before it. Later turns are reverted too.
+    pub fn undo(&self, session_id: Option<&str>, turn: Option<u64>) -> Result<Restore> {
+        let _lock = self.lock()?;
+        let mut state = self.read_state()?;
+        let index = match turn {
+            Some(id) => position(&state, id)?,
+            None => state
+                .turns
+                .iter()
+                .rposition(|t| !t.undone && session_id.is_none_or(|id| t.session_id == id))
+                .ok_or(CheckpointError::NothingToUndo)?,
+        };
+        let current = self.write_tree()?;
+        let paths = touched(state.turns[index..].iter().filter(|t| !t.undone));
+        let files = self.restore(&current, &state.turns[index].before, &paths)?;
+        let mut undone = Vec::new();
+        for t in state.turns[index..].iter_mut().filter(|t| !t.undone) {
+            t.undone = true;
+            undone.push(t.id);
+        }
+        let turn = state.turns[index].clone();
+        state.redo.push(RedoEntry {
+            turn: turn.id,
+            tree: current,
+            undone,
+        });
+        self.write_state(&state)?;
+        Ok(Restore { turn, files })
+    }
+
+    /// Without `turn`, returns the workspace to the state the latest undo (of `session_id` when
+    /// given) replaced. With `turn`, restores the state right after that turn.
+    pub fn redo(&self, session_id: Option<&str>, turn: Option<u64>) -> Result<Restore> {
+        let _lock = self.lock()?;
+        let mut state = self.read_state()?;
+        let current = self.write_tree()?;
+        let (index, files) = match turn {
+            Some(id) => {
+                let index = position(&state, id)?;
+                // The turns whose undone flag changes.
+                let paths = touched(
+                    state
+                        .turns
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, t)| t.undone != (*i > index))
+                        .map(|(_, t)| t),
+                );
+                let files = self.restore(&current, &state.turns[index].after, &paths)?;
+                for (i, t) in state.turns.iter_mut().enumerate() {
+                    t.undone = i > index;
+                }
+                state.redo.clear();
+                (index, files)
+            }
+            None => {
+                let entry = state
+                    .redo
+                    .iter()
+                    .rposition(|r| {
+                        session_id.is_none_or(|id| {
+                            state
+                                .turns
+                                .iter()
+                                .any(|t| t.id == r.turn && t.session_id == id)
+                        })
+                    })
+                    .map(|i| state.redo.remove(i))
+                    .ok_or(CheckpointError::NothingToRedo)?;
+                let paths = touched(state.turns.iter().filter(|t| entry.undone.contains(&t.id)));
+                let files = self.restore(&current, &entry.tree, &paths)?;
+                for t in state.turns.iter_mut() {
+                    if entry.undone.contains(&t.id) {
+                        t.undone = false;
+                    }
+                }
+                (position(&state, entry.turn)?, files)
+            }
+        };
+        self.write_state(&state)?;
+        Ok(Restore {
+            turn: state.turns[index].clone(),
+            files,
+        })
+    }
+
+    /// Makes the workspace files among `paths` that differ between trees `current` and `target`
+    /// match `target`. Returns the paths written or deleted.
+    fn restore(
+        &self,
+        current: &str,
+        target: &str,
+        paths: &BTreeSet<&str>,
+    ) -> Result<Vec<String>> {
+        let mut changes = self.diff(current, target)?;
+        changes.retain(|(_, path)| paths.contains(path.as_str()));
+        let mut checkout = Vec::new();
+        for (status, path) in &changes {
+            if status == "D" {
+                let file = self.workspace.join(path);
+                match fs::remove_file(&file) {
+                    Ok(()) => remove_empty_parents(&file, &self.workspace),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                }
+            } else {
+                checkout.extend_from_slice(path.as_bytes());
+                checkout.push(0);
+            }
+        }
+        if !checkout.is_empty() {
+            self.git_with_input(
+                &[
+                    "checkout",
+                    target,
+                    "--pathspec-from-file=-",
+                    "--pathspec-file-nul",
+                ],
+                &checkout,
+            )?;
+        }
+        Ok(changes.into_iter().map(|(_, path)| path).collect())
+    }
+
+    /// (status letter, path) of every file that differs between two trees.
+    fn diff(&self, from: &str, to: &str) -> Result<Vec<(String, String)>> {
+        let out = self.git(&["diff", "--name-status", "--no-renames", "-z", from, to])?;
+        let mut fields = out.split('\0').filter(|f| !f.is_empty());
+        let mut changes = Vec::new();
+        while let (Some(status), Some(path)) = (fields.next(), fields.next()) {
+            changes.push((status.to_string(), path.to_string()));
+        }
+        Ok(changes)
+    }
+
+    fn write_tree(&self) -> Result<String> {
+        self.init()?;
+        self.git(&["add", "--all", "--", "."])?;
+        Ok(self.git(&["write-tree"])?.trim().to_string())
+    }
+
+    fn git_dir(&self) -> PathBuf {
+        self.dir.join("git")
+    }
+
+    /// Creates the shadow repository on first use.
+    fn init(&self) -> Result<()> {
+        let git_dir = self.git_dir();
+        if git_dir.join("HEAD").exists() {
+            return Ok(());
+        }
+        fs::create_dir_all(&git_dir)?;
+        self.git(&["init", "--quiet"])?;
+        let info = git_dir.join("info");
+        fs::create_dir_all(&info)?;
+        fs::write(info.join("exclude"), EXCLUDES.join("\n") + "\n")?;
+        Ok(())
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new("git");
+        command
+            .arg("--git-dir")
+            .arg(self.git_dir())
+            .arg("--work-tree")
+            .arg(&self.workspace)
+            .args(["-c", "core.autocrlf=false", "-c", "core.quotepath=off"])
+            .args(["-c", "gc.auto=0"])
+            .args(args)
+            .current_dir(&self.workspace)
+            .env("GIT_LITERAL_PATHSPECS", "1")
+            .env_remove("GIT_INDEX_FILE")
+            .env_remove("GIT_OBJECT_DIRECTORY")
+            .stdin(Stdio::null());
+        command
+    }
+
+    fn git(&self, args: &[&str]) -> Result<String> {
+        let output = self
+            .command(args)
+            .output()
+            .map_err(CheckpointError::GitUnavailable)?;
+        check(args, &output)
+    }
+
+    fn git_with_input(&self, args: &[&str], input: &[u8]) -> Result<String> {
+        let mut child = self
+            .command(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(CheckpointError::GitUnavailable)?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(input)?;
+        }
+        let output = child.wait_with_output()?;
+        check(args, &output)
+    }
+
+    fn state_path(&self) -> PathBuf {
+        self.dir.join("turns.json")
+    }
+
+    fn read_state(&self) -> Result<State> {
+        match fs::read_to_string(self.state_path()) {
+            Ok(content) => Ok(serde_json::from_str(&content)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(State::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_state(&self, state: &State) -> Result<()> {
+        let path = self.state_path();
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(state)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Serializes snapshots and restores across processes; released on drop.
+    fn lock(&self) -> Result<File> {
+        fs::create_dir_all(&self.dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.dir.join("lock"))?;
+        file.lock()?;
+        Ok(file)
+    }
+}
+
+/// Paths changed by any of `turns`.
+fn touched<'a>(turns: impl Iterator<Item = &'a Turn>) -> BTreeSet<&'a str> {
+    turns
+        .flat_map(|t| t.files.iter().map(String::as_str))
+        .collect()
+}
+
+fn position(state: &State, id: u64) -> Result<usize> {
+    state
+        .turns
+        .iter()
+        .position(|t| t.id == id)
+        .ok_or(CheckpointError::TurnNotFound(id))
+}
+
+fn check(args: &[&str], output: &std::process::Output) -> Result<String> {
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(CheckpointError::Git {
+            command: args.first().copied().unwrap_or_default().to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        })
+    }
+}
+
+/// Removes directories left empty by deleting `file`, up to (not including) `root`.
+fn remove_empty_parents(file: &Path, root: &Path) {
+    let mut dir = file.parent();
+    while let Some(d) = dir {
+        if d == root || !d.starts_with(root) || fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+}
@@ -0,0 +1,65 @@
+use crate::checkpoint::{CheckpointError, CheckpointStore, Restore};
+use anyhow::Result;
+
+fn workspace_store() -> Result<CheckpointStore> {
+    Ok(CheckpointStore::new(std::env::current_dir()?))
+}
+
+/// Lists the recorded turns of the workspace in the current directory.
+pub async fn list_checkpoints(session_id: Option<&str>) -> Result<()> {
+    let turns = workspace_store()?.turns(session_id)?;
+    if turns.is_empty() {
+        println!("No checkpoints recorded in this workspace.");
+        return Ok(());
+    }
+    println!("Checkpoints (newest last):");
+    println!("{}", "─".repeat(60));
+    for turn in &turns {
+        let state = if turn.undone { " (undone)" } else { "" };
+        println!(
+            "🕘 turn {}{}  session {}  {}",
+            turn.id,
+            state,
+            turn.session_id,
+            turn.created_at.format("%Y-%m-%d %H:%M:%S")
+        );
+        for path in &turn.files {
+            println!("    {}", path);
+        }
+    }
+    Ok(())
+}
+
+/// Restores the workspace files to the state before a turn.
+pub async fn undo(session_id: Option<&str>, turn: Option<u64>) -> Result<()> {
+    report("Undid", workspace_store()?.undo(session_id, turn))
+}
+
+/// Restores the workspace files undone by the latest undo, or to the state after a turn.
+pub async fn redo(session_id: Option<&str>, turn: Option<u64>) -> Result<()> {
+    report("Redid", workspace_store()?.redo(session_id, turn))
+}
+
+fn report(verb: &str, outcome: crate::checkpoint::Result<Restore>) -> Result<()> {
+    match outcome {
+        Ok(restore) => {
+            println!(
+                "{} turn {} of session {} ({} files restored)",
+                verb,
+                restore.turn.id,
+                restore.turn.session_id,
+                restore.files.len()
+            );
+            for path in &restore.files {
+                println!("    {}", path);
+            }
+            println!("{}", crate::checkpoint::IGNORED_NOTE);
+            Ok(())
+        }
+        Err(e @ (CheckpointError::NothingToUndo | CheckpointError::NothingToRedo)) => {
+            println!("{}.", e);
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
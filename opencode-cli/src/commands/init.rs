@@ -3,6 +3,7 @@
 //! When langchain feature is enabled, uses the configured LLM to generate content from project scan; otherwise uses rule-based placeholder.
 
 use anyhow::Result;
+use opencode_core::model_registry::ModelRegistry;
 use std::path::Path;
 
 const DEFAULT_IGNORE: &[&str] = &[
@@ -108,7 +109,7 @@ fn parse_cargo_workspace_members(cargo_path: &Path) -> Vec<String> {
 
 /// Scan project root: tree, Cargo workspace members, package.json, README preview.
 pub fn scan_project(project_root: &Path) -> ScanResult {
-    tklog::info!("scan_project start", project_root.display());
+    tracing::info!(root = %project_root.display(), "scan_project start");
 
     let tree = build_tree(project_root);
     let cargo_path = project_root.join("Cargo.toml");
@@ -136,12 +137,10 @@ pub fn scan_project(project_root: &Path) -> ScanResult {
         "Unknown".to_string()
     };
 
-    tklog::info!(
-        "scan_project done",
-        "tree_lines",
-        tree.lines().count(),
-        "members",
-        workspace_members.len()
+    tracing::info!(
+        tree_lines = tree.lines().count(),
+        members = workspace_members.len(),
+        "scan_project done"
     );
     ScanResult {
         tree,
@@ -237,17 +236,16 @@ fn is_deep_agent_supported(provider_type: &str, _model_name: &str) -> bool {
     }
 }
 
-/// Model names that langchain-ai-rust recognizes as OpenAI (gpt-*, o1-*, othello-*).
-fn is_openai_model_recognized(model_name: &str) -> bool {
-    model_name.starts_with("gpt-")
-        || model_name.starts_with("o1-")
-        || model_name.starts_with("othello-")
-}
-
 /// For create_deep_agent we must pass a model name the crate recognizes as OpenAI. When provider is openai
-/// and the configured model is not recognized, use a fallback so the library uses the OpenAI client; base_url is already set.
-fn effective_deep_agent_model(provider_type: &str, model_name: &str) -> String {
-    if provider_type == "openai" && !is_openai_model_recognized(model_name) {
+/// and the model is not in the capability registry, or cannot call tools, use a fallback so the
+/// library uses the OpenAI client; base_url is already set.
+fn effective_deep_agent_model(
+    registry: &ModelRegistry,
+    provider_type: &str,
+    model_name: &str,
+) -> String {
+    let usable = registry.find(model_name).is_some_and(|caps| caps.tools);
+    if provider_type == "openai" && !usable {
         "gpt-4o-mini".to_string()
     } else {
         model_name.to_string()
@@ -274,10 +272,10 @@ async fn try_llm_then_fallback(
     let can_use_llm = provider_info.is_some() && !api_key.trim().is_empty();
 
     if !can_use_llm {
-        tklog::info!("no provider or API key, using rule-based content");
+        tracing::info!("no provider or API key, using rule-based content");
         let fallback = generate_agents_md_content(project_root, scan);
         std::fs::write(path, &fallback)?;
-        tklog::info!("AGENTS.md written (fallback)", path.display());
+        tracing::info!(path = %path.display(), "AGENTS.md written (fallback)");
         return Ok(None);
     }
 
@@ -300,9 +298,13 @@ async fn try_llm_then_fallback(
         let model_for_deep_agent = if base_url.is_some() {
             model_name.to_string()
         } else {
-            effective_deep_agent_model(&provider_type, model_name)
+            effective_deep_agent_model(
+                &config.core_config().model_registry(),
+                &provider_type,
+                model_name,
+            )
         };
-        tklog::info!("trying DeepAgent for init", &provider_type);
+        tracing::info!(provider_type = %provider_type, "trying DeepAgent for init");
         match opencode_provider::try_deep_agent_agents_md(
             project_root,
             &model_for_deep_agent,
@@ -312,55 +314,40 @@ async fn try_llm_then_fallback(
         .await
         {
             Ok(None) => {
-                tklog::info!("DeepAgent wrote AGENTS.md");
+                tracing::info!("DeepAgent wrote AGENTS.md");
                 return Ok(None);
             }
             Ok(Some(content)) => {
-                tklog::info!("DeepAgent returned content, writing AGENTS.md");
+                tracing::info!("DeepAgent returned content, writing AGENTS.md");
                 std::fs::write(path, content)?;
-                tklog::info!("AGENTS.md written (from DeepAgent content)", path.display());
+                tracing::info!(path = %path.display(), "AGENTS.md written (from DeepAgent content)");
                 return Ok(None);
             }
             Err(e) => {
-                tklog::info!("DeepAgent failed, falling back to single generate", &e);
+                tracing::info!(error = %e, "DeepAgent failed, falling back to single generate");
             }
         }
     } else if provider_type == "ollama" {
-        tklog::info!(
-            "Skipping DeepAgent for ollama (crate feature not enabled)",
-            &provider_type
+        tracing::info!(
+            provider_type = %provider_type,
+            "Skipping DeepAgent for ollama (crate feature not enabled)"
         );
     }
 
-    let provider = match provider_type.as_str() {
-        "openai" => opencode_provider::LangChainAdapter::from_openai(
-            api_key.clone(),
-            base_url.clone(),
-            model.clone(),
-        )
-        .ok()
-        .map(std::sync::Arc::new),
-        "ollama" => opencode_provider::LangChainAdapter::from_ollama(base_url, model)
-            .ok()
-            .map(std::sync::Arc::new),
-        "qwen" => opencode_provider::LangChainAdapter::from_qwen(api_key, base_url, model)
-            .ok()
-            .map(std::sync::Arc::new),
-        "anthropic" => opencode_provider::LangChainAdapter::from_anthropic(api_key)
-            .ok()
-            .map(std::sync::Arc::new),
-        _ => None,
-    };
+    let provider = opencode_provider::ProviderFactory::default()
+        .build_chain(&config.provider_chain(), config.retry_policy())
+        .ok();
 
     let Some(provider) = provider else {
-        tklog::info!("provider build failed or unsupported type, using rule-based content");
+        tracing::info!("provider build failed or unsupported type, using rule-based content");
         let fallback = generate_agents_md_content(project_root, scan);
         std::fs::write(path, &fallback)?;
-        tklog::info!("AGENTS.md written (fallback)", path.display());
+        tracing::info!(path = %path.display(), "AGENTS.md written (fallback)");
         return Ok(None);
     };
 
-    let adapter = opencode_provider::ProviderAdapter::new(provider);
+    let adapter = opencode_provider::ProviderAdapter::new(provider)
+        .with_redactor(std::sync::Arc::new(config.redactor()?));
     let context = format!(
         "Directory structure:\n```\n{}\n```\n\nStack / workspace: {}\n\nREADME preview:\n{}",
         scan.tree,
@@ -382,20 +369,27 @@ Output a single Markdown document with these sections: ## Project overview, ## D
     let messages = vec![opencode_core::agent::Message {
         role: opencode_core::agent::MessageRole::User,
         content: prompt,
+        tool_call_id: None,
+        tool_calls: Vec::new(),
     }];
+    let capabilities = config
+        .core_config()
+        .model_registry()
+        .capabilities(model.as_deref());
     let request = opencode_core::agent::ProviderRequest {
         messages,
         model: None,
-        temperature: Some(0.3),
-        max_tokens: Some(4096),
+        temperature: capabilities.temperature.map(|_| 0.3),
+        max_tokens: Some(capabilities.max_output_tokens),
+        tools: Vec::new(),
     };
     match adapter.generate(request).await {
         Ok(response) => {
-            tklog::info!("LLM generate ok, writing AGENTS.md");
+            tracing::info!("LLM generate ok, writing AGENTS.md");
             Ok(Some(String::from(strip_markdown_fence(&response.content))))
         }
         Err(e) => {
-            tklog::info!("LLM generate failed", &e);
+            tracing::info!(error = %e, "LLM generate failed");
             Ok(Some(generate_agents_md_content(project_root, scan)))
         }
     }
@@ -406,21 +400,21 @@ Output a single Markdown document with these sections: ## Project overview, ## D
 /// Uses LLM to generate content when langchain feature is enabled and provider is configured; otherwise uses rule-based content.
 /// Returns `true` when the file was written.
 pub async fn init_agents_md(project_root: &Path, _force: bool) -> Result<bool> {
-    tklog::info!("init_agents_md called", project_root.display());
+    tracing::info!(root = %project_root.display(), "init_agents_md called");
     let path = project_root.join("AGENTS.md");
-    tklog::info!("AGENTS.md path", path.display());
+    tracing::info!(path = %path.display(), "AGENTS.md path");
 
     let scan = scan_project(project_root);
-    tklog::info!("scan completed, generating content");
+    tracing::info!("scan completed, generating content");
 
     let content = match try_llm_then_fallback(project_root, &scan, &path).await? {
         None => return Ok(true), // already written (e.g. by DeepAgent)
         Some(s) => s,
     };
 
-    tklog::info!("writing AGENTS.md", path.display());
+    tracing::info!(path = %path.display(), "writing AGENTS.md");
     std::fs::write(&path, content)?;
-    tklog::info!("AGENTS.md written", path.display());
+    tracing::info!(path = %path.display(), "AGENTS.md written");
     Ok(true)
 }
 
@@ -1,6 +1,9 @@
 pub mod app;
+pub mod checkpoints;
 pub mod config;
 pub mod init;
+pub mod models;
 pub mod run;
 pub mod serve;
 pub mod sessions;
+pub mod stats;
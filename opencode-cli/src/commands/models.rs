@@ -0,0 +1,56 @@
+use anyhow::{anyhow, Result};
+use crate::config::AppConfig;
+use opencode_core::model_registry::ModelCapabilities;
+use opencode_provider::ProviderFactory;
+
+/// Prints the models offered by the provider with `provider_id`, or by the default provider.
+pub async fn list_models(provider_id: Option<&str>) -> Result<()> {
+    let config = AppConfig::load().unwrap_or_default();
+    let provider_config = config
+        .provider_config(provider_id)
+        .ok_or_else(|| anyhow!("Unknown provider: {}", provider_id.unwrap_or_default()))?;
+
+    let models = ProviderFactory::default()
+        .list_models(&provider_config)
+        .await?;
+    if models.is_empty() {
+        println!("Provider '{}' did not report any models.", provider_config.id);
+        return Ok(());
+    }
+
+    let registry = config.core_config().model_registry();
+    println!(
+        "Models for '{}' ({}):",
+        provider_config.id, provider_config.provider_type
+    );
+    for model in models {
+        let capabilities = match registry.find(&model.id) {
+            Some(caps) => format_capabilities(caps),
+            None => "unknown capabilities".to_string(),
+        };
+        if model.name == model.id {
+            println!("  {:<40} {}", model.id, capabilities);
+        } else {
+            println!("  {:<40} {} ({})", model.id, capabilities, model.name);
+        }
+    }
+    Ok(())
+}
+
+/// e.g. `128k context, 16k output, tools, vision`.
+pub fn format_capabilities(caps: &ModelCapabilities) -> String {
+    let mut parts = vec![
+        format!("{}k context", caps.context_length / 1000),
+        format!("{}k output", caps.max_output_tokens / 1000),
+    ];
+    for (supported, name) in [
+        (caps.tools, "tools"),
+        (caps.vision, "vision"),
+        (caps.reasoning, "reasoning"),
+    ] {
+        if supported {
+            parts.push(name.to_string());
+        }
+    }
+    parts.join(", ")
+}
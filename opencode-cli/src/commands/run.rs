@@ -1,94 +1,33 @@
 use anyhow::Result;
-use opencode_core::session::{Session, Message, Role};
+use opencode_core::session::{Session, Message, MessageMeta, Role};
 use opencode_provider::ProviderAdapter;
-use std::sync::Arc;
 use chrono::Utc;
 use crate::config::AppConfig;
-use crate::session_store;
+use crate::message_processor::{process_message_async, LogLevel, SessionUpdate};
+use crate::session_store::SessionStore;
+use opencode_core::permission::PermissionReply;
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+use tokio::sync::mpsc;
 
 pub async fn run_command(command: &str) -> Result<()> {
     println!("Processing command: {}", command);
     
     // Load configuration
     let config = AppConfig::load().unwrap_or_else(|_| AppConfig::default());
-    let session_dir = config.session_dir();
     
     // Initialize provider
-    let provider_info = config.get_default_provider();
-    let provider_type = provider_info
-            .as_ref()
-            .map(|p| p.provider_type.clone())
-            .unwrap_or_else(|| "openai".to_string());
-    let base_url = provider_info.as_ref().and_then(|p| p.base_url.clone());
-    let model = provider_info.as_ref().and_then(|p| p.model.clone());
-
-    let api_key = provider_info
-        .as_ref()
-        .and_then(|p| p.api_key.clone())
-        .or_else(|| std::env::var("OPENAI_API_KEY").ok())
-        .or_else(|| std::env::var("OPENCODE_OPENAI_API_KEY").ok())
-        .unwrap_or_else(|| "".to_string());
-
-    let provider: Arc<dyn opencode_provider::Provider> = match provider_type.as_str() {
-        "openai" => {
-            if api_key.trim().is_empty() {
-                eprintln!("Error: No API key configured. Please set OPENAI_API_KEY or OPENCODE_OPENAI_API_KEY environment variable.");
-                std::process::exit(1);
-            }
-            match opencode_provider::LangChainAdapter::from_openai(api_key, base_url, model) {
-                Ok(adapter) => Arc::new(adapter),
-                Err(e) => {
-                    eprintln!("Error initializing OpenAI provider: {}", e);
-                    std::process::exit(1);
-                }
-            }
-        }
-        "ollama" => {
-            match opencode_provider::LangChainAdapter::from_ollama(base_url, model) {
-                Ok(adapter) => Arc::new(adapter),
-                Err(e) => {
-                    eprintln!("Error initializing Ollama provider: {}", e);
-                    std::process::exit(1);
-                }
-            }
-        }
-        "qwen" => {
-            if api_key.trim().is_empty() {
-                eprintln!("Error: No API key configured for Qwen. Please set the API key.");
-                std::process::exit(1);
-            }
-            match opencode_provider::LangChainAdapter::from_qwen(api_key, base_url, model) {
-                Ok(adapter) => Arc::new(adapter),
-                Err(e) => {
-                    eprintln!("Error initializing Qwen provider: {}", e);
-                    std::process::exit(1);
-                }
-            }
-        }
-        "anthropic" => {
-            if api_key.trim().is_empty() {
-                eprintln!("Error: No API key configured. Please set the API key.");
-                std::process::exit(1);
-            }
-            match opencode_provider::LangChainAdapter::from_anthropic(api_key) {
-                Ok(adapter) => Arc::new(adapter),
-                Err(e) => {
-                    eprintln!("Error initializing Anthropic provider: {}", e);
-                    std::process::exit(1);
-                }
-            }
-        }
-        _ => {
-            eprintln!("Unsupported provider type: {}", provider_type);
+    let provider = match opencode_provider::ProviderFactory::default()
+        .build_chain(&config.provider_chain(), config.retry_policy())
+    {
+        Ok(provider) => provider,
+        Err(e) => {
+            eprintln!("Error: {}", e);
             std::process::exit(1);
         }
     };
 
-    let provider_adapter = ProviderAdapter::new(provider);
-
-    // Create or load a temporary session for this command
-    let session_id = uuid::Uuid::new_v4().to_string();
-    let session_file = session_dir.join(&session_id).join("session.json");
+    let provider_adapter = ProviderAdapter::new(provider).with_redactor(Arc::new(config.redactor()?));
 
     // Create a new session for the command
     let mut session = Session::new();
@@ -111,17 +50,24 @@ pub async fn run_command(command: &str) -> Result<()> {
                 Role::User => opencode_core::agent::MessageRole::User,
                 Role::Assistant => opencode_core::agent::MessageRole::Assistant,
                 Role::System => opencode_core::agent::MessageRole::System,
-                Role::Tool => opencode_core::agent::MessageRole::System,
+                Role::Tool => opencode_core::agent::MessageRole::Tool,
             },
             content: m.content.clone(),
+            tool_call_id: None,
+            tool_calls: Vec::new(),
         })
         .collect();
 
+    let capabilities = config
+        .core_config()
+        .model_registry()
+        .capabilities(config.default_provider_config().model.as_deref());
     let request = opencode_core::agent::ProviderRequest {
         messages,
         model: None,
-        temperature: Some(0.7),
-        max_tokens: Some(4096),
+        temperature: capabilities.temperature,
+        max_tokens: Some(capabilities.max_output_tokens),
+        tools: Vec::new(),
     };
 
     use opencode_core::agent::Provider;
@@ -132,14 +78,14 @@ pub async fn run_command(command: &str) -> Result<()> {
                 role: Role::Assistant,
                 content: response.content.clone(),
                 created_at: Utc::now(),
-                meta: None,
+                meta: response.usage.map(|usage| MessageMeta {
+                    usage: Some(usage),
+                    ..Default::default()
+                }),
             };
             session.push_message(assistant_message);
 
-            if let Some(parent) = session_file.parent() {
-                let _ = std::fs::create_dir_all(parent);
-            }
-            if let Err(e) = session_store::save_session(&session_file, &session) {
+            if let Err(e) = config.session_store().save(&session) {
                 eprintln!("Warning: Could not save session: {}", e);
             }
             println!("{}", response.content);
@@ -152,3 +98,84 @@ pub async fn run_command(command: &str) -> Result<()> {
 
     Ok(())
 }
+
+/// Runs one agent turn in a new session of the workspace in the current directory, printing the
+/// reply as it streams. Tool calls matching `ask` permission rules are confirmed on the terminal.
+pub async fn run_agent(command: &str, agent: &str) -> Result<()> {
+    let workspace = std::env::current_dir()?;
+    let config = AppConfig::load_from_workspace(&workspace)?;
+    let session = Session::new();
+    config.session_store().save(&session)?;
+    let session_id = session.id.to_string();
+    println!("Session {} ({})", session_id, agent);
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<(String, SessionUpdate)>();
+    let printer = tokio::spawn(async move {
+        while let Some((_, update)) = rx.recv().await {
+            match update {
+                SessionUpdate::Reply(Some(chunk)) => {
+                    print!("{}", chunk);
+                    let _ = std::io::stdout().flush();
+                }
+                SessionUpdate::Reply(None) => println!(),
+                SessionUpdate::Log(entry) if entry.level != LogLevel::Info => {
+                    eprintln!("{}", entry.message)
+                }
+                SessionUpdate::Log(_) | SessionUpdate::State(_) => {}
+                SessionUpdate::PermissionRequest(pending) => {
+                    let request = pending.request;
+                    println!();
+                    println!("🔐 {} wants to run {}", request.tool_id, request.resources.join(", "));
+                    println!("   arguments: {}", request.arguments);
+                    if let Some(diff) = &request.diff {
+                        println!("{}", diff);
+                    }
+                    let reply = tokio::task::spawn_blocking(prompt_permission)
+                        .await
+                        .unwrap_or(PermissionReply::Deny);
+                    pending.reply.respond(reply);
+                }
+            }
+        }
+    });
+
+    let outcome = process_message_async(
+        &session_id,
+        command,
+        agent,
+        config,
+        Some(workspace),
+        tx,
+        None,
+        &opencode_provider::ProviderFactory::default(),
+    )
+    .await;
+    let _ = printer.await;
+    outcome
+}
+
+/// Asks `Allow? [y]es / [n]o / [a]lways` on the terminal; end of input denies.
+fn prompt_permission() -> PermissionReply {
+    let stdin = std::io::stdin();
+    loop {
+        print!("Allow? [y]es / [n]o / [a]lways: ");
+        let _ = std::io::stdout().flush();
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => return PermissionReply::Deny,
+            Ok(_) => {}
+        }
+        if let Some(reply) = parse_permission_answer(&line) {
+            return reply;
+        }
+    }
+}
+
+pub(crate) fn parse_permission_answer(answer: &str) -> Option<PermissionReply> {
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => Some(PermissionReply::Once),
+        "a" | "always" => Some(PermissionReply::Always),
+        "n" | "no" => Some(PermissionReply::Deny),
+        _ => None,
+    }
+}
@@ -1,10 +1,38 @@
 use anyhow::Result;
 use crate::config::AppConfig;
-use crate::session_store;
-use opencode_core::session::Session;
-use std::fs;
+use crate::export::{export_session as render_session, ExportFormat};
+use crate::import::{import_session as convert_session, ImportFormat};
+use crate::search::{search, SearchQuery};
+use crate::session_store::{SessionStore, SessionSummary};
+use opencode_core::compaction;
+use opencode_core::context::ContextManager;
+use opencode_core::session::{Session, SCHEMA_VERSION};
+use opencode_core::usage::UsageReport;
+use super::stats::{format_totals, format_usage};
+use std::collections::{HashMap, HashSet};
+use std::io::IsTerminal;
+use std::path::Path;
 
-pub async fn list_sessions() -> Result<()> {
+/// Which sessions `sessions list` shows.
+#[derive(Debug, Clone, Default)]
+pub struct SessionFilter {
+    /// Only sessions with this tag.
+    pub tag: Option<String>,
+    pub pinned_only: bool,
+}
+
+impl SessionFilter {
+    fn is_empty(&self) -> bool {
+        self.tag.is_none() && !self.pinned_only
+    }
+
+    fn matches(&self, summary: &SessionSummary) -> bool {
+        (!self.pinned_only || summary.pinned)
+            && self.tag.as_ref().is_none_or(|tag| summary.tags.contains(tag))
+    }
+}
+
+pub async fn list_sessions(filter: SessionFilter) -> Result<()> {
     let config = AppConfig::load().unwrap_or_else(|_| AppConfig::default());
     let session_dir = config.session_dir();
     
@@ -13,64 +41,143 @@ pub async fn list_sessions() -> Result<()> {
         return Ok(());
     }
     
-    let entries = fs::read_dir(&session_dir)?;
     let mut sessions = Vec::new();
     
-    for entry in entries {
-        let entry = entry?;
-        if entry.file_type()?.is_dir() {
-            let session_file = entry.path().join("session.json");
-            if session_file.exists() {
-                match session_store::load_session(&session_file) {
-                    Ok(session) => {
-                        let title = if session.messages.is_empty() {
-                            "New Session".to_string()
-                        } else {
-                            session.messages.first().map(|m| {
-                                let content = &m.content;
-                                if content.len() > 50 {
-                                    format!("{}...", &content[..50])
-                                } else {
-                                    content.clone()
-                                }
-                            }).unwrap_or("New Session".to_string())
-                        };
-                        
-                        sessions.push((entry.file_name().to_string_lossy().to_string(), title, session.updated_at));
-                    }
-                    Err(_) => {
-                        sessions.push((entry.file_name().to_string_lossy().to_string(), "Corrupted Session".to_string(), chrono::Utc::now()));
-                    }
+    // The store lists pinned sessions first, then newest first; forks are printed under their
+    // parent.
+    for (id, summary) in config.session_store().list()? {
+        match summary {
+            Ok(summary) => {
+                if !filter.matches(&summary) {
+                    continue;
+                }
+                let mut title = summary.display_title();
+                if summary.pinned {
+                    title = format!("📌 {}", title);
+                }
+                if !summary.tags.is_empty() {
+                    title = format!("{} [{}]", title, summary.tags.join(", "));
                 }
+                let updated_at = summary.updated_at.format("%Y-%m-%d %H:%M:%S").to_string();
+                let fork = summary.parent_session_id.map(|parent| (parent, summary.forked_at_index.unwrap_or(0)));
+                
+                sessions.push(SessionRow { id, title, updated_at, fork });
             }
+            Err(e) if filter.is_empty() => {
+                sessions.push(SessionRow {
+                    id,
+                    title: format!("Unreadable: {}", e),
+                    updated_at: String::new(),
+                    fork: None,
+                });
+            }
+            Err(_) => {}
         }
     }
     
-    sessions.sort_by(|a, b| b.2.cmp(&a.2)); // Sort by updated time, newest first
-    
     if sessions.is_empty() {
         println!("No sessions found.");
     } else {
         println!("Found {} session(s):", sessions.len());
-        for (id, title, updated_at) in sessions {
-            println!("{:<36} {:<50} {}", id, title, updated_at.format("%Y-%m-%d %H:%M:%S"));
+        for (depth, row) in session_tree(&sessions) {
+            let id = if depth == 0 {
+                row.id.clone()
+            } else {
+                format!("{}└─ {}", "   ".repeat(depth - 1), row.id)
+            };
+            let title = match &row.fork {
+                Some((_, at)) => format!("[fork @{}] {}", at, row.title),
+                None => row.title.clone(),
+            };
+            println!("{:<36} {:<50} {}", id, title, row.updated_at);
         }
     }
     
     Ok(())
 }
 
+struct SessionRow {
+    id: String,
+    title: String,
+    updated_at: String,
+    /// Parent session id and the number of messages copied from it.
+    fork: Option<(String, usize)>,
+}
+
+/// Orders rows depth-first so each fork follows its parent. Forks whose parent no longer
+/// exists are shown at the top level.
+fn session_tree(rows: &[SessionRow]) -> Vec<(usize, &SessionRow)> {
+    let known: HashSet<&str> = rows.iter().map(|r| r.id.as_str()).collect();
+    let mut children: HashMap<&str, Vec<&SessionRow>> = HashMap::new();
+    let mut roots = Vec::new();
+    for row in rows {
+        let parent = row
+            .fork
+            .as_ref()
+            .map(|(parent, _)| parent.as_str())
+            .filter(|parent| known.contains(parent) && *parent != row.id);
+        match parent {
+            Some(parent) => children.entry(parent).or_default().push(row),
+            None => roots.push(row),
+        }
+    }
+    let mut out = Vec::with_capacity(rows.len());
+    let mut stack: Vec<(usize, &SessionRow)> = roots.into_iter().rev().map(|r| (0, r)).collect();
+    let mut seen = HashSet::new();
+    while let Some((depth, row)) = stack.pop() {
+        if !seen.insert(row.id.as_str()) {
+            continue;
+        }
+        out.push((depth, row));
+        if let Some(kids) = children.get(row.id.as_str()) {
+            stack.extend(kids.iter().rev().map(|k| (depth + 1, *k)));
+        }
+    }
+    // Rows caught in a parent cycle are never reached from a root.
+    for row in rows {
+        if !seen.contains(row.id.as_str()) {
+            out.push((0, row));
+        }
+    }
+    out
+}
+
+/// Creates a session with the first `at` messages of `session_id` (all of them by default).
+pub async fn fork_session(session_id: &str, at: Option<usize>) -> Result<()> {
+    let config = AppConfig::load().unwrap_or_else(|_| AppConfig::default());
+    let store = config.session_store();
+    
+    if !store.exists(session_id) {
+        eprintln!("Session '{}' does not exist.", session_id);
+        return Ok(());
+    }
+    
+    let session = store.load(session_id)?;
+    let at = at.unwrap_or(session.messages.len());
+    let fork = session.fork(at).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Cannot fork at message {}: session '{}' has {} messages",
+            at,
+            session_id,
+            session.messages.len()
+        )
+    })?;
+    store.save(&fork)?;
+    println!("Forked session {} at message {}: {}", session_id, at, fork.id);
+    
+    Ok(())
+}
+
 pub async fn delete_session(session_id: &str) -> Result<()> {
     let config = AppConfig::load().unwrap_or_else(|_| AppConfig::default());
-    let session_dir = config.session_dir();
-    let session_path = session_dir.join(session_id);
+    let store = config.session_store();
     
-    if !session_path.exists() {
+    if !store.exists(session_id) {
         eprintln!("Session '{}' does not exist.", session_id);
         return Ok(());
     }
     
-    fs::remove_dir_all(&session_path)?;
+    store.delete(session_id)?;
     println!("Deleted session: {}", session_id);
     
     Ok(())
@@ -78,20 +185,38 @@ pub async fn delete_session(session_id: &str) -> Result<()> {
 
 pub async fn show_session(session_id: &str) -> Result<()> {
     let config = AppConfig::load().unwrap_or_else(|_| AppConfig::default());
-    let session_dir = config.session_dir();
-    let session_file = session_dir.join(session_id).join("session.json");
+    let store = config.session_store();
     
-    if !session_file.exists() {
+    if !store.exists(session_id) {
         eprintln!("Session '{}' does not exist.", session_id);
         return Ok(());
     }
     
-    let session: Session = session_store::load_session(&session_file)?;
+    let session: Session = store.load(session_id)?;
     
     println!("Session ID: {}", session.id);
+    println!("Title: {}", session.display_title());
+    if !session.tags.is_empty() {
+        println!("Tags: {}", session.tags.join(", "));
+    }
+    if session.pinned {
+        println!("Pinned: yes");
+    }
     println!("Created: {}", session.created_at);
     println!("Updated: {}", session.updated_at);
+    if let Some(parent) = &session.parent_session_id {
+        println!("Forked from: {} at message {}", parent, session.forked_at_index.unwrap_or(0));
+    }
     println!("Messages: {}", session.messages.len());
+    if !session.archived.is_empty() {
+        println!("Archived by /compact: {} messages", session.archived.len());
+    }
+    if let Some(status) = store.load_status(session_id)? {
+        println!("State: {:?} (since {})", status.state, status.updated_at);
+        if let Some(error) = &status.error {
+            println!("Last error: {}", error);
+        }
+    }
     println!("\nMessages:");
     println!("{}", "=" .repeat(60));
     
@@ -99,14 +224,263 @@ pub async fn show_session(session_id: &str) -> Result<()> {
         let role_prefix = match message.role {
             opencode_core::session::Role::User => "👤 USER",
             opencode_core::session::Role::Assistant => "🤖 ASSISTANT", 
+            opencode_core::session::Role::System if message.is_summary() => "📝 SUMMARY",
             opencode_core::session::Role::System => "⚙️ SYSTEM",
             opencode_core::session::Role::Tool => "🔧 TOOL",
         };
         
         println!("[{}] {}", i + 1, role_prefix);
         println!("{}", message.content);
+        if let Some(usage) = message.meta.as_ref().and_then(|m| m.usage.as_ref()) {
+            println!(
+                "({}: {} tokens)",
+                usage.model.as_deref().unwrap_or("unknown model"),
+                format_usage(usage)
+            );
+        }
         println!("{}", "-" .repeat(60));
     }
+
+    let report = UsageReport::from_sessions([&session], &config.core_config().model_registry().pricing());
+    if report.total.responses > 0 {
+        println!("Usage: {}", format_totals(&report.total));
+    }
+    
+    Ok(())
+}
+
+/// Converts sessions saved by earlier versions (`session.json`) to the JSONL log format.
+pub async fn migrate_sessions() -> Result<()> {
+    let config = AppConfig::load().unwrap_or_else(|_| AppConfig::default());
+    let store = config.session_store();
+    let converted = store.migrate_legacy()?;
+    let upgraded = store.upgrade_logs()?;
+    if converted.is_empty() && upgraded.is_empty() {
+        println!("No sessions to migrate.");
+        return Ok(());
+    }
+    let mut failed = 0;
+    for (id, result) in converted {
+        match result {
+            Ok(()) => println!("Migrated session: {}", id),
+            Err(e) => {
+                failed += 1;
+                eprintln!("Failed to migrate session {}: {}", id, e);
+            }
+        }
+    }
+    for (id, result) in upgraded {
+        match result {
+            Ok(from) => println!("Upgraded session {} from schema version {} to {}", id, from, SCHEMA_VERSION),
+            Err(e) => {
+                failed += 1;
+                eprintln!("Failed to upgrade session {}: {}", id, e);
+            }
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{} session(s) could not be migrated", failed);
+    }
+    Ok(())
+}
+
+/// Validates every session in the session directory without changing anything: each one must
+/// load after upgrading to the current schema. Fails when any session is unreadable.
+pub async fn check_sessions() -> Result<()> {
+    let config = AppConfig::load().unwrap_or_else(|_| AppConfig::default());
+    let store = config.session_store();
+    let ids = store.session_ids()?;
+    if ids.is_empty() {
+        println!("No sessions found in {}.", store.root().display());
+        return Ok(());
+    }
+
+    let (mut ok, mut outdated, mut failed) = (0, 0, 0);
+    for id in &ids {
+        match store.check(id) {
+            Ok(check) if check.needs_migration() => {
+                outdated += 1;
+                let format = if check.legacy { "session.json" } else { "session.jsonl" };
+                println!(
+                    "{} needs migration ({}, schema version {}, {} messages)",
+                    id, format, check.schema_version, check.message_count
+                );
+            }
+            Ok(_) => ok += 1,
+            Err(e) => {
+                failed += 1;
+                println!("{} ERROR: {}", id, e);
+            }
+        }
+    }
+    println!(
+        "\nChecked {} session(s) against schema version {}: {} current, {} need migration, {} unreadable.",
+        ids.len(),
+        SCHEMA_VERSION,
+        ok,
+        outdated,
+        failed
+    );
+    if outdated > 0 {
+        println!("Run `opencode sessions migrate` to rewrite outdated sessions.");
+    }
+    if failed > 0 {
+        anyhow::bail!("{} session(s) could not be read", failed);
+    }
+    Ok(())
+}
+
+/// Writes a transcript of `session_id` to `output`, or to stdout when no output is given.
+pub async fn export_session(session_id: &str, format: ExportFormat, output: Option<&Path>) -> Result<()> {
+    let config = AppConfig::load().unwrap_or_else(|_| AppConfig::default());
+    let store = config.session_store();
+    
+    if !store.exists(session_id) {
+        eprintln!("Session '{}' does not exist.", session_id);
+        return Ok(());
+    }
+    
+    let session = store.load(session_id)?;
+    let pricing = config.core_config().model_registry().pricing();
+    let transcript = render_session(&session, format, &pricing)?;
+    match output {
+        Some(path) => {
+            std::fs::write(path, transcript)?;
+            println!("Exported session {} to {}", session_id, path.display());
+        }
+        None => print!("{}", transcript),
+    }
     
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Converts a conversation from another tool into a new session.
+pub async fn import_session(file: &Path, format: ImportFormat) -> Result<()> {
+    let config = AppConfig::load().unwrap_or_else(|_| AppConfig::default());
+    let session = convert_session(file, format)?;
+    config.session_store().save(&session)?;
+    println!(
+        "Imported {} messages from {} as session {}",
+        session.messages.len(),
+        file.display(),
+        session.id
+    );
+    
+    Ok(())
+}
+
+/// Prints messages matching `query`, newest first, with the matches highlighted.
+pub async fn search_sessions(query: SearchQuery) -> Result<()> {
+    let config = AppConfig::load().unwrap_or_else(|_| AppConfig::default());
+    let hits = search(&config.session_store(), &query)?;
+    
+    if hits.is_empty() {
+        println!("No messages match \"{}\".", query.text);
+        return Ok(());
+    }
+    
+    let (before, after) = if std::io::stdout().is_terminal() {
+        ("\x1b[1;33m", "\x1b[0m")
+    } else {
+        ("**", "**")
+    };
+    println!("Found {} message(s):", hits.len());
+    for hit in hits {
+        println!(
+            "{} #{:<4} {:<9} {}",
+            hit.session_id,
+            hit.message_index + 1,
+            format!("{:?}", hit.role).to_lowercase(),
+            hit.created_at.format("%Y-%m-%d %H:%M")
+        );
+        println!("    {}", hit.highlighted(before, after));
+    }
+    
+    Ok(())
+}
+
+/// Loads `session_id`, applies `edit` and saves it. Prints an error when the session is missing.
+fn update_session(session_id: &str, edit: impl FnOnce(&mut Session)) -> Result<Option<Session>> {
+    let config = AppConfig::load().unwrap_or_else(|_| AppConfig::default());
+    let store = config.session_store();
+    
+    if !store.exists(session_id) {
+        eprintln!("Session '{}' does not exist.", session_id);
+        return Ok(None);
+    }
+    
+    let mut session = store.load(session_id)?;
+    edit(&mut session);
+    store.save(&session)?;
+    Ok(Some(session))
+}
+
+/// Sets the title of `session_id`; an empty title clears it.
+pub async fn rename_session(session_id: &str, title: &str) -> Result<()> {
+    let title = title.trim();
+    if let Some(session) = update_session(session_id, |s| {
+        s.title = (!title.is_empty()).then(|| title.to_string());
+    })? {
+        println!("Session {}: {}", session_id, session.display_title());
+    }
+    Ok(())
+}
+
+/// Adds `tags` to `session_id`, or removes them with `remove`.
+pub async fn tag_session(session_id: &str, tags: &[String], remove: bool) -> Result<()> {
+    if let Some(session) = update_session(session_id, |s| {
+        for tag in tags {
+            if remove {
+                s.remove_tag(tag);
+            } else {
+                s.add_tag(tag);
+            }
+        }
+    })? {
+        println!("Session {} tags: {}", session_id, session.tags.join(", "));
+    }
+    Ok(())
+}
+
+pub async fn pin_session(session_id: &str, pinned: bool) -> Result<()> {
+    if update_session(session_id, |s| s.pinned = pinned)?.is_some() {
+        let action = if pinned { "Pinned" } else { "Unpinned" };
+        println!("{} session: {}", action, session_id);
+    }
+    Ok(())
+}
+
+/// Summarizes all but the latest `keep_turns` turns of `session_id` with the default provider
+/// and archives them (the CLI form of `/compact`).
+pub async fn compact_session(session_id: &str, keep_turns: usize) -> Result<()> {
+    let config = AppConfig::load().unwrap_or_else(|_| AppConfig::default());
+    let store = config.session_store();
+
+    if !store.exists(session_id) {
+        eprintln!("Session '{}' does not exist.", session_id);
+        return Ok(());
+    }
+
+    let mut session = store.load(session_id)?;
+    let provider = opencode_provider::ProviderFactory::default()
+        .build_chain(&config.provider_chain(), config.retry_policy())?;
+    let provider = opencode_provider::ProviderAdapter::new(provider)
+        .with_redactor(std::sync::Arc::new(config.redactor()?));
+    let model = config.default_provider_config().model;
+    let capabilities = config
+        .core_config()
+        .model_registry()
+        .capabilities(model.as_deref());
+    let context = ContextManager::new(model.as_deref(), &capabilities);
+    match compaction::compact_session(&mut session, &provider, model, &context, keep_turns).await? {
+        Some(outcome) => {
+            store.save(&session)?;
+            println!(
+                "Compacted {} messages of session {} into a summary; {} recent messages kept.",
+                outcome.archived, session_id, outcome.kept
+            );
+        }
+        None => println!("Nothing to compact in session {}.", session_id),
+    }
+    Ok(())
+}
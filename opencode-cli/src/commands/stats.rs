@@ -0,0 +1,76 @@
+use anyhow::Result;
+use crate::config::AppConfig;
+use crate::session_store::SessionStore;
+use opencode_core::session::Session;
+use opencode_core::usage::{TokenUsage, UsageReport, UsageTotals};
+
+/// Prints token usage and cost for all sessions in the session directory, grouped by model,
+/// day and session.
+pub async fn show_stats() -> Result<()> {
+    let config = AppConfig::load().unwrap_or_default();
+    let session_dir = config.session_dir();
+
+    let store = config.session_store();
+    let mut sessions: Vec<Session> = Vec::new();
+    for (id, summary) in store.list()? {
+        match summary.and_then(|_| store.load(&id)) {
+            Ok(session) => sessions.push(session),
+            Err(e) => eprintln!("Skipping session {}: {}", id, e),
+        }
+    }
+
+    let pricing = config.core_config().model_registry().pricing();
+    let report = UsageReport::from_sessions(&sessions, &pricing);
+    if report.total.responses == 0 {
+        println!("No token usage recorded in {:?}.", session_dir);
+        return Ok(());
+    }
+
+    println!("Total: {}", format_totals(&report.total));
+
+    println!("\nBy model:");
+    for (model, totals) in &report.by_model {
+        println!("  {:<36} {}", model, format_totals(totals));
+    }
+
+    println!("\nBy day:");
+    for (day, totals) in report.by_day.iter().rev() {
+        println!("  {:<36} {}", day, format_totals(totals));
+    }
+
+    println!("\nBy session:");
+    let mut by_session: Vec<_> = report.by_session.iter().collect();
+    by_session.sort_by(|a, b| b.1.cost.total_cmp(&a.1.cost));
+    for (id, totals) in by_session {
+        println!("  {:<36} {}", id, format_totals(totals));
+    }
+
+    Ok(())
+}
+
+/// One-line token breakdown, e.g. `1200 prompt (800 cached) + 300 completion (120 reasoning)`.
+pub fn format_usage(usage: &TokenUsage) -> String {
+    let mut out = format!("{} prompt", usage.prompt_tokens);
+    if usage.cached_tokens > 0 {
+        out.push_str(&format!(" ({} cached)", usage.cached_tokens));
+    }
+    out.push_str(&format!(" + {} completion", usage.completion_tokens));
+    if usage.reasoning_tokens > 0 {
+        out.push_str(&format!(" ({} reasoning)", usage.reasoning_tokens));
+    }
+    out
+}
+
+/// Token breakdown plus cost; notes responses whose model has no configured price.
+pub fn format_totals(totals: &UsageTotals) -> String {
+    let mut out = format!(
+        "{} responses, {} tokens, ${:.4}",
+        totals.responses,
+        format_usage(&totals.usage),
+        totals.cost
+    );
+    if totals.unpriced > 0 {
+        out.push_str(&format!(" ({} without a price)", totals.unpriced));
+    }
+    out
+}
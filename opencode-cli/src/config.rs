@@ -1,3 +1,4 @@
+use crate::session_store::JsonlSessionStore;
 use anyhow::Result;
 use opencode_core::config::{Config as CoreConfig, ProviderConfig as CoreProviderConfig};
 use std::path::{Path, PathBuf};
@@ -68,6 +69,54 @@ impl AppConfig {
         })
     }
 
+    /// Config of the default provider (same selection as `get_default_provider`), for building it
+    /// through `ProviderFactory`. Falls back to an `openai` entry keyed from the environment.
+    pub fn default_provider_config(&self) -> CoreProviderConfig {
+        self.config
+            .providers
+            .iter()
+            .find(|p| p.id == "default")
+            .or_else(|| self.config.providers.first())
+            .cloned()
+            .unwrap_or_else(|| CoreProviderConfig {
+                id: "default".to_string(),
+                provider_type: "openai".to_string(),
+                api_key: None,
+                base_url: None,
+                model: None,
+            })
+    }
+
+    /// Config of the provider with `id`, or of the default provider when `id` is None.
+    pub fn provider_config(&self, id: Option<&str>) -> Option<CoreProviderConfig> {
+        match id {
+            Some(id) => self.config.providers.iter().find(|p| p.id == id).cloned(),
+            None => Some(self.default_provider_config()),
+        }
+    }
+
+    /// Default provider followed by the configured `fallback_providers` (unknown ids are skipped).
+    pub fn provider_chain(&self) -> Vec<CoreProviderConfig> {
+        let primary = self.default_provider_config();
+        let fallbacks = self
+            .config
+            .fallback_providers
+            .iter()
+            .filter(|id| **id != primary.id)
+            .filter_map(|id| self.config.providers.iter().find(|p| p.id == *id).cloned());
+        std::iter::once(primary.clone()).chain(fallbacks).collect()
+    }
+
+    /// Retry policy for provider calls, from the `retry` config section.
+    pub fn retry_policy(&self) -> opencode_provider::RetryPolicy {
+        opencode_provider::RetryPolicy::from(&self.config.retry)
+    }
+
+    /// Secret masking for provider requests and tool results, from the `redaction` config section.
+    pub fn redactor(&self) -> opencode_core::error::Result<opencode_core::Redactor> {
+        opencode_core::Redactor::new(&self.config.redaction)
+    }
+
     /// List all configured providers (id and info) for TUI display.
     pub fn list_providers(&self) -> Vec<ProviderListItem> {
         self.config
@@ -164,6 +213,11 @@ impl AppConfig {
         self.config.storage.session_dir.clone()
     }
 
+    /// Session store rooted at `session_dir`.
+    pub fn session_store(&self) -> JsonlSessionStore {
+        JsonlSessionStore::new(self.session_dir())
+    }
+
     /// Expose the inner core config for command loading and template expansion.
     pub fn core_config(&self) -> &CoreConfig {
         &self.config
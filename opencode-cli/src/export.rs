@@ -0,0 +1,434 @@
+//! Session transcripts for reading outside the tool: Markdown, a self-contained HTML page with
+//! highlighted code blocks, and a JSON document. All formats include timestamps, tool calls and
+//! results, and per-response model and token usage.
+
+use chrono::{DateTime, Utc};
+use opencode_core::session::{Message, Role, Session, ToolCall};
+use opencode_core::usage::{price_for, ModelPrice, TokenUsage, UsageReport};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::str::FromStr;
+use syntect::highlighting::ThemeSet;
+use syntect::html::highlighted_html_for_string;
+use syntect::parsing::SyntaxSet;
+
+/// Theme for code blocks in HTML exports (one of syntect's defaults).
+const HTML_THEME: &str = "InspiredGitHub";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "md" | "markdown" => Ok(ExportFormat::Markdown),
+            "html" => Ok(ExportFormat::Html),
+            "json" => Ok(ExportFormat::Json),
+            other => Err(format!("unknown export format '{}' (expected md, html or json)", other)),
+        }
+    }
+}
+
+/// Renders `session` in `format`. `pricing` is used for the cost figures.
+pub fn export_session(
+    session: &Session,
+    format: ExportFormat,
+    pricing: &HashMap<String, ModelPrice>,
+) -> anyhow::Result<String> {
+    let transcript = Transcript::new(session, pricing);
+    match format {
+        ExportFormat::Markdown => Ok(transcript.markdown()),
+        ExportFormat::Html => transcript.html(),
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(&transcript)?),
+    }
+}
+
+/// Export view of a session; also the schema of the JSON format.
+#[derive(Serialize)]
+struct Transcript<'a> {
+    id: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_session_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    forked_at_index: Option<usize>,
+    usage: UsageSummary,
+    messages: Vec<TranscriptMessage<'a>>,
+    /// Messages replaced by a `/compact` summary, oldest first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    archived: Vec<TranscriptMessage<'a>>,
+}
+
+#[derive(Serialize)]
+struct UsageSummary {
+    responses: usize,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    cached_tokens: u64,
+    reasoning_tokens: u64,
+    /// USD, over the responses whose model has a price.
+    cost: f64,
+    unpriced_responses: usize,
+}
+
+#[derive(Serialize)]
+struct TranscriptMessage<'a> {
+    index: usize,
+    role: &'static str,
+    created_at: DateTime<Utc>,
+    content: &'a str,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    summary: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    tool_calls: &'a [ToolCall],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<&'a TokenUsage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cost: Option<f64>,
+}
+
+impl<'a> Transcript<'a> {
+    fn new(session: &'a Session, pricing: &HashMap<String, ModelPrice>) -> Self {
+        let total = UsageReport::from_sessions([session], pricing).total;
+        Self {
+            id: session.id.to_string(),
+            created_at: session.created_at,
+            updated_at: session.updated_at,
+            parent_session_id: session.parent_session_id.map(|id| id.to_string()),
+            forked_at_index: session.forked_at_index,
+            usage: UsageSummary {
+                responses: total.responses,
+                prompt_tokens: total.usage.prompt_tokens,
+                completion_tokens: total.usage.completion_tokens,
+                cached_tokens: total.usage.cached_tokens,
+                reasoning_tokens: total.usage.reasoning_tokens,
+                cost: total.cost,
+                unpriced_responses: total.unpriced,
+            },
+            messages: session
+                .messages
+                .iter()
+                .enumerate()
+                .map(|(index, m)| TranscriptMessage::new(index, m, pricing))
+                .collect(),
+            archived: session
+                .archived
+                .iter()
+                .enumerate()
+                .map(|(index, m)| TranscriptMessage::new(index, m, pricing))
+                .collect(),
+        }
+    }
+
+    fn markdown(&self) -> String {
+        let mut out = format!("# Session {}\n\n", self.id);
+        for line in self.header_lines() {
+            let _ = writeln!(out, "- {}", line);
+        }
+        for (i, m) in self.messages.iter().chain(&self.archived).enumerate() {
+            if i == self.messages.len() {
+                out.push_str("\n---\n\n# Archived messages\n\nReplaced by the summary above through `/compact`.\n");
+            }
+            let _ = write!(out, "\n---\n\n## {}. {}", m.index + 1, m.title());
+            let _ = writeln!(out, " · {}\n", format_time(&m.created_at));
+            if let Some(line) = m.usage_line() {
+                let _ = writeln!(out, "*{}*\n", line);
+            }
+            if m.role == "tool" {
+                out.push_str(&fenced("", m.content));
+            } else if !m.content.is_empty() {
+                out.push_str(m.content.trim_end());
+                out.push('\n');
+            }
+            for call in m.tool_calls {
+                let _ = writeln!(out, "\n**Tool call** `{}` (id `{}`)\n", call.name, call.id);
+                out.push_str(&fenced("json", &pretty_json(&call.arguments)));
+            }
+        }
+        out
+    }
+
+    fn html(&self) -> anyhow::Result<String> {
+        let syntaxes = SyntaxSet::load_defaults_newlines();
+        let themes = ThemeSet::load_defaults();
+        let theme = themes
+            .themes
+            .get(HTML_THEME)
+            .ok_or_else(|| anyhow::anyhow!("missing highlight theme {}", HTML_THEME))?;
+        let highlight = |lang: &str, code: &str| -> anyhow::Result<String> {
+            let syntax = syntaxes
+                .find_syntax_by_token(lang)
+                .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+            Ok(highlighted_html_for_string(code, &syntaxes, syntax, theme)?)
+        };
+
+        let mut body = String::new();
+        let _ = writeln!(body, "<h1>Session {}</h1>\n<ul class=\"meta\">", escape(&self.id));
+        for line in self.header_lines() {
+            let _ = writeln!(body, "<li>{}</li>", escape(&line));
+        }
+        body.push_str("</ul>\n");
+        for (i, m) in self.messages.iter().chain(&self.archived).enumerate() {
+            if i == self.messages.len() {
+                body.push_str("<h1>Archived messages</h1>\n<p class=\"meta\">Replaced by the summary above through <code>/compact</code>.</p>\n");
+            }
+            let _ = writeln!(
+                body,
+                "<section class=\"msg {}\">\n<h2>{}. {} <time>{}</time></h2>",
+                m.role,
+                m.index + 1,
+                escape(&m.title()),
+                format_time(&m.created_at)
+            );
+            if let Some(line) = m.usage_line() {
+                let _ = writeln!(body, "<p class=\"usage\">{}</p>", escape(&line));
+            }
+            if m.role == "tool" {
+                let _ = writeln!(body, "<pre class=\"output\">{}</pre>", escape(m.content));
+            } else {
+                for block in split_code_blocks(m.content) {
+                    match block {
+                        Block::Text(text) if text.trim().is_empty() => {}
+                        Block::Text(text) => {
+                            let _ = writeln!(body, "<div class=\"text\">{}</div>", escape(text.trim()));
+                        }
+                        Block::Code { lang, code } => body.push_str(&highlight(lang, &code)?),
+                    }
+                }
+            }
+            for call in m.tool_calls {
+                let _ = writeln!(
+                    body,
+                    "<p class=\"call\">Tool call <code>{}</code> (id <code>{}</code>)</p>",
+                    escape(&call.name),
+                    escape(&call.id)
+                );
+                body.push_str(&highlight("json", &pretty_json(&call.arguments))?);
+            }
+            body.push_str("</section>\n");
+        }
+        Ok(format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Session {}</title>\n<style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+            escape(&self.id),
+            HTML_STYLE,
+            body
+        ))
+    }
+
+    fn header_lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("Created: {}", format_time(&self.created_at)),
+            format!("Updated: {}", format_time(&self.updated_at)),
+            format!("Messages: {}", self.messages.len()),
+        ];
+        if !self.archived.is_empty() {
+            lines.push(format!("Archived by /compact: {}", self.archived.len()));
+        }
+        if let Some(parent) = &self.parent_session_id {
+            lines.push(format!(
+                "Forked from: {} at message {}",
+                parent,
+                self.forked_at_index.unwrap_or(0)
+            ));
+        }
+        let u = &self.usage;
+        if u.responses > 0 {
+            let mut line = format!(
+                "Usage: {} responses, {} prompt + {} completion tokens, ${:.4}",
+                u.responses, u.prompt_tokens, u.completion_tokens, u.cost
+            );
+            if u.unpriced_responses > 0 {
+                let _ = write!(line, " ({} without a price)", u.unpriced_responses);
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+impl<'a> TranscriptMessage<'a> {
+    fn new(index: usize, message: &'a Message, pricing: &HashMap<String, ModelPrice>) -> Self {
+        let meta = message.meta.as_ref();
+        let usage = meta.and_then(|m| m.usage.as_ref());
+        Self {
+            index,
+            role: role_name(&message.role),
+            created_at: message.created_at,
+            content: &message.content,
+            summary: message.is_summary(),
+            tool_name: meta.and_then(|m| m.tool_name.as_deref()),
+            tool_call_id: meta.and_then(|m| m.tool_call_id.as_deref()),
+            tool_calls: meta.map(|m| m.tool_calls.as_slice()).unwrap_or_default(),
+            usage,
+            cost: usage.and_then(|u| {
+                let model = u.model.as_deref()?;
+                price_for(pricing, model).map(|p| p.cost(u))
+            }),
+        }
+    }
+
+    /// Heading such as `Assistant` or `Tool result: read_file (call_1)`.
+    fn title(&self) -> String {
+        match self.role {
+            "tool" => {
+                let mut title = format!("Tool result: {}", self.tool_name.unwrap_or("unknown"));
+                if let Some(id) = self.tool_call_id {
+                    let _ = write!(title, " ({})", id);
+                }
+                title
+            }
+            "user" => "User".to_string(),
+            "assistant" => "Assistant".to_string(),
+            _ if self.summary => "Summary of earlier messages".to_string(),
+            _ => "System".to_string(),
+        }
+    }
+
+    fn usage_line(&self) -> Option<String> {
+        let usage = self.usage?;
+        let mut line = format!(
+            "{}: {} prompt + {} completion tokens",
+            usage.model.as_deref().unwrap_or("unknown model"),
+            usage.prompt_tokens,
+            usage.completion_tokens
+        );
+        if usage.cached_tokens > 0 {
+            let _ = write!(line, ", {} cached", usage.cached_tokens);
+        }
+        if usage.reasoning_tokens > 0 {
+            let _ = write!(line, ", {} reasoning", usage.reasoning_tokens);
+        }
+        if let Some(cost) = self.cost {
+            let _ = write!(line, ", ${:.4}", cost);
+        }
+        Some(line)
+    }
+}
+
+fn role_name(role: &Role) -> &'static str {
+    match role {
+        Role::User => "user",
+        Role::Assistant => "assistant",
+        Role::System => "system",
+        Role::Tool => "tool",
+    }
+}
+
+fn format_time(t: &DateTime<Utc>) -> String {
+    t.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+}
+
+fn pretty_json(value: &serde_json::Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+}
+
+/// Fenced Markdown code block whose fence is longer than any backtick run in `code`.
+fn fenced(lang: &str, code: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in code.chars() {
+        run = if c == '`' { run + 1 } else { 0 };
+        longest = longest.max(run);
+    }
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{}{}\n{}\n{}\n", fence, lang, code.trim_end_matches('\n'), fence)
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+enum Block<'a> {
+    Text(&'a str),
+    Code { lang: &'a str, code: String },
+}
+
+/// Splits message text into plain text and fenced code blocks (``` or ~~~). An unclosed fence
+/// runs to the end of the message.
+fn split_code_blocks(content: &str) -> Vec<Block<'_>> {
+    let mut blocks = Vec::new();
+    let mut text_start = 0;
+    let mut offset = 0;
+    let mut open: Option<(&str, &str, String)> = None;
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        match open.as_mut() {
+            Some((fence, _, code)) => {
+                if trimmed.trim_end() == *fence {
+                    let (_, lang, code) = open.take().unwrap();
+                    blocks.push(Block::Code { lang, code });
+                    text_start = offset + line.len();
+                } else {
+                    code.push_str(line);
+                }
+            }
+            None => {
+                let fence_len = trimmed
+                    .chars()
+                    .take_while(|c| *c == '`' || *c == '~')
+                    .count();
+                let fence = &trimmed[..fence_len];
+                if fence_len >= 3 && fence.chars().all(|c| c == fence.as_bytes()[0] as char) {
+                    blocks.push(Block::Text(&content[text_start..offset]));
+                    let lang = trimmed[fence_len..].split_whitespace().next().unwrap_or("");
+                    open = Some((fence, lang, String::new()));
+                }
+            }
+        }
+        offset += line.len();
+    }
+    match open {
+        Some((_, lang, code)) => blocks.push(Block::Code { lang, code }),
+        None => blocks.push(Block::Text(&content[text_start..])),
+    }
+    blocks
+}
+
+const HTML_STYLE: &str = "
+body { font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', sans-serif; max-width: 960px; margin: 2rem auto; padding: 0 1rem; color: #1f2328; line-height: 1.5; }
+h1 { font-size: 1.4rem; }
+h2 { font-size: 1rem; margin: 0 0 0.5rem; }
+time, .usage, .meta { color: #656d76; font-size: 0.85rem; font-weight: normal; }
+.msg { border: 1px solid #d0d7de; border-radius: 6px; padding: 0.75rem 1rem; margin: 1rem 0; }
+.msg.user { background: #f6f8fa; }
+.msg.tool { background: #fbfbf8; }
+.msg.system { border-style: dashed; }
+.text { white-space: pre-wrap; word-wrap: break-word; margin: 0.5rem 0; }
+pre { padding: 0.75rem; border-radius: 6px; overflow-x: auto; font-size: 0.85rem; }
+pre.output { background: #f6f8fa; white-space: pre-wrap; }
+.call { margin: 0.75rem 0 0.25rem; }
+code { font-family: ui-monospace, SFMono-Regular, Menlo, monospace; }
+";
@@ -0,0 +1,506 @@
+//! Converts conversation history from other tools into a `Session`:
+//!
+//! - `openai`: a chat-completions message array, or a request body with a `messages` field.
+//! - `opencode-ts`: a session of the TypeScript opencode, either the output of its
+//!   `opencode export <id>` or a session info file in its storage directory
+//!   (`storage/session/<project>/<id>.json`, with messages in `storage/message/<id>/` and
+//!   their parts in `storage/part/<message id>/`).
+//!
+//! Tool calls become `MessageMeta::tool_calls` on the assistant message, each answered by a
+//! `Role::Tool` message with the matching `tool_call_id`.
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use opencode_core::session::{Message, MessageMeta, Role, Session, ToolCall};
+use opencode_core::usage::TokenUsage;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    OpenAi,
+    OpencodeTs,
+}
+
+impl FromStr for ImportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "openai" => Ok(ImportFormat::OpenAi),
+            "opencode-ts" => Ok(ImportFormat::OpencodeTs),
+            other => Err(format!(
+                "unknown import format '{}' (expected openai or opencode-ts)",
+                other
+            )),
+        }
+    }
+}
+
+/// Reads `path` and converts it into a new session (with a new id).
+pub fn import_session(path: &Path, format: ImportFormat) -> Result<Session> {
+    let value = read_json(path)?;
+    match format {
+        ImportFormat::OpenAi => from_openai(&value),
+        ImportFormat::OpencodeTs => {
+            if value.get("info").is_some() && value.get("messages").is_some() {
+                from_opencode_ts_export(&value)
+            } else {
+                from_opencode_ts_storage(path, &value)
+            }
+        }
+    }
+}
+
+fn read_json(path: &Path) -> Result<Value> {
+    let data = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    serde_json::from_str(&data).with_context(|| format!("parsing {}", path.display()))
+}
+
+fn message(
+    role: Role,
+    content: String,
+    created_at: DateTime<Utc>,
+    meta: Option<MessageMeta>,
+) -> Message {
+    Message {
+        role,
+        content,
+        created_at,
+        meta,
+    }
+}
+
+/// Tool result message answering `call_id`.
+fn tool_result(
+    name: Option<String>,
+    call_id: String,
+    content: String,
+    created_at: DateTime<Utc>,
+) -> Message {
+    message(
+        Role::Tool,
+        content,
+        created_at,
+        Some(MessageMeta {
+            tool_name: name,
+            tool_call_id: Some(call_id),
+            ..Default::default()
+        }),
+    )
+}
+
+// --- OpenAI chat completions ---
+
+#[derive(Deserialize)]
+struct OpenAiMessage {
+    role: String,
+    #[serde(default)]
+    content: Option<Value>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<OpenAiToolCall>,
+    /// Deprecated single-call form.
+    #[serde(default)]
+    function_call: Option<OpenAiFunction>,
+    #[serde(default)]
+    tool_call_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiToolCall {
+    id: String,
+    function: OpenAiFunction,
+}
+
+#[derive(Deserialize)]
+struct OpenAiFunction {
+    name: String,
+    /// JSON-encoded arguments.
+    #[serde(default)]
+    arguments: String,
+}
+
+/// Session from an OpenAI chat-completions message array (or an object with `messages`).
+/// The format has no timestamps, so every message gets the import time.
+pub fn from_openai(value: &Value) -> Result<Session> {
+    let messages = match value {
+        Value::Array(_) => value,
+        Value::Object(o) => o
+            .get("messages")
+            .ok_or_else(|| anyhow!("expected a message array or an object with \"messages\""))?,
+        _ => bail!("expected a message array or an object with \"messages\""),
+    };
+    let messages: Vec<OpenAiMessage> =
+        serde_json::from_value(messages.clone()).context("reading OpenAI messages")?;
+
+    let now = Utc::now();
+    let mut session = Session::new();
+    let mut call_names: HashMap<String, String> = HashMap::new();
+    for (i, m) in messages.into_iter().enumerate() {
+        let content = m.content.as_ref().map(openai_content).unwrap_or_default();
+        match m.role.as_str() {
+            "system" | "developer" => {
+                session.push_message(message(Role::System, content, now, None))
+            }
+            "user" => session.push_message(message(Role::User, content, now, None)),
+            "assistant" => {
+                let mut calls: Vec<ToolCall> = m
+                    .tool_calls
+                    .into_iter()
+                    .map(|c| ToolCall {
+                        arguments: parse_arguments(&c.function.arguments),
+                        name: c.function.name,
+                        id: c.id,
+                    })
+                    .collect();
+                if let Some(f) = m.function_call {
+                    calls.push(ToolCall {
+                        id: format!("call_{}", i),
+                        arguments: parse_arguments(&f.arguments),
+                        name: f.name,
+                    });
+                }
+                for call in &calls {
+                    call_names.insert(call.id.clone(), call.name.clone());
+                }
+                let meta = (!calls.is_empty()).then(|| MessageMeta {
+                    tool_calls: calls,
+                    ..Default::default()
+                });
+                session.push_message(message(Role::Assistant, content, now, meta));
+            }
+            "tool" => {
+                let call_id = m
+                    .tool_call_id
+                    .ok_or_else(|| anyhow!("message {}: tool message without tool_call_id", i))?;
+                let name = call_names.get(&call_id).cloned().or(m.name);
+                session.push_message(tool_result(name, call_id, content, now));
+            }
+            "function" => {
+                // Deprecated function result: answers the latest call of that function.
+                let name = m.name.unwrap_or_default();
+                let call_id = session
+                    .messages
+                    .iter()
+                    .rev()
+                    .filter_map(|m| m.meta.as_ref())
+                    .flat_map(|meta| meta.tool_calls.iter().rev())
+                    .find(|c| c.name == name)
+                    .map(|c| c.id.clone())
+                    .unwrap_or_else(|| format!("call_{}", i));
+                session.push_message(tool_result(Some(name), call_id, content, now));
+            }
+            other => bail!("message {}: unsupported role '{}'", i, other),
+        }
+    }
+    Ok(session)
+}
+
+/// Text of a `content` field: a string, or the text parts of a content array.
+fn openai_content(content: &Value) -> String {
+    match content {
+        Value::String(s) => s.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|p| match p.get("type").and_then(Value::as_str) {
+                Some("text") | Some("input_text") | Some("output_text") => {
+                    p.get("text").and_then(Value::as_str).map(str::to_string)
+                }
+                Some("image_url") => Some("[image]".to_string()),
+                Some("input_audio") => Some("[audio]".to_string()),
+                Some("file") => Some("[file]".to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// Tool arguments are JSON text; anything unparsable is kept as a string.
+fn parse_arguments(arguments: &str) -> Value {
+    if arguments.trim().is_empty() {
+        return Value::Object(Default::default());
+    }
+    serde_json::from_str(arguments).unwrap_or_else(|_| Value::String(arguments.to_string()))
+}
+
+// --- TypeScript opencode ---
+
+#[derive(Deserialize)]
+struct TsSessionInfo {
+    id: String,
+    #[serde(default)]
+    time: TsTime,
+}
+
+#[derive(Deserialize, Default)]
+struct TsTime {
+    /// Milliseconds since the epoch.
+    #[serde(default)]
+    created: Option<i64>,
+    #[serde(default)]
+    updated: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct TsMessageInfo {
+    #[serde(default)]
+    id: String,
+    role: String,
+    #[serde(default)]
+    time: TsTime,
+    #[serde(default, rename = "modelID")]
+    model_id: Option<String>,
+    #[serde(default)]
+    tokens: Option<TsTokens>,
+}
+
+#[derive(Deserialize, Default)]
+struct TsTokens {
+    #[serde(default)]
+    input: u64,
+    #[serde(default)]
+    output: u64,
+    #[serde(default)]
+    reasoning: u64,
+    #[serde(default)]
+    cache: TsCache,
+}
+
+#[derive(Deserialize, Default)]
+struct TsCache {
+    #[serde(default)]
+    read: u64,
+    #[serde(default)]
+    write: u64,
+}
+
+#[derive(Deserialize)]
+struct TsPart {
+    #[serde(default)]
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: Option<String>,
+    #[serde(default)]
+    synthetic: bool,
+    #[serde(default)]
+    tool: Option<String>,
+    #[serde(default, rename = "callID")]
+    call_id: Option<String>,
+    #[serde(default)]
+    state: Option<TsToolState>,
+    #[serde(default)]
+    filename: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TsToolState {
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    input: Value,
+    #[serde(default)]
+    output: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Session from the TypeScript `opencode export` output: `{ info, messages: [{ info, parts }] }`.
+pub fn from_opencode_ts_export(value: &Value) -> Result<Session> {
+    #[derive(Deserialize)]
+    struct Export {
+        info: TsSessionInfo,
+        messages: Vec<ExportMessage>,
+    }
+    #[derive(Deserialize)]
+    struct ExportMessage {
+        info: TsMessageInfo,
+        #[serde(default)]
+        parts: Vec<TsPart>,
+    }
+    let export: Export =
+        serde_json::from_value(value.clone()).context("reading opencode export")?;
+    let messages = export
+        .messages
+        .into_iter()
+        .map(|m| (m.info, m.parts))
+        .collect();
+    Ok(ts_session(&export.info, messages))
+}
+
+/// Session from a storage session info file; messages and parts are read from the sibling
+/// `message/` and `part/` directories of the storage root.
+fn from_opencode_ts_storage(path: &Path, value: &Value) -> Result<Session> {
+    let info: TsSessionInfo =
+        serde_json::from_value(value.clone()).context("reading opencode session info")?;
+    // <storage>/session/<project>/<id>.json
+    let storage = path
+        .ancestors()
+        .nth(3)
+        .filter(|root| root.join("message").is_dir())
+        .ok_or_else(|| {
+            anyhow!(
+                "{} is not in an opencode storage directory (expected storage/session/<project>/<id>.json)",
+                path.display()
+            )
+        })?;
+    let mut messages: Vec<(TsMessageInfo, Vec<TsPart>)> = Vec::new();
+    for message_path in json_files(&storage.join("message").join(&info.id))? {
+        let message: TsMessageInfo = serde_json::from_value(read_json(&message_path)?)
+            .with_context(|| format!("reading {}", message_path.display()))?;
+        let mut parts = Vec::new();
+        for part_path in json_files(&storage.join("part").join(&message.id))? {
+            parts.push(
+                serde_json::from_value::<TsPart>(read_json(&part_path)?)
+                    .with_context(|| format!("reading {}", part_path.display()))?,
+            );
+        }
+        parts.sort_by(|a, b| a.id.cmp(&b.id));
+        messages.push((message, parts));
+    }
+    messages.sort_by(|a, b| (a.0.time.created, &a.0.id).cmp(&(b.0.time.created, &b.0.id)));
+    Ok(ts_session(&info, messages))
+}
+
+/// `*.json` files in `dir`; none when the directory does not exist.
+fn json_files(dir: &Path) -> Result<Vec<std::path::PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "json") {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+fn ts_time(ms: Option<i64>) -> Option<DateTime<Utc>> {
+    ms.and_then(|ms| Utc.timestamp_millis_opt(ms).single())
+}
+
+fn ts_session(
+    session_info: &TsSessionInfo,
+    messages: Vec<(TsMessageInfo, Vec<TsPart>)>,
+) -> Session {
+    let mut session = Session::new();
+    for (info, parts) in messages {
+        let created_at = ts_time(info.time.created).unwrap_or_else(Utc::now);
+        match info.role.as_str() {
+            "assistant" => push_ts_assistant(&mut session, &info, parts, created_at),
+            _ => {
+                let content = parts
+                    .iter()
+                    .filter(|p| !p.synthetic)
+                    .filter_map(|p| match p.kind.as_str() {
+                        "text" => p.text.clone(),
+                        "file" => Some(format!("[file: {}]", p.filename.as_deref().unwrap_or("?"))),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                session.push_message(message(Role::User, content, created_at, None));
+            }
+        }
+    }
+    if let Some(created) = ts_time(session_info.time.created) {
+        session.created_at = created;
+    }
+    session.updated_at = ts_time(session_info.time.updated).unwrap_or(session.updated_at);
+    tracing::info!(source_id = %session_info.id, messages = session.messages.len(), "imported opencode session");
+    session
+}
+
+/// One TypeScript assistant message interleaves text and tool parts over several steps. Each
+/// run of text followed by tool calls becomes an assistant message with its tool results; the
+/// token usage goes on the last one.
+fn push_ts_assistant(
+    session: &mut Session,
+    info: &TsMessageInfo,
+    parts: Vec<TsPart>,
+    created_at: DateTime<Utc>,
+) {
+    let mut text: Vec<String> = Vec::new();
+    let mut calls: Vec<ToolCall> = Vec::new();
+    let mut results: Vec<Message> = Vec::new();
+    let mut emitted: Vec<Message> = Vec::new();
+    let flush = |text: &mut Vec<String>,
+                 calls: &mut Vec<ToolCall>,
+                 results: &mut Vec<Message>,
+                 out: &mut Vec<Message>| {
+        if text.is_empty() && calls.is_empty() {
+            return;
+        }
+        let meta = (!calls.is_empty()).then(|| MessageMeta {
+            tool_calls: std::mem::take(calls),
+            ..Default::default()
+        });
+        out.push(message(Role::Assistant, text.join("\n"), created_at, meta));
+        out.append(results);
+        text.clear();
+    };
+    for part in parts {
+        match part.kind.as_str() {
+            "text" if !part.synthetic => {
+                if !calls.is_empty() {
+                    flush(&mut text, &mut calls, &mut results, &mut emitted);
+                }
+                text.extend(part.text);
+            }
+            "tool" => {
+                let id = part.call_id.unwrap_or_else(|| part.id.clone());
+                let name = part.tool.unwrap_or_default();
+                let state = part.state;
+                calls.push(ToolCall {
+                    id: id.clone(),
+                    name: name.clone(),
+                    arguments: state
+                        .as_ref()
+                        .map(|s| s.input.clone())
+                        .unwrap_or(Value::Null),
+                });
+                let content = match state {
+                    Some(s) if s.status == "error" => {
+                        format!("Error: {}", s.error.unwrap_or_default())
+                    }
+                    Some(s) => s
+                        .output
+                        .unwrap_or_else(|| format!("[tool call {}]", s.status)),
+                    None => "[no result]".to_string(),
+                };
+                results.push(tool_result(Some(name), id, content, created_at));
+            }
+            _ => {}
+        }
+    }
+    flush(&mut text, &mut calls, &mut results, &mut emitted);
+    if emitted.is_empty() {
+        emitted.push(message(Role::Assistant, String::new(), created_at, None));
+    }
+    if let Some(tokens) = &info.tokens {
+        let usage = TokenUsage {
+            prompt_tokens: tokens.input + tokens.cache.read + tokens.cache.write,
+            completion_tokens: tokens.output + tokens.reasoning,
+            cached_tokens: tokens.cache.read,
+            reasoning_tokens: tokens.reasoning,
+            model: info.model_id.clone(),
+        };
+        if let Some(last) = emitted.iter_mut().rev().find(|m| m.role == Role::Assistant) {
+            last.meta.get_or_insert_with(Default::default).usage = Some(usage);
+        }
+    }
+    for m in emitted {
+        session.push_message(m);
+    }
+}
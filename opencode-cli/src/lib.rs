@@ -1,10 +1,23 @@
 //! OpenCode CLI library: session store, config, message processor, and commands for use by the CLI binary and Tauri app.
 
+pub mod checkpoint;
 pub mod commands;
 pub mod config;
+pub mod export;
+pub mod import;
+pub mod logging;
 pub mod message_processor;
+pub mod search;
 pub mod session_store;
 
+pub use checkpoint::{CheckpointError, CheckpointStore, Restore, Turn};
 pub use config::{AppConfig, ProviderInfo, ProviderListItem};
-pub use message_processor::{process_message_async, LogEntry, LogLevel, SessionUpdate};
-pub use session_store::{load_session, save_session};
+pub use message_processor::{
+    process_message_async, LogEntry, LogLevel, PendingPermission, PermissionResponder, SessionUpdate,
+};
+pub use session_store::{
+    JsonlSessionStore, SessionCheck, SessionStore, SessionStoreError, SessionSummary,
+};
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,68 @@
+//! Tracing output with secrets masked: wraps the writers of the `fmt` layers so prompts, responses
+//! and tool input logged by the agents never reach `logs/opencode.log` (or the console) verbatim.
+
+use opencode_core::config::{Config, RedactionConfig};
+use opencode_core::Redactor;
+use std::io::{self, Write};
+use std::sync::Arc;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Redactor for log output, from the global `redaction` config; built-in detectors only when the
+/// config cannot be read or is invalid.
+pub fn log_redactor() -> Arc<Redactor> {
+    let config = Config::load().map(|c| c.redaction).unwrap_or_default();
+    let redactor = Redactor::new(&config).unwrap_or_else(|e| {
+        eprintln!(
+            "Invalid redaction config, using the built-in detectors: {}",
+            e
+        );
+        Redactor::new(&RedactionConfig::default()).expect("default redaction config")
+    });
+    Arc::new(redactor)
+}
+
+/// `MakeWriter` whose writers mask secrets before passing log lines on to `inner`.
+pub struct RedactingMakeWriter<M> {
+    inner: M,
+    redactor: Arc<Redactor>,
+}
+
+impl<M> RedactingMakeWriter<M> {
+    pub fn new(inner: M, redactor: Arc<Redactor>) -> Self {
+        Self { inner, redactor }
+    }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for RedactingMakeWriter<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer(),
+            redactor: self.redactor.clone(),
+        }
+    }
+}
+
+/// Writer for one event. The `fmt` layer writes each formatted event in a single call, so
+/// secrets spanning lines (private keys) are masked whole.
+pub struct RedactingWriter<W> {
+    inner: W,
+    redactor: Arc<Redactor>,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let (masked, found) = self.redactor.redact(&String::from_utf8_lossy(buf));
+        if found.is_empty() {
+            self.inner.write_all(buf)?;
+        } else {
+            self.inner.write_all(masked.as_bytes())?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -1,5 +1,12 @@
 use anyhow::Result;
 use opencode_cli::commands;
+use opencode_cli::export::ExportFormat;
+use opencode_cli::import::ImportFormat;
+use opencode_cli::logging::{log_redactor, RedactingMakeWriter};
+use opencode_cli::search;
+use opencode_core::session::Role;
+use chrono::{DateTime, Utc};
+use std::path::PathBuf;
 use clap::{Parser, Subcommand};
 use tracing_subscriber::{
     fmt,
@@ -30,6 +37,9 @@ enum Commands {
     Run {
         /// The command to run
         command: String,
+        /// Run a full agent turn (with tools) in the current directory as this agent
+        #[arg(long)]
+        agent: Option<String>,
     },
     /// (Deprecated) HTTP server – planned removal; use app or run for CLI
     #[clap(hide = true)]
@@ -43,6 +53,38 @@ enum Commands {
         #[command(subcommand)]
         subcommand: SessionCommands,
     },
+    /// Show token usage and cost per model, day and session
+    Stats,
+    /// List the file checkpoints recorded for agent turns in this workspace
+    Checkpoints {
+        /// Only turns of this session
+        #[arg(long)]
+        session: Option<String>,
+    },
+    /// Restore workspace files to the state before the last agent turn
+    Undo {
+        /// Only consider turns of this session
+        #[arg(long)]
+        session: Option<String>,
+        /// Restore the state before this turn instead (later turns are reverted too)
+        #[arg(long)]
+        turn: Option<u64>,
+    },
+    /// Restore workspace files undone by the last undo
+    Redo {
+        /// Only consider undos of this session's turns
+        #[arg(long)]
+        session: Option<String>,
+        /// Restore the state right after this turn instead
+        #[arg(long)]
+        turn: Option<u64>,
+    },
+    /// List the models a provider offers
+    Models {
+        /// Provider id from the config (defaults to the default provider)
+        #[arg(long)]
+        provider: Option<String>,
+    },
     /// Manage configuration
     Config {
         #[command(subcommand)]
@@ -53,7 +95,14 @@ enum Commands {
 #[derive(Subcommand)]
 enum SessionCommands {
     /// List all sessions
-    List,
+    List {
+        /// Only sessions with this tag
+        #[arg(long)]
+        tag: Option<String>,
+        /// Only pinned sessions
+        #[arg(long)]
+        pinned: bool,
+    },
     /// Show a specific session
     Show {
         /// Session ID to show
@@ -64,6 +113,85 @@ enum SessionCommands {
         /// Session ID to delete
         session_id: String,
     },
+    /// Start a new session from the first messages of an existing one
+    Fork {
+        /// Session ID to fork
+        session_id: String,
+        /// Number of messages to copy (defaults to the whole history)
+        #[arg(long)]
+        at: Option<usize>,
+    },
+    /// Summarize older turns of a session and archive them
+    Compact {
+        /// Session ID to compact
+        session_id: String,
+        /// Number of latest turns to keep verbatim
+        #[arg(long, default_value_t = opencode_core::compaction::DEFAULT_KEEP_TURNS)]
+        keep: usize,
+    },
+    /// Export a session transcript
+    Export {
+        /// Session ID to export
+        session_id: String,
+        /// Output format: md, html or json
+        #[arg(long, default_value = "md")]
+        format: ExportFormat,
+        /// File to write (defaults to stdout)
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Import a conversation from another tool as a new session
+    Import {
+        /// File to import
+        file: PathBuf,
+        /// Source format: openai (chat-completions messages) or opencode-ts (TypeScript opencode
+        /// export or storage session file)
+        #[arg(long)]
+        format: ImportFormat,
+    },
+    /// Set a session title (an empty title clears it)
+    Rename {
+        session_id: String,
+        title: String,
+    },
+    /// Add tags to a session
+    Tag {
+        session_id: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+        /// Remove the tags instead
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Pin a session to the top of the list
+    Pin {
+        session_id: String,
+    },
+    /// Unpin a session
+    Unpin {
+        session_id: String,
+    },
+    /// Search messages across all sessions
+    Search {
+        /// Words to look for; every word must occur in a message (prefixes match)
+        query: String,
+        /// Only messages with this role: user, assistant, tool or system
+        #[arg(long, value_parser = search::parse_role)]
+        role: Option<Role>,
+        /// Only messages since this date (YYYY-MM-DD or RFC 3339)
+        #[arg(long, value_parser = search::parse_since)]
+        since: Option<DateTime<Utc>>,
+        /// Maximum number of results (0 for all)
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+    /// Convert sessions saved by earlier versions (session.json, older schema versions) to the
+    /// current format
+    Migrate {
+        /// Only validate every session and report what would be migrated; writes nothing
+        #[arg(long)]
+        check: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -76,15 +204,9 @@ enum ConfigCommands {
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Ensure logs directory exists (for both tracing and tklog)
+    // Ensure logs directory exists; tracing writes ./logs/opencode.log (secrets masked)
     let _ = std::fs::create_dir_all("logs");
 
-    // File logging to ./logs/opencode.log for debugging (e.g. /init flow)
-    tklog::LOG
-        .set_console(false)
-        .set_level(tklog::LEVEL::Info)
-        .set_cutmode_by_size("logs/opencode.log", 1 << 20, 5, false);
-
     let cli = Cli::parse();
 
     let filter = EnvFilter::try_from_default_env()
@@ -95,10 +217,11 @@ async fn main() -> Result<()> {
                 .append(true)
                 .open("logs/opencode.log")?;
             let (file_writer, guard) = tracing_appender::non_blocking(file);
+            let redactor = log_redactor();
             tracing_subscriber::registry()
                 .with(filter)
-                .with(fmt::Layer::new().with_writer(std::io::stdout))
-                .with(fmt::Layer::new().with_writer(file_writer))
+                .with(fmt::Layer::new().with_writer(RedactingMakeWriter::new(std::io::stdout, redactor.clone())))
+                .with(fmt::Layer::new().with_writer(RedactingMakeWriter::new(file_writer, redactor)))
                 .init();
         guard
     };
@@ -106,13 +229,36 @@ async fn main() -> Result<()> {
     match cli.command {
         Commands::App => commands::app::run_app(),
         Commands::Init { refresh } => commands::init::run_init(refresh).await,
-        Commands::Run { command } => commands::run::run_command(&command).await,
+        Commands::Run { command, agent: None } => commands::run::run_command(&command).await,
+        Commands::Run { command, agent: Some(agent) } => commands::run::run_agent(&command, &agent).await,
         Commands::Serve { port } => commands::serve::serve(port).await,
         Commands::Sessions { subcommand } => match subcommand {
-            SessionCommands::List => commands::sessions::list_sessions().await,
+            SessionCommands::List { tag, pinned } => {
+                commands::sessions::list_sessions(commands::sessions::SessionFilter { tag, pinned_only: pinned }).await
+            }
             SessionCommands::Show { session_id } => commands::sessions::show_session(&session_id).await,
             SessionCommands::Delete { session_id } => commands::sessions::delete_session(&session_id).await,
+            SessionCommands::Fork { session_id, at } => commands::sessions::fork_session(&session_id, at).await,
+            SessionCommands::Compact { session_id, keep } => commands::sessions::compact_session(&session_id, keep).await,
+            SessionCommands::Export { session_id, format, output } => {
+                commands::sessions::export_session(&session_id, format, output.as_deref()).await
+            }
+            SessionCommands::Import { file, format } => commands::sessions::import_session(&file, format).await,
+            SessionCommands::Rename { session_id, title } => commands::sessions::rename_session(&session_id, &title).await,
+            SessionCommands::Tag { session_id, tags, remove } => commands::sessions::tag_session(&session_id, &tags, remove).await,
+            SessionCommands::Pin { session_id } => commands::sessions::pin_session(&session_id, true).await,
+            SessionCommands::Unpin { session_id } => commands::sessions::pin_session(&session_id, false).await,
+            SessionCommands::Search { query, role, since, limit } => {
+                commands::sessions::search_sessions(search::SearchQuery { text: query, role, since, limit }).await
+            }
+            SessionCommands::Migrate { check: false } => commands::sessions::migrate_sessions().await,
+            SessionCommands::Migrate { check: true } => commands::sessions::check_sessions().await,
         },
+        Commands::Stats => commands::stats::show_stats().await,
+        Commands::Checkpoints { session } => commands::checkpoints::list_checkpoints(session.as_deref()).await,
+        Commands::Undo { session, turn } => commands::checkpoints::undo(session.as_deref(), turn).await,
+        Commands::Redo { session, turn } => commands::checkpoints::redo(session.as_deref(), turn).await,
+        Commands::Models { provider } => commands::models::list_models(provider.as_deref()).await,
         Commands::Config { subcommand } => match subcommand {
             ConfigCommands::Show => commands::config::show_config().await,
             ConfigCommands::Reset => commands::config::reset_config().await,
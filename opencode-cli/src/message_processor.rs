@@ -1,19 +1,28 @@
 //! Async message processing: loads session, runs agent (deep or streaming), sends reply chunks and log entries via channel.
 //! Used by the Tauri app backend; channel type is UI-agnostic.
 
+use crate::checkpoint::{self, CheckpointError, CheckpointStore};
 use crate::config::AppConfig;
-use crate::session_store;
+use crate::session_store::{self, SessionStore};
 use anyhow::Result;
 use chrono::Utc;
-use opencode_core::agent::Context;
-use opencode_core::ids::SessionId;
-use opencode_core::session::{Message as SessionMessage, Role, Session};
-use opencode_core::AgentManager;
+use opencode_core::agent::{
+    Context, Message as ProviderMessage, MessageRole, Provider, ProviderRequest,
+};
+use opencode_core::compaction;
+use opencode_core::context::ContextManager;
+use opencode_core::permission::{PermissionPrompt, PermissionReply, PermissionRequest};
+use opencode_core::redact::{Redaction, RedactingTool};
+use opencode_core::session::{Message as SessionMessage, Role};
+use opencode_core::{AgentManager, SessionStateHandle, SessionStateMachine, SessionStatus};
 use opencode_core::tool::ToolContext;
-use std::path::PathBuf;
-use std::str::FromStr;
-use std::sync::Arc;
-use tokio::sync::mpsc;
+use opencode_provider::ProviderFactory;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot};
+
+/// Tools that change the workspace; when the rules deny all of them the agent is read-only.
+const MUTATING_TOOLS: &[&str] = &["write", "edit", "multiedit", "patch", "bash"];
 
 /// Session UI update: either a reply chunk/done or a log entry for the log panel.
 #[derive(Clone)]
@@ -22,6 +31,30 @@ pub enum SessionUpdate {
     Reply(Option<String>),
     /// Append to the session's log panel.
     Log(LogEntry),
+    /// The session's turn state changed (also persisted with the session).
+    State(SessionStatus),
+    /// A tool call needs the user's approval; the turn waits until `reply` is answered.
+    PermissionRequest(PendingPermission),
+}
+
+/// A permission request of a running turn and the channel its answer goes back through.
+#[derive(Clone)]
+pub struct PendingPermission {
+    pub request: PermissionRequest,
+    pub reply: PermissionResponder,
+}
+
+/// Answers a `PendingPermission`. Only the first answer counts; dropping every clone unanswered
+/// denies the call.
+#[derive(Clone)]
+pub struct PermissionResponder(Arc<Mutex<Option<oneshot::Sender<PermissionReply>>>>);
+
+impl PermissionResponder {
+    /// Sends `reply` to the waiting turn; false when it was already answered or has ended.
+    pub fn respond(&self, reply: PermissionReply) -> bool {
+        let sender = self.0.lock().ok().and_then(|mut s| s.take());
+        sender.is_some_and(|s| s.send(reply).is_ok())
+    }
 }
 
 /// One line in the session log panel (agent lifecycle, tool calls, etc.).
@@ -41,6 +74,8 @@ pub enum LogLevel {
 /// Process one user message: load or create session, run agent (deep or streaming), send reply chunks and logs via `tx`.
 /// Caller (e.g. Tauri backend) should forward `(session_id, SessionUpdate)` to the frontend (e.g. via Tauri events).
 /// If `command_id` is set, user input is formatted via opencode_core::format_input_for_command before being sent to the agent.
+/// The default provider from `config` is built through `provider_factory`.
+#[allow(clippy::too_many_arguments)]
 pub async fn process_message_async(
     session_id: &str,
     input: &str,
@@ -49,6 +84,7 @@ pub async fn process_message_async(
     workspace_path: Option<PathBuf>,
     tx: mpsc::UnboundedSender<(String, SessionUpdate)>,
     command_id: Option<String>,
+    provider_factory: &ProviderFactory,
 ) -> Result<()> {
     let effective_input: String = match &command_id {
         Some(id) => opencode_core::format_input_for_command(
@@ -82,171 +118,160 @@ pub async fn process_message_async(
     );
     tracing::info!(session_id = %session_id, input_len = effective_input.len(), "process_message_async started");
 
-    let session_dir = config.session_dir();
-    let session_file = session_dir.join(session_id).join("session.json");
-    let mut session = if session_file.exists() {
-        session_store::load_session(&session_file).unwrap_or_else(|_| Session::new())
+    let store = config.session_store();
+    let opened = if store.exists(session_id) {
+        store.load(session_id).map_err(|e| ("load", e))
     } else {
-        let s = Session::with_id(
-            SessionId::from_str(session_id).unwrap_or_else(|_| SessionId::new()),
-        );
-        let path = session_dir.join(session_id).join("session.json");
-        let _ = session_store::save_session(&path, &s);
-        s
+        session_store::new_session(&store, session_id)
+            .inspect(|s| {
+                if let Err(e) = store.save(s) {
+                    tracing::warn!("Failed to save session: {}", e);
+                }
+            })
+            .map_err(|e| ("create", e))
+    };
+    let mut session = match opened {
+        Ok(session) => session,
+        Err((action, e)) => {
+            // Never start over on top of a session that could not be read, nor under another id.
+            tracing::error!(session_id = %session_id, error = %e, "Failed to {} session", action);
+            send_log(LogLevel::Error, format!("Failed to {} session: {}", action, e));
+            let _ = tx.send((
+                session_id.to_string(),
+                SessionUpdate::Reply(Some(format!("Error: {}", e))),
+            ));
+            let _ = tx.send((session_id.to_string(), SessionUpdate::Reply(None)));
+            return Err(anyhow::anyhow!("Failed to {} session: {}", action, e));
+        }
     };
     let session_id_owned = session_id.to_string();
 
-    let provider_adapter = {
-        let provider_info = config.get_default_provider();
-        let provider_type = provider_info
-            .as_ref()
-            .map(|p| p.provider_type.clone())
-            .unwrap_or_else(|| "openai".to_string());
-        tracing::info!(provider_type = %provider_type, "provider selected");
-        let base_url = provider_info.as_ref().and_then(|p| p.base_url.clone());
-        let model = provider_info.as_ref().and_then(|p| p.model.clone());
-
-        let api_key = provider_info
-            .as_ref()
-            .and_then(|p| p.api_key.clone())
-            .or_else(|| std::env::var("OPENAI_API_KEY").ok())
-            .or_else(|| std::env::var("OPENCODE_OPENAI_API_KEY").ok())
-            .unwrap_or_else(|| "".to_string());
-
-        let provider: Arc<dyn opencode_provider::Provider> = match provider_type.as_str() {
-            "openai" => {
-                if api_key.trim().is_empty() {
-                    tracing::error!("No API key configured for OpenAI");
-                    let _ = tx.send((
-                        session_id_owned.clone(),
-                        SessionUpdate::Reply(Some(
-                            "Error: No API key configured. Configure provider and API key.".to_string(),
-                        )),
-                    ));
-                    let _ = tx.send((session_id_owned.clone(), SessionUpdate::Reply(None)));
-                    return Err(anyhow::anyhow!("No API key configured"));
-                }
-                match opencode_provider::LangChainAdapter::from_openai(api_key, base_url, model) {
-                    Ok(adapter) => Arc::new(adapter),
-                    Err(e) => {
-                        tracing::error!(error = %e, "Failed to initialize OpenAI provider");
-                        let _ = tx.send((
-                            session_id_owned.clone(),
-                            SessionUpdate::Reply(Some(format!(
-                                "Error initializing OpenAI provider: {}",
-                                e
-                            ))),
-                        ));
-                        let _ = tx.send((session_id_owned.clone(), SessionUpdate::Reply(None)));
-                        return Err(anyhow::anyhow!("Failed to initialize provider: {}", e));
-                    }
-                }
-            }
-            "ollama" => {
-                match opencode_provider::LangChainAdapter::from_ollama(base_url, model) {
-                    Ok(adapter) => Arc::new(adapter),
-                    Err(e) => {
-                        tracing::error!(error = %e, "Failed to initialize Ollama provider");
-                        let _ = tx.send((
-                            session_id_owned.clone(),
-                            SessionUpdate::Reply(Some(format!(
-                                "Error initializing Ollama provider: {}",
-                                e
-                            ))),
-                        ));
-                        let _ = tx.send((session_id_owned.clone(), SessionUpdate::Reply(None)));
-                        return Err(anyhow::anyhow!("Failed to initialize provider: {}", e));
-                    }
-                }
-            }
-            "qwen" => {
-                if api_key.trim().is_empty() {
-                    tracing::error!("No API key configured for Qwen");
-                    let _ = tx.send((
-                        session_id_owned.clone(),
-                        SessionUpdate::Reply(Some(
-                            "Error: No API key configured for Qwen.".to_string(),
-                        )),
-                    ));
-                    let _ = tx.send((session_id_owned.clone(), SessionUpdate::Reply(None)));
-                    return Err(anyhow::anyhow!("No API key configured"));
-                }
-                match opencode_provider::LangChainAdapter::from_qwen(api_key, base_url, model) {
-                    Ok(adapter) => Arc::new(adapter),
-                    Err(e) => {
-                        tracing::error!(error = %e, "Failed to initialize Qwen provider");
-                        let _ = tx.send((
-                            session_id_owned.clone(),
-                            SessionUpdate::Reply(Some(format!(
-                                "Error initializing Qwen provider: {}",
-                                e
-                            ))),
-                        ));
-                        let _ = tx.send((session_id_owned.clone(), SessionUpdate::Reply(None)));
-                        return Err(anyhow::anyhow!("Failed to initialize provider: {}", e));
-                    }
-                }
+    if let Some(id @ (checkpoint::UNDO_COMMAND_ID | checkpoint::REDO_COMMAND_ID)) = command_id.as_deref() {
+        return restore_checkpoint(session_id, id, input, workspace_path.as_deref(), &tx).await;
+    }
+
+    let model = config.default_provider_config().model;
+    let capabilities = config
+        .core_config()
+        .model_registry()
+        .capabilities(model.as_deref());
+    let context_window = ContextManager::new(model.as_deref(), &capabilities);
+    // Secrets are masked in tool results and provider requests; each redaction is logged.
+    let redactor = {
+        let tx_redact = tx.clone();
+        let session_id_redact = session_id_owned.clone();
+        let on_redact = Arc::new(move |redaction: &Redaction| {
+            let _ = tx_redact.send((
+                session_id_redact.clone(),
+                SessionUpdate::Log(LogEntry {
+                    level: LogLevel::Warn,
+                    message: redaction.to_string(),
+                }),
+            ));
+        });
+        match config.redactor() {
+            Ok(redactor) => Arc::new(redactor.with_reporter(on_redact)),
+            Err(e) => {
+                tracing::error!(error = %e, "Invalid redaction config");
+                let _ = tx.send((
+                    session_id_owned.clone(),
+                    SessionUpdate::Reply(Some(format!("Error: {}", e))),
+                ));
+                let _ = tx.send((session_id_owned.clone(), SessionUpdate::Reply(None)));
+                return Err(anyhow::anyhow!("Invalid redaction config: {}", e));
             }
-            "anthropic" => {
-                if api_key.trim().is_empty() {
-                    tracing::error!("No API key configured for Anthropic");
-                    let _ = tx.send((
-                        session_id_owned.clone(),
-                        SessionUpdate::Reply(Some(
-                            "Error: No API key configured.".to_string(),
-                        )),
-                    ));
-                    let _ = tx.send((session_id_owned.clone(), SessionUpdate::Reply(None)));
-                    return Err(anyhow::anyhow!("No API key configured"));
-                }
-                match opencode_provider::LangChainAdapter::from_anthropic(api_key) {
-                    Ok(adapter) => Arc::new(adapter),
-                    Err(e) => {
-                        tracing::error!(error = %e, "Failed to initialize Anthropic provider");
-                        let _ = tx.send((
-                            session_id_owned.clone(),
-                            SessionUpdate::Reply(Some(format!(
-                                "Error initializing Anthropic provider: {}",
-                                e
-                            ))),
-                        ));
-                        let _ = tx.send((session_id_owned.clone(), SessionUpdate::Reply(None)));
-                        return Err(anyhow::anyhow!("Failed to initialize provider: {}", e));
-                    }
-                }
+        }
+    };
+    // The message is stored as typed; the provider adapter masks it in the request.
+    redactor.report("your message", effective_input);
+
+    let provider_adapter = {
+        let chain = config.provider_chain();
+        let chain_ids: Vec<&str> = chain.iter().map(|p| p.id.as_str()).collect();
+        tracing::info!(provider_type = %chain[0].provider_type, chain = ?chain_ids, "provider selected");
+        match provider_factory.build_chain(&chain, config.retry_policy()) {
+            Ok(provider) => {
+                opencode_provider::ProviderAdapter::new(provider).with_redactor(redactor.clone())
             }
-            _ => {
-                tracing::error!(provider_type = %provider_type, "Unsupported provider type");
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to initialize provider");
                 let _ = tx.send((
                     session_id_owned.clone(),
-                    SessionUpdate::Reply(Some(format!(
-                        "Unsupported provider type: {}",
-                        provider_type
-                    ))),
+                    SessionUpdate::Reply(Some(format!("Error: {}", e))),
                 ));
                 let _ = tx.send((session_id_owned.clone(), SessionUpdate::Reply(None)));
-                return Err(anyhow::anyhow!(
-                    "Unsupported provider type: {}",
-                    provider_type
-                ));
+                return Err(anyhow::anyhow!("Failed to initialize provider: {}", e));
             }
-        };
+        }
+    };
+
+    if command_id.as_deref() == Some(compaction::COMMAND_ID) {
+        return compact(session_id, input, &mut session, &store, &provider_adapter, model, &context_window, &tx).await;
+    }
 
-        opencode_provider::ProviderAdapter::new(provider)
+    // Snapshot the workspace so the files this turn changes can be restored by /undo.
+    let checkpoints = workspace_path
+        .as_ref()
+        .filter(|_| agent_name != "plan")
+        .map(CheckpointStore::new);
+    let before = match checkpoints.clone() {
+        Some(c) => match checkpoint_task(move || c.snapshot()).await {
+            Ok(tree) => Some(tree),
+            Err(e @ CheckpointError::NotProject(_)) => {
+                tracing::info!("{}", e);
+                None
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "workspace snapshot failed; turn will not be undoable");
+                None
+            }
+        },
+        None => None,
     };
 
-    {
+    let session_state = session_state_handle(session_id, &config, &tx);
+    if let Err(e) = session_state.begin_turn() {
+        tracing::warn!(error = %e, "session state at turn start");
+    }
+
+    let turn: Result<()> = async {
         use opencode_tools::registry::ToolRegistry;
         use opencode_tools::tools;
         let mut tool_registry = ToolRegistry::new();
         tools::register_all_tools(&mut tool_registry);
+        tool_registry.register(tools::bash::BashTool::with_config(config.core_config().bash.clone()));
 
         let tools: Vec<Arc<dyn opencode_core::tool::Tool>> = tool_registry
             .list()
             .iter()
             .filter_map(|id| tool_registry.get(id))
-            .cloned()
+            .map(|tool| RedactingTool::wrap(tool.clone(), redactor.clone()))
             .collect();
+        // Tools the permission rules deny outright are not offered (the plan agent's built-in rules
+        // deny everything but reading); every call is still checked against the rules.
+        let prompt = Arc::new(UpdatePrompt {
+            session_id: session_id_owned.clone(),
+            tx: tx.clone(),
+            session_state: session_state.clone(),
+            workspace_path: workspace_path.clone(),
+        });
+        let permissions = match config.core_config().permissions(agent_name) {
+            Ok(p) => Arc::new(p.with_prompt(prompt)),
+            Err(e) => {
+                tracing::error!(error = %e, "Invalid permission rules");
+                let _ = tx.send((
+                    session_id_owned.clone(),
+                    SessionUpdate::Reply(Some(format!("Error: invalid permission rules: {}", e))),
+                ));
+                let _ = tx.send((session_id_owned.clone(), SessionUpdate::Reply(None)));
+                return Err(anyhow::anyhow!("Invalid permission rules: {}", e));
+            }
+        };
+        let tools: Vec<Arc<dyn opencode_core::tool::Tool>> = tools
+            .into_iter()
+            .filter(|t| !permissions.denies_tool(t.id()))
+            .collect();
+        let read_only = MUTATING_TOOLS.iter().all(|id| permissions.denies_tool(id));
 
         let mut agent_manager = AgentManager::new();
         if let Err(e) = agent_manager.switch(agent_name) {
@@ -264,10 +289,25 @@ pub async fn process_message_async(
             message_id: uuid::Uuid::new_v4().to_string(),
             agent: agent_name.to_string(),
             workspace_path: workspace_path.as_ref().map(|p| p.to_string_lossy().into_owned()),
+            max_iterations: config
+                .core_config()
+                .max_agent_iterations
+                .and_then(|n| usize::try_from(n).ok()),
+            context_window: context_window.clone(),
+            capabilities,
+            session_state: Some(session_state.clone()),
+            permissions: Some(permissions.clone()),
+            allowed_paths: config
+                .core_config()
+                .allowed_paths
+                .iter()
+                .map(|p| p.to_string_lossy().into_owned())
+                .collect(),
         };
 
         let use_deep_agent = (agent_name == "build" || agent_name == "plan")
             && !tools.is_empty()
+            && ctx.capabilities.tools
             && provider_adapter.inner().as_llm().is_some();
 
         if use_deep_agent {
@@ -279,23 +319,14 @@ pub async fn process_message_async(
 
         if use_deep_agent {
             let llm = provider_adapter.inner().as_llm().unwrap();
-            let tools_for_agent: Vec<Arc<dyn opencode_core::tool::Tool>> = if agent_name == "plan" {
-                const READ_ONLY_IDS: &[&str] =
-                    &["read", "ls", "list_files", "grep", "codesearch", "glob"];
-                tools
-                    .iter()
-                    .filter(|t| READ_ONLY_IDS.contains(&t.id()))
-                    .cloned()
-                    .collect()
-            } else {
-                tools.clone()
-            };
             let tool_ctx = ToolContext {
                 session_id: ctx.session_id.clone(),
                 message_id: ctx.message_id.clone(),
                 agent: ctx.agent.clone(),
                 call_id: None,
                 workspace_path: ctx.workspace_path.clone(),
+                permissions: ctx.permissions.clone(),
+                allowed_paths: ctx.allowed_paths.clone(),
             };
             let tx_log = tx.clone();
             let session_id_log = session_id_owned.clone();
@@ -327,19 +358,23 @@ pub async fn process_message_async(
             );
             let turn_config = opencode_provider::DeepAgentTurnConfig {
                 workspace_path: workspace_path.clone(),
-                read_only: agent_name == "plan",
-                use_crate_filesystem: agent_name != "plan",
+                read_only,
+                // The crate's filesystem tools would bypass the permission checks; the opencode
+                // tools cover the same operations.
+                use_crate_filesystem: false,
                 on_tool_call: Some(on_tool_call),
-                max_history_messages: Some(24),
-                max_message_content_len: Some(4000),
+                context_window: Some(context_window),
                 max_iterations: config.core_config().max_agent_iterations,
+                session_state: Some(session_state.clone()),
+                redactor: Some(redactor.clone()),
+                model: model.clone(),
             };
             send_log(LogLevel::Info, "deep_agent invoke started".to_string());
             match opencode_provider::run_deep_agent_turn(
                 &llm,
                 &session.messages,
                 effective_input,
-                &tools_for_agent,
+                &tools,
                 &tool_ctx,
                 turn_config,
             )
@@ -347,21 +382,10 @@ pub async fn process_message_async(
             {
                 Ok(reply) => {
                     send_log(LogLevel::Info, "deep_agent invoke done".to_string());
-                    session.push_message(SessionMessage {
-                        role: Role::User,
-                        content: effective_input.to_string(),
-                        created_at: Utc::now(),
-                        meta: None,
-                    });
-                    session.push_message(SessionMessage {
-                        role: Role::Assistant,
-                        content: reply.clone(),
-                        created_at: Utc::now(),
-                        meta: None,
-                    });
+                    push_deep_agent_turn(&mut session, effective_input, &reply);
                     const MAX_CHUNK_LEN: usize = 200;
                     let mut chunks: Vec<String> = Vec::new();
-                    for part in reply.split_inclusive('\n') {
+                    for part in reply.content.split_inclusive('\n') {
                         if part.len() <= MAX_CHUNK_LEN {
                             chunks.push(part.to_string());
                         } else {
@@ -377,8 +401,7 @@ pub async fn process_message_async(
                         tokio::time::sleep(std::time::Duration::from_millis(20)).await;
                     }
                     let _ = tx.send((session_id_owned.clone(), SessionUpdate::Reply(None)));
-                    let save_path = session_dir.join(&session_id_owned).join("session.json");
-                    if let Err(e) = session_store::save_session(&save_path, &session) {
+                    if let Err(e) = store.save(&session) {
                         tracing::warn!("Failed to save session: {}", e);
                     }
                 }
@@ -405,8 +428,7 @@ pub async fn process_message_async(
                         SessionUpdate::Reply(Some(format!("Error: {}", e))),
                     ));
                     let _ = tx.send((session_id_owned.clone(), SessionUpdate::Reply(None)));
-                    let save_path = session_dir.join(&session_id_owned).join("session.json");
-                    if let Err(save_err) = session_store::save_session(&save_path, &session) {
+                    if let Err(save_err) = store.save(&session) {
                         tracing::warn!("Failed to save session: {}", save_err);
                     }
                     return Err(anyhow::anyhow!("Deep agent failed: {}", e));
@@ -421,6 +443,7 @@ pub async fn process_message_async(
                     let _ = tx_forward.send((id, SessionUpdate::Reply(opt)));
                 }
             });
+            let message_count = session.messages.len();
             let stream_ok = agent_manager
                 .process_stream(
                     &ctx,
@@ -432,23 +455,16 @@ pub async fn process_message_async(
                 )
                 .await;
 
-            if let Ok(()) = stream_ok {
-                let save_path = session_dir.join(&session_id_owned).join("session.json");
-                if let Err(e) = session_store::save_session(&save_path, &session) {
-                    tracing::warn!("Failed to save session: {}", e);
-                }
-            } else {
-                tracing::debug!("stream not supported, using process()");
-                match agent_manager
-                    .process(&ctx, effective_input, &mut session, &provider_adapter, &tools)
-                    .await
-                {
-                    Ok(_) => {
-                        let save_path =
-                            session_dir.join(&session_id_owned).join("session.json");
-                        if let Err(e) = session_store::save_session(&save_path, &session) {
-                            tracing::warn!("Failed to save session: {}", e);
-                        }
+            let outcome = match stream_ok {
+                // Streaming failed before the turn changed the session (e.g. it is unsupported):
+                // run the turn with process(). Later errors already ran tools, so the turn is not
+                // replayed; the partial turn is kept instead.
+                Err(_) if session.messages.len() == message_count => {
+                    tracing::debug!("stream not supported, using process()");
+                    let outcome = agent_manager
+                        .process(&ctx, effective_input, &mut session, &provider_adapter, &tools)
+                        .await;
+                    if outcome.is_ok() {
                         if let Some(last_msg) = session.messages.last() {
                             if matches!(last_msg.role, Role::Assistant) {
                                 let _ = tx.send((
@@ -459,19 +475,375 @@ pub async fn process_message_async(
                             }
                         }
                     }
-                    Err(e) => {
-                        tracing::error!(error = %e, "Agent processing failed");
-                        let _ = tx.send((
-                            session_id_owned.clone(),
-                            SessionUpdate::Reply(Some(format!("Error: {}", e))),
-                        ));
-                        let _ = tx.send((session_id_owned.clone(), SessionUpdate::Reply(None)));
-                        return Err(anyhow::anyhow!("Agent processing failed: {}", e));
+                    outcome
+                }
+                other => other,
+            };
+            match outcome {
+                Ok(()) => {
+                    if let Err(e) = store.save(&session) {
+                        tracing::warn!("Failed to save session: {}", e);
+                    }
+                }
+                Err(e) => {
+                    tracing::error!(error = %e, "Agent processing failed");
+                    if session.messages.len() > message_count {
+                        // Keep the partial turn (e.g. the repeated calls of a doom loop) so the
+                        // user can see what ran and what failed.
+                        session.push_message(SessionMessage {
+                            role: Role::Assistant,
+                            content: format!("Error: {}", e),
+                            created_at: Utc::now(),
+                            meta: None,
+                        });
+                        if let Err(save_err) = store.save(&session) {
+                            tracing::warn!("Failed to save session: {}", save_err);
+                        }
                     }
+                    let _ = tx.send((
+                        session_id_owned.clone(),
+                        SessionUpdate::Reply(Some(format!("Error: {}", e))),
+                    ));
+                    let _ = tx.send((session_id_owned.clone(), SessionUpdate::Reply(None)));
+                    return Err(anyhow::anyhow!("Agent processing failed: {}", e));
+                }
+            }
+        }
+        Ok(())
+    }
+    .await;
+    session_state.finish_turn(turn.as_ref().err().map(|e| e.to_string()).as_deref());
+
+    if let (Some(checkpoints), Some(before)) = (checkpoints, before) {
+        let id = session_id.to_string();
+        match checkpoint_task(move || checkpoints.record(&id, &before)).await {
+            Ok(Some(recorded)) => send_log(
+                LogLevel::Info,
+                format!("checkpoint turn {} files={}", recorded.id, recorded.files.len()),
+            ),
+            Ok(None) => {}
+            Err(e) => tracing::warn!(error = %e, "recording checkpoint failed"),
+        }
+    }
+    turn?;
+
+    if config.core_config().generate_titles && session.title.is_none() && is_first_exchange(&session) {
+        match generate_title(&session, &provider_adapter, model.clone()).await {
+            Some(title) => {
+                tracing::info!(session_id = %session_id, title = %title, "generated session title");
+                session.title = Some(title);
+                if let Err(e) = store.save(&session) {
+                    tracing::warn!("Failed to save session: {}", e);
                 }
             }
+            None => tracing::debug!(session_id = %session_id, "no session title generated"),
         }
     }
 
     Ok(())
 }
+
+/// Runs `/compact [turns to keep]` on `session` and reports the outcome as the reply.
+#[allow(clippy::too_many_arguments)]
+async fn compact(
+    session_id: &str,
+    args: &str,
+    session: &mut opencode_core::session::Session,
+    store: &impl SessionStore,
+    provider: &dyn Provider,
+    model: Option<String>,
+    context: &ContextManager,
+    tx: &mpsc::UnboundedSender<(String, SessionUpdate)>,
+) -> Result<()> {
+    let reply = |text: String| {
+        let _ = tx.send((session_id.to_string(), SessionUpdate::Reply(Some(text))));
+        let _ = tx.send((session_id.to_string(), SessionUpdate::Reply(None)));
+    };
+    let keep_turns = match args.trim() {
+        "" => compaction::DEFAULT_KEEP_TURNS,
+        n => match n.parse::<usize>() {
+            Ok(n) => n,
+            Err(_) => {
+                reply(format!("Usage: /compact [turns to keep] (got '{}')", n));
+                return Ok(());
+            }
+        },
+    };
+    match compaction::compact_session(session, provider, model, context, keep_turns).await {
+        Ok(Some(outcome)) => {
+            store.save(session)?;
+            tracing::info!(session_id = %session_id, archived = outcome.archived, "session compacted");
+            reply(format!(
+                "Compacted {} earlier messages into a summary; {} recent messages kept.",
+                outcome.archived, outcome.kept
+            ));
+            Ok(())
+        }
+        Ok(None) => {
+            reply("Nothing to compact yet.".to_string());
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "compaction failed");
+            reply(format!("Error: {}", e));
+            Err(anyhow::anyhow!("Compaction failed: {}", e))
+        }
+    }
+}
+
+/// State machine of the session, restored from its last recorded status. Every change is
+/// persisted next to the session and sent to the UI as `SessionUpdate::State`.
+fn session_state_handle(
+    session_id: &str,
+    config: &AppConfig,
+    tx: &mpsc::UnboundedSender<(String, SessionUpdate)>,
+) -> SessionStateHandle {
+    let store = config.session_store();
+    let machine = match store.load_status(session_id) {
+        Ok(Some(status)) => SessionStateMachine::from_status(&status),
+        Ok(None) => SessionStateMachine::new(),
+        Err(e) => {
+            tracing::warn!(session_id = %session_id, error = %e, "Failed to read session state");
+            SessionStateMachine::new()
+        }
+    };
+    let session_id = session_id.to_string();
+    let tx = tx.clone();
+    let on_change: opencode_core::OnStateChange = Arc::new(move |status: &SessionStatus| {
+        if let Err(e) = store.save_status(&session_id, status) {
+            tracing::warn!(session_id = %session_id, error = %e, "Failed to save session state");
+        }
+        let _ = tx.send((session_id.clone(), SessionUpdate::State(status.clone())));
+    });
+    SessionStateHandle::new(machine, Some(on_change))
+}
+
+/// Puts permission requests of a turn to the UI as `SessionUpdate::PermissionRequest`; the session
+/// waits for the user meanwhile. "Always" answers are saved to the project config.
+struct UpdatePrompt {
+    session_id: String,
+    tx: mpsc::UnboundedSender<(String, SessionUpdate)>,
+    session_state: SessionStateHandle,
+    workspace_path: Option<PathBuf>,
+}
+
+#[async_trait::async_trait]
+impl PermissionPrompt for UpdatePrompt {
+    async fn ask(&self, request: PermissionRequest) -> PermissionReply {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        if let Err(e) = self.session_state.await_user() {
+            tracing::warn!(error = %e, "session state at permission request");
+        }
+        let resources = request.resources.clone();
+        let pending = PendingPermission {
+            request,
+            reply: PermissionResponder(Arc::new(Mutex::new(Some(reply_tx)))),
+        };
+        let _ = self
+            .tx
+            .send((self.session_id.clone(), SessionUpdate::PermissionRequest(pending)));
+        // A request nobody can answer is denied.
+        let reply = reply_rx.await.unwrap_or(PermissionReply::Deny);
+        if let Err(e) = self.session_state.user_answered() {
+            tracing::warn!(error = %e, "session state after permission reply");
+        }
+        tracing::info!(session_id = %self.session_id, ?resources, ?reply, "permission answered");
+        if let (PermissionReply::Always, Some(workspace)) = (reply, &self.workspace_path) {
+            if let Err(e) = remember_permission(workspace, &resources) {
+                tracing::warn!(error = %e, "saving permission rule failed");
+            }
+        }
+        reply
+    }
+}
+
+/// Allows `resources` from now on in the project config of `workspace`.
+pub(crate) fn remember_permission(workspace: &Path, resources: &[String]) -> Result<()> {
+    let mut config = opencode_core::config::Config::load_from_dir(workspace)?;
+    for resource in resources {
+        config.permission.allow_first(resource);
+    }
+    config.save_to_dir(workspace)?;
+    Ok(())
+}
+
+/// Runs a checkpoint operation on the blocking thread pool: its git commands read and write the
+/// whole workspace.
+async fn checkpoint_task<T: Send + 'static>(
+    task: impl FnOnce() -> checkpoint::Result<T> + Send + 'static,
+) -> checkpoint::Result<T> {
+    tokio::task::spawn_blocking(task)
+        .await
+        .unwrap_or_else(|e| Err(CheckpointError::Io(std::io::Error::other(e))))
+}
+
+/// Runs `/undo [turn]` or `/redo [turn]` against the workspace checkpoints and reports the
+/// restored files as the reply.
+async fn restore_checkpoint(
+    session_id: &str,
+    command_id: &str,
+    args: &str,
+    workspace_path: Option<&std::path::Path>,
+    tx: &mpsc::UnboundedSender<(String, SessionUpdate)>,
+) -> Result<()> {
+    let reply = |text: String| {
+        let _ = tx.send((session_id.to_string(), SessionUpdate::Reply(Some(text))));
+        let _ = tx.send((session_id.to_string(), SessionUpdate::Reply(None)));
+    };
+    let Some(workspace) = workspace_path else {
+        reply(format!("Error: /{} needs an open workspace.", command_id));
+        return Ok(());
+    };
+    let turn = match args.trim() {
+        "" => None,
+        n => match n.trim_start_matches('#').parse::<u64>() {
+            Ok(n) => Some(n),
+            Err(_) => {
+                reply(format!("Usage: /{} [turn number] (got '{}')", command_id, n));
+                return Ok(());
+            }
+        },
+    };
+    let store = CheckpointStore::new(workspace);
+    // A turn number names any turn of the workspace; without one only this session's turns count.
+    let session = turn.is_none().then(|| session_id.to_string());
+    let undo = command_id == checkpoint::UNDO_COMMAND_ID;
+    let outcome = checkpoint_task(move || {
+        if undo {
+            store.undo(session.as_deref(), turn)
+        } else {
+            store.redo(session.as_deref(), turn)
+        }
+    })
+    .await;
+    match outcome {
+        Ok(restore) => {
+            tracing::info!(session_id = %session_id, turn = restore.turn.id, command = command_id, "checkpoint restored");
+            reply(describe_restore(command_id, &restore));
+            Ok(())
+        }
+        Err(e @ (CheckpointError::NothingToUndo | CheckpointError::NothingToRedo | CheckpointError::TurnNotFound(_))) => {
+            reply(format!("{}.", e));
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "checkpoint restore failed");
+            reply(format!("Error: {}", e));
+            Err(anyhow::anyhow!("/{} failed: {}", command_id, e))
+        }
+    }
+}
+
+/// "Undid turn 3 (2 files): ..." summary of a restore.
+fn describe_restore(command_id: &str, restore: &checkpoint::Restore) -> String {
+    let verb = if command_id == checkpoint::UNDO_COMMAND_ID { "Undid" } else { "Redid" };
+    let mut out = format!(
+        "{} turn {} ({} file{} restored)",
+        verb,
+        restore.turn.id,
+        restore.files.len(),
+        if restore.files.len() == 1 { "" } else { "s" }
+    );
+    for path in &restore.files {
+        out.push_str("\n- ");
+        out.push_str(path);
+    }
+    out.push_str("\n\n");
+    out.push_str(checkpoint::IGNORED_NOTE);
+    out
+}
+
+/// True when the session holds exactly one user message, answered by the assistant.
+fn is_first_exchange(session: &opencode_core::session::Session) -> bool {
+    session.messages.iter().filter(|m| m.role == Role::User).count() == 1
+        && session.messages.last().is_some_and(|m| m.role == Role::Assistant)
+}
+
+/// Records a finished deep agent turn: the user's input, then the reply with the turn's token
+/// usage (so `opencode stats` counts it like BuildAgent replies).
+pub(crate) fn push_deep_agent_turn(
+    session: &mut opencode_core::Session,
+    input: &str,
+    reply: &opencode_provider::DeepAgentReply,
+) {
+    session.push_message(SessionMessage {
+        role: Role::User,
+        content: input.to_string(),
+        created_at: Utc::now(),
+        meta: None,
+    });
+    session.push_message(SessionMessage {
+        role: Role::Assistant,
+        content: reply.content.clone(),
+        created_at: Utc::now(),
+        meta: reply.usage.clone().map(|usage| opencode_core::session::MessageMeta {
+            usage: Some(usage),
+            ..Default::default()
+        }),
+    });
+}
+
+/// Longest title kept from the model's answer, in characters.
+const MAX_TITLE_CHARS: usize = 80;
+
+/// Asks the provider for a short title of the first exchange. None when the request fails or
+/// the answer is empty.
+async fn generate_title(
+    session: &opencode_core::session::Session,
+    provider: &dyn Provider,
+    model: Option<String>,
+) -> Option<String> {
+    let exchange: String = session
+        .messages
+        .iter()
+        .filter(|m| matches!(m.role, Role::User | Role::Assistant))
+        .map(|m| {
+            let text: String = m.content.chars().take(1000).collect();
+            format!("{:?}: {}\n", m.role, text)
+        })
+        .collect();
+    let request = ProviderRequest {
+        messages: vec![
+            ProviderMessage {
+                role: MessageRole::System,
+                content: "Write a short title (at most 6 words) for this conversation. \
+                          Reply with the title only, without quotes or punctuation at the end."
+                    .to_string(),
+                tool_call_id: None,
+                tool_calls: Vec::new(),
+            },
+            ProviderMessage {
+                role: MessageRole::User,
+                content: exchange,
+                tool_call_id: None,
+                tool_calls: Vec::new(),
+            },
+        ],
+        model,
+        temperature: Some(0.2),
+        max_tokens: Some(32),
+        tools: Vec::new(),
+    };
+    match provider.generate(request).await {
+        Ok(response) => clean_title(&response.content),
+        Err(e) => {
+            tracing::warn!(error = %e, "title generation failed");
+            None
+        }
+    }
+}
+
+/// First non-empty line of `raw` without surrounding quotes, cut to `MAX_TITLE_CHARS`.
+pub(crate) fn clean_title(raw: &str) -> Option<String> {
+    let line = raw.lines().map(str::trim).find(|l| !l.is_empty())?;
+    let line = line.strip_prefix("Title:").unwrap_or(line);
+    let title: String = line
+        .trim()
+        .trim_matches(|c| matches!(c, '"' | '\'' | '`' | '*'))
+        .trim()
+        .chars()
+        .take(MAX_TITLE_CHARS)
+        .collect();
+    let title = title.trim_end();
+    (!title.is_empty()).then(|| title.to_string())
+}
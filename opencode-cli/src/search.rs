@@ -0,0 +1,367 @@
+//! Full-text search over session messages.
+//!
+//! Each session folder has a `search.json` index: the role and time of every message plus an
+//! inverted index from terms to message indices. `JsonlSessionStore` updates it on every save;
+//! a missing or stale index (one that does not cover the current log length) is rebuilt on the
+//! next search. Terms are lowercased words; CJK characters are indexed one by one so queries
+//! work without word segmentation.
+//!
+//! A message matches when every query term is a prefix of one of its terms. Snippets highlight
+//! the matched text and come from the stored messages, so only matching sessions are loaded.
+
+use crate::session_store::{JsonlSessionStore, SessionStore, SESSION_FILE};
+use chrono::{DateTime, Utc};
+use opencode_core::session::{Message, Role};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const INDEX_FILE: &str = "search.json";
+const INDEX_VERSION: u32 = 1;
+
+/// Characters of context shown on each side of the first match.
+const SNIPPET_CONTEXT: usize = 60;
+
+/// Index of one session, stored as `<id>/search.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SessionIndex {
+    version: u32,
+    /// Length of `session.jsonl` this index covers.
+    log_len: u64,
+    messages: Vec<IndexedMessage>,
+    /// Term to indices of the messages containing it.
+    terms: BTreeMap<String, Vec<usize>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexedMessage {
+    role: Role,
+    created_at: DateTime<Utc>,
+}
+
+impl SessionIndex {
+    pub fn build(messages: &[Message], log_len: u64) -> Self {
+        let mut index = Self {
+            version: INDEX_VERSION,
+            ..Default::default()
+        };
+        index.append(messages, log_len);
+        index
+    }
+
+    /// Number of messages indexed.
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn log_len(&self) -> u64 {
+        self.log_len
+    }
+
+    /// Indexes `messages` as the next messages of the session, now `log_len` bytes long.
+    pub fn append(&mut self, messages: &[Message], log_len: u64) {
+        for message in messages {
+            let index = self.messages.len();
+            for term in terms(&message.content).into_iter().collect::<BTreeSet<_>>() {
+                self.terms.entry(term).or_default().push(index);
+            }
+            self.messages.push(IndexedMessage {
+                role: message.role.clone(),
+                created_at: message.created_at,
+            });
+        }
+        self.log_len = log_len;
+    }
+
+    /// Reads the index in `dir`; None when it is missing, unreadable or from another version.
+    pub fn load(dir: &Path) -> Option<Self> {
+        let data = fs::read(dir.join(INDEX_FILE)).ok()?;
+        serde_json::from_slice::<Self>(&data)
+            .ok()
+            .filter(|index| index.version == INDEX_VERSION)
+    }
+
+    /// Writes the index to `dir` through a temp file and rename.
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        let path = dir.join(INDEX_FILE);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(&tmp, &path)
+    }
+
+    /// Indices of the messages that contain every term (as a prefix of one of their terms).
+    fn matching(&self, query_terms: &[String]) -> BTreeSet<usize> {
+        let mut result: Option<BTreeSet<usize>> = None;
+        for term in query_terms {
+            let postings: BTreeSet<usize> = self
+                .terms
+                .range(term.clone()..)
+                .take_while(|(key, _)| key.starts_with(term.as_str()))
+                .flat_map(|(_, indices)| indices.iter().copied())
+                .collect();
+            result = Some(match result {
+                Some(r) => r.intersection(&postings).copied().collect(),
+                None => postings,
+            });
+        }
+        result.unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    pub text: String,
+    pub role: Option<Role>,
+    /// Only messages created at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Maximum number of hits (0 for no limit).
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub session_id: String,
+    pub message_index: usize,
+    pub role: Role,
+    pub created_at: DateTime<Utc>,
+    /// Text around the first match, with `…` where it was cut.
+    pub snippet: String,
+    /// Byte ranges of `snippet` that match query terms.
+    pub highlights: Vec<(usize, usize)>,
+}
+
+impl SearchHit {
+    /// Snippet with matches wrapped in `before` and `after` (e.g. ANSI codes or `**`).
+    pub fn highlighted(&self, before: &str, after: &str) -> String {
+        let mut out = String::with_capacity(self.snippet.len());
+        let mut pos = 0;
+        for &(start, end) in &self.highlights {
+            out.push_str(&self.snippet[pos..start]);
+            out.push_str(before);
+            out.push_str(&self.snippet[start..end]);
+            out.push_str(after);
+            pos = end;
+        }
+        out.push_str(&self.snippet[pos..]);
+        out
+    }
+}
+
+/// Parses a `--role` value: user, assistant, tool or system.
+pub fn parse_role(s: &str) -> Result<Role, String> {
+    match s.to_lowercase().as_str() {
+        "user" => Ok(Role::User),
+        "assistant" => Ok(Role::Assistant),
+        "tool" => Ok(Role::Tool),
+        "system" => Ok(Role::System),
+        other => Err(format!(
+            "unknown role '{}' (expected user, assistant, tool or system)",
+            other
+        )),
+    }
+}
+
+/// Parses a `--since` value: a date (`2024-05-01`, midnight UTC) or an RFC 3339 time.
+pub fn parse_since(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc());
+    }
+    DateTime::parse_from_rfc3339(s)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|_| format!("invalid date '{}' (expected YYYY-MM-DD or RFC 3339)", s))
+}
+
+/// Searches every session in `store`, newest messages first. Stale indexes are rebuilt.
+pub fn search(store: &JsonlSessionStore, query: &SearchQuery) -> anyhow::Result<Vec<SearchHit>> {
+    let query_terms = terms(&query.text);
+    if query_terms.is_empty() {
+        anyhow::bail!("search query has no words to look for");
+    }
+    let root = store.root();
+    if !root.exists() {
+        return Ok(Vec::new());
+    }
+    store.migrate_legacy()?;
+
+    let mut hits = Vec::new();
+    for entry in fs::read_dir(root)? {
+        let dir = entry?.path();
+        let Ok(log_len) = fs::metadata(dir.join(SESSION_FILE)).map(|m| m.len()) else {
+            continue;
+        };
+        let Some(id) = dir.file_name().and_then(|n| n.to_str()).map(str::to_string) else {
+            continue;
+        };
+        let mut session = None;
+        let index = match SessionIndex::load(&dir).filter(|i| i.log_len == log_len) {
+            Some(index) => index,
+            None => {
+                let loaded = match store.load(&id) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        tracing::warn!(session_id = %id, error = %e, "skipping unreadable session in search");
+                        continue;
+                    }
+                };
+                let index = SessionIndex::build(&loaded.messages, log_len);
+                if let Err(e) = index.save(&dir) {
+                    tracing::warn!(session_id = %id, error = %e, "failed to write search index");
+                }
+                session = Some(loaded);
+                index
+            }
+        };
+
+        let matching: Vec<usize> = index
+            .matching(&query_terms)
+            .into_iter()
+            .filter(|&i| {
+                let m = &index.messages[i];
+                query.role.as_ref().is_none_or(|r| *r == m.role)
+                    && query.since.is_none_or(|since| m.created_at >= since)
+            })
+            .collect();
+        if matching.is_empty() {
+            continue;
+        }
+        let session = match session.map(Ok).unwrap_or_else(|| store.load(&id)) {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::warn!(session_id = %id, error = %e, "skipping unreadable session in search");
+                continue;
+            }
+        };
+        for i in matching {
+            let Some(message) = session.messages.get(i) else {
+                continue;
+            };
+            let (snippet, highlights) = snippet(&message.content, &query_terms);
+            hits.push(SearchHit {
+                session_id: id.clone(),
+                message_index: i,
+                role: message.role.clone(),
+                created_at: message.created_at,
+                snippet,
+                highlights,
+            });
+        }
+    }
+    hits.sort_by_key(|h| std::cmp::Reverse(h.created_at));
+    if query.limit > 0 {
+        hits.truncate(query.limit);
+    }
+    Ok(hits)
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}'   // Hiragana, Katakana
+        | '\u{3400}'..='\u{4dbf}' // CJK Extension A
+        | '\u{4e00}'..='\u{9fff}' // CJK Unified Ideographs
+        | '\u{ac00}'..='\u{d7af}' // Hangul syllables
+        | '\u{f900}'..='\u{faff}' // CJK Compatibility Ideographs
+    )
+}
+
+fn is_word_char(c: char) -> bool {
+    (c.is_alphanumeric() || c == '_') && !is_cjk(c)
+}
+
+/// Lowercase char, when lowercasing does not change the number of chars.
+fn fold(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
+
+/// Terms of `text`: lowercase alphanumeric words (`_` included) and single CJK characters.
+pub fn terms(text: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut word = String::new();
+    for c in text.chars() {
+        if is_cjk(c) {
+            if !word.is_empty() {
+                out.push(std::mem::take(&mut word));
+            }
+            out.push(c.to_string());
+        } else if is_word_char(c) {
+            word.push(fold(c));
+        } else if !word.is_empty() {
+            out.push(std::mem::take(&mut word));
+        }
+    }
+    if !word.is_empty() {
+        out.push(word);
+    }
+    out
+}
+
+/// Window of `content` around the first match of any term, and the byte ranges of all term
+/// matches inside it.
+fn snippet(content: &str, query_terms: &[String]) -> (String, Vec<(usize, usize)>) {
+    let chars: Vec<char> = content.chars().collect();
+    let folded: Vec<char> = chars.iter().map(|c| fold(*c)).collect();
+    let mut marked = vec![false; chars.len()];
+    let mut first = None;
+    for term in query_terms {
+        let term: Vec<char> = term.chars().collect();
+        let cjk = term.iter().all(|c| is_cjk(*c));
+        for i in 0..folded.len().saturating_sub(term.len() - 1) {
+            if folded[i..i + term.len()] != term[..] {
+                continue;
+            }
+            if cjk {
+                marked[i..i + term.len()].fill(true);
+            } else {
+                // Words match by prefix, so highlight from a word start to the word's end.
+                if i > 0 && is_word_char(chars[i - 1]) {
+                    continue;
+                }
+                let mut end = i + term.len();
+                while end < chars.len() && is_word_char(chars[end]) {
+                    end += 1;
+                }
+                marked[i..end].fill(true);
+            }
+            first = Some(first.map_or(i, |f: usize| f.min(i)));
+        }
+    }
+    let first = first.unwrap_or(0);
+    let start = first.saturating_sub(SNIPPET_CONTEXT);
+    let end = (first + SNIPPET_CONTEXT * 2).min(chars.len());
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    let mut highlights = Vec::new();
+    let mut open: Option<usize> = None;
+    for i in start..end {
+        match (marked[i], open) {
+            (true, None) => open = Some(snippet.len()),
+            (false, Some(s)) => {
+                highlights.push((s, snippet.len()));
+                open = None;
+            }
+            _ => {}
+        }
+        // Newlines would break one-line result lists.
+        let c = chars[i];
+        snippet.push(if c == '\n' || c == '\r' { ' ' } else { c });
+    }
+    if let Some(s) = open {
+        highlights.push((s, snippet.len()));
+    }
+    if end < chars.len() {
+        snippet.push('…');
+    }
+    (snippet, highlights)
+}
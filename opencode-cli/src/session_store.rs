@@ -1,21 +1,773 @@
-use anyhow::Result;
-use opencode_core::session::Session;
-use std::path::Path;
-
-/// Saves a session to disk. Creates parent directory if needed.
-/// Path should be the full path to session.json.
-pub fn save_session(path: &Path, session: &Session) -> Result<()> {
-    if let Some(parent) = path.parent() {
-        std::fs::create_dir_all(parent)?;
-    }
-    let data = serde_json::to_string_pretty(session)?;
-    std::fs::write(path, data)?;
-    Ok(())
-}
-
-/// Loads a session from disk.
-/// Path should be the full path to session.json.
-pub fn load_session(path: &Path) -> Result<Session> {
-    let data = std::fs::read_to_string(path)?;
-    Ok(serde_json::from_str(&data)?)
+//! Session persistence. `JsonlSessionStore` keeps each session as an append-only JSONL log in
+//! `<session_dir>/<id>/session.jsonl`: a `session` record with the session fields, the
+//! `archived` records of messages replaced by a compaction summary, then one `message` record per
+//! message. Turns append only what is new; full rewrites (e.g. after removing messages or
+//! compacting) go through a temp file and rename. Writers hold an exclusive lock on
+//! `<id>/.lock`, readers a shared one.
+//!
+//! The turn state of a session (`SessionStatus`: processing, waiting for a tool, completed, ...)
+//! changes many times per turn and is kept out of the log, in `<id>/state.json`.
+//!
+//! Every `session` record carries the `schema_version` it was written with; the records after it
+//! are upgraded from that version on load (see `opencode_core::session::schema`), so logs appended
+//! by older builds stay readable. `upgrade_logs` rewrites them in the current version.
+//!
+//! A line that fails to parse makes the session unreadable (`SessionStoreError::Corrupt`) and
+//! writes to it are refused, so a damaged file is never replaced by a partial session. The one
+//! exception is an unterminated last line, which is what a crash in the middle of an append
+//! leaves behind: it is ignored on load and cut off by the next write.
+
+use crate::search::SessionIndex;
+use chrono::{DateTime, Utc};
+use opencode_core::ids::SessionId;
+use opencode_core::session::schema::{self, SchemaError};
+use opencode_core::session::{display_title, Message, Session, SCHEMA_VERSION};
+use opencode_core::SessionStatus;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use thiserror::Error;
+
+pub const SESSION_FILE: &str = "session.jsonl";
+/// File written by earlier versions (one pretty-printed JSON document).
+pub const LEGACY_SESSION_FILE: &str = "session.json";
+const LOCK_FILE: &str = ".lock";
+/// Latest `SessionStatus` of the session.
+pub const STATE_FILE: &str = "state.json";
+
+#[derive(Error, Debug)]
+pub enum SessionStoreError {
+    #[error("Session '{0}' not found")]
+    NotFound(String),
+
+    #[error("Session file {path} is corrupt at line {line}: {reason}")]
+    Corrupt {
+        path: PathBuf,
+        line: usize,
+        reason: String,
+    },
+
+    #[error("Session file {path} has schema version {version}; this build reads up to {SCHEMA_VERSION}")]
+    UnsupportedVersion { path: PathBuf, version: u32 },
+
+    #[error("Session '{0}' already exists")]
+    AlreadyExists(String),
+
+    #[error("Invalid session id: {0}")]
+    InvalidId(String),
+
+    /// Another writer changed the session since it was loaded, and saving would drop its changes.
+    #[error("Session '{0}' was changed by another writer; reload it before saving")]
+    Conflict(String),
+
+    #[error("Session I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Session serialization error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, SessionStoreError>;
+
+/// Listing entry. `JsonlSessionStore` builds it by reading the session's whole log (the message
+/// records are parsed too, for the count and the first message).
+#[derive(Debug, Clone)]
+pub struct SessionSummary {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub message_count: usize,
+    /// Content of the first message, for display.
+    pub first_message: Option<String>,
+    /// Set for forked sessions (see `Session::fork`).
+    pub parent_session_id: Option<String>,
+    pub forked_at_index: Option<usize>,
+    pub title: Option<String>,
+    pub tags: Vec<String>,
+    pub pinned: bool,
+}
+
+impl SessionSummary {
+    /// The title, or the start of the first message (see `Session::display_title`).
+    pub fn display_title(&self) -> String {
+        display_title(self.title.as_deref(), self.first_message.as_deref())
+    }
+}
+
+/// Outcome of validating one stored session with `JsonlSessionStore::check`.
+#[derive(Debug, Clone)]
+pub struct SessionCheck {
+    /// Still a `session.json` document (converted by `migrate_legacy`).
+    pub legacy: bool,
+    /// Oldest schema version in the stored data.
+    pub schema_version: u32,
+    pub message_count: usize,
+}
+
+impl SessionCheck {
+    /// Whether `sessions migrate` would rewrite the session.
+    pub fn needs_migration(&self) -> bool {
+        self.legacy || self.schema_version < SCHEMA_VERSION
+    }
+}
+
+/// Where sessions are persisted. Implementations must be safe to use from several processes.
+pub trait SessionStore: Send + Sync {
+    fn exists(&self, id: &str) -> bool;
+
+    fn load(&self, id: &str) -> Result<Session>;
+
+    /// Persists `session`, appending the messages added since this store last loaded or saved
+    /// it; messages another writer appended meanwhile are kept. Rewrites the session when
+    /// messages were removed or archived, and fails with `Conflict` when that would drop another
+    /// writer's changes.
+    fn save(&self, session: &Session) -> Result<()>;
+
+    /// Atomically rewrites the stored session with `session`.
+    fn replace(&self, session: &Session) -> Result<()>;
+
+    fn delete(&self, id: &str) -> Result<()>;
+
+    /// Turn state last recorded for the session; None when it never ran a turn.
+    fn load_status(&self, id: &str) -> Result<Option<SessionStatus>>;
+
+    /// Records the session's turn state.
+    fn save_status(&self, id: &str, status: &SessionStatus) -> Result<()>;
+
+    /// Sessions pinned first, then newest first. Unreadable sessions are reported as errors in
+    /// place.
+    fn list(&self) -> Result<Vec<(String, Result<SessionSummary>)>>;
+}
+
+/// Log line. Read back as raw JSON by `parse_log` so that it can be upgraded before it is
+/// deserialized.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record {
+    /// Session fields except `messages`; the last one in the file wins.
+    Session(Map<String, Value>),
+    /// Entry of `Session::archived`; only written by full rewrites.
+    Archived(Box<Message>),
+    Message(Box<Message>),
+}
+
+/// Parsed log: the latest session fields, the messages, and the byte length of the complete
+/// records (less than the file length when the last append was cut short). Everything is
+/// upgraded to `SCHEMA_VERSION`; `oldest_version` is the oldest version found in the file.
+struct Log {
+    fields: Map<String, Value>,
+    oldest_version: u32,
+    archived: Vec<Message>,
+    messages: Vec<Message>,
+    valid_len: u64,
+}
+
+/// What a store last loaded or wrote of one session. `save` appends the messages after
+/// `messages`, and sees from `len` whether somebody else wrote in between.
+#[derive(Clone, Copy)]
+struct Synced {
+    messages: usize,
+    archived: usize,
+    len: u64,
+    /// The log also holds messages another writer appended, which the session lacks; it must
+    /// not be rewritten from the session.
+    shared: bool,
+}
+
+pub struct JsonlSessionStore {
+    root: PathBuf,
+    written: Mutex<HashMap<String, Synced>>,
+}
+
+impl JsonlSessionStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            written: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn session_dir(&self, id: &str) -> Result<PathBuf> {
+        if id.is_empty() || id.contains(['/', '\\']) || id == "." || id == ".." {
+            return Err(SessionStoreError::InvalidId(id.to_string()));
+        }
+        Ok(self.root.join(id))
+    }
+
+    fn session_file(&self, id: &str) -> Result<PathBuf> {
+        Ok(self.session_dir(id)?.join(SESSION_FILE))
+    }
+
+    /// Locks the session directory, creating it for writers. Readers of a missing session get
+    /// `NotFound`.
+    fn lock(&self, id: &str, exclusive: bool) -> Result<File> {
+        let dir = self.session_dir(id)?;
+        if exclusive {
+            fs::create_dir_all(&dir)?;
+        } else if !dir.exists() {
+            return Err(SessionStoreError::NotFound(id.to_string()));
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(dir.join(LOCK_FILE))?;
+        if exclusive {
+            file.lock()?;
+        } else {
+            file.lock_shared()?;
+        }
+        Ok(file)
+    }
+
+    /// Converts every `session.json` folder without a `session.jsonl` into the JSONL format.
+    /// The old file is kept as `session.json.bak`. Returns the outcome per converted session id.
+    pub fn migrate_legacy(&self) -> Result<Vec<(String, Result<()>)>> {
+        if !self.root.exists() {
+            return Ok(Vec::new());
+        }
+        let mut results = Vec::new();
+        for entry in fs::read_dir(&self.root)? {
+            let path = entry?.path();
+            let Some(id) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if path.join(LEGACY_SESSION_FILE).exists() && !path.join(SESSION_FILE).exists() {
+                results.push((id.to_string(), self.migrate_one(id)));
+            }
+        }
+        Ok(results)
+    }
+
+    fn migrate_one(&self, id: &str) -> Result<()> {
+        let dir = self.session_dir(id)?;
+        let legacy = dir.join(LEGACY_SESSION_FILE);
+        let _lock = self.lock(id, true)?;
+        if dir.join(SESSION_FILE).exists() {
+            return Ok(());
+        }
+        let mut session = read_legacy(&legacy)?;
+        adopt_id(&mut session, id);
+        self.write_all(id, &session)?;
+        fs::rename(&legacy, dir.join(format!("{}.bak", LEGACY_SESSION_FILE)))?;
+        tracing::info!(session_id = %id, "migrated session.json to session.jsonl");
+        Ok(())
+    }
+
+    /// Rewrites every session log that has records older than `SCHEMA_VERSION`. Returns the
+    /// version each rewritten session had.
+    pub fn upgrade_logs(&self) -> Result<Vec<(String, Result<u32>)>> {
+        let mut results = Vec::new();
+        for id in self.session_ids()? {
+            if !self.root.join(&id).join(SESSION_FILE).exists() {
+                continue;
+            }
+            match self.upgrade_one(&id) {
+                Ok(None) => {}
+                Ok(Some(version)) => results.push((id, Ok(version))),
+                Err(e) => results.push((id, Err(e))),
+            }
+        }
+        Ok(results)
+    }
+
+    fn upgrade_one(&self, id: &str) -> Result<Option<u32>> {
+        let _lock = self.lock(id, true)?;
+        let log = self.read_log(id)?;
+        if log.oldest_version >= SCHEMA_VERSION {
+            return Ok(None);
+        }
+        let from = log.oldest_version;
+        let mut session = into_session(log.fields, log.archived, log.messages)?;
+        adopt_id(&mut session, id);
+        self.write_all(id, &session)?;
+        tracing::info!(session_id = %id, from, to = SCHEMA_VERSION, "upgraded session log");
+        Ok(Some(from))
+    }
+
+    /// Reads and upgrades the stored session in memory, without writing anything.
+    pub fn check(&self, id: &str) -> Result<SessionCheck> {
+        let dir = self.session_dir(id)?;
+        if !dir.join(SESSION_FILE).exists() && dir.join(LEGACY_SESSION_FILE).exists() {
+            let path = dir.join(LEGACY_SESSION_FILE);
+            let version = fs::read(&path)
+                .ok()
+                .and_then(|data| serde_json::from_slice::<Map<String, Value>>(&data).ok())
+                .and_then(|fields| schema::version_of(&fields).ok())
+                .unwrap_or(0);
+            let session = read_legacy(&path)?;
+            return Ok(SessionCheck {
+                legacy: true,
+                schema_version: version,
+                message_count: session.messages.len(),
+            });
+        }
+        let _lock = self.lock(id, false)?;
+        let log = self.read_log(id)?;
+        let message_count = log.messages.len();
+        let oldest_version = log.oldest_version;
+        into_session(log.fields, log.archived, log.messages)?;
+        Ok(SessionCheck {
+            legacy: false,
+            schema_version: oldest_version,
+            message_count,
+        })
+    }
+
+    /// Names of the session folders, sorted.
+    pub fn session_ids(&self) -> Result<Vec<String>> {
+        if !self.root.exists() {
+            return Ok(Vec::new());
+        }
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(&self.root)? {
+            let path = entry?.path();
+            if !path.is_dir() {
+                continue;
+            }
+            let has_session =
+                path.join(SESSION_FILE).exists() || path.join(LEGACY_SESSION_FILE).exists();
+            if let (true, Some(id)) = (has_session, path.file_name().and_then(|n| n.to_str())) {
+                ids.push(id.to_string());
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn read_log(&self, id: &str) -> Result<Log> {
+        let path = self.session_file(id)?;
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(SessionStoreError::NotFound(id.to_string()))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        parse_log(&path, &data)
+    }
+
+    /// Writes the whole session to a temp file and renames it over the log. Caller holds the lock.
+    fn write_all(&self, id: &str, session: &Session) -> Result<()> {
+        let path = self.session_file(id)?;
+        let tmp = path.with_extension("jsonl.tmp");
+        let mut out = Vec::new();
+        out.extend(encode(&Record::Session(session_fields(session)?))?);
+        for message in &session.archived {
+            out.extend(encode(&Record::Archived(Box::new(message.clone())))?);
+        }
+        for message in &session.messages {
+            out.extend(encode(&Record::Message(Box::new(message.clone())))?);
+        }
+        {
+            let mut file = File::create(&tmp)?;
+            file.write_all(&out)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, &path)?;
+        sync_dir(path.parent());
+        self.remember(id, session, out.len() as u64, false);
+        self.update_index(id, session, None, out.len() as u64);
+        Ok(())
+    }
+
+    fn remember(&self, id: &str, session: &Session, len: u64, shared: bool) {
+        if let Ok(mut written) = self.written.lock() {
+            let synced = Synced {
+                messages: session.messages.len(),
+                archived: session.archived.len(),
+                len,
+                shared,
+            };
+            written.insert(id.to_string(), synced);
+        }
+    }
+
+    /// Brings the search index up to date after a write. `appended` is the message count and log
+    /// length before an append; the index is extended when it covered exactly that, and rebuilt
+    /// otherwise. Failures only cost a rebuild on the next search.
+    fn update_index(
+        &self,
+        id: &str,
+        session: &Session,
+        appended: Option<(usize, u64)>,
+        log_len: u64,
+    ) {
+        let Ok(dir) = self.session_dir(id) else {
+            return;
+        };
+        let index = match (appended, SessionIndex::load(&dir)) {
+            (Some((stored, prev_len)), Some(mut index))
+                if index.len() == stored && index.log_len() == prev_len =>
+            {
+                index.append(&session.messages[stored..], log_len);
+                index
+            }
+            _ => SessionIndex::build(&session.messages, log_len),
+        };
+        if let Err(e) = index.save(&dir) {
+            tracing::warn!(session_id = %id, error = %e, "failed to update search index");
+        }
+    }
+
+    fn summary(&self, id: &str) -> Result<SessionSummary> {
+        let _lock = self.lock(id, false)?;
+        let log = self.read_log(id)?;
+        let session = into_session(log.fields, Vec::new(), Vec::new())?;
+        Ok(SessionSummary {
+            id: id.to_string(),
+            created_at: session.created_at,
+            updated_at: session.updated_at,
+            message_count: log.messages.len(),
+            first_message: log.messages.into_iter().next().map(|m| m.content),
+            parent_session_id: session.parent_session_id.map(|id| id.to_string()),
+            forked_at_index: session.forked_at_index,
+            title: session.title,
+            tags: session.tags,
+            pinned: session.pinned,
+        })
+    }
+}
+
+impl SessionStore for JsonlSessionStore {
+    fn exists(&self, id: &str) -> bool {
+        self.session_dir(id)
+            .map(|dir| dir.join(SESSION_FILE).exists() || dir.join(LEGACY_SESSION_FILE).exists())
+            .unwrap_or(false)
+    }
+
+    fn load(&self, id: &str) -> Result<Session> {
+        let dir = self.session_dir(id)?;
+        if !dir.join(SESSION_FILE).exists() && dir.join(LEGACY_SESSION_FILE).exists() {
+            self.migrate_one(id)?;
+        }
+        let _lock = self.lock(id, false)?;
+        let log = self.read_log(id)?;
+        let file_len = fs::metadata(self.session_file(id)?)?.len();
+        let mut session = into_session(log.fields, log.archived, log.messages)?;
+        adopt_id(&mut session, id);
+        self.remember(id, &session, file_len, false);
+        Ok(session)
+    }
+
+    fn save(&self, session: &Session) -> Result<()> {
+        let id = session.id.to_string();
+        let dir = self.session_dir(&id)?;
+        if !dir.join(SESSION_FILE).exists() && dir.join(LEGACY_SESSION_FILE).exists() {
+            self.migrate_one(&id)?;
+        }
+        let _lock = self.lock(&id, true)?;
+        let path = self.session_file(&id)?;
+
+        let file_len = match fs::metadata(&path) {
+            Ok(m) => m.len(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return self.write_all(&id, session),
+            Err(e) => return Err(e.into()),
+        };
+        let known = self.written.lock().ok().and_then(|w| w.get(&id).copied());
+        // `others` is the log when another writer appended since this store last synced.
+        let (stored, valid_len, others) = match known {
+            Some(synced) if synced.len == file_len => {
+                // Appends cannot drop messages or add archived ones.
+                if synced.messages > session.messages.len()
+                    || synced.archived != session.archived.len()
+                {
+                    if synced.shared {
+                        return Err(SessionStoreError::Conflict(id));
+                    }
+                    return self.write_all(&id, session);
+                }
+                (synced.messages, synced.len, None)
+            }
+            Some(synced) => {
+                let count = synced.messages;
+                let log = self.read_log(&id)?;
+                if count > session.messages.len()
+                    || synced.archived != session.archived.len()
+                    || log.archived.len() != synced.archived
+                    || log.messages.len() < count
+                {
+                    return Err(SessionStoreError::Conflict(id));
+                }
+                tracing::info!(
+                    session_id = %id,
+                    appended = log.messages.len() - count,
+                    "session was appended to by another writer; keeping both"
+                );
+                (count, log.valid_len, Some(log))
+            }
+            None => {
+                // Not loaded through this store: the stored messages are taken as its start.
+                let log = self.read_log(&id)?;
+                if log.messages.len() > session.messages.len()
+                    || log.archived.len() != session.archived.len()
+                {
+                    return Err(SessionStoreError::Conflict(id));
+                }
+                (log.messages.len(), log.valid_len, None)
+            }
+        };
+
+        let mut out = encode(&Record::Session(session_fields(session)?))?;
+        for message in &session.messages[stored..] {
+            out.extend(encode(&Record::Message(Box::new(message.clone())))?);
+        }
+        if valid_len < file_len {
+            tracing::warn!(session_id = %id, "dropping incomplete last record of session log");
+            OpenOptions::new()
+                .write(true)
+                .open(&path)?
+                .set_len(valid_len)?;
+        }
+        let mut file = OpenOptions::new().append(true).open(&path)?;
+        file.write_all(&out)?;
+        file.sync_data()?;
+        let new_len = valid_len + out.len() as u64;
+        let shared = others.is_some() || known.is_some_and(|synced| synced.shared);
+        self.remember(&id, session, new_len, shared);
+        match others {
+            Some(log) => {
+                let mut merged = session.clone();
+                merged.messages = log.messages;
+                merged.messages.extend_from_slice(&session.messages[stored..]);
+                self.update_index(&id, &merged, None, new_len);
+            }
+            None => self.update_index(&id, session, Some((stored, valid_len)), new_len),
+        }
+        Ok(())
+    }
+
+    fn replace(&self, session: &Session) -> Result<()> {
+        let id = session.id.to_string();
+        let _lock = self.lock(&id, true)?;
+        if let Err(e) = self.read_log(&id) {
+            if !matches!(e, SessionStoreError::NotFound(_)) {
+                return Err(e);
+            }
+        }
+        self.write_all(&id, session)
+    }
+
+    fn delete(&self, id: &str) -> Result<()> {
+        let dir = self.session_dir(id)?;
+        if !dir.exists() {
+            return Err(SessionStoreError::NotFound(id.to_string()));
+        }
+        fs::remove_dir_all(&dir)?;
+        if let Ok(mut written) = self.written.lock() {
+            written.remove(id);
+        }
+        Ok(())
+    }
+
+    fn load_status(&self, id: &str) -> Result<Option<SessionStatus>> {
+        match fs::read(self.session_dir(id)?.join(STATE_FILE)) {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save_status(&self, id: &str, status: &SessionStatus) -> Result<()> {
+        let _lock = self.lock(id, true)?;
+        let path = self.session_dir(id)?.join(STATE_FILE);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(status)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<(String, Result<SessionSummary>)>> {
+        if !self.root.exists() {
+            return Ok(Vec::new());
+        }
+        let mut sessions = Vec::new();
+        for (id, migrated) in self.migrate_legacy()? {
+            if let Err(e) = migrated {
+                sessions.push((id, Err(e)));
+            }
+        }
+        for entry in fs::read_dir(&self.root)? {
+            let path = entry?.path();
+            if !path.join(SESSION_FILE).exists() {
+                continue;
+            }
+            let Some(id) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            sessions.push((id.to_string(), self.summary(id)));
+        }
+        sessions.sort_by(|a, b| {
+            let key = |s: &Result<SessionSummary>| {
+                s.as_ref().ok().map(|s| (s.pinned, s.updated_at))
+            };
+            key(&b.1).cmp(&key(&a.1))
+        });
+        Ok(sessions)
+    }
+}
+
+fn encode(record: &Record) -> Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+fn session_fields(session: &Session) -> Result<Map<String, Value>> {
+    match serde_json::to_value(session)? {
+        Value::Object(mut fields) => {
+            fields.remove("messages");
+            fields.remove("archived");
+            Ok(fields)
+        }
+        _ => unreachable!("Session serializes to an object"),
+    }
+}
+
+fn into_session(
+    mut fields: Map<String, Value>,
+    archived: Vec<Message>,
+    messages: Vec<Message>,
+) -> Result<Session> {
+    fields.insert("messages".to_string(), Value::Array(Vec::new()));
+    let mut session: Session = serde_json::from_value(Value::Object(fields))?;
+    session.archived = archived;
+    session.messages = messages;
+    Ok(session)
+}
+
+/// Reads a `session.json` document, upgrading it to the current schema.
+fn read_legacy(path: &Path) -> Result<Session> {
+    let corrupt = |line: usize, reason: String| SessionStoreError::Corrupt {
+        path: path.to_path_buf(),
+        line,
+        reason,
+    };
+    let data = fs::read_to_string(path)?;
+    let mut document: Value =
+        serde_json::from_str(&data).map_err(|e| corrupt(e.line(), e.to_string()))?;
+    schema::migrate_session(&mut document).map_err(|e| match e {
+        SchemaError::TooNew(version) => SessionStoreError::UnsupportedVersion {
+            path: path.to_path_buf(),
+            version,
+        },
+        SchemaError::Invalid(reason) => corrupt(1, reason),
+    })?;
+    serde_json::from_value(document).map_err(|e| corrupt(1, e.to_string()))
+}
+
+/// Older versions could save a session under a folder other than its id; the folder wins so
+/// that saving goes back to the same place.
+fn adopt_id(session: &mut Session, id: &str) {
+    if let Ok(folder_id) = SessionId::from_str(id) {
+        session.id = folder_id;
+    }
+}
+
+fn parse_log(path: &Path, data: &[u8]) -> Result<Log> {
+    let corrupt = |line: usize, reason: String| SessionStoreError::Corrupt {
+        path: path.to_path_buf(),
+        line,
+        reason,
+    };
+    let schema_error = |line: usize, e: SchemaError| match e {
+        SchemaError::TooNew(version) => SessionStoreError::UnsupportedVersion {
+            path: path.to_path_buf(),
+            version,
+        },
+        SchemaError::Invalid(reason) => corrupt(line, reason),
+    };
+    let mut fields = None;
+    let mut archived = Vec::new();
+    let mut messages = Vec::new();
+    // Version of the records that follow, from the last session record.
+    let mut version = 0;
+    let mut oldest_version = SCHEMA_VERSION;
+    let mut offset = 0usize;
+    let mut line_no = 0;
+    while offset < data.len() {
+        line_no += 1;
+        let Some(end) = data[offset..].iter().position(|b| *b == b'\n') else {
+            // Unterminated last line: an append that did not complete.
+            tracing::warn!(path = %path.display(), line = line_no, "ignoring incomplete last record");
+            break;
+        };
+        let line = &data[offset..offset + end];
+        offset += end + 1;
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let mut record = match serde_json::from_slice::<Value>(line) {
+            Ok(Value::Object(record)) => record,
+            Ok(_) => return Err(corrupt(line_no, "record is not an object".to_string())),
+            Err(e) => return Err(corrupt(line_no, e.to_string())),
+        };
+        let kind = match record.remove("type") {
+            Some(Value::String(kind)) => kind,
+            _ => return Err(corrupt(line_no, "record without a type".to_string())),
+        };
+        match kind.as_str() {
+            "session" => {
+                version = schema::version_of(&record).map_err(|e| schema_error(line_no, e))?;
+                schema::check_version(version).map_err(|e| schema_error(line_no, e))?;
+                oldest_version = oldest_version.min(version);
+                fields = Some((line_no, record));
+            }
+            "archived" | "message" => {
+                let mut value = Value::Object(record);
+                schema::migrate_message(&mut value, version)
+                    .map_err(|e| schema_error(line_no, e))?;
+                let message: Message = serde_json::from_value(value)
+                    .map_err(|e| corrupt(line_no, e.to_string()))?;
+                if kind == "archived" {
+                    archived.push(message);
+                } else {
+                    messages.push(message);
+                }
+            }
+            other => return Err(corrupt(line_no, format!("unknown record type '{}'", other))),
+        }
+    }
+    let (fields_line, mut fields) =
+        fields.ok_or_else(|| corrupt(1, "missing session record".to_string()))?;
+    schema::migrate_session_fields(&mut fields).map_err(|e| schema_error(fields_line, e))?;
+    Ok(Log {
+        fields,
+        oldest_version,
+        archived,
+        messages,
+        valid_len: offset as u64,
+    })
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: Option<&Path>) {
+    if let Some(dir) = dir {
+        if let Ok(d) = File::open(dir) {
+            let _ = d.sync_all();
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: Option<&Path>) {}
+
+/// New empty session with `id`. Fails with `InvalidId` when `id` is not a valid session id and
+/// with `AlreadyExists` when `store` already has a session with it.
+pub fn new_session(store: &impl SessionStore, id: &str) -> Result<Session> {
+    let session_id =
+        SessionId::from_str(id).map_err(|_| SessionStoreError::InvalidId(id.to_string()))?;
+    if store.exists(id) {
+        return Err(SessionStoreError::AlreadyExists(id.to_string()));
+    }
+    Ok(Session::with_id(session_id))
 }
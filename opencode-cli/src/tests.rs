@@ -0,0 +1,590 @@
+#[cfg(test)]
+mod tests {
+    use crate::session_store::{
+        JsonlSessionStore, SessionStore, SessionStoreError, LEGACY_SESSION_FILE, SESSION_FILE,
+    };
+    use chrono::Utc;
+    use opencode_core::session::{Message, Role, Session, SCHEMA_VERSION};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    fn temp_root() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("opencode-sessions-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn message(role: Role, content: &str) -> Message {
+        Message {
+            role,
+            content: content.to_string(),
+            created_at: Utc::now(),
+            meta: None,
+        }
+    }
+
+    #[test]
+    fn jsonl_store_appends_and_reloads() {
+        let root = temp_root();
+        let store = JsonlSessionStore::new(&root);
+        let mut session = Session::new();
+        let id = session.id.to_string();
+        session.push_message(message(Role::User, "hello"));
+        store.save(&session).unwrap();
+        session.push_message(message(Role::Assistant, "hi"));
+        store.save(&session).unwrap();
+
+        let file = root.join(&id).join(SESSION_FILE);
+        let lines = fs::read_to_string(&file).unwrap().lines().count();
+        assert_eq!(lines, 4, "two saves append a session record plus the new messages");
+
+        // A second store (another process) sees the same log and appends after it.
+        let other = JsonlSessionStore::new(&root);
+        let mut loaded = other.load(&id).unwrap();
+        assert_eq!(loaded.messages.len(), 2);
+        assert_eq!(loaded.messages[1].content, "hi");
+        loaded.push_message(message(Role::User, "again"));
+        other.save(&loaded).unwrap();
+        assert_eq!(store.load(&id).unwrap().messages.len(), 3);
+
+        // Fewer messages than stored: the log is rewritten.
+        loaded.messages.truncate(1);
+        other.save(&loaded).unwrap();
+        assert_eq!(store.load(&id).unwrap().messages.len(), 1);
+
+        let listed = store.list().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].1.as_ref().unwrap().first_message.as_deref(), Some("hello"));
+
+        // New sessions keep the requested id or fail; they never fall back to a random one.
+        use crate::session_store::new_session;
+        assert!(matches!(new_session(&store, &id), Err(SessionStoreError::AlreadyExists(_))));
+        assert!(matches!(new_session(&store, "not an id"), Err(SessionStoreError::InvalidId(_))));
+        let fresh = opencode_core::ids::SessionId::new().to_string();
+        assert_eq!(new_session(&store, &fresh).unwrap().id.to_string(), fresh);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn jsonl_store_keeps_messages_of_two_writers() {
+        let root = temp_root();
+        let first = JsonlSessionStore::new(&root);
+        let mut session = Session::new();
+        let id = session.id.to_string();
+        session.push_message(message(Role::User, "hello"));
+        first.save(&session).unwrap();
+
+        // Both writers load the same session, then each appends its own turn.
+        let second = JsonlSessionStore::new(&root);
+        let mut a = first.load(&id).unwrap();
+        let mut b = second.load(&id).unwrap();
+        a.push_message(message(Role::User, "from a"));
+        b.push_message(message(Role::User, "from b 1"));
+        b.push_message(message(Role::User, "from b 2"));
+        second.save(&b).unwrap();
+        first.save(&a).unwrap();
+        a.push_message(message(Role::Assistant, "to a"));
+        first.save(&a).unwrap();
+
+        let reader = JsonlSessionStore::new(&root);
+        let contents: Vec<String> =
+            reader.load(&id).unwrap().messages.into_iter().map(|m| m.content).collect();
+        assert_eq!(contents, vec!["hello", "from b 1", "from b 2", "from a", "to a"]);
+
+        // Dropping messages after the other writer appended would erase its turn.
+        a.messages.truncate(1);
+        assert!(matches!(first.save(&a), Err(SessionStoreError::Conflict(_))));
+        b.messages.truncate(1);
+        assert!(matches!(second.save(&b), Err(SessionStoreError::Conflict(_))));
+        // A store that never loaded the session cannot rewrite it either.
+        let fresh = JsonlSessionStore::new(&root);
+        assert!(matches!(fresh.save(&b), Err(SessionStoreError::Conflict(_))));
+        assert_eq!(reader.load(&id).unwrap().messages.len(), 5);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn jsonl_store_recovers_torn_tail_and_refuses_corrupt_log() {
+        let root = temp_root();
+        let store = JsonlSessionStore::new(&root);
+        let mut session = Session::new();
+        let id = session.id.to_string();
+        session.push_message(message(Role::User, "one"));
+        store.save(&session).unwrap();
+        let file = root.join(&id).join(SESSION_FILE);
+
+        // An append cut short by a crash leaves an unterminated line.
+        OpenOptions::new()
+            .append(true)
+            .open(&file)
+            .unwrap()
+            .write_all(b"{\"type\":\"message\",\"role\":\"us")
+            .unwrap();
+        let mut loaded = JsonlSessionStore::new(&root).load(&id).unwrap();
+        assert_eq!(loaded.messages.len(), 1);
+        loaded.push_message(message(Role::Assistant, "two"));
+        store.save(&loaded).unwrap();
+        assert_eq!(store.load(&id).unwrap().messages.len(), 2);
+
+        // A damaged line in the middle makes the session unreadable and unwritable.
+        let data = fs::read_to_string(&file).unwrap();
+        let mut lines: Vec<&str> = data.lines().collect();
+        lines[1] = "not json";
+        fs::write(&file, lines.join("\n") + "\n").unwrap();
+        let fresh = JsonlSessionStore::new(&root);
+        assert!(matches!(fresh.load(&id), Err(SessionStoreError::Corrupt { line: 2, .. })));
+        loaded.push_message(message(Role::User, "three"));
+        assert!(matches!(fresh.save(&loaded), Err(SessionStoreError::Corrupt { .. })));
+        assert!(matches!(fresh.replace(&loaded), Err(SessionStoreError::Corrupt { .. })));
+        assert!(fs::read_to_string(&file).unwrap().contains("not json"));
+        assert!(fresh.list().unwrap()[0].1.is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn jsonl_store_migrates_legacy_session_json() {
+        let root = temp_root();
+        let mut session = Session::new();
+        let id = session.id.to_string();
+        session.push_message(message(Role::User, "legacy"));
+        fs::create_dir_all(root.join(&id)).unwrap();
+        fs::write(
+            root.join(&id).join(LEGACY_SESSION_FILE),
+            serde_json::to_string_pretty(&session).unwrap(),
+        )
+        .unwrap();
+
+        let store = JsonlSessionStore::new(&root);
+        let migrated = store.migrate_legacy().unwrap();
+        assert_eq!(migrated.len(), 1);
+        assert!(migrated[0].1.is_ok());
+        assert!(root.join(&id).join(SESSION_FILE).exists());
+        assert!(!root.join(&id).join(LEGACY_SESSION_FILE).exists());
+        assert!(root.join(&id).join("session.json.bak").exists());
+        assert_eq!(store.load(&id).unwrap().messages[0].content, "legacy");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn export_includes_tool_calls_usage_and_highlighted_code() {
+        use crate::export::{export_session, ExportFormat};
+        use opencode_core::session::{MessageMeta, ToolCall};
+        use opencode_core::usage::{ModelPrice, TokenUsage};
+        use std::collections::HashMap;
+
+        let mut session = Session::new();
+        session.push_message(message(Role::User, "Show <main>"));
+        let mut call = message(Role::Assistant, "Reading it.");
+        call.meta = Some(MessageMeta {
+            tool_calls: vec![ToolCall {
+                id: "call_1".to_string(),
+                name: "read".to_string(),
+                arguments: serde_json::json!({ "path": "src/main.rs" }),
+            }],
+            usage: Some(TokenUsage {
+                prompt_tokens: 1000,
+                completion_tokens: 100,
+                model: Some("gpt-4o".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        session.push_message(call);
+        let mut result = message(Role::Tool, "fn main() {}\n```");
+        result.meta = Some(MessageMeta {
+            tool_name: Some("read".to_string()),
+            tool_call_id: Some("call_1".to_string()),
+            ..Default::default()
+        });
+        session.push_message(result);
+        session.push_message(message(Role::Assistant, "Here:\n```rust\nfn main() {}\n```\n"));
+        let pricing = HashMap::from([(
+            "gpt-4o".to_string(),
+            ModelPrice { input: 2.5, output: 10.0, cached_input: None },
+        )]);
+
+        let md = export_session(&session, ExportFormat::Markdown, &pricing).unwrap();
+        assert!(md.contains("**Tool call** `read` (id `call_1`)"));
+        assert!(md.contains("## 3. Tool result: read (call_1)"));
+        assert!(md.contains("````\nfn main() {}\n```\n````"), "fence outgrows backticks in content");
+        assert!(md.contains("gpt-4o: 1000 prompt + 100 completion tokens, $0.0035"));
+
+        let html = export_session(&session, ExportFormat::Html, &pricing).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("Show &lt;main&gt;"));
+        assert!(html.contains("<span style="), "code blocks are highlighted inline");
+        assert!(!html.contains("<link") && !html.contains("<script"), "no external resources");
+
+        let json: serde_json::Value =
+            serde_json::from_str(&export_session(&session, ExportFormat::Json, &pricing).unwrap())
+                .unwrap();
+        assert_eq!(json["messages"][1]["tool_calls"][0]["name"], "read");
+        assert_eq!(json["messages"][2]["tool_call_id"], "call_1");
+        assert_eq!(json["usage"]["completion_tokens"], 100);
+    }
+
+    #[test]
+    fn deep_agent_turn_usage_is_stored_with_the_reply() {
+        use crate::message_processor::push_deep_agent_turn;
+        use opencode_core::usage::{ModelPrice, TokenUsage, UsageReport};
+        use opencode_provider::DeepAgentReply;
+        use std::collections::HashMap;
+
+        let root = temp_root();
+        let store = JsonlSessionStore::new(&root);
+        let mut session = Session::new();
+        let id = session.id.to_string();
+        push_deep_agent_turn(
+            &mut session,
+            "explain main.rs",
+            &DeepAgentReply {
+                content: "It prints hello.".to_string(),
+                usage: Some(TokenUsage {
+                    prompt_tokens: 2000,
+                    completion_tokens: 200,
+                    model: Some("gpt-4o".to_string()),
+                    ..Default::default()
+                }),
+            },
+        );
+        store.save(&session).unwrap();
+
+        let loaded = JsonlSessionStore::new(&root).load(&id).unwrap();
+        assert_eq!(loaded.messages[0].role, Role::User);
+        let usage = loaded.messages[1].meta.as_ref().and_then(|m| m.usage.as_ref()).unwrap();
+        assert_eq!(usage.total_tokens(), 2200);
+
+        let pricing = HashMap::from([(
+            "gpt-4o".to_string(),
+            ModelPrice { input: 2.5, output: 10.0, cached_input: None },
+        )]);
+        let report = UsageReport::from_sessions([&loaded], &pricing);
+        assert!((report.total.cost - 0.007).abs() < 1e-9);
+    }
+
+    #[test]
+    fn import_maps_roles_tool_calls_and_usage() {
+        use crate::import::{from_openai, from_opencode_ts_export};
+
+        let openai = serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [
+                { "role": "system", "content": "Be brief." },
+                { "role": "user", "content": [{ "type": "text", "text": "List files" }] },
+                { "role": "assistant", "content": null, "tool_calls": [{
+                    "id": "call_1", "type": "function",
+                    "function": { "name": "glob", "arguments": "{\"pattern\":\"*\"}" }
+                }]},
+                { "role": "tool", "tool_call_id": "call_1", "content": "a.rs\nb.rs" },
+                { "role": "assistant", "content": "Two files." }
+            ]
+        });
+        let session = from_openai(&openai).unwrap();
+        let roles: Vec<Role> = session.messages.iter().map(|m| m.role.clone()).collect();
+        assert_eq!(
+            roles,
+            [Role::System, Role::User, Role::Assistant, Role::Tool, Role::Assistant]
+        );
+        assert_eq!(session.messages[1].content, "List files");
+        let call = &session.messages[2].meta.as_ref().unwrap().tool_calls[0];
+        assert_eq!((call.id.as_str(), call.name.as_str()), ("call_1", "glob"));
+        assert_eq!(call.arguments["pattern"], "*");
+        let result = session.messages[3].meta.as_ref().unwrap();
+        assert_eq!(result.tool_call_id.as_deref(), Some("call_1"));
+        assert_eq!(result.tool_name.as_deref(), Some("glob"));
+
+        // One TypeScript assistant message with text, a tool call, then more text.
+        let ts = serde_json::json!({
+            "info": { "id": "ses_1", "title": "t", "time": { "created": 1700000000000i64, "updated": 1700000060000i64 } },
+            "messages": [
+                { "info": { "id": "msg_1", "role": "user", "time": { "created": 1700000000000i64 } },
+                  "parts": [{ "id": "prt_1", "type": "text", "text": "Read main" }] },
+                { "info": { "id": "msg_2", "role": "assistant", "time": { "created": 1700000001000i64 },
+                            "modelID": "claude-sonnet-4", "providerID": "anthropic",
+                            "tokens": { "input": 100, "output": 20, "reasoning": 5, "cache": { "read": 50, "write": 0 } } },
+                  "parts": [
+                    { "id": "prt_2", "type": "step-start" },
+                    { "id": "prt_3", "type": "text", "text": "Reading." },
+                    { "id": "prt_4", "type": "tool", "tool": "read", "callID": "toolu_1",
+                      "state": { "status": "completed", "input": { "filePath": "main.rs" }, "output": "fn main() {}" } },
+                    { "id": "prt_5", "type": "text", "text": "It is empty." }
+                  ] }
+            ]
+        });
+        let session = from_opencode_ts_export(&ts).unwrap();
+        let roles: Vec<Role> = session.messages.iter().map(|m| m.role.clone()).collect();
+        assert_eq!(roles, [Role::User, Role::Assistant, Role::Tool, Role::Assistant]);
+        assert_eq!(session.created_at.timestamp(), 1_700_000_000);
+        assert_eq!(session.messages[1].content, "Reading.");
+        assert_eq!(session.messages[1].meta.as_ref().unwrap().tool_calls[0].id, "toolu_1");
+        assert_eq!(session.messages[2].content, "fn main() {}");
+        let usage = session.messages[3].meta.as_ref().unwrap().usage.as_ref().unwrap();
+        assert_eq!(usage.prompt_tokens, 150);
+        assert_eq!(usage.completion_tokens, 25);
+        assert_eq!(usage.model.as_deref(), Some("claude-sonnet-4"));
+    }
+
+    #[test]
+    fn search_finds_messages_and_keeps_index_current() {
+        use crate::search::{search, SearchQuery, SessionIndex};
+
+        let root = temp_root();
+        let store = JsonlSessionStore::new(&root);
+        let mut session = Session::new();
+        let id = session.id.to_string();
+        session.push_message(message(Role::User, "How do I configure the Ollama provider?"));
+        session.push_message(message(Role::Assistant, "Set provider_type to ollama."));
+        store.save(&session).unwrap();
+        session.push_message(message(Role::User, "配置文件在哪里？"));
+        store.save(&session).unwrap();
+
+        // The index was extended on save and covers the whole log.
+        let log_len = fs::metadata(root.join(&id).join(SESSION_FILE)).unwrap().len();
+        let index = SessionIndex::load(&root.join(&id)).unwrap();
+        assert_eq!((index.len(), index.log_len()), (3, log_len));
+
+        let query = |text: &str, role: Option<Role>| SearchQuery {
+            text: text.to_string(),
+            role,
+            ..Default::default()
+        };
+        let hits = search(&store, &query("olla config", None)).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].message_index, 0);
+        assert_eq!(
+            hits[0].highlighted("[", "]"),
+            "How do I [configure] the [Ollama] provider?"
+        );
+        assert_eq!(search(&store, &query("ollama", Some(Role::Assistant))).unwrap().len(), 1);
+        let cjk = search(&store, &query("配置", None)).unwrap();
+        assert_eq!(cjk[0].highlighted("[", "]"), "[配置]文件在哪里？");
+        let future = SearchQuery {
+            since: Some(Utc::now() + chrono::Duration::days(1)),
+            ..query("ollama", None)
+        };
+        assert!(search(&store, &future).unwrap().is_empty());
+
+        // A stale index (log changed behind the store's back) is rebuilt by the next search.
+        fs::remove_file(root.join(&id).join("search.json")).unwrap();
+        assert_eq!(search(&store, &query("provider", None)).unwrap().len(), 2);
+        assert!(SessionIndex::load(&root.join(&id)).is_some());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn titles_tags_and_pins_round_trip_through_store() {
+        let root = temp_root();
+        let store = JsonlSessionStore::new(&root);
+        let mut older = Session::new();
+        older.push_message(message(Role::User, &"数据库迁移".repeat(20)));
+        store.save(&older).unwrap();
+        let mut newer = Session::new();
+        newer.push_message(message(Role::User, "\n  Fix the build\nmore"));
+        store.save(&newer).unwrap();
+
+        // Derived titles cut on char boundaries and skip blank lines.
+        let listed = store.list().unwrap();
+        let title = |i: usize| listed[i].1.as_ref().unwrap().display_title();
+        assert_eq!(title(0), "Fix the build");
+        assert_eq!(title(1), format!("{}...", "数据库迁移".repeat(10)));
+
+        older.title = Some("DB migration".to_string());
+        assert!(older.add_tag("db"));
+        assert!(!older.add_tag(" db "));
+        older.add_tag("infra");
+        older.pinned = true;
+        store.save(&older).unwrap();
+        newer.add_tag("build");
+        assert!(newer.remove_tag("build"));
+        store.save(&newer).unwrap();
+
+        let loaded = store.load(&older.id.to_string()).unwrap();
+        assert_eq!(loaded.title.as_deref(), Some("DB migration"));
+        assert_eq!(loaded.tags, vec!["db", "infra"]);
+        assert!(loaded.pinned);
+        // Pinned sessions come first even when older.
+        let listed = store.list().unwrap();
+        let first = listed[0].1.as_ref().unwrap();
+        assert_eq!(listed[0].0, older.id.to_string());
+        assert_eq!((first.display_title().as_str(), first.pinned), ("DB migration", true));
+        assert!(listed[1].1.as_ref().unwrap().tags.is_empty());
+
+        assert_eq!(
+            crate::message_processor::clean_title("\"Fixing the build\"\nextra").as_deref(),
+            Some("Fixing the build")
+        );
+        assert_eq!(crate::message_processor::clean_title("  \n"), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn jsonl_store_keeps_archive_of_compacted_session() {
+        let root = temp_root();
+        let store = JsonlSessionStore::new(&root);
+        let mut session = Session::new();
+        let id = session.id.to_string();
+        for content in ["one", "two", "three", "four"] {
+            session.push_message(message(Role::User, content));
+        }
+        store.save(&session).unwrap();
+
+        let summary = opencode_core::compaction::summary_message("Earlier: one, two", 2, None);
+        session.compact(2, summary);
+        store.save(&session).unwrap();
+        session.push_message(message(Role::User, "five"));
+        store.save(&session).unwrap();
+
+        let loaded = store.load(&id).unwrap();
+        let contents = |ms: &[Message]| ms.iter().map(|m| m.content.clone()).collect::<Vec<_>>();
+        assert_eq!(contents(&loaded.archived), vec!["one", "two"]);
+        assert!(loaded.messages[0].is_summary());
+        assert_eq!(contents(&loaded.messages[1..]), vec!["three", "four", "five"]);
+        let log = fs::read_to_string(root.join(&id).join(SESSION_FILE)).unwrap();
+        assert_eq!(log.matches("\"type\":\"archived\"").count(), 2);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn jsonl_store_upgrades_old_schema_versions_and_refuses_newer() {
+        let root = temp_root();
+        let store = JsonlSessionStore::new(&root);
+        let id = uuid::Uuid::new_v4().to_string();
+        fs::create_dir_all(root.join(&id)).unwrap();
+        let file = root.join(&id).join(SESSION_FILE);
+        // A version 0 record (early format) followed by an append from a current build.
+        let log = format!(
+            concat!(
+                r#"{{"type":"session","id":"{id}","created_at":"2026-02-02T14:08:32Z","updated_at":"2026-02-02T14:08:33Z","project_id":"default","title":"New session - 2026-02-02"}}"#,
+                "\n",
+                r#"{{"type":"message","id":"m1","role":"user","content":"hello","timestamp":"2026-02-02T14:08:32Z"}}"#,
+                "\n",
+                r#"{{"type":"session","schema_version":1,"id":"{id}","created_at":"2026-02-02T14:08:32Z","updated_at":"2026-02-02T14:08:34Z"}}"#,
+                "\n",
+                r#"{{"type":"message","role":"Assistant","content":"hi","created_at":"2026-02-02T14:08:34Z"}}"#,
+                "\n"
+            ),
+            id = id
+        );
+        fs::write(&file, log).unwrap();
+
+        let check = store.check(&id).unwrap();
+        assert!(check.needs_migration());
+        assert_eq!((check.schema_version, check.message_count), (0, 2));
+        let session = store.load(&id).unwrap();
+        assert_eq!(session.schema_version, SCHEMA_VERSION);
+        assert_eq!(session.messages[0].role, Role::User);
+        assert_eq!(session.messages[0].created_at.to_rfc3339(), "2026-02-02T14:08:32+00:00");
+        assert_eq!(session.title, None, "placeholder titles are dropped");
+
+        let upgraded = store.upgrade_logs().unwrap();
+        assert_eq!(upgraded.len(), 1);
+        assert_eq!(*upgraded[0].1.as_ref().unwrap(), 0);
+        assert!(!store.check(&id).unwrap().needs_migration());
+        assert!(store.upgrade_logs().unwrap().is_empty());
+        assert_eq!(store.load(&id).unwrap().messages.len(), 2);
+
+        // A log from a newer build is neither read nor written.
+        let newer = fs::read_to_string(&file).unwrap().replace(
+            &format!("\"schema_version\":{}", SCHEMA_VERSION),
+            "\"schema_version\":99",
+        );
+        fs::write(&file, &newer).unwrap();
+        let other = JsonlSessionStore::new(&root);
+        assert!(matches!(
+            other.load(&id),
+            Err(SessionStoreError::UnsupportedVersion { version: 99, .. })
+        ));
+        assert!(other.save(&Session::with_id(id.parse().unwrap())).is_err());
+        assert_eq!(fs::read_to_string(&file).unwrap(), newer);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn checkpoints_undo_and_redo_turns() {
+        use crate::checkpoint::{CheckpointError, CheckpointStore};
+
+        let root = temp_root();
+        fs::write(root.join("a.txt"), "one").unwrap();
+        let checkpoints = CheckpointStore::new(&root);
+        // Outside a project nothing is snapshotted.
+        assert!(matches!(checkpoints.snapshot(), Err(CheckpointError::NotProject(_))));
+        assert!(!root.join(".opencode").exists());
+        fs::create_dir(root.join(".git")).unwrap();
+
+        // Turn 1 edits a file and creates one in a new directory, as write/edit/bash would.
+        let before = checkpoints.snapshot().unwrap();
+        fs::write(root.join("a.txt"), "two").unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/new.rs"), "fn main() {}").unwrap();
+        let first = checkpoints.record("s1", &before).unwrap().unwrap();
+        assert_eq!(first.files, vec!["a.txt", "src/new.rs"]);
+
+        // Turn 2 deletes a file; a turn without changes is not recorded.
+        let before = checkpoints.snapshot().unwrap();
+        fs::remove_file(root.join("a.txt")).unwrap();
+        let second = checkpoints.record("s1", &before).unwrap().unwrap();
+        let before = checkpoints.snapshot().unwrap();
+        assert!(checkpoints.record("s1", &before).unwrap().is_none());
+
+        // The user edits a file no turn touched; undo and redo leave it alone.
+        fs::write(root.join("notes.md"), "mine").unwrap();
+        let undone = checkpoints.undo(Some("s1"), None).unwrap();
+        assert_eq!(undone.turn.id, second.id);
+        assert_eq!(undone.files, vec!["a.txt"]);
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "two");
+        assert_eq!(fs::read_to_string(root.join("notes.md")).unwrap(), "mine");
+
+        // Undoing turn 1 by number also reverts everything after it.
+        checkpoints.redo(None, None).unwrap();
+        assert!(!root.join("a.txt").exists());
+        assert_eq!(fs::read_to_string(root.join("notes.md")).unwrap(), "mine");
+        checkpoints.undo(None, Some(first.id)).unwrap();
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "one");
+        assert!(!root.join("src").exists());
+        assert_eq!(fs::read_to_string(root.join("notes.md")).unwrap(), "mine");
+        assert!(checkpoints.turns(None).unwrap().iter().all(|t| t.undone));
+        assert!(matches!(
+            checkpoints.undo(Some("s1"), None),
+            Err(CheckpointError::NothingToUndo)
+        ));
+
+        checkpoints.redo(None, Some(first.id)).unwrap();
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "two");
+        assert_eq!(
+            fs::read_to_string(root.join("src/new.rs")).unwrap(),
+            "fn main() {}"
+        );
+        assert!(matches!(
+            checkpoints.redo(None, None),
+            Err(CheckpointError::NothingToRedo)
+        ));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn always_answers_are_saved_to_the_project_config() {
+        use crate::commands::run::parse_permission_answer;
+        use crate::message_processor::remember_permission;
+        use opencode_core::permission::{PermissionAction, PermissionReply};
+
+        assert_eq!(parse_permission_answer(" Y\n"), Some(PermissionReply::Once));
+        assert_eq!(parse_permission_answer("a"), Some(PermissionReply::Always));
+        assert_eq!(parse_permission_answer("no"), Some(PermissionReply::Deny));
+        assert_eq!(parse_permission_answer("maybe"), None);
+
+        let root = temp_root();
+        let mut config = opencode_core::config::Config::load_from_dir(&root).unwrap();
+        config.permission = serde_json::from_str(r#"{"bash:*": "ask"}"#).unwrap();
+        config.save_to_dir(&root).unwrap();
+        remember_permission(&root, &["bash:rm -rf build/*".to_string()]).unwrap();
+
+        let config = opencode_core::config::Config::load_from_dir(&root).unwrap();
+        assert_eq!(config.permission.0.len(), 2);
+        let permissions = config.permissions("build").unwrap();
+        // The saved rule matches exactly the approved resource, ahead of the `ask` rule.
+        assert_eq!(permissions.check("bash:rm -rf build/*"), PermissionAction::Allow);
+        assert_eq!(permissions.check("bash:rm -rf build/x"), PermissionAction::Ask);
+        fs::remove_dir_all(&root).ok();
+    }
+}
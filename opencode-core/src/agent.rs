@@ -1,6 +1,11 @@
-use crate::error::Result;
-use crate::session::Session;
-use crate::tool::Tool;
+use crate::context::ContextManager;
+use crate::error::{Error, Result};
+use crate::model_registry::ModelCapabilities;
+use crate::permission::{self, PermissionManager};
+use crate::session::{Session, ToolCall};
+use crate::session_state::SessionStateHandle;
+use crate::tool::{Tool, ToolContext};
+use crate::usage::TokenUsage;
 use async_trait::async_trait;
 use futures::Stream;
 use futures::StreamExt;
@@ -27,6 +32,10 @@ pub trait Provider: Send + Sync {
 pub struct ProviderChunk {
     pub content: String,
     pub done: bool,
+    /// Fully assembled tool calls; providers emit these once all argument deltas have arrived.
+    pub tool_calls: Vec<ToolCall>,
+    /// Token usage for the whole response, when the provider reports it (usually near the end).
+    pub usage: Option<TokenUsage>,
 }
 
 #[derive(Debug, Clone)]
@@ -35,12 +44,16 @@ pub struct ProviderRequest {
     pub model: Option<String>,
     pub temperature: Option<f32>,
     pub max_tokens: Option<u32>,
+    /// Tools the model may call. Empty means plain chat.
+    pub tools: Vec<ToolDefinition>,
 }
 
 #[derive(Debug, Clone)]
 pub struct ProviderResponse {
     pub content: String,
-    pub usage: Option<serde_json::Value>,
+    pub usage: Option<TokenUsage>,
+    /// Tool calls requested by the model. Empty when the response is a final answer.
+    pub tool_calls: Vec<ToolCall>,
 }
 
 #[derive(Debug, Clone)]
@@ -49,10 +62,32 @@ pub struct ModelInfo {
     pub name: String,
 }
 
+/// Tool schema advertised to the provider (name, description, JSON Schema parameters).
+#[derive(Debug, Clone)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+impl ToolDefinition {
+    pub fn from_tool(tool: &dyn Tool) -> Self {
+        Self {
+            name: tool.id().to_string(),
+            description: tool.description().to_string(),
+            parameters: tool.parameters(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Message {
     pub role: MessageRole,
     pub content: String,
+    /// Set on `MessageRole::Tool` messages: id of the call this message answers.
+    pub tool_call_id: Option<String>,
+    /// Set on assistant messages that requested tool calls.
+    pub tool_calls: Vec<ToolCall>,
 }
 
 #[derive(Debug, Clone)]
@@ -60,6 +95,7 @@ pub enum MessageRole {
     System,
     User,
     Assistant,
+    Tool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -73,10 +109,26 @@ pub struct Context {
     pub session_id: String,
     pub message_id: String,
     pub agent: String,
-    /// Current project/workspace directory for prompts and future tool execution.
+    /// Current project/workspace directory for prompts and tool execution.
     pub workspace_path: Option<String>,
+    /// Max provider round-trips per turn (Config.max_agent_iterations). None uses the default (10).
+    pub max_iterations: Option<usize>,
+    /// Token budget for the history sent with each request (see `ContextManager::new`).
+    pub context_window: ContextManager,
+    /// Capabilities of the model answering this turn: output limit, sampling, tool and streaming
+    /// support (see `ModelRegistry`).
+    pub capabilities: ModelCapabilities,
+    /// State machine of the session, driven by tool calls; a doom loop aborts the turn.
+    pub session_state: Option<SessionStateHandle>,
+    /// Rules every tool call is checked against; None allows all.
+    pub permissions: Option<Arc<PermissionManager>>,
+    /// Directories outside `workspace_path` the file tools may access (Config.allowed_paths).
+    pub allowed_paths: Vec<String>,
 }
 
+/// Provider round-trips per turn when `Context::max_iterations` is unset.
+const DEFAULT_MAX_ITERATIONS: usize = 10;
+
 /// Builds the system message content for BuildAgent: coding assistant role and project context.
 fn build_system_message(ctx: &Context) -> String {
     let mut s = "You are a coding assistant in OpenCode. Help the user with code, project analysis, and development tasks.".to_string();
@@ -88,6 +140,188 @@ fn build_system_message(ctx: &Context) -> String {
     s
 }
 
+/// Converts a stored session message to a provider message, carrying tool call linkage from meta.
+fn to_provider_message(m: &crate::session::Message) -> Message {
+    use crate::session::Role;
+
+    let meta = m.meta.as_ref();
+    Message {
+        role: match m.role {
+            Role::User => MessageRole::User,
+            Role::Assistant => MessageRole::Assistant,
+            Role::System => MessageRole::System,
+            Role::Tool => MessageRole::Tool,
+        },
+        content: m.content.clone(),
+        tool_call_id: meta.and_then(|meta| meta.tool_call_id.clone()),
+        tool_calls: meta.map(|meta| meta.tool_calls.clone()).unwrap_or_default(),
+    }
+}
+
+/// Session history fitted to the context window as provider messages, with the system prompt
+/// prepended when the session does not store one. A compaction summary at the head does not
+/// count as a system prompt, and neither does the omission note `fit` adds.
+fn provider_messages(ctx: &Context, session: &Session) -> Vec<Message> {
+    let fitted = ctx.context_window.fit(&session.messages);
+    let has_system_prompt = session
+        .messages
+        .first()
+        .is_some_and(|m| m.role == crate::session::Role::System && !m.is_summary());
+    let mut messages: Vec<Message> = fitted.messages.iter().map(to_provider_message).collect();
+    if !has_system_prompt {
+        messages.insert(
+            0,
+            Message {
+                role: MessageRole::System,
+                content: build_system_message(ctx),
+                tool_call_id: None,
+                tool_calls: Vec::new(),
+            },
+        );
+    }
+    messages
+}
+
+fn assistant_message(
+    content: String,
+    tool_calls: Vec<ToolCall>,
+    usage: Option<TokenUsage>,
+) -> crate::session::Message {
+    let meta = if tool_calls.is_empty() && usage.is_none() {
+        None
+    } else {
+        Some(crate::session::MessageMeta {
+            tool_calls,
+            usage,
+            ..Default::default()
+        })
+    };
+    crate::session::Message {
+        role: crate::session::Role::Assistant,
+        content,
+        created_at: chrono::Utc::now(),
+        meta,
+    }
+}
+
+/// Tool schemas for the request; empty when the model does not support tool calling.
+fn tool_definitions(ctx: &Context, tools: &[Arc<dyn Tool>]) -> Vec<ToolDefinition> {
+    if !ctx.capabilities.tools {
+        return Vec::new();
+    }
+    tools
+        .iter()
+        .map(|t| ToolDefinition::from_tool(t.as_ref()))
+        .collect()
+}
+
+fn iteration_limit_message(max_iterations: usize) -> String {
+    format!(
+        "Stopped after {} agent iterations without a final answer. Send another message to continue.",
+        max_iterations
+    )
+}
+
+/// Executes each tool call and records its output (or error) as a `Role::Tool` message.
+/// Failures are reported back to the model rather than aborting the turn, unless the session
+/// state machine detects a doom loop (the same call failing repeatedly). The calls of the batch
+/// after an abort are answered as not executed, so the history stays valid for the next request.
+async fn execute_tool_calls(
+    ctx: &Context,
+    session: &mut Session,
+    tools: &[Arc<dyn Tool>],
+    calls: &[ToolCall],
+) -> Result<()> {
+    use crate::session::{Message, MessageMeta, Role};
+
+    for (i, call) in calls.iter().enumerate() {
+        let tool_ctx = ToolContext {
+            session_id: ctx.session_id.clone(),
+            message_id: ctx.message_id.clone(),
+            agent: ctx.agent.clone(),
+            call_id: Some(call.id.clone()),
+            workspace_path: ctx.workspace_path.clone(),
+            permissions: ctx.permissions.clone(),
+            allowed_paths: ctx.allowed_paths.clone(),
+        };
+        if let Some(state) = &ctx.session_state {
+            if let Err(e) = state.tool_started(&call.name) {
+                skip_tool_calls(session, &calls[i..], &e.to_string());
+                return Err(e);
+            }
+        }
+        let (content, failed) = match tools.iter().find(|t| t.id() == call.name) {
+            Some(tool) => {
+                tracing::info!(tool_id = %call.name, call_id = %call.id, "agent tool call start");
+                let result =
+                    match permission::authorize(&call.name, &call.arguments, &tool_ctx).await {
+                        Ok(_) => tool.execute(call.arguments.clone(), &tool_ctx).await,
+                        Err(e) => Err(e),
+                    };
+                match result {
+                    Ok(result) => {
+                        tracing::info!(
+                            tool_id = %call.name,
+                            output_len = result.output.len(),
+                            "agent tool call ok"
+                        );
+                        (result.output, false)
+                    }
+                    Err(e) => {
+                        tracing::warn!(tool_id = %call.name, error = %e, "agent tool call err");
+                        (format!("Error: {}", e), true)
+                    }
+                }
+            }
+            None => {
+                tracing::warn!(tool_id = %call.name, "agent requested unknown tool");
+                (format!("Error: unknown tool '{}'", call.name), true)
+            }
+        };
+        let verdict = match &ctx.session_state {
+            Some(state) => {
+                let error = failed.then_some(content.as_str());
+                state.tool_finished(&call.name, &call.arguments, error)
+            }
+            None => Ok(()),
+        };
+        session.push_message(Message {
+            role: Role::Tool,
+            content,
+            created_at: chrono::Utc::now(),
+            meta: Some(MessageMeta {
+                tool_name: Some(call.name.clone()),
+                tool_call_id: Some(call.id.clone()),
+                ..Default::default()
+            }),
+        });
+        if let Err(e) = verdict {
+            skip_tool_calls(session, &calls[i + 1..], &e.to_string());
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Records `calls` as not executed (with `reason`), so every tool call in the history is
+/// answered by a `Role::Tool` message.
+fn skip_tool_calls(session: &mut Session, calls: &[ToolCall], reason: &str) {
+    use crate::session::{Message, MessageMeta, Role};
+
+    for call in calls {
+        session.push_message(Message {
+            role: Role::Tool,
+            content: format!("Error: not executed: {}", reason),
+            created_at: chrono::Utc::now(),
+            meta: Some(MessageMeta {
+                tool_name: Some(call.name.clone()),
+                tool_call_id: Some(call.id.clone()),
+                ..Default::default()
+            }),
+        });
+    }
+}
+
 #[async_trait]
 pub trait Agent: Send + Sync {
     async fn process(
@@ -113,6 +347,8 @@ pub trait Agent: Send + Sync {
     fn mode(&self) -> AgentMode;
 }
 
+/// Build agent: runs the tool-calling loop. Each provider response either requests tool calls
+/// (executed and fed back as `Role::Tool` messages) or is the final answer for the turn.
 pub struct BuildAgent;
 
 impl BuildAgent {
@@ -129,7 +365,7 @@ impl Agent for BuildAgent {
         input: &str,
         session: &mut Session,
         provider: &dyn Provider,
-        _tools: &[Arc<dyn Tool>],
+        tools: &[Arc<dyn Tool>],
     ) -> Result<()> {
         use crate::session::{Message, Role};
         use chrono::Utc;
@@ -142,88 +378,82 @@ impl Agent for BuildAgent {
         };
         session.push_message(user_message);
 
-        let mut messages: Vec<crate::agent::Message> = session
-            .messages
-            .iter()
-            .map(|m| crate::agent::Message {
-                role: match m.role {
-                    Role::User => crate::agent::MessageRole::User,
-                    Role::Assistant => crate::agent::MessageRole::Assistant,
-                    Role::System => crate::agent::MessageRole::System,
-                    Role::Tool => crate::agent::MessageRole::System,
-                },
-                content: m.content.clone(),
-            })
-            .collect();
-
-        if messages.first().map(|m| !matches!(m.role, MessageRole::System)).unwrap_or(true) {
-            let system_content = build_system_message(ctx);
-            messages.insert(
-                0,
-                crate::agent::Message {
-                    role: MessageRole::System,
-                    content: system_content,
-                },
+        let definitions = tool_definitions(ctx, tools);
+        let max_iterations = ctx.max_iterations.unwrap_or(DEFAULT_MAX_ITERATIONS).max(1);
+
+        for iteration in 1..=max_iterations {
+            let messages = provider_messages(ctx, session);
+            let message_count = messages.len();
+            let total_prompt_chars: usize = messages.iter().map(|m| m.content.len()).sum();
+            let last_user_msg_trunc: Option<String> = messages
+                .iter()
+                .rev()
+                .find(|m| matches!(m.role, MessageRole::User))
+                .map(|m| m.content.chars().take(200).collect());
+            let request = ProviderRequest {
+                messages,
+                model: None,
+                temperature: ctx.capabilities.temperature,
+                max_tokens: Some(ctx.capabilities.max_output_tokens),
+                tools: definitions.clone(),
+            };
+
+            tracing::info!(
+                session_id = %ctx.session_id,
+                agent = %ctx.agent,
+                iteration,
+                message_count,
+                total_prompt_chars,
+                tool_count = request.tools.len(),
+                model = ?request.model,
+                temperature = ?request.temperature,
+                max_tokens = ?request.max_tokens,
+                "LLM request start"
             );
-        }
-
-        let message_count = messages.len();
-        let total_prompt_chars: usize = messages.iter().map(|m| m.content.len()).sum();
-        let last_user_msg_trunc: Option<String> = messages
-            .iter()
-            .rev()
-            .find(|m| matches!(m.role, MessageRole::User))
-            .map(|m| if m.content.len() > 200 { m.content[..200].to_string() } else { m.content.clone() });
-        let request = ProviderRequest {
-            messages,
-            model: None,
-            temperature: Some(0.7),
-            max_tokens: Some(4096),
-        };
-
-        tracing::info!(
-            session_id = %ctx.session_id,
-            agent = %ctx.agent,
-            message_count,
-            total_prompt_chars,
-            model = ?request.model,
-            temperature = ?request.temperature,
-            max_tokens = ?request.max_tokens,
-            "LLM request start"
-        );
-        if let Some(ref trunc) = last_user_msg_trunc {
-            tracing::debug!(last_user_msg_trunc = %trunc, "LLM request detail");
-        }
-
-        let response = match provider.generate(request).await {
-            Ok(r) => {
-                tracing::info!(
-                    response_len = r.content.len(),
-                    usage = ?r.usage,
-                    "LLM response received"
-                );
-                let trunc = if r.content.len() > 500 {
-                    r.content.get(..500).unwrap_or(&r.content)
-                } else {
-                    r.content.as_str()
-                };
-                tracing::debug!(response_trunc = %trunc, "LLM response detail");
-                r
+            if let Some(ref trunc) = last_user_msg_trunc {
+                tracing::debug!(last_user_msg_trunc = %trunc, "LLM request detail");
             }
-            Err(e) => {
-                tracing::error!(error = %e, "provider.generate failed");
-                return Err(e);
-            }
-        };
 
-        let assistant_message = Message {
-            role: Role::Assistant,
-            content: response.content,
-            created_at: Utc::now(),
-            meta: None,
-        };
-        session.push_message(assistant_message);
+            let response = match provider.generate(request).await {
+                Ok(r) => {
+                    tracing::info!(
+                        response_len = r.content.len(),
+                        tool_calls = r.tool_calls.len(),
+                        usage = ?r.usage,
+                        "LLM response received"
+                    );
+                    let trunc = if r.content.len() > 500 {
+                        r.content.get(..500).unwrap_or(&r.content)
+                    } else {
+                        r.content.as_str()
+                    };
+                    tracing::debug!(response_trunc = %trunc, "LLM response detail");
+                    r
+                }
+                Err(e) => {
+                    tracing::error!(error = %e, "provider.generate failed");
+                    return Err(e);
+                }
+            };
+
+            let tool_calls = response.tool_calls;
+            session.push_message(assistant_message(
+                response.content,
+                tool_calls.clone(),
+                response.usage,
+            ));
+            if tool_calls.is_empty() {
+                return Ok(());
+            }
+            execute_tool_calls(ctx, session, tools, &tool_calls).await?;
+        }
 
+        tracing::warn!(max_iterations, "agent stopped: max iterations reached");
+        session.push_message(assistant_message(
+            iteration_limit_message(max_iterations),
+            Vec::new(),
+            None,
+        ));
         Ok(())
     }
 
@@ -233,52 +463,38 @@ impl Agent for BuildAgent {
         input: &str,
         session: &mut Session,
         provider: &dyn Provider,
-        _tools: &[Arc<dyn Tool>],
+        tools: &[Arc<dyn Tool>],
         stream_tx: UnboundedSender<(String, Option<String>)>,
     ) -> Result<()> {
         use crate::session::{Message, Role};
         use chrono::Utc;
 
-        // Build request including the new user message but do not push to session yet
-        // so that on stream Err the caller can fall back to process() without double user message.
-        let mut messages: Vec<crate::agent::Message> = session
-            .messages
-            .iter()
-            .map(|m| crate::agent::Message {
-                role: match m.role {
-                    Role::User => crate::agent::MessageRole::User,
-                    Role::Assistant => crate::agent::MessageRole::Assistant,
-                    Role::System => crate::agent::MessageRole::System,
-                    Role::Tool => crate::agent::MessageRole::System,
-                },
-                content: m.content.clone(),
-            })
-            .collect();
-
-        if messages.first().map(|m| !matches!(m.role, MessageRole::System)).unwrap_or(true) {
-            let system_content = build_system_message(ctx);
-            messages.insert(
-                0,
-                crate::agent::Message {
-                    role: MessageRole::System,
-                    content: system_content,
-                },
-            );
+        if !ctx.capabilities.streaming {
+            return Err(Error::Provider(
+                "Streaming is not supported by this model".to_string(),
+            ));
         }
+        let definitions = tool_definitions(ctx, tools);
+        let max_iterations = ctx.max_iterations.unwrap_or(DEFAULT_MAX_ITERATIONS).max(1);
+        let request_for = |messages: Vec<crate::agent::Message>| ProviderRequest {
+            messages,
+            model: None,
+            temperature: ctx.capabilities.temperature,
+            max_tokens: Some(ctx.capabilities.max_output_tokens),
+            tools: definitions.clone(),
+        };
 
+        // Build request including the new user message but do not push to session yet
+        // so that on stream Err the caller can fall back to process() without double user message.
+        let mut messages = provider_messages(ctx, session);
         messages.push(crate::agent::Message {
             role: MessageRole::User,
             content: input.to_string(),
+            tool_call_id: None,
+            tool_calls: Vec::new(),
         });
 
-        let request = ProviderRequest {
-            messages,
-            model: None,
-            temperature: Some(0.7),
-            max_tokens: Some(4096),
-        };
-
-        let mut stream = match provider.stream(request).await {
+        let mut stream = match provider.stream(request_for(messages)).await {
             Ok(s) => s,
             Err(e) => {
                 tracing::debug!(error = %e, "provider.stream not supported, use process()");
@@ -294,25 +510,54 @@ impl Agent for BuildAgent {
         };
         session.push_message(user_message);
 
-        let mut buffer = String::new();
-        while let Some(item) = stream.next().await {
-            let chunk = item?;
-            let _ = stream_tx.send((ctx.session_id.clone(), Some(chunk.content.clone())));
-            buffer.push_str(&chunk.content);
-            if chunk.done {
+        let mut iteration = 1;
+        loop {
+            let mut buffer = String::new();
+            let mut tool_calls = Vec::new();
+            let mut usage = None;
+            while let Some(item) = stream.next().await {
+                let chunk = item?;
+                if !chunk.content.is_empty() {
+                    let _ = stream_tx.send((ctx.session_id.clone(), Some(chunk.content.clone())));
+                    buffer.push_str(&chunk.content);
+                }
+                tool_calls.extend(chunk.tool_calls);
+                if let Some(chunk_usage) = chunk.usage {
+                    tracing::info!(iteration, usage = ?chunk_usage, "LLM stream usage");
+                    usage = Some(chunk_usage);
+                }
+                if chunk.done {
+                    break;
+                }
+            }
+
+            let finished = tool_calls.is_empty();
+            let wrote_text = !buffer.is_empty();
+            session.push_message(assistant_message(buffer, tool_calls.clone(), usage));
+            if finished {
                 break;
             }
+            if iteration >= max_iterations {
+                tracing::warn!(max_iterations, "agent stopped: max iterations reached");
+                // Every call in the history needs a result, or providers reject the next request.
+                skip_tool_calls(session, &tool_calls, "iteration limit reached");
+                let note = iteration_limit_message(max_iterations);
+                let _ = stream_tx.send((ctx.session_id.clone(), Some(format!("\n\n{}", note))));
+                session.push_message(assistant_message(note, Vec::new(), None));
+                break;
+            }
+
+            execute_tool_calls(ctx, session, tools, &tool_calls).await?;
+            if wrote_text {
+                let _ = stream_tx.send((ctx.session_id.clone(), Some("\n\n".to_string())));
+            }
+            iteration += 1;
+            stream = provider
+                .stream(request_for(provider_messages(ctx, session)))
+                .await?;
         }
         let _ = stream_tx.send((ctx.session_id.clone(), None));
 
-        let assistant_message = Message {
-            role: Role::Assistant,
-            content: buffer,
-            created_at: Utc::now(),
-            meta: None,
-        };
-        session.push_message(assistant_message);
-
         Ok(())
     }
 
@@ -52,8 +52,9 @@ fn builtin_commands() -> Vec<CommandDef> {
         CommandDef {
             id: "undo".to_string(),
             label: "undo".to_string(),
-            description: Some("Undo last change".to_string()),
-            template: Some("User requested /undo. Revert the last edit or change as appropriate.".to_string()),
+            description: Some("Restore files to before the last turn (optional: turn number)".to_string()),
+            // Handled by the caller from the workspace checkpoints, not sent to the model.
+            template: Some("User requested /undo $ARGUMENTS".to_string()),
             agent: None,
             model: None,
             subtask: None,
@@ -61,8 +62,9 @@ fn builtin_commands() -> Vec<CommandDef> {
         CommandDef {
             id: "redo".to_string(),
             label: "redo".to_string(),
-            description: Some("Redo last undone change".to_string()),
-            template: Some("User requested /redo. Reapply the last reverted change.".to_string()),
+            description: Some("Restore files undone by /undo (optional: turn number)".to_string()),
+            // Handled by the caller from the workspace checkpoints, not sent to the model.
+            template: Some("User requested /redo $ARGUMENTS".to_string()),
             agent: None,
             model: None,
             subtask: None,
@@ -71,7 +73,17 @@ fn builtin_commands() -> Vec<CommandDef> {
             id: "share".to_string(),
             label: "share".to_string(),
             description: Some("Share conversation".to_string()),
-            template: Some("User requested /share. Help prepare or export this conversation for sharing.".to_string()),
+            template: Some("User requested /share. Tell them a transcript of this session can be exported with `opencode sessions export <session_id> --format md|html|json [-o <file>]` (the session id is listed by `opencode sessions list`), and offer to summarize the conversation for the share.".to_string()),
+            agent: None,
+            model: None,
+            subtask: None,
+        },
+        CommandDef {
+            id: crate::compaction::COMMAND_ID.to_string(),
+            label: "compact".to_string(),
+            description: Some("Summarize older turns and archive them (optional: turns to keep)".to_string()),
+            // Handled by the caller (see `compaction::compact_session`), not sent to the model.
+            template: Some("User requested /compact $ARGUMENTS".to_string()),
             agent: None,
             model: None,
             subtask: None,
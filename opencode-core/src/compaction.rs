@@ -0,0 +1,190 @@
+//! Persistent compaction (`/compact`): the provider summarizes the older turns of a session, the
+//! summary is stored as a system message at the head of the history and the originals move to
+//! `Session::archived`. Later requests send the summary plus the recent turns; `ContextManager`
+//! always keeps system messages, so the summary survives pruning. Histories too long for one
+//! request are summarized in chunks, each merging the summary of the chunks before it.
+
+use crate::agent::{Message, MessageRole, Provider, ProviderRequest};
+use crate::context::ContextManager;
+use crate::error::{Error, Result};
+use crate::session::{self, MessageMeta, Role, Session};
+use crate::usage::TokenUsage;
+use std::fmt::Write;
+
+/// Id of the built-in `/compact` command.
+pub const COMMAND_ID: &str = "compact";
+
+/// Turns kept verbatim when `/compact` is given no count.
+pub const DEFAULT_KEEP_TURNS: usize = 2;
+
+/// Characters of one message included in the summarization request.
+const MAX_MESSAGE_CHARS: usize = 4000;
+/// Characters of a tool call's JSON arguments included.
+const MAX_ARGUMENT_CHARS: usize = 500;
+/// Tokens of a summarization request besides the prompt and the transcript (message framing).
+const REQUEST_OVERHEAD: usize = 16;
+
+const SUMMARY_PROMPT: &str = "You compact a conversation between a user and a coding assistant. \
+Write a summary that lets the assistant continue the work without the original messages. \
+Use these Markdown sections, leaving out empty ones:\n\
+## Goal\n## Decisions\n## Files and code\n## Open tasks\n## Key facts\n\
+Keep file paths, identifiers, commands and error messages exact. If the conversation starts \
+with an earlier summary, merge it into yours. Reply with the summary only.";
+
+/// Result of a compaction.
+#[derive(Debug, Clone)]
+pub struct Compaction {
+    /// Messages moved to the archive (including an earlier summary).
+    pub archived: usize,
+    /// Messages left after the summary.
+    pub kept: usize,
+    pub usage: Option<TokenUsage>,
+}
+
+/// Summarizes everything but the latest `keep_turns` turns of `session` and replaces it with the
+/// summary. Each summarization request fits the prompt budget of `context`. Returns None, leaving
+/// the session untouched, when there is too little to compact.
+pub async fn compact_session(
+    session: &mut Session,
+    provider: &dyn Provider,
+    model: Option<String>,
+    context: &ContextManager,
+    keep_turns: usize,
+) -> Result<Option<Compaction>> {
+    let Some(end) = session.compaction_point(keep_turns) else {
+        return Ok(None);
+    };
+    let entries: Vec<String> = session.messages[..end].iter().map(entry).collect();
+    let room = context
+        .budget()
+        .saturating_sub(context.count(SUMMARY_PROMPT) + REQUEST_OVERHEAD);
+    let mut summary: Option<String> = None;
+    let mut usage: Option<TokenUsage> = None;
+    let mut next = 0;
+    let mut requests = 0;
+    while next < entries.len() {
+        let mut transcript = summary
+            .as_deref()
+            .map(|s| format!("[Earlier summary]\n{}\n\n", s))
+            .unwrap_or_default();
+        let mut tokens = context.count(&transcript);
+        let first = next;
+        while next < entries.len() {
+            let entry_tokens = context.count(&entries[next]);
+            if tokens + entry_tokens > room {
+                if next > first {
+                    break;
+                }
+                // A single message over the budget is cut to what is left.
+                let left = room.saturating_sub(tokens);
+                let chars = entries[next].chars().count() * left / entry_tokens.max(1);
+                transcript.push_str(&cut(&entries[next], chars));
+                tokens += left;
+            } else {
+                transcript.push_str(&entries[next]);
+                tokens += entry_tokens;
+            }
+            next += 1;
+        }
+
+        let request = ProviderRequest {
+            messages: vec![
+                Message {
+                    role: MessageRole::System,
+                    content: SUMMARY_PROMPT.to_string(),
+                    tool_call_id: None,
+                    tool_calls: Vec::new(),
+                },
+                Message {
+                    role: MessageRole::User,
+                    content: transcript,
+                    tool_call_id: None,
+                    tool_calls: Vec::new(),
+                },
+            ],
+            model: model.clone(),
+            temperature: Some(0.2),
+            max_tokens: None,
+            tools: Vec::new(),
+        };
+        let response = provider.generate(request).await?;
+        let text = response.content.trim();
+        if text.is_empty() {
+            return Err(Error::Provider("empty summary".to_string()));
+        }
+        summary = Some(text.to_string());
+        match (&mut usage, &response.usage) {
+            (Some(total), Some(u)) => total.add(u),
+            (None, Some(u)) => usage = Some(u.clone()),
+            _ => {}
+        }
+        requests += 1;
+    }
+    if requests > 1 {
+        tracing::info!(requests, messages = end, "compaction summarized in chunks");
+    }
+
+    let archived = end;
+    let summary = summary.unwrap_or_default();
+    session.compact(end, summary_message(&summary, archived, usage.clone()));
+    Ok(Some(Compaction {
+        archived,
+        kept: session.messages.len() - 1,
+        usage,
+    }))
+}
+
+/// The system message that stands for `archived` earlier messages.
+pub fn summary_message(
+    summary: &str,
+    archived: usize,
+    usage: Option<TokenUsage>,
+) -> session::Message {
+    session::Message {
+        role: Role::System,
+        content: format!(
+            "Summary of the {} earlier messages of this conversation (archived):\n\n{}",
+            archived, summary
+        ),
+        created_at: chrono::Utc::now(),
+        meta: Some(MessageMeta {
+            summary: true,
+            usage,
+            ..Default::default()
+        }),
+    }
+}
+
+fn cut(text: &str, max_chars: usize) -> String {
+    let mut chars = text.chars();
+    let mut out: String = chars.by_ref().take(max_chars).collect();
+    if chars.next().is_some() {
+        out.push_str(" ... (cut)");
+    }
+    out
+}
+
+/// Plain-text rendering of one message for the summarizer, with long contents cut.
+fn entry(m: &session::Message) -> String {
+    let mut out = String::new();
+    let label = match m.role {
+        Role::System if m.is_summary() => "Earlier summary",
+        Role::System => "System",
+        Role::User => "User",
+        Role::Assistant => "Assistant",
+        Role::Tool => "Tool result",
+    };
+    let _ = write!(out, "[{}]", label);
+    if let Some(name) = m.meta.as_ref().and_then(|meta| meta.tool_name.as_deref()) {
+        let _ = write!(out, " {}", name);
+    }
+    out.push('\n');
+    out.push_str(&cut(&m.content, MAX_MESSAGE_CHARS));
+    out.push('\n');
+    for call in m.meta.iter().flat_map(|meta| &meta.tool_calls) {
+        let arguments = cut(&call.arguments.to_string(), MAX_ARGUMENT_CHARS);
+        let _ = writeln!(out, "(called {} with {})", call.name, arguments);
+    }
+    out.push('\n');
+    out
+}
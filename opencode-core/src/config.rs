@@ -1,4 +1,7 @@
 use crate::error::{Error, Result};
+use crate::model_registry::ModelOverride;
+use crate::permission::{PermissionManager, PermissionRules};
+use crate::usage::ModelPrice;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
@@ -28,9 +31,38 @@ pub struct Config {
     /// Custom commands: name -> { template, description?, agent?, model?, subtask? }.
     #[serde(default)]
     pub command: Option<HashMap<String, CommandOption>>,
-    /// Max agent steps per turn (deep agent). When unset, langchain default (10) is used. Set to e.g. 25 to allow longer runs.
+    /// Max agent steps per turn (deep agent and BuildAgent tool loop). When unset, the default (10) is used. Set to e.g. 25 to allow longer runs.
     #[serde(default)]
     pub max_agent_iterations: Option<i32>,
+    /// Provider ids tried in order when the default provider stays unavailable after retries (e.g. `["ollama"]`).
+    #[serde(default)]
+    pub fallback_providers: Vec<String>,
+    /// Retry policy for transient provider failures (429, 5xx, connection errors).
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Model prices (USD per million tokens) keyed by model name or name prefix, used for cost reports.
+    #[serde(default)]
+    pub pricing: HashMap<String, ModelPrice>,
+    /// Model capability overrides keyed by model name or prefix (see `ModelRegistry`).
+    #[serde(default)]
+    pub models: HashMap<String, ModelOverride>,
+    /// Ask the model for a short session title after the first exchange.
+    #[serde(default)]
+    pub generate_titles: bool,
+    /// Tool permission rules, resource glob -> allow | deny | ask (see `permission`), e.g.
+    /// `{"bash:git push*": "deny", "write:src/**": "allow"}`. Project rules come before global ones.
+    #[serde(default, skip_serializing_if = "PermissionRules::is_empty")]
+    pub permission: PermissionRules,
+    /// Directories outside the workspace the file tools may access (absolute, or relative to the
+    /// workspace), e.g. `["../shared", "/opt/sdk/include"]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_paths: Vec<PathBuf>,
+    /// Timeout, output limits, environment and sandbox of the bash tool.
+    #[serde(default)]
+    pub bash: BashConfig,
+    /// Masking of secrets in tool results, provider requests and logs (see `redact`).
+    #[serde(default)]
+    pub redaction: RedactionConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,12 +75,101 @@ pub struct ProviderConfig {
     pub model: Option<String>,
 }
 
+/// Exponential backoff for transient provider failures. A server `Retry-After` overrides the
+/// computed delay; one longer than `max_backoff_ms` moves on to the next fallback provider.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    pub max_retries: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 30_000,
+        }
+    }
+}
+
+/// How the bash tool runs commands. Commands run in the workspace in their own process group,
+/// which is killed when `timeout_secs` runs out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BashConfig {
+    pub timeout_secs: u64,
+    /// Bytes kept of stdout and of stderr each; longer output keeps its start and end.
+    pub max_output_bytes: usize,
+    /// Environment variables passed to commands (case-insensitive globs); empty passes all.
+    pub env_allow: Vec<String>,
+    /// Environment variables never passed to commands, e.g. API keys.
+    pub env_deny: Vec<String>,
+    pub sandbox: SandboxConfig,
+}
+
+impl Default for BashConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 120,
+            max_output_bytes: 30_000,
+            env_allow: Vec::new(),
+            env_deny: ["*API_KEY*", "*TOKEN*", "*SECRET*", "*PASSWORD*", "AWS_*"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            sandbox: SandboxConfig::default(),
+        }
+    }
+}
+
+/// Optional Linux sandbox of the bash tool.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SandboxConfig {
+    /// Landlock file access policy: system directories and `read_paths` are read-only; the
+    /// workspace, `allowed_paths`, the temp directory and `write_paths` are writable; everything
+    /// else (e.g. the home directory) is inaccessible.
+    pub filesystem: bool,
+    /// Run commands in a network namespace of their own, without network access.
+    pub deny_network: bool,
+    pub read_paths: Vec<PathBuf>,
+    pub write_paths: Vec<PathBuf>,
+}
+
+/// Which secrets are masked before text leaves the machine or reaches the log file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedactionConfig {
+    pub enabled: bool,
+    /// Also mask long random-looking tokens no built-in pattern knows.
+    pub entropy: bool,
+    /// Extra regexes; a match is masked, or only its `secret` group when it has one, e.g.
+    /// `"internal_token=(?P<secret>\\w+)"`.
+    pub patterns: Vec<String>,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            entropy: true,
+            patterns: Vec::new(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentConfig {
     pub name: String,
     pub mode: String,
     pub model: Option<String>,
     pub provider: Option<String>,
+    /// Permission rules of this agent; they take precedence over all others.
+    #[serde(default, skip_serializing_if = "PermissionRules::is_empty")]
+    pub permission: PermissionRules,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -58,6 +179,33 @@ pub struct StorageConfig {
 }
 
 impl Config {
+    /// Built-in model capabilities with this config's `models` and `pricing` applied.
+    pub fn model_registry(&self) -> crate::model_registry::ModelRegistry {
+        crate::model_registry::ModelRegistry::from_config(self)
+    }
+
+    /// Permission rules for tool calls of `agent`: its configured rules, its built-in rules, this
+    /// config's rules, then (for a project config) the global config's rules.
+    pub fn permissions(&self, agent: &str) -> Result<PermissionManager> {
+        let mut permissions = PermissionManager::new();
+        for agent_config in self.agents.iter().filter(|a| a.name == agent) {
+            permissions.extend(&agent_config.permission)?;
+        }
+        permissions.extend(&crate::permission::agent_defaults(agent))?;
+        permissions.extend(&self.permission)?;
+        if let Ok(global_dir) = Self::config_dir() {
+            if global_dir != self.storage.config_dir {
+                let global_file = global_dir.join("config.json");
+                if global_file.exists() {
+                    let content = std::fs::read_to_string(&global_file)?;
+                    let global: Config = serde_json::from_str(&content)?;
+                    permissions.extend(&global.permission)?;
+                }
+            }
+        }
+        Ok(permissions)
+    }
+
     pub fn load() -> Result<Self> {
         let config_dir = Self::config_dir()?;
         let config_file = config_dir.join("config.json");
@@ -122,6 +270,15 @@ impl Config {
             default_agent: None,
             command: None,
             max_agent_iterations: None,
+            fallback_providers: Vec::new(),
+            retry: RetryConfig::default(),
+            pricing: HashMap::new(),
+            models: HashMap::new(),
+            generate_titles: false,
+            permission: PermissionRules::default(),
+            allowed_paths: Vec::new(),
+            bash: BashConfig::default(),
+            redaction: RedactionConfig::default(),
         }
     }
 }
@@ -142,6 +299,15 @@ impl Default for Config {
             default_agent: None,
             command: None,
             max_agent_iterations: None,
+            fallback_providers: Vec::new(),
+            retry: RetryConfig::default(),
+            pricing: HashMap::new(),
+            models: HashMap::new(),
+            generate_titles: false,
+            permission: PermissionRules::default(),
+            allowed_paths: Vec::new(),
+            bash: BashConfig::default(),
+            redaction: RedactionConfig::default(),
         }
     }
 }
@@ -0,0 +1,292 @@
+//! Token-budgeted context window: counts message tokens with a model-appropriate tokenizer and
+//! drops the oldest turns so a request fits the model's context length.
+
+use crate::model_registry::{ModelCapabilities, ModelRegistry};
+use crate::session::{Message, Role};
+use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer};
+use tiktoken_rs::CoreBPE;
+
+/// Per-message formatting overhead (role and separators) in chat formats.
+const MESSAGE_OVERHEAD: usize = 4;
+
+/// Tokens that prime the assistant reply at the end of every request.
+const REPLY_PRIMING: usize = 3;
+
+/// Truncated tool results keep at least this many tokens.
+const MIN_TRUNCATED_TOKENS: usize = 64;
+
+/// Room left for the note appended to a truncated tool result.
+const TRUNCATION_NOTE_TOKENS: usize = 24;
+
+/// BPE encoding used to count tokens. OpenAI models use their own encoding; other models are
+/// counted with cl100k, which is close enough for budgeting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    O200k,
+    Cl100k,
+}
+
+impl Encoding {
+    pub fn for_model(model: &str) -> Self {
+        match get_tokenizer(model_name(model)) {
+            Some(Tokenizer::O200kBase) | Some(Tokenizer::O200kHarmony) => Encoding::O200k,
+            _ => Encoding::Cl100k,
+        }
+    }
+
+    fn bpe(self) -> &'static CoreBPE {
+        match self {
+            Encoding::O200k => tiktoken_rs::o200k_base_singleton(),
+            Encoding::Cl100k => tiktoken_rs::cl100k_base_singleton(),
+        }
+    }
+
+    pub fn count(self, text: &str) -> usize {
+        if text.is_empty() {
+            return 0;
+        }
+        self.bpe().encode_with_special_tokens(text).len()
+    }
+}
+
+/// Model name without a `provider/` routing prefix (e.g. `openai/gpt-4o`).
+fn model_name(model: &str) -> &str {
+    model.rsplit('/').next().unwrap_or(model)
+}
+
+/// History prepared for one request.
+#[derive(Debug, Clone)]
+pub struct FittedContext {
+    pub messages: Vec<Message>,
+    /// Estimated prompt tokens of `messages`.
+    pub tokens: usize,
+    /// Messages left out because they did not fit.
+    pub omitted: usize,
+    /// Tool results that were shortened to fit.
+    pub truncated: usize,
+}
+
+/// Fits session history into a model's context window.
+///
+/// System messages, the latest turn (the last user message and everything after it, including
+/// its tool results) and turns containing a pinned message are always kept. Older turns are
+/// dropped whole, oldest first, so tool results never lose the assistant message that requested
+/// them; a note records how many messages were left out. If the kept messages still do not fit,
+/// the largest tool results of the latest turn are truncated.
+#[derive(Debug, Clone)]
+pub struct ContextManager {
+    encoding: Encoding,
+    context_limit: usize,
+    reserved_output: usize,
+}
+
+impl Default for ContextManager {
+    /// Budget of an unrecognized model (see `ModelCapabilities::default`).
+    fn default() -> Self {
+        Self::new(None, &ModelCapabilities::default())
+    }
+}
+
+impl ContextManager {
+    /// Manager for `model` with its context length, reserving its max output tokens.
+    pub fn new(model: Option<&str>, capabilities: &ModelCapabilities) -> Self {
+        Self {
+            encoding: model.map(Encoding::for_model).unwrap_or(Encoding::Cl100k),
+            context_limit: capabilities.context_length,
+            reserved_output: capabilities.max_output_tokens as usize,
+        }
+    }
+
+    /// Manager for `model` using the built-in capability table.
+    pub fn for_model(model: Option<&str>) -> Self {
+        Self::new(model, &ModelRegistry::builtin().capabilities(model))
+    }
+
+    pub fn with_context_limit(mut self, context_limit: usize) -> Self {
+        self.context_limit = context_limit;
+        self
+    }
+
+    /// Tokens kept free for the reply (the request's `max_tokens`).
+    pub fn with_reserved_output(mut self, reserved_output: usize) -> Self {
+        self.reserved_output = reserved_output;
+        self
+    }
+
+    pub fn context_limit(&self) -> usize {
+        self.context_limit
+    }
+
+    /// Prompt tokens available: the context length minus the reserved output.
+    pub fn budget(&self) -> usize {
+        self.context_limit
+            .saturating_sub(self.reserved_output)
+            .max(MIN_TRUNCATED_TOKENS)
+    }
+
+    pub fn count(&self, text: &str) -> usize {
+        self.encoding.count(text)
+    }
+
+    /// Estimated tokens of one message, including tool call names and arguments.
+    pub fn message_tokens(&self, message: &Message) -> usize {
+        let calls: usize = message
+            .meta
+            .as_ref()
+            .map(|meta| {
+                meta.tool_calls
+                    .iter()
+                    .map(|c| self.count(&c.name) + self.count(&c.arguments.to_string()))
+                    .sum()
+            })
+            .unwrap_or(0);
+        MESSAGE_OVERHEAD + self.count(&message.content) + calls
+    }
+
+    pub fn fit(&self, messages: &[Message]) -> FittedContext {
+        let budget = self.budget();
+        let mut tokens: Vec<usize> = messages.iter().map(|m| self.message_tokens(m)).collect();
+        let mut total = REPLY_PRIMING + tokens.iter().sum::<usize>();
+        let mut keep = vec![true; messages.len()];
+        let mut omitted = 0;
+
+        let latest_turn = messages
+            .iter()
+            .rposition(|m| m.role == Role::User)
+            .unwrap_or(0);
+        let note_tokens = self.message_tokens(&omission_note(messages.len()));
+        for (start, end) in turns(&messages[..latest_turn]) {
+            if total <= budget {
+                break;
+            }
+            if messages[start..end].iter().any(is_pinned) {
+                continue;
+            }
+            let droppable: Vec<usize> = (start..end)
+                .filter(|&i| messages[i].role != Role::System)
+                .collect();
+            if droppable.is_empty() {
+                continue;
+            }
+            if omitted == 0 {
+                total += note_tokens;
+            }
+            for i in droppable {
+                keep[i] = false;
+                total -= tokens[i];
+                omitted += 1;
+            }
+        }
+
+        let mut out: Vec<Message> = Vec::with_capacity(messages.len() + 1);
+        let mut truncated = 0;
+        let mut contents: Vec<Option<String>> = vec![None; messages.len()];
+        if total > budget {
+            let mut tool_results: Vec<usize> = (latest_turn..messages.len())
+                .filter(|&i| messages[i].role == Role::Tool)
+                .collect();
+            tool_results.sort_by(|a, b| tokens[*b].cmp(&tokens[*a]));
+            for i in tool_results {
+                if total <= budget {
+                    break;
+                }
+                let excess = total - budget;
+                let target = tokens[i]
+                    .saturating_sub(excess + TRUNCATION_NOTE_TOKENS)
+                    .max(MIN_TRUNCATED_TOKENS);
+                if target >= tokens[i] {
+                    continue;
+                }
+                let content = truncate_to_tokens(&messages[i].content, tokens[i], target);
+                let new_tokens = self.message_tokens(&Message {
+                    content: content.clone(),
+                    ..messages[i].clone()
+                });
+                total = total - tokens[i] + new_tokens;
+                tokens[i] = new_tokens;
+                contents[i] = Some(content);
+                truncated += 1;
+            }
+        }
+
+        let leading_system = messages
+            .iter()
+            .take_while(|m| m.role == Role::System)
+            .count();
+        for (i, message) in messages.iter().enumerate() {
+            if i == leading_system && omitted > 0 {
+                out.push(omission_note(omitted));
+            }
+            if !keep[i] {
+                continue;
+            }
+            let mut message = message.clone();
+            if let Some(content) = contents[i].take() {
+                message.content = content;
+            }
+            out.push(message);
+        }
+        if omitted > 0 {
+            tracing::info!(
+                omitted,
+                truncated,
+                tokens = total,
+                budget,
+                "context window: pruned older turns"
+            );
+        }
+
+        FittedContext {
+            messages: out,
+            tokens: total,
+            omitted,
+            truncated,
+        }
+    }
+}
+
+fn is_pinned(message: &Message) -> bool {
+    message.meta.as_ref().map(|m| m.pinned).unwrap_or(false)
+}
+
+/// Splits messages into turns, each starting at a user message. Messages before the first user
+/// message form their own turn. Returns `[start, end)` ranges.
+fn turns(messages: &[Message]) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    for (i, message) in messages.iter().enumerate() {
+        if message.role == Role::User && i > start {
+            ranges.push((start, i));
+            start = i;
+        }
+    }
+    if start < messages.len() {
+        ranges.push((start, messages.len()));
+    }
+    ranges
+}
+
+fn omission_note(omitted: usize) -> Message {
+    Message {
+        role: Role::System,
+        content: format!(
+            "[{} earlier messages were omitted to fit the context window.]",
+            omitted
+        ),
+        created_at: chrono::Utc::now(),
+        meta: None,
+    }
+}
+
+/// Keeps roughly the first `target` of `tokens` tokens of `text` (by character share).
+fn truncate_to_tokens(text: &str, tokens: usize, target: usize) -> String {
+    let chars = text.chars().count();
+    let keep = chars * target / tokens.max(1);
+    let mut out: String = text.chars().take(keep).collect();
+    out.push_str(&format!(
+        "\n... (truncated {} of {} characters to fit the context window)",
+        chars - keep,
+        chars
+    ));
+    out
+}
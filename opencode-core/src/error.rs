@@ -26,6 +26,18 @@ pub enum Error {
     #[error("Provider error: {0}")]
     Provider(String),
 
+    /// Transient provider failure (rate limit, 5xx, dropped connection) that may succeed on retry.
+    /// `retry_after` is the delay the server asked for, if any.
+    #[error("Provider unavailable: {message}")]
+    ProviderUnavailable {
+        message: String,
+        retry_after: Option<std::time::Duration>,
+    },
+
+    /// The agent repeated the same failing tool call (see `SessionStateMachine::check_doom_loop`).
+    #[error("Doom loop detected: {0}")]
+    DoomLoop(String),
+
     #[error("Validation error: {0}")]
     Validation(String),
 
@@ -2,14 +2,19 @@ pub mod agent;
 pub mod agent_manager;
 pub mod cache;
 pub mod command;
+pub mod compaction;
 pub mod config;
+pub mod context;
 pub mod error;
 pub mod ids;
+pub mod model_registry;
 pub mod permission;
 pub mod provider_manager;
+pub mod redact;
 pub mod session;
 pub mod session_state;
 pub mod tool;
+pub mod usage;
 
 #[cfg(test)]
 mod tests;
@@ -18,9 +23,16 @@ pub use error::{Error, Result};
 pub use agent::{Agent, AgentMode, BuildAgent, Context, GeneralAgent, PlanAgent};
 pub use agent_manager::AgentManager;
 pub use provider_manager::ProviderManager;
+pub use redact::{OnRedact, Redaction, RedactingTool, Redactor};
 pub use tool::{Tool, ToolContext, ToolResult};
 pub use cache::{Cache, ConcurrentCache};
 pub use ids::SessionId;
-pub use session::{Message, MessageMeta, Role, Session};
-pub use session_state::{SessionState, SessionStateMachine};
+pub use session::{Message, MessageMeta, Role, Session, ToolCall};
+pub use session_state::{
+    OnStateChange, SessionState, SessionStateHandle, SessionStateMachine, SessionStatus,
+};
+pub use context::{ContextManager, FittedContext};
+pub use compaction::{compact_session, Compaction};
 pub use command::{CommandDef, format_input_for_command, list_commands};
+pub use model_registry::{ModelCapabilities, ModelOverride, ModelRegistry};
+pub use usage::{ModelPrice, TokenUsage, UsageReport, UsageTotals};
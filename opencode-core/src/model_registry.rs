@@ -0,0 +1,260 @@
+//! What each model can do: context length, output limit, tool/vision/streaming/reasoning
+//! support, default sampling and pricing. Built-in defaults are keyed by model name prefix and
+//! can be overridden or extended through `Config::models` and `Config::pricing`.
+
+use crate::config::Config;
+use crate::usage::ModelPrice;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelCapabilities {
+    /// Context length in tokens (prompt plus output).
+    pub context_length: usize,
+    pub max_output_tokens: u32,
+    pub tools: bool,
+    pub vision: bool,
+    pub streaming: bool,
+    /// Reasoning ("thinking") model; these usually reject sampling parameters.
+    pub reasoning: bool,
+    /// Temperature sent with requests; None leaves it to the provider.
+    pub temperature: Option<f32>,
+    /// Price in USD per million tokens, when known.
+    pub pricing: Option<ModelPrice>,
+}
+
+impl Default for ModelCapabilities {
+    /// Conservative capabilities for models that are not recognized.
+    fn default() -> Self {
+        Self {
+            context_length: 32_768,
+            max_output_tokens: 4096,
+            tools: true,
+            vision: false,
+            streaming: true,
+            reasoning: false,
+            temperature: Some(0.7),
+            pricing: None,
+        }
+    }
+}
+
+/// Config entry overriding built-in capabilities; unset fields keep the built-in value.
+/// A key that matches no built-in entry exactly adds a model, based on the closest prefix match.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelOverride {
+    pub context_length: Option<usize>,
+    pub max_output_tokens: Option<u32>,
+    pub tools: Option<bool>,
+    pub vision: Option<bool>,
+    pub streaming: Option<bool>,
+    pub reasoning: Option<bool>,
+    pub temperature: Option<f32>,
+    pub pricing: Option<ModelPrice>,
+}
+
+impl ModelOverride {
+    fn apply(&self, caps: &mut ModelCapabilities) {
+        if let Some(v) = self.context_length {
+            caps.context_length = v;
+        }
+        if let Some(v) = self.max_output_tokens {
+            caps.max_output_tokens = v;
+        }
+        if let Some(v) = self.tools {
+            caps.tools = v;
+        }
+        if let Some(v) = self.vision {
+            caps.vision = v;
+        }
+        if let Some(v) = self.streaming {
+            caps.streaming = v;
+        }
+        if let Some(v) = self.reasoning {
+            caps.reasoning = v;
+        }
+        if let Some(v) = self.temperature {
+            caps.temperature = Some(v);
+        }
+        if let Some(ref v) = self.pricing {
+            caps.pricing = Some(v.clone());
+        }
+    }
+}
+
+/// Capabilities by model name or name prefix. Lookups use the exact name, else the longest
+/// matching prefix, else `ModelCapabilities::default()`.
+#[derive(Debug, Clone)]
+pub struct ModelRegistry {
+    models: BTreeMap<String, ModelCapabilities>,
+}
+
+impl ModelRegistry {
+    /// Registry with the built-in model table only.
+    pub fn builtin() -> Self {
+        let models = BUILTIN_MODELS
+            .iter()
+            .map(|m| (m.prefix.to_string(), m.capabilities()))
+            .collect();
+        Self { models }
+    }
+
+    /// Built-in table with `config.models` and `config.pricing` applied.
+    pub fn from_config(config: &Config) -> Self {
+        let mut registry = Self::builtin();
+        for (model, entry) in &config.models {
+            registry.apply(model, entry);
+        }
+        for (model, price) in &config.pricing {
+            registry.apply(
+                model,
+                &ModelOverride {
+                    pricing: Some(price.clone()),
+                    ..Default::default()
+                },
+            );
+        }
+        registry
+    }
+
+    fn apply(&mut self, model: &str, entry: &ModelOverride) {
+        let key = normalize(model);
+        let mut caps = self.get(&key);
+        entry.apply(&mut caps);
+        self.models.insert(key, caps);
+    }
+
+    /// Capabilities for `model`; unknown or unset models get the defaults.
+    pub fn capabilities(&self, model: Option<&str>) -> ModelCapabilities {
+        model.map(|m| self.get(m)).unwrap_or_default()
+    }
+
+    pub fn get(&self, model: &str) -> ModelCapabilities {
+        self.find(model).cloned().unwrap_or_default()
+    }
+
+    /// The entry for `model` (exact or longest prefix), or None when it is not recognized.
+    pub fn find(&self, model: &str) -> Option<&ModelCapabilities> {
+        let name = normalize(model);
+        self.models.get(&name).or_else(|| {
+            self.models
+                .iter()
+                .filter(|(key, _)| name.starts_with(key.as_str()))
+                .max_by_key(|(key, _)| key.len())
+                .map(|(_, caps)| caps)
+        })
+    }
+
+    /// Prices keyed by model name or prefix, for `UsageReport`.
+    pub fn pricing(&self) -> HashMap<String, ModelPrice> {
+        self.models
+            .iter()
+            .filter_map(|(key, caps)| caps.pricing.clone().map(|p| (key.clone(), p)))
+            .collect()
+    }
+}
+
+impl Default for ModelRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+/// Lowercased model name without a `provider/` routing prefix (e.g. `openai/gpt-4o`).
+fn normalize(model: &str) -> String {
+    model.rsplit('/').next().unwrap_or(model).to_lowercase()
+}
+
+struct BuiltinModel {
+    prefix: &'static str,
+    context_length: usize,
+    max_output_tokens: u32,
+    tools: bool,
+    vision: bool,
+    reasoning: bool,
+    /// USD per million tokens: input, output, cached input.
+    price: Option<(f64, f64, f64)>,
+}
+
+impl BuiltinModel {
+    fn capabilities(&self) -> ModelCapabilities {
+        ModelCapabilities {
+            context_length: self.context_length,
+            max_output_tokens: self.max_output_tokens,
+            tools: self.tools,
+            vision: self.vision,
+            streaming: true,
+            reasoning: self.reasoning,
+            temperature: if self.reasoning { None } else { Some(0.7) },
+            pricing: self.price.map(|(input, output, cached)| ModelPrice {
+                input,
+                output,
+                cached_input: Some(cached),
+            }),
+        }
+    }
+}
+
+const fn model(
+    prefix: &'static str,
+    context_length: usize,
+    max_output_tokens: u32,
+    (tools, vision, reasoning): (bool, bool, bool),
+    price: Option<(f64, f64, f64)>,
+) -> BuiltinModel {
+    BuiltinModel {
+        prefix,
+        context_length,
+        max_output_tokens,
+        tools,
+        vision,
+        reasoning,
+        price,
+    }
+}
+
+/// (tools, vision, reasoning)
+const CHAT: (bool, bool, bool) = (true, false, false);
+const VISION: (bool, bool, bool) = (true, true, false);
+const REASONING: (bool, bool, bool) = (true, true, true);
+
+/// Built-in table; prices are list prices and can be overridden in config.
+const BUILTIN_MODELS: &[BuiltinModel] = &[
+    model("gpt-5", 400_000, 128_000, REASONING, Some((1.25, 10.0, 0.125))),
+    model("gpt-5-mini", 400_000, 128_000, REASONING, Some((0.25, 2.0, 0.025))),
+    model("gpt-4.1", 1_047_576, 32_768, VISION, Some((2.0, 8.0, 0.5))),
+    model("gpt-4.1-mini", 1_047_576, 32_768, VISION, Some((0.4, 1.6, 0.1))),
+    model("gpt-4.1-nano", 1_047_576, 32_768, VISION, Some((0.1, 0.4, 0.025))),
+    model("gpt-4o", 128_000, 16_384, VISION, Some((2.5, 10.0, 1.25))),
+    model("gpt-4o-mini", 128_000, 16_384, VISION, Some((0.15, 0.6, 0.075))),
+    model("chatgpt-4o", 128_000, 16_384, (false, true, false), Some((5.0, 15.0, 5.0))),
+    model("gpt-4-turbo", 128_000, 4096, VISION, Some((10.0, 30.0, 10.0))),
+    model("gpt-4-0125", 128_000, 4096, CHAT, Some((10.0, 30.0, 10.0))),
+    model("gpt-4-1106", 128_000, 4096, CHAT, Some((10.0, 30.0, 10.0))),
+    model("gpt-4", 8192, 4096, CHAT, Some((30.0, 60.0, 30.0))),
+    model("gpt-3.5-turbo", 16_385, 4096, CHAT, Some((0.5, 1.5, 0.5))),
+    model("o1", 200_000, 100_000, REASONING, Some((15.0, 60.0, 7.5))),
+    model("o1-mini", 128_000, 65_536, (false, false, true), Some((1.1, 4.4, 0.55))),
+    model("o3", 200_000, 100_000, REASONING, Some((2.0, 8.0, 0.5))),
+    model("o3-mini", 200_000, 100_000, (true, false, true), Some((1.1, 4.4, 0.55))),
+    model("o4-mini", 200_000, 100_000, REASONING, Some((1.1, 4.4, 0.275))),
+    model("claude", 200_000, 8192, VISION, None),
+    model("claude-opus-4", 200_000, 32_000, REASONING, Some((15.0, 75.0, 1.5))),
+    model("claude-sonnet-4", 200_000, 64_000, REASONING, Some((3.0, 15.0, 0.3))),
+    model("claude-3-7-sonnet", 200_000, 64_000, REASONING, Some((3.0, 15.0, 0.3))),
+    model("claude-3-5-sonnet", 200_000, 8192, VISION, Some((3.0, 15.0, 0.3))),
+    model("claude-3-5-haiku", 200_000, 8192, VISION, Some((0.8, 4.0, 0.08))),
+    model("claude-3-opus", 200_000, 4096, VISION, Some((15.0, 75.0, 1.5))),
+    model("claude-3-haiku", 200_000, 4096, VISION, Some((0.25, 1.25, 0.03))),
+    model("gemini", 1_048_576, 8192, VISION, None),
+    model("qwen", 131_072, 8192, CHAT, None),
+    model("qwen-max", 32_768, 8192, CHAT, None),
+    model("qwen-vl", 131_072, 8192, VISION, None),
+    model("deepseek", 131_072, 8192, CHAT, None),
+    model("deepseek-reasoner", 131_072, 32_768, (true, false, true), None),
+    model("llama3", 8192, 4096, (false, false, false), None),
+    model("llama3.1", 131_072, 4096, CHAT, None),
+    model("llama3.2", 131_072, 4096, CHAT, None),
+    model("llama3.3", 131_072, 4096, CHAT, None),
+];
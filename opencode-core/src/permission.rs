@@ -1,43 +1,283 @@
+//! Tool permissions. Every tool call is described by resource strings `<tool>:<detail>` (see
+//! `resources`), e.g. `bash:git push origin main`, `write:src/lib.rs` or
+//! `webfetch:https://example.com`, and checked against glob rules mapped to allow, deny or ask.
+//! The first matching rule wins; a call is denied when any of its resources is. Rules come from
+//! the agent's config entry, the agent's built-in rules (the plan agent is read-only), the project
+//! config and the global config, in that order (see `Config::permissions`).
+//!
+//! Paths in resources are normalized first (`.` and `..` applied, made relative to the
+//! workspace), and calls naming a path outside the workspace and `ToolContext.allowed_paths` are
+//! denied, so `src/../.env` or an absolute path cannot slip past rules written for relative paths.
+//!
+//! Resources no rule matches are allowed. A call whose resources match `ask` rules waits for the
+//! manager's `PermissionPrompt`: the user allows it once, allows those resources for good (the
+//! caller persists the rule) or denies it. Without a prompt nobody can approve it, so it is
+//! denied.
+
 use crate::error::{Error, Result};
+use crate::tool::ToolContext;
+use async_trait::async_trait;
 use globset::{Glob, GlobMatcher};
-use std::collections::HashMap;
+use serde::de::{MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum PermissionAction {
     Allow,
     Deny,
     Ask,
 }
 
+/// Ordered rules: resource glob -> action. Written in config as a JSON object
+/// (`{"bash:git push*": "deny", "write:src/**": "allow"}`) whose entries keep their order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PermissionRules(pub Vec<(String, PermissionAction)>);
+
+impl PermissionRules {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Puts a rule allowing exactly `resource` ahead of the others (an "always" answer).
+    pub fn allow_first(&mut self, resource: &str) {
+        let pattern = globset::escape(resource);
+        self.0.retain(|(p, _)| *p != pattern);
+        self.0.insert(0, (pattern, PermissionAction::Allow));
+    }
+}
+
+impl Serialize for PermissionRules {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (pattern, action) in &self.0 {
+            map.serialize_entry(pattern, action)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for PermissionRules {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct RulesVisitor;
+
+        impl<'de> Visitor<'de> for RulesVisitor {
+            type Value = PermissionRules;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an object mapping resource globs to allow, deny or ask")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> std::result::Result<Self::Value, A::Error> {
+                let mut rules = Vec::new();
+                while let Some(entry) = map.next_entry::<String, PermissionAction>()? {
+                    rules.push(entry);
+                }
+                Ok(PermissionRules(rules))
+            }
+        }
+
+        deserializer.deserialize_map(RulesVisitor)
+    }
+}
+
+/// Tools the plan agent may use; everything else is denied.
+const PLAN_READ_ONLY_TOOLS: &[&str] = &["read", "ls", "list_files", "grep", "codesearch", "glob"];
+
+/// Built-in rules of an agent, applied after its configured rules.
+pub fn agent_defaults(agent: &str) -> PermissionRules {
+    match agent {
+        "plan" => {
+            let mut rules: Vec<(String, PermissionAction)> = PLAN_READ_ONLY_TOOLS
+                .iter()
+                .map(|id| (format!("{}:*", id), PermissionAction::Allow))
+                .collect();
+            rules.push(("*".to_string(), PermissionAction::Deny));
+            PermissionRules(rules)
+        }
+        _ => PermissionRules::default(),
+    }
+}
+
+/// A tool call waiting for the user's approval.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PermissionRequest {
+    pub id: String,
+    pub tool_id: String,
+    /// Resources of the call that matched an `ask` rule.
+    pub resources: Vec<String>,
+    /// The call's arguments, shortened for display.
+    pub arguments: String,
+    /// Unified diff of the change, for tools that edit files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionReply {
+    /// Run this call.
+    Once,
+    /// Run this call and allow its resources from now on.
+    Always,
+    Deny,
+}
+
+/// Asks the user about calls matching `ask` rules.
+#[async_trait]
+pub trait PermissionPrompt: Send + Sync {
+    async fn ask(&self, request: PermissionRequest) -> PermissionReply;
+}
+
+/// Longest `PermissionRequest::arguments` preview, in characters.
+const ARGUMENTS_PREVIEW_LEN: usize = 400;
+
 pub struct PermissionManager {
-    rules: HashMap<String, PermissionAction>,
-    matchers: Vec<(GlobMatcher, PermissionAction)>,
+    /// Pattern, compiled glob and action, in precedence order.
+    matchers: Vec<(String, GlobMatcher, PermissionAction)>,
+    prompt: Option<Arc<dyn PermissionPrompt>>,
+    /// Resources answered "always" while this manager is in use.
+    approved: Mutex<Vec<String>>,
+}
+
+impl fmt::Debug for PermissionManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PermissionManager")
+            .field("rules", &self.matchers.iter().map(|(p, _, a)| (p, a)).collect::<Vec<_>>())
+            .field("prompt", &self.prompt.is_some())
+            .finish()
+    }
 }
 
 impl PermissionManager {
     pub fn new() -> Self {
         Self {
-            rules: HashMap::new(),
             matchers: Vec::new(),
+            prompt: None,
+            approved: Mutex::new(Vec::new()),
         }
     }
 
+    /// Calls matching `ask` rules are put to `prompt`; without one they are denied.
+    pub fn with_prompt(mut self, prompt: Arc<dyn PermissionPrompt>) -> Self {
+        self.prompt = Some(prompt);
+        self
+    }
+
     pub fn add_rule(&mut self, pattern: &str, action: PermissionAction) -> Result<()> {
         let glob = Glob::new(pattern)
             .map_err(|e| Error::Validation(format!("Invalid glob pattern {}: {}", pattern, e)))?;
         let matcher = glob.compile_matcher();
-        self.matchers.push((matcher, action.clone()));
-        self.rules.insert(pattern.to_string(), action);
+        self.matchers.push((pattern.to_string(), matcher, action));
         Ok(())
     }
 
+    /// Appends `rules` after the ones already added (which take precedence).
+    pub fn extend(&mut self, rules: &PermissionRules) -> Result<()> {
+        for (pattern, action) in &rules.0 {
+            self.add_rule(pattern, *action)?;
+        }
+        Ok(())
+    }
+
+    /// Action of the first rule matching `resource`; `Allow` when none matches.
     pub fn check(&self, resource: &str) -> PermissionAction {
-        for (matcher, action) in &self.matchers {
-            if matcher.is_match(resource) {
-                return action.clone();
+        self.rule(resource).unwrap_or(PermissionAction::Allow)
+    }
+
+    /// Action of the first rule matching `resource`.
+    fn rule(&self, resource: &str) -> Option<PermissionAction> {
+        self.matchers
+            .iter()
+            .find(|(_, matcher, _)| matcher.is_match(resource))
+            .map(|(_, _, action)| *action)
+    }
+
+    /// True when every call of `tool_id` is denied: a rule denying `<tool>:` (e.g. `<tool>:*` or
+    /// `*`) applies before any rule allowing part of it. Such tools are not offered to the agent.
+    pub fn denies_tool(&self, tool_id: &str) -> bool {
+        let prefix = format!("{}:", tool_id);
+        for (pattern, matcher, action) in &self.matchers {
+            if matcher.is_match(&prefix) {
+                return *action == PermissionAction::Deny;
+            }
+            if pattern.starts_with(&prefix) && *action != PermissionAction::Deny {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// Checks all resources of a call. Fails with `Error::PermissionDenied` when one is denied;
+    /// otherwise returns `Ask` when one matches an `ask` rule and was not approved yet, else `Allow`.
+    pub fn authorize(
+        &self,
+        tool_id: &str,
+        args: &Value,
+        workspace: Option<&str>,
+    ) -> Result<PermissionAction> {
+        Ok(if self.pending(tool_id, args, workspace)?.is_empty() {
+            PermissionAction::Allow
+        } else {
+            PermissionAction::Ask
+        })
+    }
+
+    /// Like `authorize`, but puts calls that need approval to the prompt and waits for the answer.
+    pub async fn request(&self, tool_id: &str, args: &Value, workspace: Option<&str>) -> Result<()> {
+        let pending = self.pending(tool_id, args, workspace)?;
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let Some(prompt) = &self.prompt else {
+            tracing::warn!(tool_id, "no permission prompt; denying a call that needs approval");
+            return Err(Error::PermissionDenied(format!(
+                "{} (needs approval, but nobody can be asked)",
+                pending.join(", ")
+            )));
+        };
+        let request = PermissionRequest {
+            id: uuid::Uuid::new_v4().to_string(),
+            tool_id: tool_id.to_string(),
+            resources: pending.clone(),
+            arguments: arguments_preview(args),
+            diff: diff_preview(tool_id, args, workspace).await,
+        };
+        match prompt.ask(request).await {
+            PermissionReply::Once => Ok(()),
+            PermissionReply::Always => {
+                self.approved_resources().extend(pending);
+                Ok(())
             }
+            PermissionReply::Deny => Err(Error::PermissionDenied(pending.join(", "))),
         }
-        PermissionAction::Ask
+    }
+
+    /// Resources of a call that need approval; fails when one is denied.
+    fn pending(&self, tool_id: &str, args: &Value, workspace: Option<&str>) -> Result<Vec<String>> {
+        let mut pending = Vec::new();
+        for resource in resources(tool_id, args, workspace) {
+            match self.rule(&resource) {
+                Some(PermissionAction::Deny) => return Err(Error::PermissionDenied(resource)),
+                Some(PermissionAction::Ask) if !self.approved_resources().contains(&resource) => {
+                    pending.push(resource)
+                }
+                _ => {}
+            }
+        }
+        Ok(pending)
+    }
+
+    fn approved_resources(&self) -> std::sync::MutexGuard<'_, Vec<String>> {
+        self.approved.lock().unwrap_or_else(|e| e.into_inner())
     }
 }
 
@@ -46,3 +286,258 @@ impl Default for PermissionManager {
         Self::new()
     }
 }
+
+/// Checks a tool call against the permissions in `ctx`, asking the user when a rule says so;
+/// calls without permissions pass. Calls naming a path outside the workspace and the allowed
+/// directories are denied.
+pub async fn authorize(tool_id: &str, args: &Value, ctx: &ToolContext) -> Result<()> {
+    match &ctx.permissions {
+        Some(permissions) => {
+            confine(tool_id, args, ctx)?;
+            permissions
+                .request(tool_id, args, ctx.workspace_path.as_deref())
+                .await
+        }
+        None => Ok(()),
+    }
+}
+
+/// Fails with `Error::PermissionDenied` when a path of the call lies outside the workspace (the
+/// current directory when unset) and `ctx.allowed_paths`.
+fn confine(tool_id: &str, args: &Value, ctx: &ToolContext) -> Result<()> {
+    let workspace = ctx.workspace_path.as_deref();
+    let Some(root) = workspace_root(workspace) else {
+        return Ok(());
+    };
+    let allowed: Vec<PathBuf> = ctx
+        .allowed_paths
+        .iter()
+        .map(|dir| normalize(&root.join(dir)))
+        .collect();
+    for path in call_paths(tool_id, args) {
+        if path.trim().is_empty() {
+            continue;
+        }
+        let full = absolute_path(&path, workspace);
+        if !full.starts_with(&root) && !allowed.iter().any(|dir| full.starts_with(dir)) {
+            return Err(Error::PermissionDenied(format!(
+                "{} is outside the workspace",
+                path
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Resource strings of a tool call: `<tool>:<detail>`, with paths normalized and relative to
+/// `workspace` (the current directory when None) when inside it. A patch yields one resource
+/// per file; unknown tools yield `<tool>:`.
+pub fn resources(tool_id: &str, args: &Value, workspace: Option<&str>) -> Vec<String> {
+    let paths = call_paths(tool_id, args);
+    if !paths.is_empty() {
+        return paths
+            .iter()
+            .map(|path| format!("{}:{}", tool_id, relative_path(path, workspace)))
+            .collect();
+    }
+    let str_arg = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|k| args.get(*k).and_then(Value::as_str))
+            .unwrap_or_default()
+            .to_string()
+    };
+    let detail = match tool_id {
+        "bash" => str_arg(&["command"]).trim().to_string(),
+        "webfetch" => str_arg(&["url"]),
+        "websearch" | "codesearch" => str_arg(&["query"]),
+        "task" => str_arg(&["description"]),
+        "todo" => str_arg(&["action"]),
+        "batch" => args
+            .get("commands")
+            .and_then(Value::as_array)
+            .map(|commands| {
+                commands
+                    .iter()
+                    .filter_map(|c| c.get("tool").and_then(Value::as_str))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .unwrap_or_default(),
+        _ => String::new(),
+    };
+    vec![format!("{}:{}", tool_id, detail)]
+}
+
+/// Paths a call of `tool_id` reads or writes, as the model gave them (empty when missing).
+fn call_paths(tool_id: &str, args: &Value) -> Vec<String> {
+    let str_arg = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|k| args.get(*k).and_then(Value::as_str))
+            .unwrap_or_default()
+            .to_string()
+    };
+    match tool_id {
+        "read" | "write" | "edit" | "ls" | "list_files" | "grep" => {
+            vec![str_arg(&["path", "file_path"])]
+        }
+        "multiedit" | "lsp" => vec![str_arg(&["file_path", "path"])],
+        "glob" => vec![str_arg(&["base_path", "path"])],
+        "patch" => patch_files(&str_arg(&["patch_text"])),
+        _ => Vec::new(),
+    }
+}
+
+/// `path` normalized and relative to the workspace when it lies inside it (`.` for the root
+/// itself); otherwise the normalized absolute path.
+fn relative_path(path: &str, workspace: Option<&str>) -> String {
+    if path.trim().is_empty() {
+        return String::new();
+    }
+    let full = absolute_path(path, workspace);
+    if let Some(rest) = workspace_root(workspace).and_then(|root| {
+        full.strip_prefix(root).ok().map(Path::to_path_buf)
+    }) {
+        return if rest.as_os_str().is_empty() {
+            ".".to_string()
+        } else {
+            rest.to_string_lossy().into_owned()
+        };
+    }
+    full.to_string_lossy().into_owned()
+}
+
+/// The directory relative paths are taken from: `workspace`, else the current directory.
+fn workspace_root(workspace: Option<&str>) -> Option<PathBuf> {
+    match workspace {
+        Some(root) => Some(normalize(Path::new(root))),
+        None => std::env::current_dir().ok().map(|dir| normalize(&dir)),
+    }
+}
+
+/// `path` joined to the workspace root and normalized.
+fn absolute_path(path: &str, workspace: Option<&str>) -> PathBuf {
+    let path = Path::new(path.trim());
+    match workspace_root(workspace) {
+        Some(root) => normalize(&root.join(path)),
+        None => normalize(path),
+    }
+}
+
+/// `path` with `.` and `..` applied lexically; symlinks are not followed. `..` does not go above
+/// the root of an absolute path.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if normalized.file_name().is_some() {
+                    normalized.pop();
+                } else if !normalized.has_root() {
+                    normalized.push("..");
+                }
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Files named by the `---`/`+++` headers of a unified diff (`a/`/`b/` prefixes removed).
+fn patch_files(patch: &str) -> Vec<String> {
+    let mut files: Vec<String> = Vec::new();
+    for line in patch.lines() {
+        let Some(name) = line
+            .strip_prefix("+++ ")
+            .or_else(|| line.strip_prefix("--- "))
+        else {
+            continue;
+        };
+        let name = name.split('\t').next().unwrap_or_default().trim();
+        if name == "/dev/null" || name.is_empty() {
+            continue;
+        }
+        let name = name
+            .strip_prefix("a/")
+            .or_else(|| name.strip_prefix("b/"))
+            .unwrap_or(name)
+            .to_string();
+        if !files.contains(&name) {
+            files.push(name);
+        }
+    }
+    files
+}
+
+fn arguments_preview(args: &Value) -> String {
+    let text = args.to_string();
+    if text.chars().count() > ARGUMENTS_PREVIEW_LEN {
+        let cut: String = text.chars().take(ARGUMENTS_PREVIEW_LEN).collect();
+        format!("{}…", cut)
+    } else {
+        text
+    }
+}
+
+/// The change a file-editing call would make, as a unified diff.
+async fn diff_preview(tool_id: &str, args: &Value, workspace: Option<&str>) -> Option<String> {
+    let str_arg = |key: &str| args.get(key).and_then(Value::as_str);
+    let (path, new) = match tool_id {
+        "patch" => return str_arg("patch_text").map(str::to_string),
+        "write" => {
+            let path = str_arg("path")?;
+            (path, str_arg("content")?.to_string())
+        }
+        "edit" => {
+            let path = str_arg("path")?;
+            let mut lines: Vec<String> = read_file(path, workspace)
+                .await
+                .lines()
+                .map(str::to_string)
+                .collect();
+            let mut edits = args.get("edits")?.as_array()?.clone();
+            edits.sort_by_key(|e| e.get("start_line").and_then(Value::as_u64));
+            for edit in edits.iter().rev() {
+                let line = |key: &str| edit.get(key).and_then(Value::as_u64).map(|n| n as usize);
+                let (start, end) = (line("start_line")?, line("end_line")?);
+                if start > end || end > lines.len() {
+                    return None;
+                }
+                let content = edit.get("content").and_then(Value::as_str).unwrap_or_default();
+                lines.splice(start..end, content.lines().map(str::to_string));
+            }
+            (path, lines.join("\n"))
+        }
+        "multiedit" => {
+            let path = str_arg("file_path")?;
+            let mut content = read_file(path, workspace).await;
+            for edit in args.get("edits")?.as_array()? {
+                let text = |key: &str| edit.get(key).and_then(Value::as_str).unwrap_or_default();
+                content = if edit.get("replace_all").and_then(Value::as_bool) == Some(true) {
+                    content.replace(text("old_string"), text("new_string"))
+                } else {
+                    content.replacen(text("old_string"), text("new_string"), 1)
+                };
+            }
+            (path, content)
+        }
+        _ => return None,
+    };
+    let old = read_file(path, workspace).await;
+    let name = relative_path(path, workspace);
+    let diff = similar::TextDiff::from_lines(&old, &new)
+        .unified_diff()
+        .header(&format!("a/{}", name), &format!("b/{}", name))
+        .to_string();
+    Some(diff)
+}
+
+/// Contents of `path` (relative to `workspace` unless absolute); empty when it lies outside the
+/// workspace or cannot be read.
+async fn read_file(path: &str, workspace: Option<&str>) -> String {
+    let full = absolute_path(path, workspace);
+    if !workspace_root(workspace).is_some_and(|root| full.starts_with(root)) {
+        return String::new();
+    }
+    tokio::fs::read_to_string(full).await.unwrap_or_default()
+}
@@ -0,0 +1,366 @@
+//! Secret detection and redaction. A `Redactor` finds API keys, private keys, credentials in
+//! URLs, headers and assignments, high-entropy tokens and the custom patterns of
+//! `Config.redaction`, and masks each as `[REDACTED:<detector>]`. It is applied to tool results
+//! (`RedactingTool`), to every provider request and to the log file, so secrets read from the
+//! workspace do not leave the machine.
+
+use crate::config::RedactionConfig;
+use crate::error::{Error, Result};
+use crate::tool::{Tool, ToolContext, ToolResult};
+use async_trait::async_trait;
+use regex::{Captures, Regex};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Start of every mask; text already masked is never matched again.
+const MASK_PREFIX: &str = "[REDACTED:";
+
+/// Detector name of the patterns in `RedactionConfig.patterns`.
+const CUSTOM_DETECTOR: &str = "custom";
+
+/// Detector name of random-looking tokens.
+const ENTROPY_DETECTOR: &str = "high_entropy";
+
+/// Shannon entropy (bits per character) above which a long token counts as random.
+const ENTROPY_THRESHOLD: f64 = 4.0;
+
+/// Built-in detectors by name. When a pattern has a `secret` group only that part is masked.
+const BUILTIN_PATTERNS: &[(&str, &str)] = &[
+    (
+        "private_key",
+        r"(?s)-----BEGIN [A-Z0-9 ]*PRIVATE KEY(?: BLOCK)?-----.*?(?:-----END [A-Z0-9 ]*PRIVATE KEY(?: BLOCK)?-----|\z)",
+    ),
+    ("anthropic_api_key", r"\bsk-ant-[A-Za-z0-9_-]{20,}"),
+    (
+        "openai_api_key",
+        r"\bsk-(?:proj-|svcacct-)?[A-Za-z0-9_-]{20,}",
+    ),
+    ("stripe_key", r"\b[rs]k_(?:live|test)_[A-Za-z0-9]{16,}"),
+    ("aws_access_key", r"\b(?:AKIA|ASIA)[A-Z0-9]{16}\b"),
+    (
+        "github_token",
+        r"\b(?:gh[pousr]_[A-Za-z0-9]{36,}|github_pat_[A-Za-z0-9_]{22,})",
+    ),
+    ("gitlab_token", r"\bglpat-[A-Za-z0-9_-]{20,}"),
+    ("slack_token", r"\bxox[abposr]-[A-Za-z0-9-]{10,}"),
+    ("google_api_key", r"\bAIza[A-Za-z0-9_-]{35}"),
+    (
+        "jwt",
+        r"\beyJ[A-Za-z0-9_-]{8,}\.eyJ[A-Za-z0-9_-]{8,}\.[A-Za-z0-9_-]{8,}",
+    ),
+    (
+        "bearer_token",
+        r"(?i)\bbearer\s+(?P<secret>[A-Za-z0-9._~+/-]{16,}=*)",
+    ),
+    (
+        "url_credentials",
+        r"\b[a-zA-Z][a-zA-Z0-9+.-]*://[^/\s:@]+:(?P<secret>[^/\s@]+)@",
+    ),
+    (
+        "assigned_secret",
+        r#"(?i)\b[a-z0-9_.-]*(?:api[_-]?key|secret|token|passw(?:or)?d|access[_-]?key|private[_-]?key)[a-z0-9_]*["']?\s*[:=]\s*["']?(?P<secret>[A-Za-z0-9+/=_.~!@#$%^&*-]{8,})"#,
+    ),
+];
+
+/// Tokens checked for entropy: long runs without path separators, so file paths stay readable.
+const ENTROPY_TOKEN: &str = r"[A-Za-z0-9+_-]{32,}={0,2}";
+
+/// Secrets masked in one text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redaction {
+    /// What the text was, e.g. "read output".
+    pub source: String,
+    /// Detector of each masked secret.
+    pub detectors: Vec<String>,
+}
+
+impl fmt::Display for Redaction {
+    /// "Redacted 2 secrets (aws_access_key, private_key) from read output".
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for detector in &self.detectors {
+            *counts.entry(detector).or_default() += 1;
+        }
+        let names: Vec<String> = counts
+            .iter()
+            .map(|(name, n)| match n {
+                1 => name.to_string(),
+                n => format!("{} ×{}", name, n),
+            })
+            .collect();
+        write!(
+            f,
+            "Redacted {} secret{} ({}) from {}",
+            self.detectors.len(),
+            if self.detectors.len() == 1 { "" } else { "s" },
+            names.join(", "),
+            self.source
+        )
+    }
+}
+
+/// Called with every redaction `Redactor::mask` makes (or `Redactor::report` finds), e.g. to show
+/// it in the session log.
+pub type OnRedact = Arc<dyn Fn(&Redaction) + Send + Sync>;
+
+struct Detector {
+    name: String,
+    regex: Regex,
+    /// Only mask values mixing letters and digits (keeps `token = next_token()` readable).
+    mixed_only: bool,
+}
+
+pub struct Redactor {
+    detectors: Vec<Detector>,
+    entropy: Option<Regex>,
+    on_redact: Option<OnRedact>,
+}
+
+impl Redactor {
+    /// Redactor with the built-in detectors and the custom patterns of `config`. A disabled
+    /// config masks nothing. Fails with `Error::Config` on an invalid pattern.
+    pub fn new(config: &RedactionConfig) -> Result<Self> {
+        if !config.enabled {
+            return Ok(Self {
+                detectors: Vec::new(),
+                entropy: None,
+                on_redact: None,
+            });
+        }
+        let mut detectors: Vec<Detector> = BUILTIN_PATTERNS
+            .iter()
+            .map(|(name, pattern)| Detector {
+                name: name.to_string(),
+                regex: Regex::new(pattern).expect("built-in redaction pattern"),
+                mixed_only: *name == "assigned_secret",
+            })
+            .collect();
+        for pattern in &config.patterns {
+            let regex = Regex::new(pattern).map_err(|e| {
+                Error::Config(format!("Invalid redaction pattern {}: {}", pattern, e))
+            })?;
+            detectors.push(Detector {
+                name: CUSTOM_DETECTOR.to_string(),
+                regex,
+                mixed_only: false,
+            });
+        }
+        let entropy = config
+            .entropy
+            .then(|| Regex::new(ENTROPY_TOKEN).expect("entropy token pattern"));
+        Ok(Self {
+            detectors,
+            entropy,
+            on_redact: None,
+        })
+    }
+
+    /// Reports the redactions of `mask` to `on_redact`.
+    pub fn with_reporter(mut self, on_redact: OnRedact) -> Self {
+        self.on_redact = Some(on_redact);
+        self
+    }
+
+    /// `text` with its secrets masked, and the detector of each masked secret.
+    pub fn redact(&self, text: &str) -> (String, Vec<String>) {
+        let mut found = Vec::new();
+        let mut text = text.to_string();
+        for detector in &self.detectors {
+            if detector.regex.is_match(&text) {
+                text = detector.apply(&text, &mut found);
+            }
+        }
+        if let Some(entropy) = &self.entropy {
+            text = entropy
+                .replace_all(&text, |caps: &Captures| {
+                    let token = &caps[0];
+                    if looks_random(token) {
+                        found.push(ENTROPY_DETECTOR.to_string());
+                        mask(ENTROPY_DETECTOR)
+                    } else {
+                        token.to_string()
+                    }
+                })
+                .into_owned();
+        }
+        (text, found)
+    }
+
+    /// `text` with its secrets masked; the redaction is reported as coming from `source`.
+    pub fn mask(&self, source: &str, text: &str) -> String {
+        let (masked, detectors) = self.redact(text);
+        self.notify(source, detectors);
+        masked
+    }
+
+    /// Reports the secrets in `text` as coming from `source` without masking anything: for text
+    /// kept as is locally and masked later on its way out (e.g. the user's message, which the
+    /// provider adapter masks in the request).
+    pub fn report(&self, source: &str, text: &str) {
+        let (_, detectors) = self.redact(text);
+        self.notify(source, detectors);
+    }
+
+    fn notify(&self, source: &str, detectors: Vec<String>) {
+        if detectors.is_empty() {
+            return;
+        }
+        let redaction = Redaction {
+            source: source.to_string(),
+            detectors,
+        };
+        tracing::info!(
+            source,
+            count = redaction.detectors.len(),
+            "secrets redacted"
+        );
+        if let Some(on_redact) = &self.on_redact {
+            on_redact(&redaction);
+        }
+    }
+
+    /// Masks every string in `value` without reporting.
+    pub fn redact_value(&self, value: &mut Value) {
+        match value {
+            Value::String(s) => {
+                let (masked, found) = self.redact(s);
+                if !found.is_empty() {
+                    *s = masked;
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|v| self.redact_value(v)),
+            Value::Object(map) => map.values_mut().for_each(|v| self.redact_value(v)),
+            _ => {}
+        }
+    }
+}
+
+impl Detector {
+    fn apply(&self, text: &str, found: &mut Vec<String>) -> String {
+        self.regex
+            .replace_all(text, |caps: &Captures| {
+                let whole = caps.get(0).expect("whole match");
+                let secret = caps.name("secret").unwrap_or(whole);
+                let value = secret.as_str();
+                if value.contains(MASK_PREFIX)
+                    || (self.mixed_only && !mixes_letters_and_digits(value))
+                {
+                    return whole.as_str().to_string();
+                }
+                found.push(self.name.clone());
+                format!(
+                    "{}{}{}",
+                    &text[whole.start()..secret.start()],
+                    mask(&self.name),
+                    &text[secret.end()..whole.end()]
+                )
+            })
+            .into_owned()
+    }
+}
+
+fn mask(detector: &str) -> String {
+    format!("{}{}]", MASK_PREFIX, detector)
+}
+
+fn mixes_letters_and_digits(value: &str) -> bool {
+    value.chars().any(|c| c.is_ascii_alphabetic()) && value.chars().any(|c| c.is_ascii_digit())
+}
+
+/// Upper and lower case letters and digits with high entropy: hashes (hex), UUIDs and long
+/// identifiers do not qualify.
+fn looks_random(token: &str) -> bool {
+    if !(token.chars().any(|c| c.is_ascii_uppercase())
+        && token.chars().any(|c| c.is_ascii_lowercase())
+        && token.chars().any(|c| c.is_ascii_digit()))
+    {
+        return false;
+    }
+    let mut counts: BTreeMap<char, usize> = BTreeMap::new();
+    for c in token.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+    let len = token.chars().count() as f64;
+    let entropy: f64 = counts
+        .values()
+        .map(|&n| {
+            let p = n as f64 / len;
+            -p * p.log2()
+        })
+        .sum();
+    entropy > ENTROPY_THRESHOLD
+}
+
+/// Tools that write files; they refuse arguments with a mask, which the model could only have
+/// copied from masked output and which would replace the real secret on disk.
+const WRITING_TOOLS: &[&str] = &["write", "edit", "multiedit", "patch"];
+
+/// True when a string in `value` contains a mask.
+fn contains_mask(value: &Value) -> bool {
+    match value {
+        Value::String(s) => s.contains(MASK_PREFIX),
+        Value::Array(items) => items.iter().any(contains_mask),
+        Value::Object(map) => map.values().any(contains_mask),
+        _ => false,
+    }
+}
+
+/// A tool whose results are masked by a `Redactor` before the agent (and the provider) sees them.
+/// Secrets in the output are reported; the title, metadata and error messages are masked silently.
+/// File-writing tools reject arguments that contain a mask.
+pub struct RedactingTool {
+    tool: Arc<dyn Tool>,
+    redactor: Arc<Redactor>,
+}
+
+impl RedactingTool {
+    pub fn wrap(tool: Arc<dyn Tool>, redactor: Arc<Redactor>) -> Arc<dyn Tool> {
+        Arc::new(Self { tool, redactor })
+    }
+}
+
+#[async_trait]
+impl Tool for RedactingTool {
+    fn id(&self) -> &str {
+        self.tool.id()
+    }
+
+    fn description(&self) -> &str {
+        self.tool.description()
+    }
+
+    fn parameters(&self) -> Value {
+        self.tool.parameters()
+    }
+
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolResult> {
+        if WRITING_TOOLS.contains(&self.tool.id()) && contains_mask(&args) {
+            return Err(Error::Tool(format!(
+                "The {} call contains {}…] text: secrets in tool output are masked, so writing it \
+                 would replace the real value. Leave the lines with masked secrets unchanged \
+                 (edit around them) or ask the user to change them.",
+                self.tool.id(),
+                MASK_PREFIX
+            )));
+        }
+        match self.tool.execute(args, ctx).await {
+            Ok(mut result) => {
+                let source = format!("{} output", self.tool.id());
+                result.output = self.redactor.mask(&source, &result.output);
+                result.title = self.redactor.redact(&result.title).0;
+                self.redactor.redact_value(&mut result.metadata);
+                Ok(result)
+            }
+            Err(e) => {
+                let (message, found) = self.redactor.redact(&e.to_string());
+                if found.is_empty() {
+                    Err(e)
+                } else {
+                    Err(Error::Tool(message))
+                }
+            }
+        }
+    }
+}
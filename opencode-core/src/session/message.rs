@@ -1,7 +1,9 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 use super::role::Role;
+use crate::usage::TokenUsage;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
@@ -9,14 +11,45 @@ pub struct Message {
     pub content: String,
     pub created_at: DateTime<Utc>,
 
-    /// Optional metadata for inspection/debugging only.
-    /// Never used for control flow.
+    /// Optional metadata: tool call linkage (replayed to the provider on the next request)
+    /// plus fields for inspection/debugging.
     #[serde(default)]
     pub meta: Option<MessageMeta>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct MessageMeta {
     pub tool_name: Option<String>,
     pub tool_call_id: Option<String>,
+    /// Tool calls requested by an assistant message; each is answered by a `Role::Tool` message
+    /// whose `tool_call_id` matches.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// Token usage of the model response that produced this (assistant) message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>,
+    /// Pinned messages keep their turn in the context window when older turns are pruned.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// Set on the system message written by `/compact` in place of the archived turns.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub summary: bool,
+}
+
+impl Message {
+    /// Whether this is a compaction summary (see `Session::compact`).
+    pub fn is_summary(&self) -> bool {
+        self.meta.as_ref().is_some_and(|m| m.summary)
+    }
+}
+
+/// A single tool invocation requested by the model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// Provider-assigned id, echoed back in the matching tool result.
+    pub id: String,
+    /// Tool id (see `Tool::id`).
+    pub name: String,
+    /// Parsed JSON arguments.
+    pub arguments: Value,
 }
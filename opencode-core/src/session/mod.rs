@@ -1,7 +1,9 @@
 pub mod message;
 pub mod role;
+pub mod schema;
 pub mod session;
 
-pub use message::{Message, MessageMeta};
+pub use message::{Message, MessageMeta, ToolCall};
 pub use role::Role;
-pub use session::Session;
+pub use schema::{SchemaError, SCHEMA_VERSION};
+pub use session::{display_title, Session};
@@ -0,0 +1,126 @@
+//! Versioned session schema. Stored sessions carry `schema_version`; documents without it are
+//! version 0. `migrate_session` upgrades a whole session document and `migrate_message` a single
+//! message (session logs record the version of every append, so messages are upgraded from the
+//! version they were written with). Each change to the data model that older files cannot be
+//! read with adds a step to `MIGRATIONS` and bumps `SCHEMA_VERSION`.
+
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+/// Version written by this build.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Error, Debug)]
+pub enum SchemaError {
+    #[error("schema version {0} is newer than this build supports ({SCHEMA_VERSION})")]
+    TooNew(u32),
+
+    #[error("{0}")]
+    Invalid(String),
+}
+
+/// One upgrade step from `from` to `from + 1`.
+struct Migration {
+    from: u32,
+    /// Rewrites the session fields (everything but the messages).
+    session: fn(&mut Map<String, Value>),
+    message: fn(&mut Map<String, Value>),
+}
+
+const MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    session: session_v0_to_v1,
+    message: message_v0_to_v1,
+}];
+
+/// Version 0 includes the early format with `project_id`, `directory` and a generated
+/// "New session - <time>" title on the session, and `id` and `timestamp` instead of `created_at`
+/// on messages.
+fn session_v0_to_v1(session: &mut Map<String, Value>) {
+    session.remove("project_id");
+    session.remove("directory");
+    let placeholder = session
+        .get("title")
+        .and_then(Value::as_str)
+        .is_some_and(|t| t.starts_with("New session - "));
+    if placeholder {
+        session.remove("title");
+    }
+}
+
+fn message_v0_to_v1(message: &mut Map<String, Value>) {
+    message.remove("id");
+    if let Some(timestamp) = message.remove("timestamp") {
+        message.entry("created_at").or_insert(timestamp);
+    }
+    // Roles were matched case-insensitively by older readers.
+    if let Some(Value::String(role)) = message.get_mut("role") {
+        let mut chars = role.chars();
+        if let Some(first) = chars.next() {
+            *role = first
+                .to_uppercase()
+                .chain(chars.flat_map(char::to_lowercase))
+                .collect();
+        }
+    }
+}
+
+/// `schema_version` of a session document or session record (0 when missing).
+pub fn version_of(fields: &Map<String, Value>) -> Result<u32, SchemaError> {
+    match fields.get("schema_version") {
+        None | Some(Value::Null) => Ok(0),
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| SchemaError::Invalid(format!("invalid schema_version {}", v))),
+    }
+}
+
+/// Fails for versions this build cannot read.
+pub fn check_version(version: u32) -> Result<(), SchemaError> {
+    if version > SCHEMA_VERSION {
+        return Err(SchemaError::TooNew(version));
+    }
+    Ok(())
+}
+
+/// Upgrades session fields (without messages) from their `schema_version` to `SCHEMA_VERSION`.
+/// Returns the version they had.
+pub fn migrate_session_fields(fields: &mut Map<String, Value>) -> Result<u32, SchemaError> {
+    let from = version_of(fields)?;
+    check_version(from)?;
+    for step in MIGRATIONS.iter().filter(|m| m.from >= from) {
+        (step.session)(fields);
+    }
+    fields.insert("schema_version".to_string(), Value::from(SCHEMA_VERSION));
+    Ok(from)
+}
+
+/// Upgrades one message written at version `from`.
+pub fn migrate_message(message: &mut Value, from: u32) -> Result<(), SchemaError> {
+    check_version(from)?;
+    let Value::Object(fields) = message else {
+        return Err(SchemaError::Invalid("message is not an object".to_string()));
+    };
+    for step in MIGRATIONS.iter().filter(|m| m.from >= from) {
+        (step.message)(fields);
+    }
+    Ok(())
+}
+
+/// Upgrades a whole session document (session fields with `messages` and `archived`) in place.
+/// Returns the version it had.
+pub fn migrate_session(document: &mut Value) -> Result<u32, SchemaError> {
+    let Value::Object(fields) = document else {
+        return Err(SchemaError::Invalid("session is not an object".to_string()));
+    };
+    let from = migrate_session_fields(fields)?;
+    for key in ["messages", "archived"] {
+        if let Some(Value::Array(messages)) = fields.get_mut(key) {
+            for message in messages {
+                migrate_message(message, from)?;
+            }
+        }
+    }
+    Ok(from)
+}
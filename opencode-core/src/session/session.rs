@@ -3,25 +3,55 @@ use serde::{Deserialize, Serialize};
 
 use crate::ids::SessionId;
 use super::message::Message;
+use super::role::Role;
+use super::schema::SCHEMA_VERSION;
 
 /// Pure data representation of a session
 /// Contains only essential fields for serialization and inspection
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
+    /// Format version (see `schema`); 0 for documents written before it existed.
+    #[serde(default)]
+    pub schema_version: u32,
     pub id: SessionId,
     pub messages: Vec<Message>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Session this one was forked from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_session_id: Option<SessionId>,
+    /// Number of parent messages copied into this session when it was forked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forked_at_index: Option<usize>,
+    /// User-given or generated title; see `display_title` for the fallback.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Pinned sessions are listed first.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// Messages replaced by a summary through `compact`, oldest first. Kept for the record; they
+    /// are not sent to the model.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub archived: Vec<Message>,
 }
 
 impl Session {
     pub fn new() -> Self {
         let now = Utc::now();
         Self {
+            schema_version: SCHEMA_VERSION,
             id: SessionId::new(),
             messages: Vec::new(),
             created_at: now,
             updated_at: now,
+            parent_session_id: None,
+            forked_at_index: None,
+            title: None,
+            tags: Vec::new(),
+            pinned: false,
+            archived: Vec::new(),
         }
     }
 
@@ -29,13 +59,88 @@ impl Session {
     pub fn with_id(id: SessionId) -> Self {
         let now = Utc::now();
         Self {
+            schema_version: SCHEMA_VERSION,
             id,
             messages: Vec::new(),
             created_at: now,
             updated_at: now,
+            parent_session_id: None,
+            forked_at_index: None,
+            title: None,
+            tags: Vec::new(),
+            pinned: false,
+            archived: Vec::new(),
         }
     }
 
+    /// New session with a copy of the first `at` messages, linked to this one as its parent.
+    /// Returns None when `at` is past the end of the history.
+    pub fn fork(&self, at: usize) -> Option<Self> {
+        if at > self.messages.len() {
+            return None;
+        }
+        let mut fork = Self::new();
+        fork.messages = self.messages[..at].to_vec();
+        fork.parent_session_id = Some(self.id);
+        fork.forked_at_index = Some(at);
+        fork.title = self.title.clone();
+        fork.tags = self.tags.clone();
+        if at > 0 {
+            // A summary in the copied prefix stands for these.
+            fork.archived = self.archived.clone();
+        }
+        Some(fork)
+    }
+
+    /// Start of the turns to keep when compacting down to the latest `keep_turns` turns (each
+    /// starting at a user message). None when fewer than two messages would be compacted.
+    pub fn compaction_point(&self, keep_turns: usize) -> Option<usize> {
+        let user_messages: Vec<usize> = self
+            .messages
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.role == Role::User)
+            .map(|(i, _)| i)
+            .collect();
+        let end = if keep_turns == 0 {
+            self.messages.len()
+        } else {
+            *user_messages.get(user_messages.len().checked_sub(keep_turns)?)?
+        };
+        (end >= 2).then_some(end)
+    }
+
+    /// Moves the first `end` messages to `archived` and puts `summary` in their place. A previous
+    /// summary among them is archived too, so the new one must cover it.
+    pub fn compact(&mut self, end: usize, summary: Message) {
+        let end = end.min(self.messages.len());
+        self.archived.extend(self.messages.drain(..end));
+        self.messages.insert(0, summary);
+        self.updated_at = Utc::now();
+    }
+
+    /// The title, or the start of the first message when the session has none.
+    pub fn display_title(&self) -> String {
+        display_title(self.title.as_deref(), self.messages.first().map(|m| m.content.as_str()))
+    }
+
+    /// Adds `tag` unless the session already has it. Returns whether it was added.
+    pub fn add_tag(&mut self, tag: &str) -> bool {
+        let tag = tag.trim();
+        if tag.is_empty() || self.tags.iter().any(|t| t == tag) {
+            return false;
+        }
+        self.tags.push(tag.to_string());
+        true
+    }
+
+    /// Removes `tag`. Returns whether the session had it.
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        let before = self.tags.len();
+        self.tags.retain(|t| t != tag.trim());
+        self.tags.len() != before
+    }
+
     /// Add a message to the session
     pub fn push_message(&mut self, message: Message) {
         self.messages.push(message);
@@ -53,6 +158,30 @@ impl Session {
     }
 }
 
+/// Characters of the first message used as a fallback title.
+const FALLBACK_TITLE_CHARS: usize = 50;
+
+/// `title` when set, else the first line of `first_message` cut to 50 characters, else
+/// "New Session". Shared by `Session::display_title` and session listings that do not load
+/// every message.
+pub fn display_title(title: Option<&str>, first_message: Option<&str>) -> String {
+    if let Some(title) = title.map(str::trim).filter(|t| !t.is_empty()) {
+        return title.to_string();
+    }
+    let Some(first) = first_message
+        .and_then(|m| m.lines().map(str::trim).find(|l| !l.is_empty()))
+    else {
+        return "New Session".to_string();
+    };
+    let mut chars = first.chars();
+    let head: String = chars.by_ref().take(FALLBACK_TITLE_CHARS).collect();
+    if chars.next().is_some() {
+        format!("{}...", head)
+    } else {
+        head
+    }
+}
+
 impl Default for Session {
     fn default() -> Self {
         Self::new()
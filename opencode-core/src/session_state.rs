@@ -1,5 +1,27 @@
-use crate::error::Result;
+//! Turn lifecycle of a session. `SessionStateMachine` enforces the allowed transitions and tracks
+//! failing tool calls; `SessionStateHandle` shares one machine between the caller and the agent
+//! loop (or the deep agent's tool adapter) for the duration of a turn and reports every change,
+//! so the caller can persist it and show it in the UI.
+//!
+//! A turn goes `Idle -> Processing`, alternates `Processing <-> WaitingForTool` per tool call and
+//! ends in `Completed`, `WaitingForUser` (the agent asked a question) or `Error`. A tool call
+//! that needs the user's permission waits in `WaitingForUser` until it is answered. When the same
+//! tool call fails with identical arguments `DOOM_LOOP_THRESHOLD` times in one turn, the turn is
+//! aborted with `Error::DoomLoop`.
+
+use crate::error::{Error, Result};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// Identical failing tool calls (same tool, same arguments) in one turn that abort it.
+pub const DOOM_LOOP_THRESHOLD: u32 = 3;
+
+/// Tool whose successful call leaves the session waiting for the user's answer.
+const QUESTION_TOOL: &str = "question";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SessionState {
@@ -11,10 +33,32 @@ pub enum SessionState {
     Completed,
 }
 
+/// Persisted snapshot of a session's state machine.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionStatus {
+    pub state: SessionState,
+    /// Consecutive failed turns.
+    #[serde(default)]
+    pub retry_count: u32,
+    /// Tool running while `WaitingForTool`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+    /// Why the last turn failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
 pub struct SessionStateMachine {
     state: SessionState,
     retry_count: u32,
     max_retries: u32,
+    tool: Option<String>,
+    error: Option<String>,
+    /// Failures per tool call (tool id and arguments) in the current turn.
+    failing_calls: HashMap<(String, String), u32>,
+    /// A `question` call succeeded in the current turn.
+    asked_user: bool,
 }
 
 impl SessionStateMachine {
@@ -23,6 +67,21 @@ impl SessionStateMachine {
             state: SessionState::Idle,
             retry_count: 0,
             max_retries: 3,
+            tool: None,
+            error: None,
+            failing_calls: HashMap::new(),
+            asked_user: false,
+        }
+    }
+
+    /// Machine restored from a persisted status.
+    pub fn from_status(status: &SessionStatus) -> Self {
+        Self {
+            state: status.state,
+            retry_count: status.retry_count,
+            tool: status.tool.clone(),
+            error: status.error.clone(),
+            ..Self::new()
         }
     }
 
@@ -63,6 +122,14 @@ impl SessionStateMachine {
                 self.state = new_state;
                 Ok(())
             }
+            (SessionState::WaitingForTool, SessionState::WaitingForUser) => {
+                self.state = new_state;
+                Ok(())
+            }
+            (SessionState::WaitingForUser, SessionState::WaitingForTool) => {
+                self.state = new_state;
+                Ok(())
+            }
             (SessionState::Error, SessionState::Idle) => {
                 self.state = new_state;
                 self.retry_count = 0;
@@ -88,8 +155,105 @@ impl SessionStateMachine {
         self.retry_count
     }
 
-    pub fn check_doom_loop(&self, recent_errors: usize) -> bool {
-        recent_errors > 5 && self.retry_count >= self.max_retries
+    pub fn status(&self) -> SessionStatus {
+        SessionStatus {
+            state: self.state,
+            retry_count: self.retry_count,
+            tool: self.tool.clone(),
+            error: self.error.clone(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// Starts a turn from whatever the previous one left. A turn still `Processing` or
+    /// `WaitingForTool` was interrupted (e.g. the process exited) and is dropped.
+    pub fn begin_turn(&mut self) -> Result<()> {
+        match self.state {
+            SessionState::Completed | SessionState::Error => self.transition(SessionState::Idle)?,
+            SessionState::Processing | SessionState::WaitingForTool => {
+                tracing::warn!(state = ?self.state, "previous turn did not finish");
+                self.state = SessionState::Idle;
+            }
+            SessionState::Idle | SessionState::WaitingForUser => {}
+        }
+        self.tool = None;
+        self.error = None;
+        self.failing_calls.clear();
+        self.asked_user = false;
+        self.transition(SessionState::Processing)
+    }
+
+    pub fn tool_started(&mut self, tool: &str) -> Result<()> {
+        self.transition(SessionState::WaitingForTool)?;
+        self.tool = Some(tool.to_string());
+        Ok(())
+    }
+
+    /// The running tool call waits for the user's permission.
+    pub fn await_user(&mut self) -> Result<()> {
+        self.transition(SessionState::WaitingForUser)
+    }
+
+    /// The user answered the permission request of the running tool call.
+    pub fn user_answered(&mut self) -> Result<()> {
+        self.transition(SessionState::WaitingForTool)
+    }
+
+    /// Records the outcome of a tool call. Fails with `Error::DoomLoop` once the same call has
+    /// failed `DOOM_LOOP_THRESHOLD` times this turn.
+    pub fn tool_finished(&mut self, tool: &str, arguments: &Value, error: Option<&str>) -> Result<()> {
+        self.transition(SessionState::Processing)?;
+        self.tool = None;
+        match error {
+            None => {
+                self.asked_user |= tool == QUESTION_TOOL;
+                Ok(())
+            }
+            Some(error) => {
+                *self
+                    .failing_calls
+                    .entry((tool.to_string(), arguments.to_string()))
+                    .or_default() += 1;
+                match self.check_doom_loop() {
+                    Some(description) => Err(Error::DoomLoop(format!("{}: {}", description, error))),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    /// The tool call that has failed `DOOM_LOOP_THRESHOLD` times with identical arguments this
+    /// turn, if any.
+    pub fn check_doom_loop(&self) -> Option<String> {
+        self.failing_calls
+            .iter()
+            .find(|(_, count)| **count >= DOOM_LOOP_THRESHOLD)
+            .map(|((tool, _), count)| {
+                format!("tool '{}' failed {} times with identical arguments", tool, count)
+            })
+    }
+
+    /// Ends the turn: `Completed` (or `WaitingForUser` after a question), or `Error` with
+    /// `error` as the reason.
+    pub fn finish_turn(&mut self, error: Option<&str>) {
+        if self.state == SessionState::WaitingForTool
+            || (self.state == SessionState::WaitingForUser && self.tool.is_some())
+        {
+            // Aborted while a tool ran or waited for permission.
+            self.state = SessionState::Processing;
+            self.tool = None;
+        }
+        let result = match error {
+            None if self.asked_user => self.transition(SessionState::WaitingForUser),
+            None => self.transition(SessionState::Completed),
+            Some(error) => {
+                self.error = Some(error.to_string());
+                self.transition(SessionState::Error)
+            }
+        };
+        if let Err(e) = result {
+            tracing::warn!(error = %e, "session state after turn");
+        }
     }
 }
 
@@ -98,3 +262,95 @@ impl Default for SessionStateMachine {
         Self::new()
     }
 }
+
+/// Called with the new status after every change.
+pub type OnStateChange = Arc<dyn Fn(&SessionStatus) + Send + Sync>;
+
+/// Shared handle to the state machine of the session whose turn is running.
+#[derive(Clone)]
+pub struct SessionStateHandle {
+    machine: Arc<Mutex<SessionStateMachine>>,
+    on_change: Option<OnStateChange>,
+    /// Notified when a doom loop is detected, for callers that cannot see tool errors.
+    doom_loop: Arc<Notify>,
+    doom_loop_reason: Arc<Mutex<Option<String>>>,
+}
+
+impl SessionStateHandle {
+    pub fn new(machine: SessionStateMachine, on_change: Option<OnStateChange>) -> Self {
+        Self {
+            machine: Arc::new(Mutex::new(machine)),
+            on_change,
+            doom_loop: Arc::new(Notify::new()),
+            doom_loop_reason: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn status(&self) -> SessionStatus {
+        self.lock().status()
+    }
+
+    pub fn begin_turn(&self) -> Result<()> {
+        self.update(|m| m.begin_turn())
+    }
+
+    pub fn tool_started(&self, tool: &str) -> Result<()> {
+        self.update(|m| m.tool_started(tool))
+    }
+
+    pub fn await_user(&self) -> Result<()> {
+        self.update(|m| m.await_user())
+    }
+
+    pub fn user_answered(&self) -> Result<()> {
+        self.update(|m| m.user_answered())
+    }
+
+    /// See `SessionStateMachine::tool_finished`. A doom loop also wakes `doom_loop()`.
+    pub fn tool_finished(&self, tool: &str, arguments: &Value, error: Option<&str>) -> Result<()> {
+        let result = self.update(|m| m.tool_finished(tool, arguments, error));
+        if let Err(Error::DoomLoop(reason)) = &result {
+            if let Ok(mut r) = self.doom_loop_reason.lock() {
+                *r = Some(reason.clone());
+            }
+            self.doom_loop.notify_one();
+        }
+        result
+    }
+
+    pub fn finish_turn(&self, error: Option<&str>) {
+        self.update(|m| {
+            m.finish_turn(error);
+            Ok(())
+        })
+        .ok();
+    }
+
+    /// Resolves with `Error::DoomLoop` once a doom loop is detected.
+    pub async fn doom_loop(&self) -> Error {
+        self.doom_loop.notified().await;
+        let reason = self
+            .doom_loop_reason
+            .lock()
+            .ok()
+            .and_then(|r| r.clone())
+            .unwrap_or_default();
+        Error::DoomLoop(reason)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SessionStateMachine> {
+        self.machine.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn update<T>(&self, f: impl FnOnce(&mut SessionStateMachine) -> Result<T>) -> Result<T> {
+        let (result, status) = {
+            let mut machine = self.lock();
+            let result = f(&mut machine);
+            (result, machine.status())
+        };
+        if let Some(on_change) = &self.on_change {
+            on_change(&status);
+        }
+        result
+    }
+}
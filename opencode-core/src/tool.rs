@@ -11,6 +11,10 @@ pub struct ToolContext {
     pub call_id: Option<String>,
     /// Current project/workspace directory for tool execution (e.g. resolving relative paths).
     pub workspace_path: Option<String>,
+    /// Rules every tool call is checked against (see `permission::authorize`); None allows all.
+    pub permissions: Option<std::sync::Arc<crate::permission::PermissionManager>>,
+    /// Directories outside `workspace_path` the file tools may access (Config.allowed_paths).
+    pub allowed_paths: Vec<String>,
 }
 
 pub struct ToolResult {
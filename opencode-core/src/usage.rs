@@ -0,0 +1,140 @@
+//! Token usage per model response and cost aggregation over sessions.
+
+use crate::session::Session;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Tokens used by one model response, stored on the assistant message that it produced.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Prompt tokens served from the provider's prompt cache (included in `prompt_tokens`).
+    #[serde(default)]
+    pub cached_tokens: u64,
+    /// Reasoning tokens (included in `completion_tokens`).
+    #[serde(default)]
+    pub reasoning_tokens: u64,
+    /// Model that produced the response, when the provider reports it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+impl TokenUsage {
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    /// Adds the token counts of `other`; `model` is left unchanged.
+    pub fn add(&mut self, other: &TokenUsage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cached_tokens += other.cached_tokens;
+        self.reasoning_tokens += other.reasoning_tokens;
+    }
+}
+
+/// Price of a model in USD per million tokens.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    /// Price for cached prompt tokens; defaults to `input` when unset.
+    #[serde(default)]
+    pub cached_input: Option<f64>,
+}
+
+impl ModelPrice {
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        let cached = usage.cached_tokens.min(usage.prompt_tokens);
+        let uncached = usage.prompt_tokens - cached;
+        let cached_price = self.cached_input.unwrap_or(self.input);
+        (uncached as f64 * self.input
+            + cached as f64 * cached_price
+            + usage.completion_tokens as f64 * self.output)
+            / 1_000_000.0
+    }
+}
+
+/// Price for `model`: the exact key, else the longest key the model name starts with (so
+/// `gpt-4o-mini` also prices `gpt-4o-mini-2024-07-18`).
+pub fn price_for<'a>(pricing: &'a HashMap<String, ModelPrice>, model: &str) -> Option<&'a ModelPrice> {
+    pricing.get(model).or_else(|| {
+        pricing
+            .iter()
+            .filter(|(key, _)| model.starts_with(key.as_str()))
+            .max_by_key(|(key, _)| key.len())
+            .map(|(_, price)| price)
+    })
+}
+
+/// Summed usage and cost for a group of responses.
+#[derive(Debug, Clone, Default)]
+pub struct UsageTotals {
+    pub usage: TokenUsage,
+    /// Cost in USD of the responses whose model has a price.
+    pub cost: f64,
+    pub responses: usize,
+    /// Responses without a known model price (not included in `cost`).
+    pub unpriced: usize,
+}
+
+impl UsageTotals {
+    fn add(&mut self, usage: &TokenUsage, cost: Option<f64>) {
+        self.usage.add(usage);
+        self.responses += 1;
+        match cost {
+            Some(c) => self.cost += c,
+            None => self.unpriced += 1,
+        }
+    }
+}
+
+/// Usage aggregated per session, per day (UTC, by message time) and per model.
+#[derive(Debug, Clone, Default)]
+pub struct UsageReport {
+    pub total: UsageTotals,
+    pub by_session: BTreeMap<String, UsageTotals>,
+    pub by_day: BTreeMap<NaiveDate, UsageTotals>,
+    /// Keyed by model name; `"unknown"` when the provider did not report one.
+    pub by_model: BTreeMap<String, UsageTotals>,
+}
+
+impl UsageReport {
+    pub fn from_sessions<'a>(
+        sessions: impl IntoIterator<Item = &'a Session>,
+        pricing: &HashMap<String, ModelPrice>,
+    ) -> Self {
+        let mut report = Self::default();
+        for session in sessions {
+            report.add_session(session, pricing);
+        }
+        report
+    }
+
+    pub fn add_session(&mut self, session: &Session, pricing: &HashMap<String, ModelPrice>) {
+        let session_id = session.id.to_string();
+        // Archived messages were paid for too.
+        for message in session.archived.iter().chain(&session.messages) {
+            let Some(usage) = message.meta.as_ref().and_then(|m| m.usage.as_ref()) else {
+                continue;
+            };
+            let model = usage.model.as_deref().unwrap_or("unknown");
+            let cost = price_for(pricing, model).map(|p| p.cost(usage));
+            self.total.add(usage, cost);
+            self.by_session
+                .entry(session_id.clone())
+                .or_default()
+                .add(usage, cost);
+            self.by_day
+                .entry(message.created_at.date_naive())
+                .or_default()
+                .add(usage, cost);
+            self.by_model
+                .entry(model.to_string())
+                .or_default()
+                .add(usage, cost);
+        }
+    }
+}
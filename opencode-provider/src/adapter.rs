@@ -1,23 +1,34 @@
-use crate::common::{build_base_url, validate_api_key};
-use crate::trait_::{GenerateRequest, GenerateResponse, ModelInfo, Provider};
+use crate::common::{
+    api_error, build_base_url, parse_tool_arguments, request_error, validate_api_key,
+};
+use crate::sse::sse_events;
+use crate::trait_::{
+    Chunk, GenerateRequest, GenerateResponse, Message, MessageRole, ModelInfo, Provider, ToolCall,
+    ToolDefinition,
+};
 use async_trait::async_trait;
-use futures::Stream;
+use futures::{Stream, StreamExt};
 use opencode_core::error::{Error, Result};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
+/// Native client of the OpenAI chat completions API (and compatible endpoints via `base_url`):
+/// SSE streaming with usage and incremental tool call deltas.
 pub struct OpenAIProvider {
     client: Arc<Client>,
     api_key: String,
     base_url: String,
+    model: Option<String>,
 }
 
 impl OpenAIProvider {
     pub fn new(api_key: String, base_url: Option<String>) -> Result<Self> {
         validate_api_key(&api_key)?;
+        // Read timeout (not a total timeout) so long streamed responses are not cut off.
         let client = Client::builder()
-            .timeout(std::time::Duration::from_secs(60))
+            .connect_timeout(std::time::Duration::from_secs(30))
+            .read_timeout(std::time::Duration::from_secs(60))
             .build()
             .map_err(|e| Error::Provider(format!("Failed to create HTTP client: {}", e)))?;
         
@@ -25,10 +36,28 @@ impl OpenAIProvider {
             client: Arc::new(client),
             api_key,
             base_url: build_base_url(base_url.as_deref(), "https://api.openai.com/v1"),
+            model: None,
         })
     }
+
+    /// Model used when the request does not name one.
+    pub fn with_model(mut self, model: Option<String>) -> Self {
+        self.model = model;
+        self
+    }
+
+    fn model(&self, request: &mut GenerateRequest) -> String {
+        request
+            .model
+            .take()
+            .or_else(|| self.model.clone())
+            .unwrap_or_else(|| DEFAULT_MODEL.to_string())
+    }
 }
 
+/// Model used when neither the request nor the config names one.
+const DEFAULT_MODEL: &str = "gpt-4o-mini";
+
 #[derive(Serialize)]
 struct OpenAIRequest {
     model: String,
@@ -36,18 +65,67 @@ struct OpenAIRequest {
     temperature: Option<f64>,
     max_tokens: Option<u32>,
     stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<OpenAITool>,
+}
+
+#[derive(Serialize)]
+struct StreamOptions {
+    include_usage: bool,
 }
 
 #[derive(Serialize, Deserialize)]
 struct OpenAIMessage {
     role: String,
-    content: String,
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<OpenAIToolCall>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+#[derive(Serialize)]
+struct OpenAITool {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: OpenAIFunctionDef,
+}
+
+#[derive(Serialize)]
+struct OpenAIFunctionDef {
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OpenAIToolCall {
+    id: String,
+    #[serde(rename = "type", default = "function_kind")]
+    kind: String,
+    function: OpenAIFunctionCall,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OpenAIFunctionCall {
+    name: String,
+    /// JSON-encoded arguments, as the API sends and expects them.
+    #[serde(default)]
+    arguments: String,
+}
+
+fn function_kind() -> String {
+    "function".to_string()
 }
 
 #[derive(Deserialize)]
 struct OpenAIResponse {
     choices: Vec<Choice>,
     usage: Option<Usage>,
+    model: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -60,32 +138,216 @@ struct Usage {
     prompt_tokens: u32,
     completion_tokens: u32,
     total_tokens: u32,
+    prompt_tokens_details: Option<PromptTokensDetails>,
+    completion_tokens_details: Option<CompletionTokensDetails>,
+}
+
+#[derive(Deserialize)]
+struct PromptTokensDetails {
+    #[serde(default)]
+    cached_tokens: u32,
+}
+
+#[derive(Deserialize)]
+struct CompletionTokensDetails {
+    #[serde(default)]
+    reasoning_tokens: u32,
+}
+
+impl Usage {
+    fn into_usage(self, model: Option<String>) -> crate::trait_::Usage {
+        crate::trait_::Usage {
+            prompt_tokens: self.prompt_tokens,
+            completion_tokens: self.completion_tokens,
+            total_tokens: self.total_tokens,
+            cached_tokens: self.prompt_tokens_details.map_or(0, |d| d.cached_tokens),
+            reasoning_tokens: self.completion_tokens_details.map_or(0, |d| d.reasoning_tokens),
+            model,
+        }
+    }
+}
+
+/// One `data:` payload of a streamed chat completion.
+#[derive(Deserialize)]
+struct StreamResponse {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+    usage: Option<Usage>,
+    model: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StreamChoice {
+    #[serde(default)]
+    delta: StreamDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+struct StreamDelta {
+    content: Option<String>,
+    tool_calls: Option<Vec<ToolCallDelta>>,
+}
+
+#[derive(Deserialize)]
+struct ToolCallDelta {
+    #[serde(default)]
+    index: usize,
+    id: Option<String>,
+    function: Option<FunctionDelta>,
+}
+
+#[derive(Deserialize)]
+struct FunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+/// Assembles tool calls from streamed deltas. The first delta for an index carries id and name;
+/// later deltas append argument fragments.
+#[derive(Default)]
+struct ToolCallAccumulator {
+    calls: std::collections::BTreeMap<usize, (String, String, String)>,
+}
+
+impl ToolCallAccumulator {
+    fn apply(&mut self, deltas: Vec<ToolCallDelta>) {
+        for delta in deltas {
+            let entry = self.calls.entry(delta.index).or_default();
+            if let Some(id) = delta.id {
+                entry.0 = id;
+            }
+            if let Some(function) = delta.function {
+                if let Some(name) = function.name {
+                    entry.1.push_str(&name);
+                }
+                if let Some(arguments) = function.arguments {
+                    entry.2.push_str(&arguments);
+                }
+            }
+        }
+    }
+
+    fn take(&mut self) -> Vec<ToolCall> {
+        std::mem::take(&mut self.calls)
+            .into_values()
+            .map(|(id, name, arguments)| ToolCall {
+                id,
+                name,
+                arguments: parse_tool_arguments(&arguments),
+            })
+            .collect()
+    }
+}
+
+/// Maps one SSE payload to a chunk. Returns None for events that carry nothing for the caller
+/// (e.g. the initial role-only delta).
+fn stream_chunk(data: &str, tool_calls: &mut ToolCallAccumulator) -> Result<Option<Chunk>> {
+    if data.trim() == "[DONE]" {
+        return Ok(Some(Chunk {
+            content: String::new(),
+            done: true,
+            tool_calls: tool_calls.take(),
+            usage: None,
+        }));
+    }
+    let parsed: StreamResponse = serde_json::from_str(data)
+        .map_err(|e| Error::Provider(format!("Failed to parse stream chunk: {}", e)))?;
+
+    let mut content = String::new();
+    let mut finished = false;
+    for choice in parsed.choices {
+        if let Some(text) = choice.delta.content {
+            content.push_str(&text);
+        }
+        if let Some(deltas) = choice.delta.tool_calls {
+            tool_calls.apply(deltas);
+        }
+        finished |= choice.finish_reason.is_some();
+    }
+    let completed = if finished { tool_calls.take() } else { Vec::new() };
+    let usage = parsed.usage.map(|u| u.into_usage(parsed.model));
+    if content.is_empty() && completed.is_empty() && usage.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(Chunk {
+        content,
+        done: false,
+        tool_calls: completed,
+        usage,
+    }))
+}
+
+fn to_openai_message(m: Message) -> OpenAIMessage {
+    let role = match m.role {
+        MessageRole::System => "system",
+        MessageRole::User => "user",
+        MessageRole::Assistant => "assistant",
+        MessageRole::Tool => "tool",
+    };
+    let tool_calls = if m.tool_calls.is_empty() {
+        None
+    } else {
+        Some(
+            m.tool_calls
+                .into_iter()
+                .map(|c| OpenAIToolCall {
+                    id: c.id,
+                    kind: function_kind(),
+                    function: OpenAIFunctionCall {
+                        name: c.name,
+                        arguments: c.arguments.to_string(),
+                    },
+                })
+                .collect(),
+        )
+    };
+    // Assistant messages that only carry tool calls are sent with null content.
+    let content = if m.content.is_empty() && tool_calls.is_some() {
+        None
+    } else {
+        Some(m.content)
+    };
+    OpenAIMessage {
+        role: role.to_string(),
+        content,
+        tool_calls,
+        tool_call_id: m.tool_call_id,
+    }
+}
+
+fn to_openai_tool(def: ToolDefinition) -> OpenAITool {
+    OpenAITool {
+        kind: "function",
+        function: OpenAIFunctionDef {
+            name: def.name,
+            description: def.description,
+            parameters: def.parameters,
+        },
+    }
+}
+
+fn from_openai_tool_call(call: OpenAIToolCall) -> ToolCall {
+    ToolCall {
+        id: call.id,
+        name: call.function.name,
+        arguments: parse_tool_arguments(&call.function.arguments),
+    }
 }
 
 #[async_trait]
 impl Provider for OpenAIProvider {
-    async fn generate(&self, request: GenerateRequest) -> Result<GenerateResponse> {
-        let model = request.model.unwrap_or_else(|| "gpt-4o-mini".to_string());
-        
-        let messages: Vec<OpenAIMessage> = request
-            .messages
-            .into_iter()
-            .map(|m| OpenAIMessage {
-                role: match m.role {
-                    crate::trait_::MessageRole::System => "system".to_string(),
-                    crate::trait_::MessageRole::User => "user".to_string(),
-                    crate::trait_::MessageRole::Assistant => "assistant".to_string(),
-                },
-                content: m.content,
-            })
-            .collect();
+    async fn generate(&self, mut request: GenerateRequest) -> Result<GenerateResponse> {
+        let model = self.model(&mut request);
 
         let req = OpenAIRequest {
             model,
-            messages,
+            messages: request.messages.into_iter().map(to_openai_message).collect(),
             temperature: request.temperature,
             max_tokens: request.max_tokens,
             stream: false,
+            stream_options: None,
+            tools: request.tools.into_iter().map(to_openai_tool).collect(),
         };
 
         let response = self
@@ -96,12 +358,10 @@ impl Provider for OpenAIProvider {
             .json(&req)
             .send()
             .await
-            .map_err(|e| Error::Provider(format!("Request failed: {}", e)))?;
+            .map_err(request_error)?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(Error::Provider(format!("API error {}: {}", status, text)));
+            return Err(api_error(response).await);
         }
 
         let openai_resp: OpenAIResponse = response
@@ -109,31 +369,95 @@ impl Provider for OpenAIProvider {
             .await
             .map_err(|e| Error::Provider(format!("Failed to parse response: {}", e)))?;
 
-        let content = openai_resp
+        let message = openai_resp
             .choices
-            .first()
+            .into_iter()
+            .next()
             .ok_or_else(|| Error::Provider("No choices in response".to_string()))?
-            .message
-            .content
-            .clone();
+            .message;
 
-        let usage = openai_resp.usage.map(|u| crate::trait_::Usage {
-            prompt_tokens: u.prompt_tokens,
-            completion_tokens: u.completion_tokens,
-            total_tokens: u.total_tokens,
-        });
+        let usage = openai_resp.usage.map(|u| u.into_usage(openai_resp.model));
 
-        Ok(GenerateResponse { content, usage })
+        Ok(GenerateResponse {
+            content: message.content.unwrap_or_default(),
+            usage,
+            tool_calls: message
+                .tool_calls
+                .unwrap_or_default()
+                .into_iter()
+                .map(from_openai_tool_call)
+                .collect(),
+        })
     }
 
     async fn stream(
         &self,
-        _request: GenerateRequest,
-    ) -> Result<Box<dyn Stream<Item = Result<crate::trait_::Chunk>> + Send + Unpin>> {
-        Err(Error::Provider("Streaming not yet implemented".to_string()))
+        mut request: GenerateRequest,
+    ) -> Result<Box<dyn Stream<Item = Result<Chunk>> + Send + Unpin>> {
+        let model = self.model(&mut request);
+
+        let req = OpenAIRequest {
+            model,
+            messages: request.messages.into_iter().map(to_openai_message).collect(),
+            temperature: request.temperature,
+            max_tokens: request.max_tokens,
+            stream: true,
+            stream_options: Some(StreamOptions { include_usage: true }),
+            tools: request.tools.into_iter().map(to_openai_tool).collect(),
+        };
+
+        let response = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json")
+            .header("Accept", "text/event-stream")
+            .json(&req)
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+
+        let mut tool_calls = ToolCallAccumulator::default();
+        let mut saw_done = false;
+        let events = sse_events(response);
+        let chunks = events
+            .map(Some)
+            .chain(futures::stream::once(async { None }))
+            .filter_map(move |event| {
+                let item = match event {
+                    Some(Ok(event)) if !saw_done => {
+                        let chunk = stream_chunk(&event.data, &mut tool_calls);
+                        if let Ok(Some(ref c)) = chunk {
+                            saw_done = c.done;
+                        }
+                        chunk.transpose()
+                    }
+                    Some(Ok(_)) => None,
+                    Some(Err(e)) => Some(Err(e)),
+                    // Body ended without [DONE]: flush whatever was assembled.
+                    None if !saw_done => Some(Ok(Chunk {
+                        content: String::new(),
+                        done: true,
+                        tool_calls: tool_calls.take(),
+                        usage: None,
+                    })),
+                    None => None,
+                };
+                futures::future::ready(item)
+            });
+        Ok(Box::new(Box::pin(chunks)))
     }
 
     fn models(&self) -> &[ModelInfo] {
         &[]
     }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        crate::models::fetch_openai_models(&self.client, &self.base_url, Some(&self.api_key), "openai")
+            .await
+    }
 }
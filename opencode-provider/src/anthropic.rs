@@ -1,24 +1,41 @@
-use crate::adapter::OpenAIProvider;
-use crate::common::{build_base_url, validate_api_key};
-use crate::trait_::{GenerateRequest, GenerateResponse, ModelInfo, Provider};
+use crate::common::{
+    api_error, build_base_url, parse_tool_arguments, request_error, validate_api_key,
+};
+use crate::sse::sse_events;
+use crate::trait_::{
+    Chunk, GenerateRequest, GenerateResponse, Message, MessageRole, ModelInfo, Provider, ToolCall,
+    ToolDefinition,
+};
 use async_trait::async_trait;
-use futures::Stream;
+use futures::{Stream, StreamExt};
 use opencode_core::error::{Error, Result};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
+const DEFAULT_MODEL: &str = "claude-3-5-sonnet-20241022";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Provider for the Anthropic Messages API (`/v1/messages`).
+///
+/// System messages are sent as the top-level `system` field, tool calls and results as
+/// `tool_use` / `tool_result` content blocks. Thinking blocks are kept out of the reply text.
 pub struct AnthropicProvider {
     client: Arc<Client>,
     api_key: String,
     base_url: String,
+    model: Option<String>,
 }
 
 impl AnthropicProvider {
     pub fn new(api_key: String, base_url: Option<String>) -> Result<Self> {
         validate_api_key(&api_key)?;
+        // Read timeout (not a total timeout) so long streamed responses are not cut off.
         let client = Client::builder()
-            .timeout(std::time::Duration::from_secs(60))
+            .connect_timeout(std::time::Duration::from_secs(30))
+            .read_timeout(std::time::Duration::from_secs(60))
             .build()
             .map_err(|e| Error::Provider(format!("Failed to create HTTP client: {}", e)))?;
 
@@ -26,112 +43,460 @@ impl AnthropicProvider {
             client: Arc::new(client),
             api_key,
             base_url: build_base_url(base_url.as_deref(), "https://api.anthropic.com/v1"),
+            model: None,
         })
     }
+
+    /// Model used when the request does not name one.
+    pub fn with_model(mut self, model: Option<String>) -> Self {
+        self.model = model;
+        self
+    }
+
+    fn build_request(&self, request: GenerateRequest, stream: bool) -> AnthropicRequest {
+        let model = request
+            .model
+            .or_else(|| self.model.clone())
+            .unwrap_or_else(|| DEFAULT_MODEL.to_string());
+        let (system, messages) = to_anthropic_messages(request.messages);
+        AnthropicRequest {
+            model,
+            messages,
+            system,
+            max_tokens: request.max_tokens.unwrap_or(4096),
+            temperature: request.temperature,
+            stream,
+            tools: request.tools.into_iter().map(to_anthropic_tool).collect(),
+        }
+    }
+
+    async fn send(&self, req: &AnthropicRequest) -> Result<reqwest::Response> {
+        let mut builder = self
+            .client
+            .post(format!("{}/messages", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .header("Content-Type", "application/json");
+        if req.stream {
+            builder = builder.header("Accept", "text/event-stream");
+        }
+        let response = builder
+            .json(req)
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+        Ok(response)
+    }
 }
 
 #[derive(Serialize)]
 struct AnthropicRequest {
     model: String,
     messages: Vec<AnthropicMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
     max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
     temperature: Option<f64>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<AnthropicTool>,
 }
 
 #[derive(Serialize)]
 struct AnthropicMessage {
-    role: String,
-    content: String,
+    role: &'static str,
+    content: Vec<ContentBlock>,
+}
+
+#[derive(Serialize)]
+struct AnthropicTool {
+    name: String,
+    description: String,
+    input_schema: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        #[serde(default)]
+        input: Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+    },
+    Thinking {
+        #[serde(default)]
+        thinking: String,
+    },
+    RedactedThinking {},
+    #[serde(other)]
+    Unknown,
 }
 
 #[derive(Deserialize)]
 struct AnthropicResponse {
     content: Vec<ContentBlock>,
     usage: Usage,
+    model: Option<String>,
 }
 
-#[derive(Deserialize)]
-struct ContentBlock {
-    text: String,
-}
-
-#[derive(Deserialize)]
+/// `input_tokens` excludes prompt cache reads and writes, which are reported separately.
+#[derive(Deserialize, Default, Clone, Copy)]
 struct Usage {
+    #[serde(default)]
     input_tokens: u32,
+    #[serde(default)]
     output_tokens: u32,
+    #[serde(default)]
+    cache_read_input_tokens: u32,
+    #[serde(default)]
+    cache_creation_input_tokens: u32,
 }
 
-#[async_trait]
-impl Provider for AnthropicProvider {
-    async fn generate(&self, request: GenerateRequest) -> Result<GenerateResponse> {
-        let model = request.model.unwrap_or_else(|| "claude-3-5-sonnet-20241022".to_string());
-
-        let messages: Vec<AnthropicMessage> = request
-            .messages
-            .into_iter()
-            .map(|m| AnthropicMessage {
-                role: match m.role {
-                    crate::trait_::MessageRole::System => "user".to_string(),
-                    crate::trait_::MessageRole::User => "user".to_string(),
-                    crate::trait_::MessageRole::Assistant => "assistant".to_string(),
-                },
-                content: m.content,
-            })
-            .collect();
-
-        let req = AnthropicRequest {
+impl Usage {
+    fn into_usage(self, model: Option<String>) -> crate::trait_::Usage {
+        let prompt_tokens =
+            self.input_tokens + self.cache_read_input_tokens + self.cache_creation_input_tokens;
+        crate::trait_::Usage {
+            prompt_tokens,
+            completion_tokens: self.output_tokens,
+            total_tokens: prompt_tokens + self.output_tokens,
+            cached_tokens: self.cache_read_input_tokens,
+            reasoning_tokens: 0,
             model,
-            messages,
-            max_tokens: request.max_tokens.unwrap_or(4096),
-            temperature: request.temperature,
+        }
+    }
+}
+
+/// One `data:` payload of a streamed message. The `type` field repeats the SSE event name.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    MessageStart {
+        message: StreamMessage,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: ContentBlock,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: BlockDelta,
+    },
+    ContentBlockStop {
+        index: usize,
+    },
+    MessageDelta {
+        #[serde(default)]
+        usage: Usage,
+    },
+    MessageStop,
+    Ping,
+    Error {
+        error: StreamError,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize)]
+struct StreamMessage {
+    #[serde(default)]
+    usage: Usage,
+    model: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BlockDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    ThinkingDelta {
+        thinking: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct StreamError {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    message: String,
+}
+
+/// Tracks open content blocks and usage across stream events.
+#[derive(Default)]
+struct StreamState {
+    /// Usage from `message_start`; `message_delta` updates the output token count.
+    usage: Usage,
+    model: Option<String>,
+    /// Open `tool_use` blocks by index: (id, name, partial JSON input).
+    tool_uses: BTreeMap<usize, (String, String, String)>,
+    thinking: String,
+}
+
+impl StreamState {
+    /// Maps one SSE payload to a chunk. Returns None for events that carry nothing for the
+    /// caller (pings, block boundaries, thinking deltas).
+    fn handle(&mut self, data: &str) -> Result<Option<Chunk>> {
+        let event: StreamEvent = serde_json::from_str(data)
+            .map_err(|e| Error::Provider(format!("Failed to parse stream event: {}", e)))?;
+        let chunk = match event {
+            StreamEvent::MessageStart { message } => {
+                self.usage = message.usage;
+                self.model = message.model;
+                None
+            }
+            StreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => match content_block {
+                ContentBlock::Text { text } if !text.is_empty() => Some(text_chunk(text)),
+                ContentBlock::ToolUse { id, name, .. } => {
+                    self.tool_uses.insert(index, (id, name, String::new()));
+                    None
+                }
+                _ => None,
+            },
+            StreamEvent::ContentBlockDelta { index, delta } => match delta {
+                BlockDelta::TextDelta { text } => Some(text_chunk(text)),
+                BlockDelta::InputJsonDelta { partial_json } => {
+                    if let Some(tool_use) = self.tool_uses.get_mut(&index) {
+                        tool_use.2.push_str(&partial_json);
+                    }
+                    None
+                }
+                BlockDelta::ThinkingDelta { thinking } => {
+                    self.thinking.push_str(&thinking);
+                    None
+                }
+                BlockDelta::Other => None,
+            },
+            StreamEvent::ContentBlockStop { index } => {
+                if !self.thinking.is_empty() {
+                    tracing::debug!(thinking = %self.thinking, "Anthropic thinking block");
+                    self.thinking.clear();
+                }
+                self.tool_uses
+                    .remove(&index)
+                    .map(|(id, name, input)| Chunk {
+                        content: String::new(),
+                        done: false,
+                        tool_calls: vec![ToolCall {
+                            id,
+                            name,
+                            arguments: parse_tool_arguments(&input),
+                        }],
+                        usage: None,
+                    })
+            }
+            // message_delta carries the cumulative output token count.
+            StreamEvent::MessageDelta { usage } => Some(Chunk {
+                content: String::new(),
+                done: false,
+                tool_calls: Vec::new(),
+                usage: Some(
+                    Usage {
+                        output_tokens: usage.output_tokens,
+                        ..self.usage
+                    }
+                    .into_usage(self.model.clone()),
+                ),
+            }),
+            StreamEvent::MessageStop => Some(done_chunk()),
+            StreamEvent::Ping | StreamEvent::Unknown => None,
+            StreamEvent::Error { error } => {
+                return Err(Error::Provider(format!(
+                    "Stream error {}: {}",
+                    error.kind, error.message
+                )))
+            }
         };
+        Ok(chunk)
+    }
+}
 
-        let response = self
-            .client
-            .post(format!("{}/messages", self.base_url))
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("Content-Type", "application/json")
-            .json(&req)
-            .send()
-            .await
-            .map_err(|e| Error::Provider(format!("Request failed: {}", e)))?;
+fn text_chunk(text: String) -> Chunk {
+    Chunk {
+        content: text,
+        done: false,
+        tool_calls: Vec::new(),
+        usage: None,
+    }
+}
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(Error::Provider(format!("API error {}: {}", status, text)));
+fn done_chunk() -> Chunk {
+    Chunk {
+        content: String::new(),
+        done: true,
+        tool_calls: Vec::new(),
+        usage: None,
+    }
+}
+
+/// Splits out system messages into the top-level `system` field and converts the rest to
+/// content blocks. Consecutive messages that map to the same role are merged, since tool
+/// results are sent as user turns.
+fn to_anthropic_messages(messages: Vec<Message>) -> (Option<String>, Vec<AnthropicMessage>) {
+    let mut system: Vec<String> = Vec::new();
+    let mut out: Vec<AnthropicMessage> = Vec::new();
+    for m in messages {
+        let (role, blocks) = match m.role {
+            MessageRole::System => {
+                system.push(m.content);
+                continue;
+            }
+            MessageRole::User => ("user", text_blocks(m.content)),
+            MessageRole::Tool => (
+                "user",
+                vec![ContentBlock::ToolResult {
+                    tool_use_id: m.tool_call_id.unwrap_or_default(),
+                    content: m.content,
+                }],
+            ),
+            MessageRole::Assistant => {
+                let mut blocks = text_blocks(m.content);
+                blocks.extend(m.tool_calls.into_iter().map(|c| ContentBlock::ToolUse {
+                    id: c.id,
+                    name: c.name,
+                    input: c.arguments,
+                }));
+                ("assistant", blocks)
+            }
+        };
+        if blocks.is_empty() {
+            continue;
         }
+        match out.last_mut() {
+            Some(last) if last.role == role => last.content.extend(blocks),
+            _ => out.push(AnthropicMessage {
+                role,
+                content: blocks,
+            }),
+        }
+    }
+    let system = if system.is_empty() {
+        None
+    } else {
+        Some(system.join("\n\n"))
+    };
+    (system, out)
+}
+
+/// The API rejects empty text blocks.
+fn text_blocks(text: String) -> Vec<ContentBlock> {
+    if text.is_empty() {
+        Vec::new()
+    } else {
+        vec![ContentBlock::Text { text }]
+    }
+}
+
+fn to_anthropic_tool(def: ToolDefinition) -> AnthropicTool {
+    AnthropicTool {
+        name: def.name,
+        description: def.description,
+        input_schema: def.parameters,
+    }
+}
+
+#[async_trait]
+impl Provider for AnthropicProvider {
+    async fn generate(&self, request: GenerateRequest) -> Result<GenerateResponse> {
+        let req = self.build_request(request, false);
+        let response = self.send(&req).await?;
 
         let anthropic_resp: AnthropicResponse = response
             .json()
             .await
             .map_err(|e| Error::Provider(format!("Failed to parse response: {}", e)))?;
 
-        let content = anthropic_resp
-            .content
-            .iter()
-            .map(|b| b.text.as_str())
-            .collect::<Vec<_>>()
-            .join("");
-
-        let usage = Some(crate::trait_::Usage {
-            prompt_tokens: anthropic_resp.usage.input_tokens,
-            completion_tokens: anthropic_resp.usage.output_tokens,
-            total_tokens: anthropic_resp.usage.input_tokens + anthropic_resp.usage.output_tokens,
-        });
+        let mut content = String::new();
+        let mut tool_calls = Vec::new();
+        for block in anthropic_resp.content {
+            match block {
+                ContentBlock::Text { text } => content.push_str(&text),
+                ContentBlock::ToolUse { id, name, input } => tool_calls.push(ToolCall {
+                    id,
+                    name,
+                    arguments: input,
+                }),
+                ContentBlock::Thinking { thinking } => {
+                    tracing::debug!(thinking = %thinking, "Anthropic thinking block");
+                }
+                _ => {}
+            }
+        }
 
-        Ok(GenerateResponse { content, usage })
+        Ok(GenerateResponse {
+            content,
+            usage: Some(anthropic_resp.usage.into_usage(anthropic_resp.model)),
+            tool_calls,
+        })
     }
 
     async fn stream(
         &self,
-        _request: GenerateRequest,
-    ) -> Result<Box<dyn Stream<Item = Result<crate::trait_::Chunk>> + Send + Unpin>> {
-        Err(Error::Provider("Streaming not yet implemented".to_string()))
+        request: GenerateRequest,
+    ) -> Result<Box<dyn Stream<Item = Result<Chunk>> + Send + Unpin>> {
+        let req = self.build_request(request, true);
+        let response = self.send(&req).await?;
+
+        let mut state = StreamState::default();
+        let mut saw_stop = false;
+        let chunks = sse_events(response)
+            .map(Some)
+            .chain(futures::stream::once(async { None }))
+            .filter_map(move |event| {
+                let item = match event {
+                    Some(Ok(event)) if !saw_stop => {
+                        let chunk = state.handle(&event.data);
+                        if let Ok(Some(ref c)) = chunk {
+                            saw_stop = c.done;
+                        }
+                        chunk.transpose()
+                    }
+                    Some(Ok(_)) => None,
+                    Some(Err(e)) => Some(Err(e)),
+                    // Body ended without message_stop.
+                    None if !saw_stop => Some(Ok(done_chunk())),
+                    None => None,
+                };
+                futures::future::ready(item)
+            });
+        Ok(Box::new(Box::pin(chunks)))
     }
 
     fn models(&self) -> &[ModelInfo] {
         &[]
     }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        crate::models::fetch_anthropic_models(
+            &self.client,
+            &self.base_url,
+            &self.api_key,
+            ANTHROPIC_VERSION,
+        )
+        .await
+    }
 }
@@ -1,3 +1,4 @@
+use crate::message;
 use crate::trait_::{GenerateRequest, GenerateResponse, Provider};
 use async_trait::async_trait;
 use futures::Stream;
@@ -26,44 +27,35 @@ impl CachedProvider {
 #[async_trait]
 impl Provider for CachedProvider {
     async fn generate(&self, request: GenerateRequest) -> Result<GenerateResponse> {
-        use opencode_core::agent::{ProviderRequest, Message, MessageRole};
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
-        
+        use opencode_core::agent::ProviderRequest;
+
         let core_request = ProviderRequest {
-            messages: request.messages.iter().map(|m| Message {
-                role: match m.role {
-                    crate::trait_::MessageRole::System => MessageRole::System,
-                    crate::trait_::MessageRole::User => MessageRole::User,
-                    crate::trait_::MessageRole::Assistant => MessageRole::Assistant,
-                },
-                content: m.content.clone(),
-            }).collect(),
+            messages: request.messages.iter().map(message::to_agent_message).collect(),
             model: request.model.clone(),
             temperature: request.temperature.map(|t| t as f32),
             max_tokens: request.max_tokens,
+            tools: request
+                .tools
+                .iter()
+                .map(message::from_provider_tool_definition)
+                .collect(),
         };
-        
-        let mut hasher = DefaultHasher::new();
-        let key_str = format!("{:?}", core_request);
-        key_str.hash(&mut hasher);
-        let cache_key = format!("provider:{}", hasher.finish());
-        
+        let cache_key = ProviderCache::cache_key_for_request(&core_request);
+
         if let Some(cached) = self.cache.get_response(&cache_key) {
             return Ok(GenerateResponse {
                 content: cached,
                 usage: None,
+                tool_calls: Vec::new(),
             });
         }
 
-        let response = self.provider.generate(GenerateRequest {
-            messages: request.messages.clone(),
-            model: request.model.clone(),
-            temperature: request.temperature,
-            max_tokens: request.max_tokens,
-        }).await?;
-        self.cache.cache_response(cache_key, response.content.clone());
-        
+        let response = self.provider.generate(request).await?;
+        // Only final text answers are cached; tool calls must reach the agent loop every time.
+        if response.tool_calls.is_empty() {
+            self.cache.cache_response(cache_key, response.content.clone());
+        }
+
         Ok(response)
     }
 
@@ -77,4 +69,8 @@ impl Provider for CachedProvider {
     fn models(&self) -> &[crate::trait_::ModelInfo] {
         self.provider.models()
     }
+
+    async fn list_models(&self) -> Result<Vec<crate::trait_::ModelInfo>> {
+        self.provider.list_models().await
+    }
 }
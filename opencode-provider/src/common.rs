@@ -10,3 +10,82 @@ pub fn validate_api_key(api_key: &str) -> Result<()> {
 pub fn build_base_url(base_url: Option<&str>, default: &str) -> String {
     base_url.map(|s| s.to_string()).unwrap_or_else(|| default.to_string())
 }
+
+/// Parses tool call arguments sent by the model as a JSON string. Empty input becomes `{}`;
+/// malformed JSON is passed through as a string so the tool can report a validation error.
+pub fn parse_tool_arguments(raw: &str) -> serde_json::Value {
+    if raw.trim().is_empty() {
+        return serde_json::json!({});
+    }
+    serde_json::from_str(raw).unwrap_or_else(|_| serde_json::Value::String(raw.to_string()))
+}
+
+/// Maps a failed request to an error. Connection failures and timeouts are transient.
+pub fn request_error(e: reqwest::Error) -> Error {
+    let message = format!("Request failed: {}", e);
+    if e.is_connect() || e.is_timeout() || e.is_request() {
+        Error::ProviderUnavailable {
+            message,
+            retry_after: None,
+        }
+    } else {
+        Error::Provider(message)
+    }
+}
+
+/// Maps a non-success response to an error. 429 and 5xx are transient and carry the
+/// `Retry-After` delay when the server sent one.
+pub async fn api_error(response: reqwest::Response) -> Error {
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_retry_after);
+    let text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+    let message = format!("API error {}: {}", status, text);
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+        Error::ProviderUnavailable {
+            message,
+            retry_after,
+        }
+    } else {
+        Error::Provider(message)
+    }
+}
+
+/// Parses a `Retry-After` value: delay in seconds or an HTTP date.
+pub fn parse_retry_after(value: &str) -> Option<std::time::Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(std::time::Duration::from_secs(secs));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = at.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(delay.to_std().unwrap_or_default())
+}
+
+/// Retry delay named in an error message, for clients that drop the `Retry-After` header but keep
+/// the server's hint in the text: "Retry-After: 20", "retry after 20s" or "Please try again in
+/// 1.5s" (seconds unless `ms` or minutes are given).
+pub fn retry_after_hint(message: &str) -> Option<std::time::Duration> {
+    let lower = message.to_lowercase();
+    ["retry-after:", "retry after", "try again in"]
+        .iter()
+        .find_map(|marker| {
+            let rest = lower[lower.find(marker)? + marker.len()..].trim_start();
+            let end = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            let value: f64 = rest[..end].parse().ok()?;
+            let unit = rest[end..].trim_start();
+            let secs = if unit.starts_with("ms") || unit.starts_with("millisecond") {
+                value / 1000.0
+            } else if unit.starts_with('m') {
+                value * 60.0
+            } else {
+                value
+            };
+            std::time::Duration::try_from_secs_f64(secs).ok()
+        })
+}
@@ -7,13 +7,43 @@ use langchain_ai_rust::agent::{create_deep_agent_from_llm, DeepAgentConfig};
 use langchain_ai_rust::language_models::llm::LLM;
 use langchain_ai_rust::language_models::{GenerateResult, LLMError};
 use langchain_ai_rust::schemas::messages::Message;
+use opencode_core::context::ContextManager;
 use opencode_core::error::{Error, Result};
+use opencode_core::redact::Redactor;
 use opencode_core::tool::{Tool, ToolContext};
+use opencode_core::usage::TokenUsage;
+use opencode_core::SessionStateHandle;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 /// Wraps Arc<dyn LLM> so it can be passed to create_deep_agent_from_llm (which expects Into<Box<dyn LLM>>).
-struct LlmArcWrapper(Arc<dyn LLM>);
+/// Adds up the token usage the responses report, since the agent only returns the reply text.
+#[derive(Clone)]
+struct LlmArcWrapper {
+    llm: Arc<dyn LLM>,
+    usage: Arc<Mutex<Option<TokenUsage>>>,
+    model: Option<String>,
+}
+
+impl LlmArcWrapper {
+    fn record(
+        usage: &Mutex<Option<TokenUsage>>,
+        model: &Option<String>,
+        tokens: &langchain_ai_rust::language_models::TokenUsage,
+    ) {
+        let mut usage = usage.lock().unwrap_or_else(|e| e.into_inner());
+        usage
+            .get_or_insert_with(|| TokenUsage {
+                model: model.clone(),
+                ..Default::default()
+            })
+            .add(&TokenUsage {
+                prompt_tokens: tokens.prompt_tokens.into(),
+                completion_tokens: tokens.completion_tokens.into(),
+                ..Default::default()
+            });
+    }
+}
 
 #[async_trait]
 impl LLM for LlmArcWrapper {
@@ -21,7 +51,11 @@ impl LLM for LlmArcWrapper {
         &self,
         messages: &[Message],
     ) -> std::result::Result<GenerateResult, LLMError> {
-        self.0.generate(messages).await
+        let result = self.llm.generate(messages).await?;
+        if let Some(tokens) = &result.tokens {
+            Self::record(&self.usage, &self.model, tokens);
+        }
+        Ok(result)
     }
 
     async fn stream(
@@ -36,14 +70,30 @@ impl LLM for LlmArcWrapper {
         >,
         LLMError,
     > {
-        self.0.stream(messages).await
+        use futures::StreamExt;
+
+        let usage = self.usage.clone();
+        let model = self.model.clone();
+        let stream = self.llm.stream(messages).await?;
+        Ok(Box::pin(stream.inspect(move |item| {
+            if let Ok(langchain_ai_rust::schemas::StreamData {
+                tokens: Some(tokens),
+                ..
+            }) = item
+            {
+                Self::record(&usage, &model, tokens);
+            }
+        })))
     }
 }
 
-impl langchain_ai_rust::language_models::llm::LLMClone for LlmArcWrapper {
-    fn clone_box(&self) -> Box<dyn LLM> {
-        Box::new(LlmArcWrapper(Arc::clone(&self.0)))
-    }
+/// The final reply of a deep agent turn.
+#[derive(Debug, Clone)]
+pub struct DeepAgentReply {
+    pub content: String,
+    /// Token usage summed over every model call of the turn (including in-turn summaries);
+    /// None when the provider reported none.
+    pub usage: Option<TokenUsage>,
 }
 
 /// Builds system prompt for a deep agent turn (coding assistant + optional workspace).
@@ -107,12 +157,18 @@ pub struct DeepAgentTurnConfig {
     pub use_crate_filesystem: bool,
     /// When set, called after each tool run with event details (for TUI log).
     pub on_tool_call: Option<OnToolCall>,
-    /// If set, only the last N messages are sent to the agent (keeps first System if outside window).
-    pub max_history_messages: Option<usize>,
-    /// If set, truncate each message content to this many characters (UTF-8 safe), appending " ... (truncated)".
-    pub max_message_content_len: Option<usize>,
+    /// Token budget: history is fitted to it before the turn, and the agent's in-turn
+    /// summarization starts once the conversation grows past it. Defaults to `ContextManager::default()`.
+    pub context_window: Option<ContextManager>,
     /// If set, cap agent steps per turn (langchain default is 10). Set in config as max_agent_iterations.
     pub max_iterations: Option<i32>,
+    /// Session state machine driven by the tool runs; a doom loop aborts the turn.
+    pub session_state: Option<SessionStateHandle>,
+    /// Masks secrets in the history and input sent to the model. Tool results are masked by
+    /// passing `RedactingTool`s.
+    pub redactor: Option<Arc<Redactor>>,
+    /// Model name recorded with the turn's token usage (for pricing).
+    pub model: Option<String>,
 }
 
 impl Default for DeepAgentTurnConfig {
@@ -122,65 +178,18 @@ impl Default for DeepAgentTurnConfig {
             read_only: false,
             use_crate_filesystem: true,
             on_tool_call: None,
-            max_history_messages: None,
-            max_message_content_len: None,
+            context_window: None,
             max_iterations: None,
+            session_state: None,
+            redactor: None,
+            model: None,
         }
     }
 }
 
-/// Compress session history for context window: optional sliding window and per-message truncation.
-fn compress_session_messages(
-    messages: &[opencode_core::Message],
-    max_history: Option<usize>,
-    max_content_len: Option<usize>,
-) -> Vec<opencode_core::Message> {
-    use opencode_core::Role;
-
-    let mut out: Vec<opencode_core::Message> = if let Some(n) = max_history {
-        let len = messages.len();
-        let start = len.saturating_sub(n);
-        let first_system_idx = messages.iter().position(|m| m.role == Role::System);
-        if let Some(sys_idx) = first_system_idx {
-            if sys_idx < start {
-                let mut v = vec![messages[sys_idx].clone()];
-                v.extend_from_slice(&messages[start..]);
-                v
-            } else {
-                messages[start..].to_vec()
-            }
-        } else {
-            messages[start..].to_vec()
-        }
-    } else {
-        messages.to_vec()
-    };
-
-    if let Some(max_len) = max_content_len {
-        const TRUNCATED_SUFFIX: &str = " ... (truncated)";
-        out = out
-            .into_iter()
-            .map(|m| {
-                let content = if m.content.chars().count() > max_len {
-                    let truncated: String = m.content.chars().take(max_len).collect();
-                    format!("{}{}", truncated, TRUNCATED_SUFFIX)
-                } else {
-                    m.content
-                };
-                opencode_core::Message {
-                    content,
-                    ..m
-                }
-            })
-            .collect();
-    }
-
-    out
-}
-
 /// Runs one deep agent turn: builds agent with the given LLM and tools, converts message history
-/// + new user input to langchain messages, invokes the agent, and returns the final reply string.
-/// No streaming; the caller should send the reply to the UI in one shot.
+/// + new user input to langchain messages, invokes the agent, and returns the final reply with the
+/// turn's token usage. No streaming; the caller should send the reply to the UI in one shot.
 pub async fn run_deep_agent_turn(
     llm: &Arc<dyn langchain_ai_rust::language_models::llm::LLM>,
     session_messages: &[opencode_core::Message],
@@ -188,7 +197,7 @@ pub async fn run_deep_agent_turn(
     tools: &[Arc<dyn Tool>],
     tool_ctx: &ToolContext,
     config: DeepAgentTurnConfig,
-) -> Result<String> {
+) -> Result<DeepAgentReply> {
     let on_tool_call = config.on_tool_call.clone();
     let langchain_tools: Vec<Arc<dyn langchain_ai_rust::tools::Tool>> = tools
         .iter()
@@ -196,9 +205,12 @@ pub async fn run_deep_agent_turn(
         .map(|t| {
             let ctx = tool_ctx.clone();
             let cb = on_tool_call.clone();
-            Arc::new(crate::langchain_tool_adapter::LangChainToolAdapter::new_with_context_and_callback(
-                t, ctx, cb,
-            )) as Arc<dyn langchain_ai_rust::tools::Tool>
+            Arc::new(
+                crate::langchain_tool_adapter::LangChainToolAdapter::new_with_context_and_callback(
+                    t, ctx, cb,
+                )
+                .with_session_state(config.session_state.clone()),
+            ) as Arc<dyn langchain_ai_rust::tools::Tool>
         })
         .collect();
     let tool_names: Vec<String> = tools.iter().map(|t| t.id().to_string()).collect();
@@ -219,11 +231,18 @@ pub async fn run_deep_agent_turn(
     if config.read_only {
         agent_config = agent_config.with_filesystem(false);
     }
+    let counted_llm = LlmArcWrapper {
+        llm: llm.clone(),
+        usage: Arc::new(Mutex::new(None)),
+        model: config.model.clone(),
+    };
+    let context_window = config.context_window.clone().unwrap_or_default();
+    // Summarize within the turn by token budget only; tool-heavy turns hit any message count fast.
     let summarization = SummarizationMiddleware::new()
-        .with_message_threshold(50)
-        .with_token_threshold(4000)
+        .with_message_threshold(usize::MAX)
+        .with_token_threshold(context_window.budget() * 3 / 4)
         .with_preserve_recent(10)
-        .with_summarizer(llm.clone());
+        .with_summarizer(Arc::new(counted_llm.clone()));
     agent_config = agent_config.with_middleware(vec![Arc::new(summarization)]);
 
     let system_prompt = build_turn_system_prompt(
@@ -232,7 +251,7 @@ pub async fn run_deep_agent_turn(
     );
 
     let mut agent = create_deep_agent_from_llm(
-        LlmArcWrapper(llm.clone()),
+        counted_llm.clone(),
         &langchain_tools,
         Some(&system_prompt),
         agent_config,
@@ -243,22 +262,42 @@ pub async fn run_deep_agent_turn(
         agent = agent.with_max_iterations(n);
     }
 
-    let compressed = compress_session_messages(
-        session_messages,
-        config.max_history_messages,
-        config.max_message_content_len,
-    );
-    let mut messages = session_messages_to_langchain(&compressed);
+    let mut fitted = context_window.fit(session_messages);
+    let mut user_input = user_input.to_string();
+    if let Some(redactor) = &config.redactor {
+        for message in &mut fitted.messages {
+            let (masked, found) = redactor.redact(&message.content);
+            if !found.is_empty() {
+                message.content = masked;
+            }
+        }
+        user_input = redactor.redact(&user_input).0;
+    }
+    let mut messages = session_messages_to_langchain(&fitted.messages);
     messages.push(Message::new_human_message(user_input));
 
-    let reply = agent
-        .invoke_messages(messages)
-        .await
-        .map_err(|e| Error::Provider(format!("DeepAgent invoke failed: {}", e)))?;
+    // The agent feeds tool errors back to the model, so a doom loop is cut off from here.
+    let invoke = agent.invoke_messages(messages);
+    let reply = match &config.session_state {
+        Some(state) => tokio::select! {
+            reply = invoke => reply,
+            e = state.doom_loop() => return Err(e),
+        },
+        None => invoke.await,
+    }
+    .map_err(|e| Error::Provider(format!("DeepAgent invoke failed: {}", e)))?;
 
     // Strip <think> / think> blocks so the UI shows only the visible reply. If the model
     // returns non-JSON (e.g. <think> first), the output parser may not recognize tool
     // calls and will return text only; stripping still ensures a clean reply.
     let cleaned = crate::langchain_adapter::strip_thinking_blocks(&reply);
-    Ok(cleaned)
+    let usage = counted_llm
+        .usage
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take();
+    Ok(DeepAgentReply {
+        content: cleaned,
+        usage,
+    })
 }
@@ -0,0 +1,194 @@
+//! Builds providers from `ProviderConfig` by `provider_type`. Built-in types are registered by
+//! `ProviderFactory::default()`; embedders can add their own with `register`.
+
+use crate::failover::{FailoverProvider, RetryPolicy};
+use crate::models::ModelCatalog;
+use crate::trait_::{ModelInfo, Provider};
+use crate::{AnthropicProvider, LangChainAdapter, OpenAIProvider};
+use opencode_core::config::ProviderConfig;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ProviderFactoryError {
+    #[error("Unsupported provider type: {0}")]
+    UnsupportedType(String),
+
+    #[error("No provider configured")]
+    NoProvider,
+
+    #[error("No API key configured for provider '{0}'")]
+    MissingApiKey(String),
+
+    #[error("Error initializing {provider_type} provider: {source}")]
+    Init {
+        provider_type: String,
+        #[source]
+        source: opencode_core::error::Error,
+    },
+}
+
+impl From<ProviderFactoryError> for opencode_core::error::Error {
+    fn from(e: ProviderFactoryError) -> Self {
+        opencode_core::error::Error::Provider(e.to_string())
+    }
+}
+
+/// Builds a provider from its config. The config's `api_key` is already resolved (see
+/// `ProviderFactory::build`).
+pub type ProviderBuilder = Arc<
+    dyn Fn(&ProviderConfig) -> Result<Arc<dyn Provider>, ProviderFactoryError> + Send + Sync,
+>;
+
+#[derive(Clone)]
+pub struct ProviderFactory {
+    builders: BTreeMap<String, ProviderBuilder>,
+    catalog: Arc<ModelCatalog>,
+}
+
+impl ProviderFactory {
+    /// Factory without any provider types registered.
+    pub fn empty() -> Self {
+        Self {
+            builders: BTreeMap::new(),
+            catalog: Arc::new(ModelCatalog::default()),
+        }
+    }
+
+    /// Registers (or replaces) the builder for `provider_type`.
+    pub fn register<F>(&mut self, provider_type: impl Into<String>, builder: F)
+    where
+        F: Fn(&ProviderConfig) -> Result<Arc<dyn Provider>, ProviderFactoryError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.builders.insert(provider_type.into(), Arc::new(builder));
+    }
+
+    pub fn provider_types(&self) -> Vec<&str> {
+        self.builders.keys().map(String::as_str).collect()
+    }
+
+    pub fn supports(&self, provider_type: &str) -> bool {
+        self.builders.contains_key(provider_type)
+    }
+
+    /// Builds the provider for `config.provider_type`. A missing `api_key` is filled from the
+    /// environment (see `env_api_key`) before the builder runs.
+    pub fn build(&self, config: &ProviderConfig) -> Result<Arc<dyn Provider>, ProviderFactoryError> {
+        let builder = self
+            .builders
+            .get(&config.provider_type)
+            .ok_or_else(|| ProviderFactoryError::UnsupportedType(config.provider_type.clone()))?;
+        let mut config = config.clone();
+        config.api_key = config
+            .api_key
+            .filter(|k| !k.trim().is_empty())
+            .or_else(|| env_api_key(&config.provider_type));
+        builder(&config)
+    }
+
+    /// Builds `chain[0]` with the rest as fallbacks, wrapped in a `FailoverProvider` so transient
+    /// failures are retried per `policy`. Fallbacks that fail to build are logged and skipped.
+    pub fn build_chain(
+        &self,
+        chain: &[ProviderConfig],
+        policy: RetryPolicy,
+    ) -> Result<Arc<dyn Provider>, ProviderFactoryError> {
+        let (primary, fallbacks) = chain
+            .split_first()
+            .ok_or(ProviderFactoryError::NoProvider)?;
+        let mut providers = vec![(primary.id.clone(), self.build(primary)?)];
+        for config in fallbacks {
+            match self.build(config) {
+                Ok(provider) => providers.push((config.id.clone(), provider)),
+                Err(e) => {
+                    tracing::warn!(provider = %config.id, error = %e, "skipping fallback provider")
+                }
+            }
+        }
+        Ok(Arc::new(FailoverProvider::new(providers, policy)))
+    }
+
+    /// Models offered by the provider `config` describes, cached per provider id, type and base
+    /// URL for the catalog's TTL.
+    pub async fn list_models(
+        &self,
+        config: &ProviderConfig,
+    ) -> opencode_core::error::Result<Vec<ModelInfo>> {
+        let key = format!(
+            "{}|{}|{}",
+            config.id,
+            config.provider_type,
+            config.base_url.as_deref().unwrap_or("")
+        );
+        let provider = self.build(config)?;
+        self.catalog.list(&key, provider.as_ref()).await
+    }
+}
+
+impl Default for ProviderFactory {
+    /// Factory with the built-in types: openai (also for OpenAI-compatible endpoints, via
+    /// `base_url`) and anthropic use the native clients; ollama and qwen go through langchain.
+    fn default() -> Self {
+        let mut factory = Self::empty();
+        factory.register("openai", |c| {
+            let api_key = require_api_key(c)?;
+            OpenAIProvider::new(api_key, c.base_url.clone())
+                .map(|p| Arc::new(p.with_model(c.model.clone())) as Arc<dyn Provider>)
+                .map_err(|e| init_error(c, e))
+        });
+        factory.register("ollama", |c| {
+            LangChainAdapter::from_ollama(c.base_url.clone(), c.model.clone())
+                .map(|p| Arc::new(p) as Arc<dyn Provider>)
+                .map_err(|e| init_error(c, e))
+        });
+        factory.register("qwen", |c| {
+            let api_key = require_api_key(c)?;
+            LangChainAdapter::from_qwen(api_key, c.base_url.clone(), c.model.clone())
+                .map(|p| Arc::new(p) as Arc<dyn Provider>)
+                .map_err(|e| init_error(c, e))
+        });
+        factory.register("anthropic", |c| {
+            let api_key = require_api_key(c)?;
+            AnthropicProvider::new(api_key, c.base_url.clone())
+                .map(|p| Arc::new(p.with_model(c.model.clone())) as Arc<dyn Provider>)
+                .map_err(|e| init_error(c, e))
+        });
+        factory
+    }
+}
+
+/// API key from the environment, from the variables of `provider_type` only: `OPENAI_API_KEY` or
+/// `OPENCODE_OPENAI_API_KEY` for openai, `DASHSCOPE_API_KEY` for qwen, `ANTHROPIC_API_KEY` for
+/// anthropic. A key is never sent to another vendor's endpoint.
+pub fn env_api_key(provider_type: &str) -> Option<String> {
+    let names: &[&str] = match provider_type {
+        "openai" => &["OPENAI_API_KEY", "OPENCODE_OPENAI_API_KEY"],
+        "qwen" => &["DASHSCOPE_API_KEY"],
+        "anthropic" => &["ANTHROPIC_API_KEY"],
+        _ => &[],
+    };
+    names
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|k| !k.trim().is_empty())
+}
+
+/// Returns the config's API key, or `MissingApiKey` when it is unset or blank.
+pub fn require_api_key(config: &ProviderConfig) -> Result<String, ProviderFactoryError> {
+    config
+        .api_key
+        .clone()
+        .filter(|k| !k.trim().is_empty())
+        .ok_or_else(|| ProviderFactoryError::MissingApiKey(config.id.clone()))
+}
+
+fn init_error(config: &ProviderConfig, source: opencode_core::error::Error) -> ProviderFactoryError {
+    ProviderFactoryError::Init {
+        provider_type: config.provider_type.clone(),
+        source,
+    }
+}
@@ -0,0 +1,224 @@
+//! Retry with exponential backoff and failover across a chain of providers.
+
+use crate::langchain_adapter::llm_error;
+use crate::trait_::{Chunk, GenerateRequest, GenerateResponse, ModelInfo, Provider};
+use async_trait::async_trait;
+use futures::Stream;
+use langchain_ai_rust::language_models::llm::LLM;
+use langchain_ai_rust::language_models::{GenerateResult, LLMError};
+use langchain_ai_rust::schemas::{Message, StreamData};
+use opencode_core::config::RetryConfig;
+use opencode_core::error::{Error, Result};
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::from(&RetryConfig::default())
+    }
+}
+
+impl From<&RetryConfig> for RetryPolicy {
+    fn from(c: &RetryConfig) -> Self {
+        Self {
+            max_retries: c.max_retries,
+            initial_backoff: Duration::from_millis(c.initial_backoff_ms),
+            max_backoff: Duration::from_millis(c.max_backoff_ms),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `attempt` (0-based), or None to stop retrying this provider.
+    /// A server-provided `retry_after` is used as is unless it exceeds `max_backoff`.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if attempt >= self.max_retries {
+            return None;
+        }
+        match retry_after {
+            Some(d) if d > self.max_backoff => None,
+            Some(d) => Some(d),
+            None => {
+                let factor = 2u32.saturating_pow(attempt);
+                Some(self.initial_backoff.saturating_mul(factor).min(self.max_backoff))
+            }
+        }
+    }
+}
+
+/// Provider that retries transient failures (`Error::ProviderUnavailable`) with backoff, then
+/// moves on to the next provider in the chain. Other errors are returned immediately.
+///
+/// Streams are retried only while establishing the stream; errors after the first chunk are
+/// passed through. `as_llm` returns a `FailoverLlm` over the providers' LLMs, so deep agent turns
+/// are retried and fail over the same way.
+pub struct FailoverProvider {
+    providers: Vec<(String, Arc<dyn Provider>)>,
+    policy: RetryPolicy,
+}
+
+impl FailoverProvider {
+    /// `providers` are (id, provider) pairs in the order they are tried; must not be empty.
+    pub fn new(providers: Vec<(String, Arc<dyn Provider>)>, policy: RetryPolicy) -> Self {
+        assert!(!providers.is_empty(), "FailoverProvider needs at least one provider");
+        Self { providers, policy }
+    }
+
+    pub fn provider_ids(&self) -> Vec<&str> {
+        self.providers.iter().map(|(id, _)| id.as_str()).collect()
+    }
+
+    async fn call<T, F, Fut>(&self, op: F) -> Result<T>
+    where
+        F: Fn(Arc<dyn Provider>) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        with_failover(&self.providers, &self.policy, provider_retry_after, op).await
+    }
+}
+
+/// The server's retry delay of a transient provider error; None for other errors.
+fn provider_retry_after(e: &Error) -> Option<Option<Duration>> {
+    match e {
+        Error::ProviderUnavailable { retry_after, .. } => Some(*retry_after),
+        _ => None,
+    }
+}
+
+/// Runs `op` on the members of `chain` in order. A transient error (one `transient` returns a
+/// retry delay for) is retried with backoff per `policy`, then the next member is tried; other
+/// errors are returned at once.
+async fn with_failover<P, T, E, F, Fut>(
+    chain: &[(String, P)],
+    policy: &RetryPolicy,
+    transient: fn(&E) -> Option<Option<Duration>>,
+    op: F,
+) -> std::result::Result<T, E>
+where
+    P: Clone,
+    E: Display,
+    F: Fn(P) -> Fut,
+    Fut: Future<Output = std::result::Result<T, E>>,
+{
+    let mut last_error = None;
+    for (id, member) in chain {
+        let mut attempt = 0;
+        loop {
+            match op(member.clone()).await {
+                Ok(value) => {
+                    tracing::info!(provider = %id, attempt, "provider answered");
+                    return Ok(value);
+                }
+                Err(e) => {
+                    let Some(retry_after) = transient(&e) else {
+                        return Err(e);
+                    };
+                    let Some(delay) = policy.delay(attempt, retry_after) else {
+                        tracing::warn!(provider = %id, error = %e, "provider unavailable, trying next");
+                        last_error = Some(e);
+                        break;
+                    };
+                    tracing::warn!(
+                        provider = %id,
+                        attempt,
+                        delay_ms = delay.as_millis() as u64,
+                        error = %e,
+                        "transient provider error, retrying"
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+    Err(last_error.expect("failover chain is not empty"))
+}
+
+#[async_trait]
+impl Provider for FailoverProvider {
+    async fn generate(&self, request: GenerateRequest) -> Result<GenerateResponse> {
+        self.call(|p| {
+            let request = request.clone();
+            async move { p.generate(request).await }
+        })
+        .await
+    }
+
+    async fn stream(
+        &self,
+        request: GenerateRequest,
+    ) -> Result<Box<dyn Stream<Item = Result<Chunk>> + Send + Unpin>> {
+        self.call(|p| {
+            let request = request.clone();
+            async move { p.stream(request).await }
+        })
+        .await
+    }
+
+    fn models(&self) -> &[ModelInfo] {
+        self.providers[0].1.models()
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        self.providers[0].1.list_models().await
+    }
+
+    /// None when the primary provider has no LLM; fallbacks without one are left out.
+    fn as_llm(&self) -> Option<Arc<dyn LLM>> {
+        self.providers[0].1.as_llm()?;
+        let llms: Vec<(String, Arc<dyn LLM>)> = self
+            .providers
+            .iter()
+            .filter_map(|(id, provider)| Some((id.clone(), provider.as_llm()?)))
+            .collect();
+        Some(Arc::new(FailoverLlm {
+            llms,
+            policy: self.policy.clone(),
+        }))
+    }
+}
+
+/// LLM that retries and fails over like `FailoverProvider`, for deep agent turns. Transient
+/// errors are recognized with `llm_error`.
+#[derive(Clone)]
+pub struct FailoverLlm {
+    llms: Vec<(String, Arc<dyn LLM>)>,
+    policy: RetryPolicy,
+}
+
+fn llm_retry_after(e: &LLMError) -> Option<Option<Duration>> {
+    provider_retry_after(&llm_error("LLM invocation failed", e))
+}
+
+#[async_trait]
+impl LLM for FailoverLlm {
+    async fn generate(&self, messages: &[Message]) -> std::result::Result<GenerateResult, LLMError> {
+        with_failover(&self.llms, &self.policy, llm_retry_after, |llm| async move {
+            llm.generate(messages).await
+        })
+        .await
+    }
+
+    async fn stream(
+        &self,
+        messages: &[Message],
+    ) -> std::result::Result<
+        Pin<Box<dyn Stream<Item = std::result::Result<StreamData, LLMError>> + Send>>,
+        LLMError,
+    > {
+        with_failover(&self.llms, &self.policy, llm_retry_after, |llm| async move {
+            llm.stream(messages).await
+        })
+        .await
+    }
+}
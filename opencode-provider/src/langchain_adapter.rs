@@ -1,17 +1,43 @@
-use crate::trait_::{Chunk, GenerateRequest, GenerateResponse, Provider};
+use crate::trait_::{Chunk, GenerateRequest, GenerateResponse, Provider, Usage};
 use async_trait::async_trait;
 use futures::Stream;
 use futures::StreamExt;
 use opencode_core::error::{Error, Result};
 use std::sync::Arc;
 
+/// Provider backed by a langchain LLM. Tool definitions on requests are not forwarded; tool use
+/// for these providers goes through the deep agent path (see `as_llm`).
 pub struct LangChainAdapter {
     llm: Arc<dyn langchain_ai_rust::language_models::llm::LLM>,
+    /// Model name reported with token usage.
+    model: Option<String>,
+    listing: ModelListing,
+}
+
+/// Endpoint `list_models` queries; set by the `from_*` constructors.
+enum ModelListing {
+    None,
+    OpenAiCompatible {
+        provider: &'static str,
+        base_url: String,
+        api_key: String,
+    },
+    Ollama { root_url: String },
 }
 
 impl LangChainAdapter {
     pub fn new(llm: Arc<dyn langchain_ai_rust::language_models::llm::LLM>) -> Self {
-        Self { llm }
+        Self {
+            llm,
+            model: None,
+            listing: ModelListing::None,
+        }
+    }
+
+    /// Sets the model name reported with token usage.
+    pub fn with_model(mut self, model: Option<String>) -> Self {
+        self.model = model;
+        self
     }
 
     pub fn from_openai(
@@ -21,6 +47,13 @@ impl LangChainAdapter {
     ) -> Result<Self> {
         use langchain_ai_rust::llm::openai::{OpenAI, OpenAIConfig};
 
+        let listing = ModelListing::OpenAiCompatible {
+            provider: "openai",
+            base_url: base_url
+                .clone()
+                .unwrap_or_else(|| "https://api.openai.com/v1".to_string()),
+            api_key: api_key.clone(),
+        };
         let config = OpenAIConfig::default().with_api_key(api_key);
         let config = if let Some(url) = base_url {
             config.with_api_base(url)
@@ -28,10 +61,12 @@ impl LangChainAdapter {
             config
         };
         let model_name = model.unwrap_or_else(|| "gpt-4o-mini".to_string());
-        let openai = OpenAI::new(config).with_model(model_name);
+        let openai = OpenAI::new(config).with_model(model_name.clone());
 
         Ok(Self {
             llm: Arc::new(openai),
+            model: Some(model_name),
+            listing,
         })
     }
 
@@ -40,12 +75,20 @@ impl LangChainAdapter {
         use langchain_ai_rust::llm::openai::OpenAI;
 
         let api_base = base_url.unwrap_or_else(|| "http://localhost:11434/v1".to_string());
+        let listing = ModelListing::Ollama {
+            root_url: api_base
+                .trim_end_matches('/')
+                .trim_end_matches("/v1")
+                .to_string(),
+        };
         let config = OllamaConfig::default().with_api_base(api_base);
         let model_name = model.unwrap_or_else(|| "llama3.2".to_string());
-        let openai = OpenAI::new(config).with_model(model_name);
+        let openai = OpenAI::new(config).with_model(model_name.clone());
 
         Ok(Self {
             llm: Arc::new(openai),
+            model: Some(model_name),
+            listing,
         })
     }
 
@@ -56,6 +99,16 @@ impl LangChainAdapter {
     ) -> Result<Self> {
         use langchain_ai_rust::llm::qwen::Qwen;
 
+        let listing = ModelListing::OpenAiCompatible {
+            provider: "qwen",
+            base_url: base_url
+                .as_deref()
+                .unwrap_or("https://dashscope.aliyuncs.com/compatible-mode/v1")
+                .trim_end_matches('/')
+                .trim_end_matches("/chat/completions")
+                .to_string(),
+            api_key: api_key.clone(),
+        };
         let qwen = Qwen::new().with_api_key(api_key);
         let qwen = if let Some(url) = base_url {
             qwen.with_base_url(url)
@@ -63,25 +116,60 @@ impl LangChainAdapter {
             qwen
         };
         let model_name = model.unwrap_or_else(|| "qwen-turbo".to_string());
-        let qwen = qwen.with_model(model_name);
+        let qwen = qwen.with_model(model_name.clone());
 
         Ok(Self {
             llm: Arc::new(qwen),
+            model: Some(model_name),
+            listing,
         })
     }
 
-    pub fn from_anthropic(_api_key: String) -> Result<Self> {
-        Err(Error::Provider(
-            "Anthropic support not available in langchain-ai-rust".to_string(),
-        ))
-    }
-
     /// Returns the underlying LLM for use with deep agent (e.g. run_deep_agent_turn).
     pub fn llm(&self) -> Arc<dyn langchain_ai_rust::language_models::llm::LLM> {
         Arc::clone(&self.llm)
     }
 }
 
+/// Maps a langchain error to a provider error. Network failures, timeouts and rate limits are
+/// reported as `Error::ProviderUnavailable` so they can be retried; the wrapped client errors
+/// are not exposed as types, so this falls back to matching the message. The clients drop the
+/// response headers, so the retry delay is taken from a hint in the message (see
+/// `retry_after_hint`).
+pub(crate) fn llm_error(context: &str, e: &langchain_ai_rust::language_models::LLMError) -> Error {
+    use langchain_ai_rust::language_models::LLMError;
+
+    let message = format!("{}: {}", context, e);
+    let transient = match e {
+        LLMError::RequestError(_) | LLMError::Timeout(_) => true,
+        _ => {
+            let text = e.to_string().to_lowercase();
+            [
+                "http error",
+                "rate limit",
+                "network error",
+                "timed out",
+                "connection reset",
+                " 429",
+                " 502",
+                " 503",
+                " 504",
+            ]
+            .iter()
+            .any(|needle| text.contains(needle))
+        }
+    };
+    if transient {
+        let retry_after = crate::common::retry_after_hint(&message);
+        Error::ProviderUnavailable {
+            message,
+            retry_after,
+        }
+    } else {
+        Error::Provider(message)
+    }
+}
+
 /// Strips `think>...</think>` and `<think>...</think>` blocks from model output so only the
 /// visible reply is shown (e.g. for One API / deep-thinking / MiniMax-style models).
 /// Unclosed blocks are dropped up to the next `\n\n` or end of string.
@@ -122,39 +210,69 @@ pub(crate) fn strip_thinking_blocks(s: &str) -> String {
     out.trim().to_string()
 }
 
+fn to_langchain_messages(
+    messages: Vec<crate::trait_::Message>,
+) -> Vec<langchain_ai_rust::schemas::Message> {
+    use langchain_ai_rust::schemas::Message;
+
+    messages
+        .into_iter()
+        .map(|m| match m.role {
+            crate::trait_::MessageRole::System => Message::new_system_message(m.content),
+            crate::trait_::MessageRole::User => Message::new_human_message(m.content),
+            crate::trait_::MessageRole::Assistant => Message::new_ai_message(m.content),
+            crate::trait_::MessageRole::Tool => {
+                Message::new_tool_message(m.content, m.tool_call_id.unwrap_or_default())
+            }
+        })
+        .collect()
+}
+
+fn to_usage(
+    tokens: langchain_ai_rust::language_models::TokenUsage,
+    model: Option<String>,
+) -> Usage {
+    Usage {
+        prompt_tokens: tokens.prompt_tokens,
+        completion_tokens: tokens.completion_tokens,
+        total_tokens: tokens.total_tokens,
+        model,
+        ..Default::default()
+    }
+}
+
 #[async_trait]
 impl Provider for LangChainAdapter {
     async fn generate(&self, request: GenerateRequest) -> Result<GenerateResponse> {
-        let prompt = request
-            .messages
-            .iter()
-            .map(|m| match m.role {
-                crate::trait_::MessageRole::System => format!("System: {}\n", m.content),
-                crate::trait_::MessageRole::User => format!("User: {}\n", m.content),
-                crate::trait_::MessageRole::Assistant => format!("Assistant: {}\n", m.content),
-            })
-            .collect::<Vec<_>>()
-            .join("");
-        tracing::info!(prompt_len = prompt.len(), "LangChainAdapter: llm.invoke start");
-        let prompt_trunc = if prompt.len() > 200 { &prompt[..200] } else { prompt.as_str() };
-        tracing::debug!(prompt_trunc = %prompt_trunc, "LangChainAdapter: request detail");
-        let response = match self.llm.invoke(&prompt).await {
+        let messages = to_langchain_messages(request.messages);
+        let prompt_len: usize = messages.iter().map(|m| m.content.len()).sum();
+        tracing::info!(
+            message_count = messages.len(),
+            prompt_len,
+            "LangChainAdapter: llm.generate start"
+        );
+        let result = match self.llm.generate(&messages).await {
             Ok(r) => {
-                tracing::info!(response_len = r.len(), "LangChainAdapter: llm.invoke succeeded");
+                tracing::info!(
+                    response_len = r.generation.len(),
+                    "LangChainAdapter: llm.generate succeeded"
+                );
                 r
             }
             Err(e) => {
-                tracing::error!(error = %e, "llm.invoke failed");
-                return Err(Error::Provider(format!("LLM invocation failed: {}", e)));
+                tracing::error!(error = %e, "llm.generate failed");
+                return Err(llm_error("LLM invocation failed", &e));
             }
         };
 
-        let response_trunc = if response.len() > 500 { &response[..500] } else { response.as_str() };
+        let response = result.generation;
+        let response_trunc = response.get(..500).unwrap_or(&response);
         tracing::debug!(response_trunc = %response_trunc, "LangChainAdapter: response detail");
         let content = strip_thinking_blocks(&response);
         Ok(GenerateResponse {
             content,
-            usage: None,
+            usage: result.tokens.map(|t| to_usage(t, self.model.clone())),
+            tool_calls: Vec::new(),
         })
     }
 
@@ -162,28 +280,21 @@ impl Provider for LangChainAdapter {
         &self,
         request: GenerateRequest,
     ) -> Result<Box<dyn Stream<Item = Result<Chunk>> + Send + Unpin>> {
-        use langchain_ai_rust::schemas::Message;
-
-        let messages: Vec<Message> = request
-            .messages
-            .into_iter()
-            .map(|m| match m.role {
-                crate::trait_::MessageRole::System => Message::new_system_message(m.content),
-                crate::trait_::MessageRole::User => Message::new_human_message(m.content),
-                crate::trait_::MessageRole::Assistant => Message::new_ai_message(m.content),
-            })
-            .collect();
+        let messages = to_langchain_messages(request.messages);
 
         let stream = self
             .llm
             .stream(&messages)
             .await
-            .map_err(|e| Error::Provider(format!("LLM stream failed: {}", e)))?;
+            .map_err(|e| llm_error("LLM stream failed", &e))?;
 
-        let mapped = stream.map(|r| {
+        let model = self.model.clone();
+        let mapped = stream.map(move |r| {
             r.map(|stream_data| Chunk {
                 content: stream_data.content,
                 done: false,
+                tool_calls: Vec::new(),
+                usage: stream_data.tokens.map(|t| to_usage(t, model.clone())),
             })
             .map_err(|e| Error::Provider(format!("Stream error: {}", e)))
         });
@@ -194,6 +305,24 @@ impl Provider for LangChainAdapter {
         &[]
     }
 
+    async fn list_models(&self) -> Result<Vec<crate::trait_::ModelInfo>> {
+        let client = reqwest::Client::new();
+        match &self.listing {
+            ModelListing::None => Ok(Vec::new()),
+            ModelListing::OpenAiCompatible {
+                provider,
+                base_url,
+                api_key,
+            } => {
+                crate::models::fetch_openai_models(&client, base_url, Some(api_key), provider)
+                    .await
+            }
+            ModelListing::Ollama { root_url } => {
+                crate::models::fetch_ollama_models(&client, root_url).await
+            }
+        }
+    }
+
     fn as_llm(&self) -> Option<Arc<dyn langchain_ai_rust::language_models::llm::LLM>> {
         Some(self.llm())
     }
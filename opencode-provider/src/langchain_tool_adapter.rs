@@ -1,6 +1,8 @@
 use async_trait::async_trait;
 use langchain_ai_rust::error::ToolError as LangChainToolError;
+use opencode_core::permission;
 use opencode_core::tool::{Tool, ToolContext};
+use opencode_core::SessionStateHandle;
 use serde_json::Value;
 use std::sync::Arc;
 
@@ -64,6 +66,8 @@ pub struct LangChainToolAdapter {
     context: Option<ToolContext>,
     /// When set, called after each tool run (for TUI log).
     on_tool_call: Option<OnToolCall>,
+    /// When set, tool runs drive the session state machine.
+    session_state: Option<SessionStateHandle>,
 }
 
 impl LangChainToolAdapter {
@@ -74,6 +78,7 @@ impl LangChainToolAdapter {
             tool,
             context: None,
             on_tool_call: None,
+            session_state: None,
         }
     }
 
@@ -83,6 +88,7 @@ impl LangChainToolAdapter {
             tool,
             context: Some(context),
             on_tool_call: None,
+            session_state: None,
         }
     }
 
@@ -96,8 +102,15 @@ impl LangChainToolAdapter {
             tool,
             context: Some(context),
             on_tool_call,
+            session_state: None,
         }
     }
+
+    /// Reports tool runs to the session state machine; a run that completes a doom loop fails.
+    pub fn with_session_state(mut self, session_state: Option<SessionStateHandle>) -> Self {
+        self.session_state = session_state;
+        self
+    }
 }
 
 #[async_trait]
@@ -129,11 +142,28 @@ impl langchain_ai_rust::tools::Tool for LangChainToolAdapter {
             agent: "langchain".to_string(),
             call_id: None,
             workspace_path: None,
+            permissions: None,
+            allowed_paths: Vec::new(),
         });
 
         let input = normalize_tool_input(tool_id, input, ctx.workspace_path.as_deref());
 
-        let result = tool.execute(input.clone(), &ctx).await;
+        if let Some(state) = &self.session_state {
+            state
+                .tool_started(tool_id)
+                .map_err(|e| LangChainToolError::ExecutionError(e.to_string()))?;
+        }
+        let result = match permission::authorize(tool_id, &input, &ctx).await {
+            Ok(_) => tool.execute(input.clone(), &ctx).await,
+            Err(e) => Err(e),
+        };
+        let verdict = match &self.session_state {
+            Some(state) => {
+                let error = result.as_ref().err().map(|e| e.to_string());
+                state.tool_finished(tool_id, &input, error.as_deref())
+            }
+            None => Ok(()),
+        };
         if let Some(ref cb) = self.on_tool_call {
             let input_preview = input.to_string();
             let input_preview = if input_preview.len() > 120 {
@@ -157,6 +187,7 @@ impl langchain_ai_rust::tools::Tool for LangChainToolAdapter {
             };
             cb(event);
         }
+        verdict.map_err(|e| LangChainToolError::ExecutionError(e.to_string()))?;
         match result {
             Ok(result) => {
                 tracing::info!(
@@ -4,10 +4,14 @@ pub mod cached_provider;
 pub mod common;
 pub mod deep_agent;
 pub mod deep_agent_turn;
+pub mod factory;
+pub mod failover;
 pub mod langchain_adapter;
 pub mod langchain_tool_adapter;
 pub mod message;
+pub mod models;
 pub mod provider_adapter;
+pub mod sse;
 pub mod trait_;
 
 #[cfg(test)]
@@ -17,8 +21,13 @@ pub use adapter::OpenAIProvider;
 pub use anthropic::AnthropicProvider;
 pub use cached_provider::CachedProvider;
 pub use deep_agent::try_deep_agent_agents_md;
-pub use deep_agent_turn::{run_deep_agent_turn, DeepAgentTurnConfig, OnToolCall, ToolCallEvent};
+pub use deep_agent_turn::{
+    run_deep_agent_turn, DeepAgentReply, DeepAgentTurnConfig, OnToolCall, ToolCallEvent,
+};
+pub use factory::{ProviderFactory, ProviderFactoryError};
+pub use failover::{FailoverLlm, FailoverProvider, RetryPolicy};
 pub use langchain_adapter::LangChainAdapter;
 pub use langchain_tool_adapter::LangChainToolAdapter;
+pub use models::ModelCatalog;
 pub use provider_adapter::ProviderAdapter;
 pub use trait_::Provider;
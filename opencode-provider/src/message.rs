@@ -1,17 +1,27 @@
 use chrono::Utc;
-use opencode_core::session::{Message as CoreMessage, Role as CoreRole};
-use crate::trait_::{Message, MessageRole};
+use opencode_core::agent::{
+    Message as AgentMessage, MessageRole as AgentRole, ToolDefinition as AgentToolDefinition,
+};
+use opencode_core::session::{
+    Message as CoreMessage, MessageMeta, Role as CoreRole, ToolCall as CoreToolCall,
+};
+use crate::trait_::{Message, MessageRole, ToolCall, ToolDefinition};
 
 pub fn to_provider_message(msg: &CoreMessage) -> Message {
     let role = match msg.role {
         CoreRole::User => MessageRole::User,
         CoreRole::Assistant => MessageRole::Assistant,
         CoreRole::System => MessageRole::System,
-        CoreRole::Tool => MessageRole::System,
+        CoreRole::Tool => MessageRole::Tool,
     };
+    let meta = msg.meta.as_ref();
     Message {
         role,
         content: msg.content.clone(),
+        tool_call_id: meta.and_then(|m| m.tool_call_id.clone()),
+        tool_calls: meta
+            .map(|m| m.tool_calls.iter().cloned().map(to_provider_tool_call).collect())
+            .unwrap_or_default(),
     }
 }
 
@@ -20,12 +30,86 @@ pub fn from_provider_message(msg: &Message) -> CoreMessage {
         MessageRole::User => CoreRole::User,
         MessageRole::Assistant => CoreRole::Assistant,
         MessageRole::System => CoreRole::System,
+        MessageRole::Tool => CoreRole::Tool,
+    };
+    let meta = if msg.tool_call_id.is_some() || !msg.tool_calls.is_empty() {
+        Some(MessageMeta {
+            tool_call_id: msg.tool_call_id.clone(),
+            tool_calls: msg.tool_calls.iter().cloned().map(from_provider_tool_call).collect(),
+            ..Default::default()
+        })
+    } else {
+        None
     };
 
     CoreMessage {
         role,
         content: msg.content.clone(),
         created_at: Utc::now(),
-        meta: None,
+        meta,
+    }
+}
+
+/// Converts a core agent message (as built by BuildAgent) to a provider message.
+pub fn from_agent_message(msg: AgentMessage) -> Message {
+    let role = match msg.role {
+        AgentRole::System => MessageRole::System,
+        AgentRole::User => MessageRole::User,
+        AgentRole::Assistant => MessageRole::Assistant,
+        AgentRole::Tool => MessageRole::Tool,
+    };
+    Message {
+        role,
+        content: msg.content,
+        tool_call_id: msg.tool_call_id,
+        tool_calls: msg.tool_calls.into_iter().map(to_provider_tool_call).collect(),
+    }
+}
+
+/// Converts a provider message back to a core agent message (e.g. for cache keys).
+pub fn to_agent_message(msg: &Message) -> AgentMessage {
+    let role = match msg.role {
+        MessageRole::System => AgentRole::System,
+        MessageRole::User => AgentRole::User,
+        MessageRole::Assistant => AgentRole::Assistant,
+        MessageRole::Tool => AgentRole::Tool,
+    };
+    AgentMessage {
+        role,
+        content: msg.content.clone(),
+        tool_call_id: msg.tool_call_id.clone(),
+        tool_calls: msg.tool_calls.iter().cloned().map(from_provider_tool_call).collect(),
+    }
+}
+
+pub fn to_provider_tool_call(call: CoreToolCall) -> ToolCall {
+    ToolCall {
+        id: call.id,
+        name: call.name,
+        arguments: call.arguments,
+    }
+}
+
+pub fn from_provider_tool_call(call: ToolCall) -> CoreToolCall {
+    CoreToolCall {
+        id: call.id,
+        name: call.name,
+        arguments: call.arguments,
+    }
+}
+
+pub fn to_provider_tool_definition(def: AgentToolDefinition) -> ToolDefinition {
+    ToolDefinition {
+        name: def.name,
+        description: def.description,
+        parameters: def.parameters,
+    }
+}
+
+pub fn from_provider_tool_definition(def: &ToolDefinition) -> AgentToolDefinition {
+    AgentToolDefinition {
+        name: def.name.clone(),
+        description: def.description.clone(),
+        parameters: def.parameters.clone(),
     }
 }
@@ -0,0 +1,154 @@
+//! Model discovery: listing endpoints for OpenAI-compatible servers, Ollama and Anthropic, plus
+//! a TTL cache so a long-running process (the desktop app's settings view) does not hit the
+//! endpoint on every call. The cache lives in memory; each `opencode models` run fetches anew.
+
+use crate::common::{api_error, request_error};
+use crate::trait_::{ModelInfo, Provider};
+use opencode_core::cache::ConcurrentCache;
+use opencode_core::error::{Error, Result};
+use reqwest::Client;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+/// How long a fetched model list is reused.
+pub const DEFAULT_MODELS_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Deserialize)]
+struct ModelList {
+    data: Vec<ListedModel>,
+}
+
+#[derive(Deserialize)]
+struct ListedModel {
+    id: String,
+    /// Set by Anthropic; OpenAI-compatible servers only send the id.
+    display_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OllamaTags {
+    models: Vec<OllamaModel>,
+}
+
+#[derive(Deserialize)]
+struct OllamaModel {
+    name: String,
+}
+
+async fn get_json<T: serde::de::DeserializeOwned>(request: reqwest::RequestBuilder) -> Result<T> {
+    let response = request.send().await.map_err(request_error)?;
+    if !response.status().is_success() {
+        return Err(api_error(response).await);
+    }
+    response
+        .json()
+        .await
+        .map_err(|e| Error::Provider(format!("Failed to parse model list: {}", e)))
+}
+
+fn sorted(mut models: Vec<ModelInfo>) -> Vec<ModelInfo> {
+    models.sort_by(|a, b| a.id.cmp(&b.id));
+    models
+}
+
+/// `GET {base_url}/models` on an OpenAI-compatible API.
+pub async fn fetch_openai_models(
+    client: &Client,
+    base_url: &str,
+    api_key: Option<&str>,
+    provider: &str,
+) -> Result<Vec<ModelInfo>> {
+    let mut request = client.get(format!("{}/models", base_url.trim_end_matches('/')));
+    if let Some(key) = api_key {
+        request = request.bearer_auth(key);
+    }
+    let list: ModelList = get_json(request).await?;
+    Ok(sorted(
+        list.data
+            .into_iter()
+            .map(|m| ModelInfo {
+                name: m.display_name.unwrap_or_else(|| m.id.clone()),
+                id: m.id,
+                provider: provider.to_string(),
+            })
+            .collect(),
+    ))
+}
+
+/// `GET {root_url}/api/tags` on an Ollama server. `root_url` is the server root, without `/v1`.
+pub async fn fetch_ollama_models(client: &Client, root_url: &str) -> Result<Vec<ModelInfo>> {
+    let request = client.get(format!("{}/api/tags", root_url.trim_end_matches('/')));
+    let tags: OllamaTags = get_json(request).await?;
+    Ok(sorted(
+        tags.models
+            .into_iter()
+            .map(|m| ModelInfo {
+                id: m.name.clone(),
+                name: m.name,
+                provider: "ollama".to_string(),
+            })
+            .collect(),
+    ))
+}
+
+/// `GET {base_url}/models` on the Anthropic API.
+pub async fn fetch_anthropic_models(
+    client: &Client,
+    base_url: &str,
+    api_key: &str,
+    anthropic_version: &str,
+) -> Result<Vec<ModelInfo>> {
+    let request = client
+        .get(format!("{}/models?limit=1000", base_url.trim_end_matches('/')))
+        .header("x-api-key", api_key)
+        .header("anthropic-version", anthropic_version);
+    let list: ModelList = get_json(request).await?;
+    Ok(sorted(
+        list.data
+            .into_iter()
+            .map(|m| ModelInfo {
+                name: m.display_name.unwrap_or_else(|| m.id.clone()),
+                id: m.id,
+                provider: "anthropic".to_string(),
+            })
+            .collect(),
+    ))
+}
+
+/// Model lists per provider, reused until the TTL expires. Failed fetches are not cached.
+pub struct ModelCatalog {
+    ttl: Duration,
+    cache: ConcurrentCache<String, (Instant, Vec<ModelInfo>)>,
+}
+
+impl ModelCatalog {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            cache: ConcurrentCache::new(),
+        }
+    }
+
+    /// Returns the cached list for `key`, or fetches it from `provider`.
+    pub async fn list(&self, key: &str, provider: &dyn Provider) -> Result<Vec<ModelInfo>> {
+        let key = key.to_string();
+        if let Some((fetched_at, models)) = self.cache.get(&key) {
+            if fetched_at.elapsed() < self.ttl {
+                return Ok(models);
+            }
+        }
+        let models = provider.list_models().await?;
+        self.cache.insert(key, (Instant::now(), models.clone()));
+        Ok(models)
+    }
+
+    pub fn invalidate(&self, key: &str) {
+        self.cache.remove(&key.to_string());
+    }
+}
+
+impl Default for ModelCatalog {
+    fn default() -> Self {
+        Self::new(DEFAULT_MODELS_TTL)
+    }
+}
@@ -1,5 +1,8 @@
-use crate::trait_::{GenerateRequest, GenerateResponse, Provider as ProviderTrait};
+use crate::message;
+use crate::trait_::{GenerateRequest, Provider as ProviderTrait, Usage};
 use opencode_core::agent::{Provider, ProviderChunk, ProviderRequest, ProviderResponse};
+use opencode_core::redact::Redactor;
+use opencode_core::usage::TokenUsage;
 use async_trait::async_trait;
 use futures::Stream;
 use futures::StreamExt;
@@ -8,11 +11,44 @@ use std::sync::Arc;
 
 pub struct ProviderAdapter {
     provider: Arc<dyn ProviderTrait>,
+    models: Vec<opencode_core::agent::ModelInfo>,
+    /// Masks secrets in every request before it is sent.
+    redactor: Option<Arc<Redactor>>,
 }
 
 impl ProviderAdapter {
     pub fn new(provider: Arc<dyn ProviderTrait>) -> Self {
-        Self { provider }
+        let models = provider
+            .models()
+            .iter()
+            .map(|m| opencode_core::agent::ModelInfo {
+                id: m.id.clone(),
+                name: m.name.clone(),
+            })
+            .collect();
+        Self {
+            provider,
+            models,
+            redactor: None,
+        }
+    }
+
+    /// Masks secrets in the messages of every request with `redactor`.
+    pub fn with_redactor(mut self, redactor: Arc<Redactor>) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    fn redact(&self, mut request: ProviderRequest) -> ProviderRequest {
+        if let Some(redactor) = &self.redactor {
+            for message in &mut request.messages {
+                let (masked, found) = redactor.redact(&message.content);
+                if !found.is_empty() {
+                    message.content = masked;
+                }
+            }
+        }
+        request
     }
 
     /// Returns the inner provider (e.g. for as_llm() when using deep agent).
@@ -29,48 +65,56 @@ fn truncate(s: &str, max: usize) -> &str {
     }
 }
 
+fn to_token_usage(u: Usage) -> TokenUsage {
+    TokenUsage {
+        prompt_tokens: u.prompt_tokens.into(),
+        completion_tokens: u.completion_tokens.into(),
+        cached_tokens: u.cached_tokens.into(),
+        reasoning_tokens: u.reasoning_tokens.into(),
+        model: u.model,
+    }
+}
+
+/// Maps a core request (messages, tool definitions) to the provider trait request.
+fn to_generate_request(request: ProviderRequest) -> GenerateRequest {
+    GenerateRequest {
+        messages: request.messages.into_iter().map(message::from_agent_message).collect(),
+        model: request.model,
+        temperature: request.temperature.map(|t| t as f64),
+        max_tokens: request.max_tokens,
+        tools: request
+            .tools
+            .into_iter()
+            .map(message::to_provider_tool_definition)
+            .collect(),
+    }
+}
+
 #[async_trait]
 impl Provider for ProviderAdapter {
     async fn generate(&self, request: ProviderRequest) -> opencode_core::error::Result<ProviderResponse> {
         let message_count = request.messages.len();
         let request_len: usize = request.messages.iter().map(|m| m.content.len()).sum();
-
-        let messages: Vec<crate::trait_::Message> = request
-            .messages
-            .into_iter()
-            .map(|m| crate::trait_::Message {
-                role: match m.role {
-                    opencode_core::agent::MessageRole::System => crate::trait_::MessageRole::System,
-                    opencode_core::agent::MessageRole::User => crate::trait_::MessageRole::User,
-                    opencode_core::agent::MessageRole::Assistant => crate::trait_::MessageRole::Assistant,
-                },
-                content: m.content,
-            })
-            .collect();
+        let provider_request = to_generate_request(self.redact(request));
 
         tracing::info!(
             message_count,
             request_len,
+            tool_count = provider_request.tools.len(),
             "ProviderAdapter: LLM generate request"
         );
-        if let Some(m) = messages.last() {
+        if let Some(m) = provider_request.messages.last() {
             tracing::debug!(
                 last_msg_trunc = %truncate(&m.content, 200),
                 "ProviderAdapter: request detail"
             );
         }
 
-        let provider_request = GenerateRequest {
-            messages,
-            model: request.model,
-            temperature: request.temperature.map(|t| t as f64),
-            max_tokens: request.max_tokens,
-        };
-
         let response = self.provider.generate(provider_request).await?;
 
         tracing::info!(
             response_len = response.content.len(),
+            tool_calls = response.tool_calls.len(),
             usage = ?response.usage,
             "ProviderAdapter: LLM generate response"
         );
@@ -81,11 +125,12 @@ impl Provider for ProviderAdapter {
 
         Ok(ProviderResponse {
             content: response.content,
-            usage: response.usage.map(|u| serde_json::json!({
-                "prompt_tokens": u.prompt_tokens,
-                "completion_tokens": u.completion_tokens,
-                "total_tokens": u.total_tokens,
-            })),
+            usage: response.usage.map(to_token_usage),
+            tool_calls: response
+                .tool_calls
+                .into_iter()
+                .map(message::from_provider_tool_call)
+                .collect(),
         })
     }
 
@@ -95,40 +140,25 @@ impl Provider for ProviderAdapter {
     ) -> opencode_core::error::Result<
         Pin<Box<dyn Stream<Item = opencode_core::error::Result<ProviderChunk>> + Send + Unpin>>,
     > {
-        let messages: Vec<crate::trait_::Message> = request
-            .messages
-            .into_iter()
-            .map(|m| crate::trait_::Message {
-                role: match m.role {
-                    opencode_core::agent::MessageRole::System => crate::trait_::MessageRole::System,
-                    opencode_core::agent::MessageRole::User => crate::trait_::MessageRole::User,
-                    opencode_core::agent::MessageRole::Assistant => {
-                        crate::trait_::MessageRole::Assistant
-                    }
-                },
-                content: m.content,
-            })
-            .collect();
-
-        let provider_request = GenerateRequest {
-            messages,
-            model: request.model,
-            temperature: request.temperature.map(|t| t as f64),
-            max_tokens: request.max_tokens,
-        };
-
-        let inner = self.provider.stream(provider_request).await?;
+        let inner = self
+            .provider
+            .stream(to_generate_request(self.redact(request))).await?;
         let mapped = inner.map(|r| {
             r.map(|c| ProviderChunk {
                 content: c.content,
                 done: c.done,
+                tool_calls: c
+                    .tool_calls
+                    .into_iter()
+                    .map(message::from_provider_tool_call)
+                    .collect(),
+                usage: c.usage.map(to_token_usage),
             })
         });
         Ok(Box::pin(mapped))
     }
 
     fn models(&self) -> &[opencode_core::agent::ModelInfo] {
-        // Convert provider models to core models
-        &[]
+        &self.models
     }
 }
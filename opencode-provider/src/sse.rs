@@ -0,0 +1,122 @@
+//! Server-sent events parsing for streaming provider responses (OpenAI-compatible and Anthropic).
+
+use futures::{Stream, StreamExt};
+use opencode_core::error::{Error, Result};
+use std::collections::VecDeque;
+
+/// One dispatched SSE event. `data` joins multiple `data:` lines with `\n`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+/// Incremental SSE parser: feed raw bytes as they arrive, collect complete events.
+#[derive(Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends bytes and returns every event completed by them (terminated by a blank line).
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if let Some(event) = self.process_line(line) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Dispatches a trailing event when the body ends without a final blank line.
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let rest = std::mem::take(&mut self.buffer);
+            let line = String::from_utf8_lossy(&rest).trim_end_matches('\r').to_string();
+            if let Some(event) = self.process_line(&line) {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((f, v)) => (f, v.strip_prefix(' ').unwrap_or(v)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => self.data.push(value.to_string()),
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+        let data = std::mem::take(&mut self.data).join("\n");
+        Some(SseEvent { event, data })
+    }
+}
+
+/// Turns a streaming HTTP response body into a stream of SSE events.
+pub fn sse_events(response: reqwest::Response) -> impl Stream<Item = Result<SseEvent>> + Send {
+    struct State<S> {
+        body: S,
+        parser: SseParser,
+        pending: VecDeque<SseEvent>,
+        ended: bool,
+    }
+
+    let state = State {
+        body: response.bytes_stream(),
+        parser: SseParser::new(),
+        pending: VecDeque::new(),
+        ended: false,
+    };
+    futures::stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.pending.pop_front() {
+                return Some((Ok(event), state));
+            }
+            if state.ended {
+                return None;
+            }
+            match state.body.next().await {
+                Some(Ok(bytes)) => state.pending.extend(state.parser.push(&bytes)),
+                Some(Err(e)) => {
+                    state.ended = true;
+                    return Some((
+                        Err(Error::Provider(format!("Stream read failed: {}", e))),
+                        state,
+                    ));
+                }
+                None => {
+                    state.ended = true;
+                    state.pending.extend(state.parser.finish());
+                }
+            }
+        }
+    })
+}
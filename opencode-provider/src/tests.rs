@@ -18,4 +18,689 @@ mod tests {
         assert_eq!(provider_msg.content, "Hello");
         assert!(matches!(provider_msg.role, MessageRole::User));
     }
+
+    #[test]
+    fn test_tool_message_conversion_roundtrip() {
+        use opencode_core::session::{MessageMeta, ToolCall};
+
+        let assistant = CoreMessage {
+            role: CoreRole::Assistant,
+            content: String::new(),
+            created_at: Utc::now(),
+            meta: Some(MessageMeta {
+                tool_calls: vec![ToolCall {
+                    id: "call_1".to_string(),
+                    name: "read".to_string(),
+                    arguments: serde_json::json!({"path": "Cargo.toml"}),
+                }],
+                ..Default::default()
+            }),
+        };
+        let provider_msg = message::to_provider_message(&assistant);
+        assert_eq!(provider_msg.tool_calls.len(), 1);
+        assert_eq!(provider_msg.tool_calls[0].arguments["path"], "Cargo.toml");
+
+        let result = crate::trait_::Message::tool_result("call_1", "contents");
+        assert!(matches!(result.role, MessageRole::Tool));
+        let core = message::from_provider_message(&result);
+        assert_eq!(core.role, CoreRole::Tool);
+        assert_eq!(
+            core.meta.as_ref().and_then(|m| m.tool_call_id.as_deref()),
+            Some("call_1")
+        );
+    }
+
+    #[test]
+    fn test_parse_tool_arguments() {
+        use crate::common::parse_tool_arguments;
+
+        assert_eq!(parse_tool_arguments(""), serde_json::json!({}));
+        assert_eq!(parse_tool_arguments(r#"{"a":1}"#)["a"], 1);
+        assert!(parse_tool_arguments("{not json").is_string());
+    }
+
+    /// Serves one canned HTTP response on a local port. Returns the base URL and a handle that
+    /// resolves to the raw request (headers and body) the client sent.
+    async fn mock_server(
+        status: &'static str,
+        content_type: &'static str,
+        body: String,
+    ) -> (String, tokio::task::JoinHandle<String>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some(header_end) = text.find("\r\n\r\n") {
+                    let content_length = text[..header_end]
+                        .lines()
+                        .find_map(|l| {
+                            let (k, v) = l.split_once(':')?;
+                            k.eq_ignore_ascii_case("content-length")
+                                .then(|| v.trim().parse::<usize>().ok())?
+                        })
+                        .unwrap_or(0);
+                    if request.len() >= header_end + 4 + content_length {
+                        break;
+                    }
+                }
+                if n == 0 {
+                    break;
+                }
+            }
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: {}\r\nConnection: close\r\n\r\n{}",
+                status, content_type, body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            socket.shutdown().await.ok();
+            String::from_utf8_lossy(&request).to_string()
+        });
+        (format!("http://{}", addr), handle)
+    }
+
+    fn sse_body(events: &[&str]) -> String {
+        events.iter().map(|e| format!("data: {}\n\n", e)).collect()
+    }
+
+    fn user_request(text: &str) -> crate::trait_::GenerateRequest {
+        crate::trait_::GenerateRequest {
+            messages: vec![crate::trait_::Message::text(MessageRole::User, text)],
+            model: Some("test-model".to_string()),
+            temperature: None,
+            max_tokens: None,
+            tools: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_sse_parser_handles_split_and_multiline_events() {
+        use crate::sse::SseParser;
+
+        let mut parser = SseParser::new();
+        assert!(parser.push(b"event: ping\r\nda").is_empty());
+        let events = parser.push(b"ta: a\r\ndata: b\r\n\r\n: comment\n\ndata: c\n");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event.as_deref(), Some("ping"));
+        assert_eq!(events[0].data, "a\nb");
+        assert_eq!(parser.finish().map(|e| e.data), Some("c".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_text_and_usage() {
+        use futures::StreamExt;
+
+        let body = sse_body(&[
+            r#"{"choices":[{"index":0,"delta":{"role":"assistant"},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"content":"Hel"},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"content":"lo"},"finish_reason":"stop"}]}"#,
+            r#"{"model":"gpt-test","choices":[],"usage":{"prompt_tokens":5,"completion_tokens":2,"total_tokens":7,"prompt_tokens_details":{"cached_tokens":3}}}"#,
+            "[DONE]",
+        ]);
+        let (base_url, request) = mock_server("200 OK", "text/event-stream", body).await;
+        // Built the way the app builds it: the configured model applies when the request has none.
+        let provider = crate::ProviderFactory::default()
+            .build(&opencode_core::config::ProviderConfig {
+                base_url: Some(base_url),
+                model: Some("gpt-config".to_string()),
+                ..provider_config("openai", Some("test-key"))
+            })
+            .unwrap();
+        let mut request_without_model = user_request("hi");
+        request_without_model.model = None;
+
+        let mut stream = provider.stream(request_without_model).await.unwrap();
+        let mut content = String::new();
+        let mut usage = None;
+        let mut done = false;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.unwrap();
+            content.push_str(&chunk.content);
+            usage = usage.or(chunk.usage);
+            done = chunk.done;
+        }
+        assert_eq!(content, "Hello");
+        assert!(done);
+        let usage = usage.unwrap();
+        assert_eq!((usage.total_tokens, usage.cached_tokens), (7, 3));
+        assert_eq!(usage.model.as_deref(), Some("gpt-test"));
+
+        let request = request.await.unwrap();
+        assert!(request.starts_with("POST /chat/completions"));
+        assert!(request.contains(r#""stream":true"#));
+        assert!(request.contains(r#""include_usage":true"#));
+        assert!(request.contains(r#""model":"gpt-config""#));
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_assembles_tool_call_deltas() {
+        use crate::trait_::Provider;
+        use futures::StreamExt;
+
+        let body = sse_body(&[
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"read","arguments":""}}]},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"path\":"}}]},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\"a.rs\"}"}}]},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":0,"delta":{},"finish_reason":"tool_calls"}]}"#,
+            "[DONE]",
+        ]);
+        let (base_url, _request) = mock_server("200 OK", "text/event-stream", body).await;
+        let provider = crate::OpenAIProvider::new("test-key".to_string(), Some(base_url)).unwrap();
+
+        let chunks: Vec<_> = provider
+            .stream(user_request("read a.rs"))
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        let calls: Vec<_> = chunks
+            .into_iter()
+            .flat_map(|c| c.unwrap().tool_calls)
+            .collect();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].id, "call_1");
+        assert_eq!(calls[0].name, "read");
+        assert_eq!(calls[0].arguments, serde_json::json!({"path": "a.rs"}));
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_reports_http_error() {
+        use crate::trait_::Provider;
+
+        let (base_url, _request) = mock_server(
+            "429 Too Many Requests",
+            "application/json",
+            r#"{"error":{"message":"rate limited"}}"#.to_string(),
+        )
+        .await;
+        let provider = crate::OpenAIProvider::new("test-key".to_string(), Some(base_url)).unwrap();
+
+        let err = provider.stream(user_request("hi")).await.err().unwrap();
+        assert!(err.to_string().contains("429"));
+        assert!(matches!(err, opencode_core::error::Error::ProviderUnavailable { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_generate_sends_system_and_tool_blocks() {
+        use crate::trait_::{Message, Provider, ToolCall, ToolDefinition};
+
+        let body = r#"{"content":[{"type":"thinking","thinking":"hmm","signature":"s"},{"type":"text","text":"Reading."},{"type":"tool_use","id":"toolu_2","name":"read","input":{"path":"b.rs"}}],"usage":{"input_tokens":10,"output_tokens":4}}"#;
+        let (base_url, request) = mock_server("200 OK", "application/json", body.to_string()).await;
+        let provider = crate::AnthropicProvider::new("test-key".to_string(), Some(base_url))
+            .unwrap()
+            .with_model(Some("claude-test".to_string()));
+
+        let mut assistant = Message::text(MessageRole::Assistant, "");
+        assistant.tool_calls = vec![ToolCall {
+            id: "toolu_1".to_string(),
+            name: "read".to_string(),
+            arguments: serde_json::json!({"path": "a.rs"}),
+        }];
+        let response = provider
+            .generate(crate::trait_::GenerateRequest {
+                messages: vec![
+                    Message::text(MessageRole::System, "Be brief."),
+                    Message::text(MessageRole::User, "read a.rs"),
+                    assistant,
+                    Message::tool_result("toolu_1", "fn main() {}"),
+                ],
+                model: None,
+                temperature: None,
+                max_tokens: None,
+                tools: vec![ToolDefinition {
+                    name: "read".to_string(),
+                    description: "Read a file".to_string(),
+                    parameters: serde_json::json!({"type": "object"}),
+                }],
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(response.content, "Reading.");
+        assert_eq!(response.tool_calls.len(), 1);
+        assert_eq!(response.tool_calls[0].arguments, serde_json::json!({"path": "b.rs"}));
+        assert_eq!(response.usage.map(|u| u.total_tokens), Some(14));
+
+        let request = request.await.unwrap();
+        let json_start = request.find("\r\n\r\n").unwrap() + 4;
+        let sent: serde_json::Value = serde_json::from_str(&request[json_start..]).unwrap();
+        assert_eq!(sent["model"], "claude-test");
+        assert_eq!(sent["system"], "Be brief.");
+        assert_eq!(sent["tools"][0]["input_schema"]["type"], "object");
+        let messages = sent["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["content"][0]["type"], "tool_use");
+        assert_eq!(messages[2]["role"], "user");
+        assert_eq!(messages[2]["content"][0]["tool_use_id"], "toolu_1");
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_stream_events() {
+        use crate::trait_::Provider;
+        use futures::StreamExt;
+
+        let events = [
+            ("message_start", r#"{"type":"message_start","message":{"usage":{"input_tokens":12,"output_tokens":1}}}"#),
+            ("content_block_start", r#"{"type":"content_block_start","index":0,"content_block":{"type":"thinking","thinking":""}}"#),
+            ("content_block_delta", r#"{"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"secret"}}"#),
+            ("content_block_stop", r#"{"type":"content_block_stop","index":0}"#),
+            ("content_block_start", r#"{"type":"content_block_start","index":1,"content_block":{"type":"text","text":""}}"#),
+            ("ping", r#"{"type":"ping"}"#),
+            ("content_block_delta", r#"{"type":"content_block_delta","index":1,"delta":{"type":"text_delta","text":"Hi "}}"#),
+            ("content_block_delta", r#"{"type":"content_block_delta","index":1,"delta":{"type":"text_delta","text":"there"}}"#),
+            ("content_block_stop", r#"{"type":"content_block_stop","index":1}"#),
+            ("content_block_start", r#"{"type":"content_block_start","index":2,"content_block":{"type":"tool_use","id":"toolu_1","name":"ls","input":{}}}"#),
+            ("content_block_delta", r#"{"type":"content_block_delta","index":2,"delta":{"type":"input_json_delta","partial_json":"{\"path\":"}}"#),
+            ("content_block_delta", r#"{"type":"content_block_delta","index":2,"delta":{"type":"input_json_delta","partial_json":"\".\"}"}}"#),
+            ("content_block_stop", r#"{"type":"content_block_stop","index":2}"#),
+            ("message_delta", r#"{"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":9}}"#),
+            ("message_stop", r#"{"type":"message_stop"}"#),
+        ];
+        let body: String = events
+            .iter()
+            .map(|(name, data)| format!("event: {}\ndata: {}\n\n", name, data))
+            .collect();
+        let (base_url, request) = mock_server("200 OK", "text/event-stream", body).await;
+        let provider = crate::AnthropicProvider::new("test-key".to_string(), Some(base_url)).unwrap();
+
+        let mut stream = provider.stream(user_request("hi")).await.unwrap();
+        let mut content = String::new();
+        let mut tool_calls = Vec::new();
+        let mut usage = None;
+        let mut done = false;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.unwrap();
+            content.push_str(&chunk.content);
+            tool_calls.extend(chunk.tool_calls);
+            usage = usage.or(chunk.usage);
+            done = chunk.done;
+        }
+        assert_eq!(content, "Hi there");
+        assert!(done);
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].arguments, serde_json::json!({"path": "."}));
+        let usage = usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (12, 9));
+
+        let request = request.await.unwrap();
+        assert!(request.starts_with("POST /messages"));
+        assert!(request.contains(r#""stream":true"#));
+    }
+
+    fn provider_config(provider_type: &str, api_key: Option<&str>) -> opencode_core::config::ProviderConfig {
+        opencode_core::config::ProviderConfig {
+            id: "test".to_string(),
+            provider_type: provider_type.to_string(),
+            api_key: api_key.map(str::to_string),
+            base_url: None,
+            model: None,
+        }
+    }
+
+    #[test]
+    fn test_provider_factory_builtin_and_custom_types() {
+        use crate::factory::{require_api_key, ProviderFactory, ProviderFactoryError};
+        use std::sync::Arc;
+
+        let mut factory = ProviderFactory::default();
+        assert_eq!(factory.provider_types(), vec!["anthropic", "ollama", "openai", "qwen"]);
+        assert!(factory.build(&provider_config("anthropic", Some("sk-test"))).is_ok());
+        // openai uses the native client (streaming tool calls), not the langchain LLM.
+        let openai = factory.build(&provider_config("openai", Some("sk-test"))).unwrap();
+        assert!(openai.as_llm().is_none());
+        assert!(factory.build(&provider_config("ollama", None)).unwrap().as_llm().is_some());
+        assert!(matches!(
+            factory.build(&provider_config("custom", None)),
+            Err(ProviderFactoryError::UnsupportedType(t)) if t == "custom"
+        ));
+
+        factory.register("custom", |c| {
+            let api_key = require_api_key(c)?;
+            crate::OpenAIProvider::new(api_key, c.base_url.clone())
+                .map(|p| Arc::new(p) as Arc<dyn crate::Provider>)
+                .map_err(|source| ProviderFactoryError::Init {
+                    provider_type: c.provider_type.clone(),
+                    source,
+                })
+        });
+        assert!(factory.supports("custom"));
+        assert!(factory.build(&provider_config("custom", Some("sk-test"))).is_ok());
+        assert!(matches!(
+            require_api_key(&provider_config("custom", Some("  "))),
+            Err(ProviderFactoryError::MissingApiKey(id)) if id == "test"
+        ));
+
+        // Keys from the environment only go to their own vendor.
+        std::env::set_var("OPENAI_API_KEY", "sk-openai-test");
+        std::env::remove_var("ANTHROPIC_API_KEY");
+        assert_eq!(crate::factory::env_api_key("openai").as_deref(), Some("sk-openai-test"));
+        assert_eq!(crate::factory::env_api_key("anthropic"), None);
+        assert_eq!(crate::factory::env_api_key("ollama"), None);
+    }
+
+    #[test]
+    fn test_retry_policy_backoff_and_retry_after() {
+        use crate::common::{parse_retry_after, retry_after_hint};
+        use crate::RetryPolicy;
+        use std::time::Duration;
+
+        let policy = RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(300),
+        };
+        assert_eq!(policy.delay(0, None), Some(Duration::from_millis(100)));
+        assert_eq!(policy.delay(1, None), Some(Duration::from_millis(200)));
+        assert_eq!(policy.delay(2, None), Some(Duration::from_millis(300)));
+        assert_eq!(policy.delay(3, None), None);
+        assert_eq!(
+            policy.delay(0, Some(Duration::from_millis(250))),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(policy.delay(0, Some(Duration::from_secs(5))), None);
+
+        assert_eq!(parse_retry_after("7"), Some(Duration::from_secs(7)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+
+        assert_eq!(
+            retry_after_hint("429: Rate limit reached. Please try again in 1.5s."),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            retry_after_hint("rate limited (Retry-After: 20)"),
+            Some(Duration::from_secs(20))
+        );
+        assert_eq!(
+            retry_after_hint("try again in 250ms"),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(retry_after_hint("rate limit exceeded"), None);
+    }
+
+    /// Fails with `ProviderUnavailable` a fixed number of times, then answers with its name.
+    struct FlakyProvider {
+        name: &'static str,
+        failures: std::sync::atomic::AtomicUsize,
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    impl FlakyProvider {
+        fn new(name: &'static str, failures: usize) -> std::sync::Arc<Self> {
+            std::sync::Arc::new(Self {
+                name,
+                failures: std::sync::atomic::AtomicUsize::new(failures),
+                calls: std::sync::atomic::AtomicUsize::new(0),
+            })
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl crate::trait_::Provider for FlakyProvider {
+        async fn generate(
+            &self,
+            _request: crate::trait_::GenerateRequest,
+        ) -> opencode_core::error::Result<crate::trait_::GenerateResponse> {
+            use std::sync::atomic::Ordering;
+
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self
+                .failures
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok()
+            {
+                return Err(opencode_core::error::Error::ProviderUnavailable {
+                    message: "API error 503".to_string(),
+                    retry_after: None,
+                });
+            }
+            Ok(crate::trait_::GenerateResponse {
+                content: self.name.to_string(),
+                usage: None,
+                tool_calls: Vec::new(),
+            })
+        }
+
+        async fn stream(
+            &self,
+            _request: crate::trait_::GenerateRequest,
+        ) -> opencode_core::error::Result<
+            Box<
+                dyn futures::Stream<Item = opencode_core::error::Result<crate::trait_::Chunk>>
+                    + Send
+                    + Unpin,
+            >,
+        > {
+            Err(opencode_core::error::Error::Provider("not supported".to_string()))
+        }
+
+        fn models(&self) -> &[crate::trait_::ModelInfo] {
+            &[]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failover_provider_retries_then_falls_back() {
+        use crate::trait_::Provider;
+        use crate::{FailoverProvider, RetryPolicy};
+        use std::sync::atomic::Ordering;
+        use std::time::Duration;
+
+        let policy = RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+        };
+
+        // Recovers within the retry budget.
+        let primary = FlakyProvider::new("primary", 2);
+        let chain = FailoverProvider::new(
+            vec![("primary".to_string(), primary.clone() as _)],
+            policy.clone(),
+        );
+        assert_eq!(chain.generate(user_request("hi")).await.unwrap().content, "primary");
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 3);
+
+        // Stays down: the fallback answers.
+        let primary = FlakyProvider::new("primary", usize::MAX);
+        let fallback = FlakyProvider::new("fallback", 0);
+        let chain = FailoverProvider::new(
+            vec![
+                ("primary".to_string(), primary.clone() as _),
+                ("fallback".to_string(), fallback.clone() as _),
+            ],
+            policy,
+        );
+        assert_eq!(chain.generate(user_request("hi")).await.unwrap().content, "fallback");
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 3);
+        assert_eq!(fallback.calls.load(Ordering::SeqCst), 1);
+
+        // Non-transient errors are not retried.
+        let err = chain.stream(user_request("hi")).await.err().unwrap();
+        assert!(err.to_string().contains("not supported"));
+    }
+
+    /// LLM that fails with a rate limit a fixed number of times, then answers with its name.
+    #[derive(Clone)]
+    struct FlakyLlm {
+        name: &'static str,
+        failures: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+        calls: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl FlakyLlm {
+        fn new(name: &'static str, failures: usize) -> Self {
+            Self {
+                name,
+                failures: std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(failures)),
+                calls: std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl langchain_ai_rust::language_models::llm::LLM for FlakyLlm {
+        async fn generate(
+            &self,
+            _messages: &[langchain_ai_rust::schemas::Message],
+        ) -> Result<
+            langchain_ai_rust::language_models::GenerateResult,
+            langchain_ai_rust::language_models::LLMError,
+        > {
+            use std::sync::atomic::Ordering;
+
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self
+                .failures
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok()
+            {
+                return Err(langchain_ai_rust::language_models::LLMError::OtherError(
+                    "429 rate limit reached, try again in 1ms".to_string(),
+                ));
+            }
+            Ok(langchain_ai_rust::language_models::GenerateResult {
+                tokens: Some(langchain_ai_rust::language_models::TokenUsage {
+                    prompt_tokens: 12,
+                    completion_tokens: 3,
+                    total_tokens: 15,
+                }),
+                generation: format!("answer from {}", self.name),
+            })
+        }
+
+        async fn stream(
+            &self,
+            _messages: &[langchain_ai_rust::schemas::Message],
+        ) -> Result<
+            std::pin::Pin<
+                Box<
+                    dyn futures::Stream<
+                            Item = Result<
+                                langchain_ai_rust::schemas::StreamData,
+                                langchain_ai_rust::language_models::LLMError,
+                            >,
+                        > + Send,
+                >,
+            >,
+            langchain_ai_rust::language_models::LLMError,
+        > {
+            Err(langchain_ai_rust::language_models::LLMError::OtherError(
+                "not supported".to_string(),
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deep_agent_turn_fails_over_to_next_provider() {
+        use crate::deep_agent_turn::{run_deep_agent_turn, DeepAgentTurnConfig};
+        use crate::trait_::Provider;
+        use crate::{FailoverProvider, LangChainAdapter, RetryPolicy};
+        use std::sync::atomic::Ordering;
+        use std::time::Duration;
+
+        let primary = FlakyLlm::new("primary", usize::MAX);
+        let fallback = FlakyLlm::new("fallback", 1);
+        let chain = FailoverProvider::new(
+            vec![
+                (
+                    "primary".to_string(),
+                    std::sync::Arc::new(LangChainAdapter::new(std::sync::Arc::new(primary.clone())))
+                        as _,
+                ),
+                (
+                    "fallback".to_string(),
+                    std::sync::Arc::new(LangChainAdapter::new(std::sync::Arc::new(
+                        fallback.clone(),
+                    ))) as _,
+                ),
+            ],
+            RetryPolicy {
+                max_retries: 1,
+                initial_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(5),
+            },
+        );
+
+        let llm = chain.as_llm().expect("langchain providers expose an LLM");
+        let reply = run_deep_agent_turn(
+            &llm,
+            &[],
+            "hi",
+            &[],
+            &opencode_core::tool::ToolContext {
+                session_id: "s".to_string(),
+                message_id: "m".to_string(),
+                agent: "build".to_string(),
+                call_id: None,
+                workspace_path: None,
+                permissions: None,
+                allowed_paths: Vec::new(),
+            },
+            DeepAgentTurnConfig {
+                use_crate_filesystem: false,
+                model: Some("gpt-4o".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert!(reply.content.contains("answer from fallback"), "{}", reply.content);
+        let usage = reply.usage.expect("the answer reported usage");
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (12, 3));
+        assert_eq!(usage.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(fallback.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_list_models_from_openai_endpoint_is_cached() {
+        use crate::models::ModelCatalog;
+        use std::time::Duration;
+
+        let body = r#"{"object":"list","data":[{"id":"gpt-4o","object":"model"},{"id":"gpt-4o-mini","object":"model"},{"id":"dall-e-3","object":"model"}]}"#;
+        let (base_url, request) = mock_server("200 OK", "application/json", body.to_string()).await;
+        let provider = crate::OpenAIProvider::new("test-key".to_string(), Some(base_url)).unwrap();
+
+        let catalog = ModelCatalog::new(Duration::from_secs(60));
+        let models = catalog.list("openai", &provider).await.unwrap();
+        let ids: Vec<&str> = models.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["dall-e-3", "gpt-4o", "gpt-4o-mini"]);
+        assert_eq!(models[0].provider, "openai");
+
+        let request = request.await.unwrap();
+        assert!(request.starts_with("GET /models "));
+        assert!(request.to_lowercase().contains("authorization: bearer test-key"));
+
+        // The mock server only answers once: a second fetch would fail, so this is the cache.
+        assert_eq!(catalog.list("openai", &provider).await.unwrap().len(), 3);
+        catalog.invalidate("openai");
+        assert!(catalog.list("openai", &provider).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_list_models_from_ollama_tags() {
+        use crate::trait_::Provider;
+
+        let body = r#"{"models":[{"name":"qwen2.5-coder:7b","size":1},{"name":"llama3.2:latest","size":2}]}"#;
+        let (root_url, request) = mock_server("200 OK", "application/json", body.to_string()).await;
+        let provider =
+            crate::LangChainAdapter::from_ollama(Some(format!("{}/v1", root_url)), None).unwrap();
+
+        let models = provider.list_models().await.unwrap();
+        let ids: Vec<&str> = models.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["llama3.2:latest", "qwen2.5-coder:7b"]);
+        assert!(models.iter().all(|m| m.provider == "ollama"));
+        assert!(request.await.unwrap().starts_with("GET /api/tags "));
+    }
 }
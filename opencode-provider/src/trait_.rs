@@ -3,28 +3,60 @@ use futures::Stream;
 use opencode_core::error::Result;
 use serde_json::Value;
 
+#[derive(Debug, Clone)]
 pub struct GenerateRequest {
     pub messages: Vec<Message>,
     pub model: Option<String>,
     pub temperature: Option<f64>,
     pub max_tokens: Option<u32>,
+    /// Tools the model may call. Empty means plain chat.
+    pub tools: Vec<ToolDefinition>,
 }
 
 pub struct GenerateResponse {
     pub content: String,
     pub usage: Option<Usage>,
+    /// Tool calls requested by the model. Empty when the response is a final answer.
+    pub tool_calls: Vec<ToolCall>,
 }
 
 pub struct Chunk {
     pub content: String,
     pub done: bool,
+    /// Fully assembled tool calls (emitted once all argument deltas for a call have arrived).
+    pub tool_calls: Vec<ToolCall>,
+    /// Token usage, reported once near the end of the stream when the provider supports it.
+    pub usage: Option<Usage>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Usage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
+    /// Prompt tokens served from the provider's prompt cache (included in `prompt_tokens`).
+    pub cached_tokens: u32,
+    /// Reasoning tokens (included in `completion_tokens`).
+    pub reasoning_tokens: u32,
+    /// Model that produced the response, when reported.
+    pub model: Option<String>,
+}
+
+/// Tool schema advertised to the model.
+#[derive(Debug, Clone)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    /// JSON Schema for the tool arguments.
+    pub parameters: Value,
+}
+
+/// A tool invocation requested by the model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: Value,
 }
 
 #[derive(Debug, Clone)]
@@ -32,12 +64,40 @@ pub enum MessageRole {
     System,
     User,
     Assistant,
+    /// Result of a tool call; `Message::tool_call_id` identifies the call.
+    Tool,
 }
 
 #[derive(Debug, Clone)]
 pub struct Message {
     pub role: MessageRole,
     pub content: String,
+    /// Set on `MessageRole::Tool` messages.
+    pub tool_call_id: Option<String>,
+    /// Set on assistant messages that requested tool calls.
+    pub tool_calls: Vec<ToolCall>,
+}
+
+impl Message {
+    /// Plain text message without tool call linkage.
+    pub fn text(role: MessageRole, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            tool_call_id: None,
+            tool_calls: Vec::new(),
+        }
+    }
+
+    /// Tool result answering the call with `tool_call_id`.
+    pub fn tool_result(tool_call_id: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            role: MessageRole::Tool,
+            content: content.into(),
+            tool_call_id: Some(tool_call_id.into()),
+            tool_calls: Vec::new(),
+        }
+    }
 }
 
 #[async_trait]
@@ -51,6 +111,12 @@ pub trait Provider: Send + Sync {
 
     fn models(&self) -> &[ModelInfo];
 
+    /// Fetches the models the provider's endpoint currently offers. Defaults to `models()`.
+    /// Callers that list repeatedly should go through `ModelCatalog` to cache the result.
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        Ok(self.models().to_vec())
+    }
+
     /// Returns the underlying LLM when this provider is backed by one (e.g. LangChainAdapter).
     /// Used to run deep agent turns with tools. Returns None for providers that do not expose an LLM.
     fn as_llm(&self) -> Option<std::sync::Arc<dyn langchain_ai_rust::language_models::llm::LLM>> {
@@ -1,5 +1,8 @@
 pub mod registry;
+pub mod sandbox;
 pub mod tools;
+pub mod workspace;
 
 pub use registry::ToolRegistry;
+pub use workspace::PathResolver;
 pub use tools::tool_wrapper::{create_tool_registry_for_agent, wrap_for_langchain};
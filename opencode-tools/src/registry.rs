@@ -1,4 +1,5 @@
 use opencode_core::error::Result;
+use opencode_core::permission;
 use opencode_core::tool::{Tool, ToolContext, ToolResult};
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -35,6 +36,7 @@ impl ToolRegistry {
         let tool = self
             .get(tool_id)
             .ok_or_else(|| opencode_core::error::Error::Tool(format!("Tool not found: {}", tool_id)))?;
+        permission::authorize(tool_id, &args, ctx).await?;
         tool.execute(args, ctx).await
     }
 }
@@ -0,0 +1,205 @@
+//! Linux sandbox of the bash tool (`Config.bash.sandbox`). File access is restricted with a
+//! Landlock ruleset and the network by moving the command into a new user and network namespace.
+//! Both are applied in the forked child right before `exec`, so the agent process itself is never
+//! restricted.
+
+use opencode_core::config::SandboxConfig;
+use opencode_core::error::{Error, Result};
+use std::path::PathBuf;
+use tokio::process::Command;
+
+/// Directories commands may read and execute from when the file system is sandboxed.
+#[cfg(target_os = "linux")]
+const SYSTEM_READ_PATHS: &[&str] = &[
+    "/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/etc", "/opt", "/nix", "/proc", "/sys",
+    "/run",
+];
+
+/// Installs the sandbox of `config` on `cmd`. `writable` are the directories the command may
+/// change besides the temp directory and `config.write_paths`.
+pub fn apply(cmd: &mut Command, config: &SandboxConfig, writable: &[PathBuf]) -> Result<()> {
+    if !config.filesystem && !config.deny_network {
+        return Ok(());
+    }
+    #[cfg(target_os = "linux")]
+    {
+        linux::apply(cmd, config, writable)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (cmd, writable);
+        Err(Error::Tool(
+            "The bash sandbox is only available on Linux".to_string(),
+        ))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::*;
+    use std::ffi::CString;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    // Landlock ABI (include/uapi/linux/landlock.h).
+    const CREATE_RULESET_VERSION: u32 = 1;
+    const RULE_PATH_BENEATH: u32 = 1;
+    const ACCESS_EXECUTE: u64 = 1 << 0;
+    const ACCESS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_READ_FILE: u64 = 1 << 2;
+    const ACCESS_READ_DIR: u64 = 1 << 3;
+    /// Everything up to `MAKE_SYM` (ABI 1).
+    const ACCESS_ABI_1: u64 = (1 << 13) - 1;
+    const ACCESS_REFER: u64 = 1 << 13;
+    const ACCESS_TRUNCATE: u64 = 1 << 14;
+    /// Rights that apply to a file rather than a directory.
+    const ACCESS_FILE: u64 =
+        ACCESS_EXECUTE | ACCESS_WRITE_FILE | ACCESS_READ_FILE | ACCESS_TRUNCATE;
+    const ACCESS_READ: u64 = ACCESS_EXECUTE | ACCESS_READ_FILE | ACCESS_READ_DIR;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    pub(super) fn apply(
+        cmd: &mut Command,
+        config: &SandboxConfig,
+        writable: &[PathBuf],
+    ) -> Result<()> {
+        let ruleset = if config.filesystem {
+            let mut read: Vec<PathBuf> = SYSTEM_READ_PATHS.iter().map(PathBuf::from).collect();
+            read.extend(config.read_paths.iter().cloned());
+            let mut write = writable.to_vec();
+            write.push(std::env::temp_dir());
+            write.push(PathBuf::from("/dev"));
+            write.extend(config.write_paths.iter().cloned());
+            Some(ruleset(&read, &write)?)
+        } else {
+            None
+        };
+        let deny_network = config.deny_network;
+        // SAFETY: the closure only makes async-signal-safe system calls. It owns the ruleset, which
+        // stays open until `cmd` is dropped.
+        unsafe {
+            cmd.pre_exec(move || {
+                let ruleset_fd = ruleset.as_ref().map(|fd| fd.as_raw_fd());
+                if deny_network && libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                if let Some(fd) = ruleset_fd {
+                    if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0
+                        || libc::syscall(libc::SYS_landlock_restrict_self, fd, 0) != 0
+                    {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    /// Landlock ruleset allowing `read` to be read and executed and `write` to be used fully.
+    /// Paths that do not exist are skipped.
+    fn ruleset(read: &[PathBuf], write: &[PathBuf]) -> Result<OwnedFd> {
+        // SAFETY: querying the ABI version takes no pointers.
+        let abi = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<RulesetAttr>(),
+                0,
+                CREATE_RULESET_VERSION,
+            )
+        };
+        if abi < 1 {
+            return Err(Error::Tool(format!(
+                "Landlock is not available: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        let mut handled = ACCESS_ABI_1;
+        if abi >= 2 {
+            handled |= ACCESS_REFER;
+        }
+        if abi >= 3 {
+            handled |= ACCESS_TRUNCATE;
+        }
+        let attr = RulesetAttr {
+            handled_access_fs: handled,
+        };
+        // SAFETY: `attr` is a valid ruleset attribute of the size passed.
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0,
+            )
+        };
+        if fd < 0 {
+            return Err(Error::Tool(format!(
+                "Creating the Landlock ruleset failed: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        // SAFETY: the kernel returned a new file descriptor we own.
+        let ruleset = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+        for path in read {
+            add_rule(&ruleset, path, ACCESS_READ & handled)?;
+        }
+        for path in write {
+            add_rule(&ruleset, path, handled)?;
+        }
+        Ok(ruleset)
+    }
+
+    fn add_rule(ruleset: &OwnedFd, path: &Path, access: u64) -> Result<()> {
+        let Ok(metadata) = std::fs::metadata(path) else {
+            return Ok(());
+        };
+        let access = if metadata.is_dir() {
+            access
+        } else {
+            access & ACCESS_FILE
+        };
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| Error::Tool(format!("Invalid sandbox path {}", path.display())))?;
+        // SAFETY: `c_path` is a valid NUL-terminated string.
+        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Ok(());
+        }
+        // SAFETY: `open` returned a new file descriptor we own.
+        let parent = unsafe { OwnedFd::from_raw_fd(fd) };
+        let attr = PathBeneathAttr {
+            allowed_access: access,
+            parent_fd: parent.as_raw_fd(),
+        };
+        // SAFETY: `attr` is a valid path-beneath rule and both descriptors are open.
+        let added = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset.as_raw_fd(),
+                RULE_PATH_BENEATH,
+                &attr as *const PathBeneathAttr,
+                0,
+            )
+        };
+        if added != 0 {
+            return Err(Error::Tool(format!(
+                "Adding {} to the Landlock ruleset failed: {}",
+                path.display(),
+                std::io::Error::last_os_error()
+            )));
+        }
+        Ok(())
+    }
+}
@@ -1,9 +1,17 @@
+use crate::sandbox;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use opencode_core::config::BashConfig;
 use opencode_core::error::{Error, Result};
 use opencode_core::tool::{Tool, ToolContext, ToolResult};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
 use tokio::process::Command;
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
@@ -11,11 +19,18 @@ pub struct BashArgs {
     pub command: String,
 }
 
-pub struct BashTool;
+pub struct BashTool {
+    config: BashConfig,
+}
 
 impl BashTool {
     pub fn new() -> Arc<Self> {
-        Arc::new(Self)
+        Self::with_config(BashConfig::default())
+    }
+
+    /// Bash tool with the timeout, output limits, environment and sandbox of `Config.bash`.
+    pub fn with_config(config: BashConfig) -> Arc<Self> {
+        Arc::new(Self { config })
     }
 }
 
@@ -42,34 +57,187 @@ impl Tool for BashTool {
         })
     }
 
-    async fn execute(&self, args: Value, _ctx: &ToolContext) -> Result<ToolResult> {
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolResult> {
         let args: BashArgs = serde_json::from_value(args)
             .map_err(|e| Error::Validation(format!("Invalid arguments: {}", e)))?;
 
-        let output = Command::new("sh")
-            .arg("-c")
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
             .arg(&args.command)
-            .output()
-            .await
-            .map_err(|e| Error::Tool(format!("Failed to execute command: {}", e)))?;
-
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .env_clear()
+            .envs(command_env(&self.config)?);
+        #[cfg(unix)]
+        cmd.process_group(0);
+        let mut writable = Vec::new();
+        if let Some(workspace) = &ctx.workspace_path {
+            cmd.current_dir(workspace);
+            writable.push(PathBuf::from(workspace));
+            writable.extend(
+                ctx.allowed_paths
+                    .iter()
+                    .map(|p| Path::new(workspace).join(p)),
+            );
+        }
+        sandbox::apply(&mut cmd, &self.config.sandbox, &writable)?;
 
-        let output_text = if !stderr.is_empty() {
-            format!("STDOUT:\n{}\n\nSTDERR:\n{}", stdout, stderr)
-        } else {
-            stdout.to_string()
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| Error::Tool(format!("Failed to execute command: {}", e)))?;
+        let mut group = ProcessGroup(child.id());
+        let cap = self.config.max_output_bytes;
+        let mut stdout = CappedOutput::new(cap);
+        let mut stderr = CappedOutput::new(cap);
+        let (out_stream, err_stream) = (child.stdout.take(), child.stderr.take());
+        let run = async {
+            tokio::join!(
+                read_capped(out_stream, &mut stdout),
+                read_capped(err_stream, &mut stderr),
+                child.wait()
+            )
+            .2
+        };
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+        let Ok(status) = tokio::time::timeout(timeout, run).await else {
+            // The output read so far helps to see where the command hung.
+            return Err(Error::Tool(format!(
+                "Command timed out after {}s and was killed. Output so far:\n{}",
+                self.config.timeout_secs,
+                combined(stdout, stderr)
+            )));
         };
+        let status =
+            status.map_err(|e| Error::Tool(format!("Failed to execute command: {}", e)))?;
+        group.0 = None;
+
+        let truncated = stdout.truncated() || stderr.truncated();
+        let output_text = combined(stdout, stderr);
 
         Ok(ToolResult {
             title: format!("Bash: {}", args.command),
             output: output_text,
             metadata: serde_json::json!({
                 "command": args.command,
-                "exit_code": output.status.code(),
-                "success": output.status.success()
+                "exit_code": status.code(),
+                "success": status.success(),
+                "truncated": truncated
             }),
         })
     }
 }
+
+/// Kills the process group of a command that did not finish (timeout, or the turn was dropped).
+struct ProcessGroup(Option<u32>);
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(pid) = self.0 {
+            // SAFETY: signals the group created for the command with `process_group(0)`.
+            unsafe {
+                libc::kill(-(pid as i32), libc::SIGKILL);
+            }
+        }
+    }
+}
+
+/// The process environment filtered by `env_allow` and `env_deny`.
+fn command_env(config: &BashConfig) -> Result<Vec<(String, String)>> {
+    let allow = glob_set(&config.env_allow)?;
+    let deny = glob_set(&config.env_deny)?;
+    Ok(std::env::vars()
+        .filter(|(name, _)| {
+            (config.env_allow.is_empty() || allow.is_match(name)) && !deny.is_match(name)
+        })
+        .collect())
+}
+
+fn glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut set = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| {
+                Error::Config(format!("Invalid environment pattern {}: {}", pattern, e))
+            })?;
+        set.add(glob);
+    }
+    set.build()
+        .map_err(|e| Error::Config(format!("Invalid environment patterns: {}", e)))
+}
+
+/// Output of a stream limited to `cap` bytes: its start and its end.
+struct CappedOutput {
+    head: Vec<u8>,
+    tail: VecDeque<u8>,
+    cap: usize,
+    total: usize,
+}
+
+impl CappedOutput {
+    fn new(cap: usize) -> Self {
+        Self {
+            head: Vec::new(),
+            tail: VecDeque::new(),
+            cap,
+            total: 0,
+        }
+    }
+
+    fn push(&mut self, mut bytes: &[u8]) {
+        self.total += bytes.len();
+        let head_cap = self.cap - self.cap / 2;
+        let room = head_cap.saturating_sub(self.head.len()).min(bytes.len());
+        self.head.extend_from_slice(&bytes[..room]);
+        bytes = &bytes[room..];
+        self.tail.extend(bytes);
+        let tail_cap = self.cap / 2;
+        if self.tail.len() > tail_cap {
+            self.tail.drain(..self.tail.len() - tail_cap);
+        }
+    }
+
+    fn truncated(&self) -> bool {
+        self.total > self.head.len() + self.tail.len()
+    }
+
+    fn into_string(self) -> String {
+        let dropped = self.total - self.head.len() - self.tail.len();
+        let head = String::from_utf8_lossy(&self.head);
+        let tail: Vec<u8> = self.tail.into_iter().collect();
+        let tail = String::from_utf8_lossy(&tail);
+        if dropped == 0 {
+            format!("{}{}", head, tail)
+        } else {
+            format!("{}\n… [{} bytes truncated] …\n{}", head, dropped, tail)
+        }
+    }
+}
+
+/// The tool output: stdout, followed by stderr under its own heading when there is any.
+fn combined(stdout: CappedOutput, stderr: CappedOutput) -> String {
+    let stdout = stdout.into_string();
+    let stderr = stderr.into_string();
+    if !stderr.is_empty() {
+        format!("STDOUT:\n{}\n\nSTDERR:\n{}", stdout, stderr)
+    } else {
+        stdout
+    }
+}
+
+/// Reads `stream` to the end into `output`, which keeps what was read when this is cancelled.
+async fn read_capped<R: AsyncRead + Unpin>(stream: Option<R>, output: &mut CappedOutput) {
+    let Some(mut stream) = stream else {
+        return;
+    };
+    let mut buf = [0u8; 8192];
+    loop {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => output.push(&buf[..n]),
+        }
+    }
+}
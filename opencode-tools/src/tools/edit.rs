@@ -1,3 +1,4 @@
+use crate::workspace::PathResolver;
 use opencode_core::error::{Error, Result};
 use opencode_core::tool::{Tool, ToolContext, ToolResult};
 use schemars::JsonSchema;
@@ -41,16 +42,17 @@ impl Tool for EditTool {
         serde_json::json!({"type": "object", "properties": {}, "required": []})
     }
 
-    async fn execute(&self, args: Value, _ctx: &ToolContext) -> Result<ToolResult> {
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolResult> {
         let args: EditArgs = serde_json::from_value(args)
             .map_err(|e| Error::Validation(format!("Invalid arguments: {}", e)))?;
+        let path = PathResolver::from_context(ctx).resolve(&args.path)?;
 
-        let content = fs::read_to_string(&args.path)
+        let content = fs::read_to_string(&path)
             .await
             .map_err(|e| Error::Tool(format!("Failed to read file {}: {}", args.path, e)))?;
 
         let mut lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
-        
+
         for edit in args.edits.iter().rev() {
             if edit.start_line > lines.len() || edit.end_line > lines.len() {
                 return Err(Error::Tool(format!(
@@ -66,13 +68,17 @@ impl Tool for EditTool {
         }
 
         let new_content = lines.join("\n");
-        fs::write(&args.path, new_content.as_bytes())
+        fs::write(&path, new_content.as_bytes())
             .await
             .map_err(|e| Error::Tool(format!("Failed to write file {}: {}", args.path, e)))?;
 
         Ok(ToolResult {
             title: format!("Edit {}", args.path),
-            output: format!("Successfully edited {} with {} edit(s)", args.path, args.edits.len()),
+            output: format!(
+                "Successfully edited {} with {} edit(s)",
+                args.path,
+                args.edits.len()
+            ),
             metadata: serde_json::json!({
                 "path": args.path,
                 "edits": args.edits.len()
@@ -1,3 +1,4 @@
+use crate::workspace::PathResolver;
 use globset::{Glob, GlobMatcher};
 use opencode_core::error::{Error, Result};
 use opencode_core::tool::{Tool, ToolContext, ToolResult};
@@ -35,7 +36,7 @@ impl Tool for GlobTool {
         serde_json::json!({"type": "object", "properties": {}, "required": []})
     }
 
-    async fn execute(&self, args: Value, _ctx: &ToolContext) -> Result<ToolResult> {
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolResult> {
         let args: GlobArgs = serde_json::from_value(args)
             .map_err(|e| Error::Validation(format!("Invalid arguments: {}", e)))?;
 
@@ -44,13 +45,17 @@ impl Tool for GlobTool {
         let matcher = glob.compile_matcher();
 
         let base_path = args.base_path.unwrap_or_else(|| ".".to_string());
+        let base_path = PathResolver::from_context(ctx).resolve(&base_path)?;
         let mut matches = Vec::new();
 
         for entry in WalkDir::new(&base_path).into_iter() {
             match entry {
                 Ok(e) => {
                     let path_str = e.path().to_string_lossy().to_string();
-                    if matcher.is_match(&path_str) {
+                    // Patterns like `src/*.rs` are relative to the base path.
+                    let relative = e.path().strip_prefix(&base_path).ok();
+                    if matcher.is_match(&path_str) || relative.is_some_and(|r| matcher.is_match(r))
+                    {
                         matches.push(path_str);
                     }
                 }
@@ -1,3 +1,4 @@
+use crate::workspace::PathResolver;
 use opencode_core::error::{Error, Result};
 use opencode_core::tool::{Tool, ToolContext, ToolResult};
 use regex::Regex;
@@ -37,9 +38,10 @@ impl Tool for GrepTool {
         serde_json::json!({"type": "object", "properties": {}, "required": []})
     }
 
-    async fn execute(&self, args: Value, _ctx: &ToolContext) -> Result<ToolResult> {
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolResult> {
         let args: GrepArgs = serde_json::from_value(args)
             .map_err(|e| Error::Validation(format!("Invalid arguments: {}", e)))?;
+        let path = PathResolver::from_context(ctx).resolve(&args.path)?;
 
         let regex = Regex::new(&args.pattern)
             .map_err(|e| Error::Validation(format!("Invalid regex pattern: {}", e)))?;
@@ -48,14 +50,19 @@ impl Tool for GrepTool {
         let mut matches = Vec::new();
 
         if recursive {
-            for entry in WalkDir::new(&args.path).into_iter() {
+            for entry in WalkDir::new(&path).into_iter() {
                 match entry {
                     Ok(e) => {
                         if e.file_type().is_file() {
                             if let Ok(content) = fs::read_to_string(e.path()).await {
                                 for (line_num, line) in content.lines().enumerate() {
                                     if regex.is_match(line) {
-                                        matches.push(format!("{}:{}:{}", e.path().display(), line_num + 1, line));
+                                        matches.push(format!(
+                                            "{}:{}:{}",
+                                            e.path().display(),
+                                            line_num + 1,
+                                            line
+                                        ));
                                     }
                                 }
                             }
@@ -67,10 +74,10 @@ impl Tool for GrepTool {
                 }
             }
         } else {
-            let content = fs::read_to_string(&args.path)
+            let content = fs::read_to_string(&path)
                 .await
                 .map_err(|e| Error::Tool(format!("Failed to read file {}: {}", args.path, e)))?;
-            
+
             for (line_num, line) in content.lines().enumerate() {
                 if regex.is_match(line) {
                     matches.push(format!("{}:{}:{}", args.path, line_num + 1, line));
@@ -1,3 +1,4 @@
+use crate::workspace::PathResolver;
 use opencode_core::error::{Error, Result};
 use opencode_core::tool::{Tool, ToolContext, ToolResult};
 use schemars::JsonSchema;
@@ -42,15 +43,16 @@ impl Tool for ListTool {
         })
     }
 
-    async fn execute(&self, args: Value, _ctx: &ToolContext) -> Result<ToolResult> {
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolResult> {
         let args: ListArgs = serde_json::from_value(args)
             .map_err(|e| Error::Validation(format!("Invalid arguments: {}", e)))?;
+        let path = PathResolver::from_context(ctx).resolve(&args.path)?;
 
         let recursive = args.recursive.unwrap_or(false);
         let mut entries = Vec::new();
 
         if recursive {
-            for entry in WalkDir::new(&args.path).max_depth(3) {
+            for entry in WalkDir::new(&path).max_depth(3) {
                 match entry {
                     Ok(e) => {
                         let path = e.path().to_string_lossy().to_string();
@@ -63,15 +65,19 @@ impl Tool for ListTool {
                 }
             }
         } else {
-            let mut dir = fs::read_dir(&args.path)
-                .await
-                .map_err(|e| Error::Tool(format!("Failed to read directory {}: {}", args.path, e)))?;
+            let mut dir = fs::read_dir(&path).await.map_err(|e| {
+                Error::Tool(format!("Failed to read directory {}: {}", args.path, e))
+            })?;
 
             while let Some(entry) = dir.next_entry().await? {
                 let path = entry.path();
                 let name = path.file_name().unwrap().to_string_lossy().to_string();
                 let metadata = entry.metadata().await?;
-                entries.push(format!("{}{}", name, if metadata.is_dir() { "/" } else { "" }));
+                entries.push(format!(
+                    "{}{}",
+                    name,
+                    if metadata.is_dir() { "/" } else { "" }
+                ));
             }
         }
 
@@ -1,3 +1,4 @@
+use crate::workspace::PathResolver;
 use opencode_core::error::{Error, Result};
 use opencode_core::tool::{Tool, ToolContext, ToolResult};
 use schemars::JsonSchema;
@@ -42,11 +43,12 @@ impl Tool for MultiEditTool {
         serde_json::json!({"type": "object", "properties": {}, "required": []})
     }
 
-    async fn execute(&self, args: Value, _ctx: &ToolContext) -> Result<ToolResult> {
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolResult> {
         let args: MultiEditArgs = serde_json::from_value(args)
             .map_err(|e| Error::Validation(format!("Invalid arguments: {}", e)))?;
+        let path = PathResolver::from_context(ctx).resolve(&args.file_path)?;
 
-        let content = fs::read_to_string(&args.file_path)
+        let content = fs::read_to_string(&path)
             .await
             .map_err(|e| Error::Tool(format!("Failed to read file {}: {}", args.file_path, e)))?;
 
@@ -73,13 +75,17 @@ impl Tool for MultiEditTool {
             }));
         }
 
-        fs::write(&args.file_path, modified_content.as_bytes())
+        fs::write(&path, modified_content.as_bytes())
             .await
             .map_err(|e| Error::Tool(format!("Failed to write file {}: {}", args.file_path, e)))?;
 
         Ok(ToolResult {
             title: format!("MultiEdit {}", args.file_path),
-            output: format!("Successfully applied {} edit(s) to {}", args.edits.len(), args.file_path),
+            output: format!(
+                "Successfully applied {} edit(s) to {}",
+                args.edits.len(),
+                args.file_path
+            ),
             metadata: serde_json::json!({
                 "file_path": args.file_path,
                 "edits": args.edits.len(),
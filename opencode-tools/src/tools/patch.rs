@@ -1,3 +1,4 @@
+use crate::workspace::PathResolver;
 use opencode_core::error::{Error, Result};
 use opencode_core::tool::{Tool, ToolContext, ToolResult};
 use schemars::JsonSchema;
@@ -33,7 +34,7 @@ impl Tool for PatchTool {
         serde_json::json!({"type": "object", "properties": {}, "required": []})
     }
 
-    async fn execute(&self, args: Value, _ctx: &ToolContext) -> Result<ToolResult> {
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolResult> {
         let args: PatchArgs = serde_json::from_value(args)
             .map_err(|e| Error::Validation(format!("Invalid arguments: {}", e)))?;
 
@@ -46,7 +47,8 @@ impl Tool for PatchTool {
         let mut i = 0;
 
         while i < lines.len() {
-            if lines[i].starts_with("---") && i + 1 < lines.len() && lines[i + 1].starts_with("+++") {
+            if lines[i].starts_with("---") && i + 1 < lines.len() && lines[i + 1].starts_with("+++")
+            {
                 let old_file = lines[i].strip_prefix("--- ").unwrap_or("").trim();
                 let new_file = lines[i + 1].strip_prefix("+++ ").unwrap_or("").trim();
                 i += 2;
@@ -86,9 +88,16 @@ impl Tool for PatchTool {
             }
         }
 
+        // Every file must be inside the workspace before any is written.
+        let resolver = PathResolver::from_context(ctx);
+        let targets = file_changes
+            .iter()
+            .map(|(file_path, _, _)| resolver.resolve(file_path))
+            .collect::<Result<Vec<_>>>()?;
+
         let mut applied = 0;
-        for (file_path, _old_content, new_content) in &file_changes {
-            if let Ok(_) = fs::write(file_path, new_content.as_bytes()).await {
+        for (target, (_file_path, _old_content, new_content)) in targets.iter().zip(&file_changes) {
+            if let Ok(_) = fs::write(target, new_content.as_bytes()).await {
                 applied += 1;
             }
         }
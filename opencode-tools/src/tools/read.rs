@@ -1,3 +1,4 @@
+use crate::workspace::PathResolver;
 use opencode_core::error::{Error, Result};
 use opencode_core::tool::{Tool, ToolContext, ToolResult};
 use schemars::JsonSchema;
@@ -42,11 +43,12 @@ impl Tool for ReadTool {
         })
     }
 
-    async fn execute(&self, args: Value, _ctx: &ToolContext) -> Result<ToolResult> {
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolResult> {
         let args: ReadArgs = serde_json::from_value(args)
             .map_err(|e| Error::Validation(format!("Invalid arguments: {}", e)))?;
+        let path = PathResolver::from_context(ctx).resolve(&args.path)?;
 
-        let content = fs::read_to_string(&args.path)
+        let content = fs::read_to_string(&path)
             .await
             .map_err(|e| Error::Tool(format!("Failed to read file {}: {}", args.path, e)))?;
 
@@ -13,6 +13,8 @@ mod tests {
             agent: "test".to_string(),
             call_id: None,
             workspace_path: None,
+            permissions: None,
+            allowed_paths: Vec::new(),
         };
 
         let args = json!({
@@ -26,23 +28,27 @@ mod tests {
     #[tokio::test]
     async fn test_write_tool() {
         let tool = write::WriteTool::new();
+        let tmp = std::env::temp_dir();
         let ctx = ToolContext {
             session_id: "test".to_string(),
             message_id: "test".to_string(),
             agent: "test".to_string(),
             call_id: None,
-            workspace_path: None,
+            workspace_path: Some(tmp.to_string_lossy().into_owned()),
+            permissions: None,
+            allowed_paths: Vec::new(),
         };
+        let path = tmp.join("test_write.txt");
 
         let args = json!({
-            "path": "/tmp/test_write.txt",
+            "path": path.to_string_lossy(),
             "content": "test content"
         });
 
         let result = tool.execute(args, &ctx).await;
         assert!(result.is_ok());
-        
-        std::fs::remove_file("/tmp/test_write.txt").ok();
+
+        std::fs::remove_file(&path).ok();
     }
 
     #[tokio::test]
@@ -54,6 +60,8 @@ mod tests {
             agent: "test".to_string(),
             call_id: None,
             workspace_path: None,
+            permissions: None,
+            allowed_paths: Vec::new(),
         };
 
         let args = json!({
@@ -65,4 +73,213 @@ mod tests {
         let result = tool.execute(args, &ctx).await;
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn masked_secrets_survive_a_read_then_edit_round_trip() {
+        use super::super::{edit, multiedit};
+        use opencode_core::redact::{RedactingTool, Redactor};
+        use std::sync::Arc;
+
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let workspace = std::env::temp_dir().join(format!("opencode-redact-{}", nanos));
+        std::fs::create_dir_all(&workspace).unwrap();
+        let secret = "OPENAI_API_KEY=sk-proj-aBcDeFgHiJkLmNoPqRsTuVwXyZ012345\nDEBUG=false\n";
+        std::fs::write(workspace.join(".env"), secret).unwrap();
+        let redactor = Arc::new(Redactor::new(&Default::default()).unwrap());
+        let wrap = |tool: Arc<dyn Tool>| RedactingTool::wrap(tool, redactor.clone());
+        let ctx = ToolContext {
+            session_id: "test".to_string(),
+            message_id: "test".to_string(),
+            agent: "test".to_string(),
+            call_id: None,
+            workspace_path: Some(workspace.to_string_lossy().into_owned()),
+            permissions: None,
+            allowed_paths: Vec::new(),
+        };
+
+        let read = wrap(read::ReadTool::new());
+        let output = read.execute(json!({"path": ".env"}), &ctx).await.unwrap().output;
+        assert!(output.contains("[REDACTED:") && !output.contains("sk-proj-"));
+
+        // Writing the masked text back is refused, whichever tool carries it.
+        let write = wrap(write::WriteTool::new());
+        let rewritten = output.replace("DEBUG=false", "DEBUG=true");
+        let Err(err) = write.execute(json!({"path": ".env", "content": rewritten}), &ctx).await else {
+            panic!("masked content was written");
+        };
+        assert!(err.to_string().contains("masked"));
+        let multiedit = wrap(multiedit::MultiEditTool::new());
+        let first_line = output.lines().next().unwrap();
+        let edits = json!([{"old_string": first_line, "new_string": format!("{}\n# key", first_line)}]);
+        assert!(multiedit
+            .execute(json!({"file_path": ".env", "edits": edits}), &ctx)
+            .await
+            .is_err());
+        assert_eq!(std::fs::read_to_string(workspace.join(".env")).unwrap(), secret);
+
+        // Edits that leave the masked line alone go through and keep the secret.
+        let edit = wrap(edit::EditTool::new());
+        let edits = json!([{"start_line": 1, "end_line": 2, "content": "DEBUG=true"}]);
+        edit.execute(json!({"path": ".env", "edits": edits}), &ctx).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(workspace.join(".env")).unwrap(),
+            "OPENAI_API_KEY=sk-proj-aBcDeFgHiJkLmNoPqRsTuVwXyZ012345\nDEBUG=true"
+        );
+        std::fs::remove_dir_all(&workspace).ok();
+    }
+
+    #[tokio::test]
+    async fn file_tools_stay_inside_the_workspace() {
+        use super::super::patch;
+        use crate::workspace::PathResolver;
+        use opencode_core::error::Error;
+
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let root = std::env::temp_dir().join(format!("opencode-ws-{}", nanos));
+        let workspace = root.join("project");
+        let shared = root.join("shared");
+        std::fs::create_dir_all(workspace.join("src")).unwrap();
+        std::fs::create_dir_all(&shared).unwrap();
+        std::fs::write(workspace.join("src/lib.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(root.join("secret.txt"), "secret\n").unwrap();
+        std::fs::write(shared.join("notes.md"), "notes\n").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&root, workspace.join("escape")).unwrap();
+
+        let ctx = ToolContext {
+            session_id: "test".to_string(),
+            message_id: "test".to_string(),
+            agent: "test".to_string(),
+            call_id: None,
+            workspace_path: Some(workspace.to_string_lossy().into_owned()),
+            permissions: None,
+            allowed_paths: vec!["../shared".to_string()],
+        };
+        let denied = |r: opencode_core::error::Result<std::path::PathBuf>| {
+            matches!(r, Err(Error::PermissionDenied(_)))
+        };
+        let resolver = PathResolver::from_context(&ctx);
+        let canonical = std::fs::canonicalize(&workspace).unwrap();
+        assert_eq!(resolver.resolve("src/lib.rs").unwrap(), canonical.join("src/lib.rs"));
+        // Files that do not exist yet resolve too, `..` included.
+        assert_eq!(resolver.resolve("new/dir/../a.rs").unwrap(), canonical.join("new/a.rs"));
+        assert!(denied(resolver.resolve("../secret.txt")));
+        assert!(denied(resolver.resolve("new/../../secret.txt")));
+        assert!(denied(resolver.resolve(&root.join("secret.txt").to_string_lossy())));
+        #[cfg(unix)]
+        assert!(denied(resolver.resolve("escape/secret.txt")));
+        assert!(resolver.resolve("../shared/notes.md").is_ok());
+        // Without a workspace the current directory is the root.
+        let cwd = PathResolver::new(None, &[]);
+        assert!(cwd.resolve("Cargo.toml").is_ok());
+        assert!(denied(cwd.resolve(&root.join("secret.txt").to_string_lossy())));
+
+        let read = read::ReadTool::new();
+        let output = read.execute(json!({"path": "src/lib.rs"}), &ctx).await.unwrap().output;
+        assert_eq!(output, "fn main() {}\n");
+        assert!(read.execute(json!({"path": "../secret.txt"}), &ctx).await.is_err());
+        let write = write::WriteTool::new();
+        assert!(write
+            .execute(json!({"path": "../pwned.txt", "content": "x"}), &ctx)
+            .await
+            .is_err());
+        assert!(!root.join("pwned.txt").exists());
+        // A patch touching one outside file writes nothing.
+        let patch_text = "--- src/lib.rs\n+++ src/lib.rs\n@@ -1 +1 @@\n-fn main() {}\n+fn main() { }\n\
+            --- ../secret.txt\n+++ ../secret.txt\n@@ -1 +1 @@\n-secret\n+leaked\n";
+        let patch = patch::PatchTool::new();
+        assert!(patch.execute(json!({"patch_text": patch_text}), &ctx).await.is_err());
+        assert_eq!(std::fs::read_to_string(root.join("secret.txt")).unwrap(), "secret\n");
+        assert_eq!(
+            std::fs::read_to_string(workspace.join("src/lib.rs")).unwrap(),
+            "fn main() {}\n"
+        );
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[tokio::test]
+    async fn bash_runs_in_the_workspace_within_limits() {
+        use super::super::bash::BashTool;
+        use opencode_core::config::BashConfig;
+
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let workspace = std::env::temp_dir().join(format!("opencode-bash-{}", nanos));
+        std::fs::create_dir_all(&workspace).unwrap();
+        let workspace = std::fs::canonicalize(&workspace).unwrap();
+        let ctx = ToolContext {
+            session_id: "test".to_string(),
+            message_id: "test".to_string(),
+            agent: "test".to_string(),
+            call_id: None,
+            workspace_path: Some(workspace.to_string_lossy().into_owned()),
+            permissions: None,
+            allowed_paths: Vec::new(),
+        };
+        let config = BashConfig {
+            timeout_secs: 1,
+            max_output_bytes: 100,
+            ..Default::default()
+        };
+        let bash = BashTool::with_config(config.clone());
+        let run = |command: &str| bash.execute(json!({ "command": command }), &ctx);
+
+        let pwd = run("pwd").await.unwrap().output;
+        assert_eq!(pwd.trim(), workspace.to_string_lossy());
+        std::env::set_var("OPENCODE_TEST_API_KEY", "sk-test");
+        let env = run("echo ${OPENCODE_TEST_API_KEY:-unset}").await.unwrap().output;
+        assert_eq!(env.trim(), "unset");
+
+        let long = run("head -c 5000 /dev/zero | tr '\\0' a").await.unwrap();
+        assert_eq!(long.metadata["truncated"], true);
+        assert!(long.output.contains("[4900 bytes truncated]"));
+        assert!(long.output.len() < 200);
+
+        // A timeout kills the whole process group, background children included.
+        let started = std::time::Instant::now();
+        let marker = workspace.join("survived");
+        let command = format!("echo started; (sleep 3; touch {}) & sleep 30", marker.display());
+        let Err(err) = run(&command).await else {
+            panic!("command did not time out");
+        };
+        let err = err.to_string();
+        assert!(err.contains("timed out") && err.contains("started"), "{}", err);
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+        assert!(!marker.exists());
+
+        #[cfg(target_os = "linux")]
+        {
+            let mut sandboxed = config;
+            sandboxed.timeout_secs = 10;
+            sandboxed.sandbox.filesystem = true;
+            sandboxed.sandbox.deny_network = true;
+            let bash = BashTool::with_config(sandboxed);
+            let run = |command: &str| bash.execute(json!({ "command": command }), &ctx);
+            match run("echo ok > inside.txt && cat inside.txt").await {
+                Ok(result) => {
+                    assert_eq!(result.output.trim(), "ok");
+                    // Only the loopback interface exists in the command's network namespace.
+                    let dev = run("cat /proc/net/dev").await.unwrap().output;
+                    assert_eq!(dev.lines().count(), 3, "{}", dev);
+                    let home = std::env::var("HOME").unwrap_or_default();
+                    if !home.is_empty() && !std::env::temp_dir().starts_with(&home) {
+                        let listed = run("ls \"$HOME\"").await.unwrap();
+                        assert_eq!(listed.metadata["success"], false);
+                    }
+                }
+                // Kernels without Landlock or unprivileged user namespaces.
+                Err(e) => eprintln!("sandbox unavailable: {}", e),
+            }
+        }
+        std::fs::remove_dir_all(&workspace).ok();
+    }
 }
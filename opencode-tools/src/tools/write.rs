@@ -1,3 +1,4 @@
+use crate::workspace::PathResolver;
 use opencode_core::error::{Error, Result};
 use opencode_core::tool::{Tool, ToolContext, ToolResult};
 use schemars::JsonSchema;
@@ -34,17 +35,22 @@ impl Tool for WriteTool {
         serde_json::json!({"type": "object", "properties": {}, "required": []})
     }
 
-    async fn execute(&self, args: Value, _ctx: &ToolContext) -> Result<ToolResult> {
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolResult> {
         let args: WriteArgs = serde_json::from_value(args)
             .map_err(|e| Error::Validation(format!("Invalid arguments: {}", e)))?;
+        let path = PathResolver::from_context(ctx).resolve(&args.path)?;
 
-        fs::write(&args.path, args.content.as_bytes())
+        fs::write(&path, args.content.as_bytes())
             .await
             .map_err(|e| Error::Tool(format!("Failed to write file {}: {}", args.path, e)))?;
 
         Ok(ToolResult {
             title: format!("Write {}", args.path),
-            output: format!("Successfully wrote {} bytes to {}", args.content.len(), args.path),
+            output: format!(
+                "Successfully wrote {} bytes to {}",
+                args.content.len(),
+                args.path
+            ),
             metadata: serde_json::json!({
                 "path": args.path,
                 "size": args.content.len()
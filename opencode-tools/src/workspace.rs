@@ -0,0 +1,91 @@
+//! Confines the file tools to the workspace. Model-supplied paths are resolved against
+//! `ToolContext.workspace_path`, canonicalized (following symlinks, including the ones in parents of
+//! files that do not exist yet) and rejected unless they stay inside the workspace or one of the
+//! directories in `ToolContext.allowed_paths`. Without a workspace the current directory is the
+//! root; when that cannot be determined either, every path is rejected.
+
+use opencode_core::error::{Error, Result};
+use opencode_core::tool::ToolContext;
+use std::path::{Component, Path, PathBuf};
+
+pub struct PathResolver {
+    root: Option<PathBuf>,
+    allowed: Vec<PathBuf>,
+}
+
+impl PathResolver {
+    /// Resolver for `workspace` (the current directory when None); relative entries of `allowed`
+    /// are taken relative to it.
+    pub fn new(workspace: Option<&Path>, allowed: &[PathBuf]) -> Self {
+        let root = match workspace {
+            Some(dir) => Some(canonical_dir(dir)),
+            None => std::env::current_dir().ok().map(|dir| canonical_dir(&dir)),
+        };
+        let allowed = match &root {
+            Some(root) => allowed
+                .iter()
+                .map(|dir| canonical_dir(&root.join(dir)))
+                .collect(),
+            None => Vec::new(),
+        };
+        Self { root, allowed }
+    }
+
+    pub fn from_context(ctx: &ToolContext) -> Self {
+        let allowed: Vec<PathBuf> = ctx.allowed_paths.iter().map(PathBuf::from).collect();
+        Self::new(ctx.workspace_path.as_deref().map(Path::new), &allowed)
+    }
+
+    /// The path `path` refers to. Fails with `Error::PermissionDenied` when it lies outside the
+    /// workspace and the allowed directories.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let Some(root) = &self.root else {
+            return Err(Error::PermissionDenied(format!(
+                "{} cannot be checked: no workspace or current directory",
+                path
+            )));
+        };
+        let resolved = canonicalize(&root.join(path))?;
+        if resolved.starts_with(root) || self.allowed.iter().any(|dir| resolved.starts_with(dir)) {
+            Ok(resolved)
+        } else {
+            Err(Error::PermissionDenied(format!(
+                "{} is outside the workspace",
+                path
+            )))
+        }
+    }
+}
+
+/// Canonical form of a configured directory; kept as given when it does not exist.
+fn canonical_dir(dir: &Path) -> PathBuf {
+    std::fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf())
+}
+
+/// Canonical form of `path`, which need not exist: the deepest existing ancestor is canonicalized
+/// and the remaining components are appended, with `..` applied to the result.
+fn canonicalize(path: &Path) -> Result<PathBuf> {
+    let mut missing = Vec::new();
+    let mut existing = path;
+    // `symlink_metadata` also finds dangling symlinks, which must not be treated as missing.
+    while existing.symlink_metadata().is_err() {
+        let (Some(parent), Some(last)) = (existing.parent(), existing.components().next_back())
+        else {
+            break;
+        };
+        missing.push(last.as_os_str().to_os_string());
+        existing = parent;
+    }
+    let mut resolved = std::fs::canonicalize(existing)
+        .map_err(|e| Error::Tool(format!("Cannot resolve {}: {}", existing.display(), e)))?;
+    for name in missing.iter().rev() {
+        match Path::new(name).components().next() {
+            Some(Component::ParentDir) => {
+                resolved.pop();
+            }
+            Some(Component::CurDir) | None => {}
+            Some(_) => resolved.push(name),
+        }
+    }
+    Ok(resolved)
+}
@@ -23,6 +23,12 @@ mod tests {
             message_id: Uuid::new_v4().to_string(),
             agent: "build".to_string(),
             workspace_path: None,
+            max_iterations: None,
+            context_window: Default::default(),
+            capabilities: Default::default(),
+            session_state: None,
+            permissions: None,
+            allowed_paths: Vec::new(),
         };
 
         let mut tool_registry = ToolRegistry::new();
@@ -4,6 +4,26 @@ mod tests {
     use opencode_tools::ToolRegistry;
     use opencode_tools::tools::register_all_tools;
     use serde_json::json;
+    use std::path::PathBuf;
+
+    /// A fresh directory under the system temp dir, used as the tools' workspace.
+    fn temp_workspace() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("opencode-tool-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn workspace_context(workspace: &PathBuf) -> ToolContext {
+        ToolContext {
+            session_id: "test".to_string(),
+            message_id: "test".to_string(),
+            agent: "test".to_string(),
+            call_id: None,
+            workspace_path: Some(workspace.to_string_lossy().into_owned()),
+            permissions: None,
+            allowed_paths: Vec::new(),
+        }
+    }
 
     #[tokio::test]
     async fn test_read_tool_execution() {
@@ -16,6 +36,8 @@ mod tests {
             agent: "test".to_string(),
             call_id: None,
             workspace_path: None,
+            permissions: None,
+            allowed_paths: Vec::new(),
         };
 
         let args = json!({
@@ -33,27 +55,34 @@ mod tests {
         let mut registry = ToolRegistry::new();
         register_all_tools(&mut registry);
         
-        let ctx = ToolContext {
-            session_id: "test".to_string(),
-            message_id: "test".to_string(),
-            agent: "test".to_string(),
-            call_id: None,
-            workspace_path: None,
-        };
+        let workspace = temp_workspace();
+        let ctx = workspace_context(&workspace);
 
-        let test_file = "/tmp/test_write_tool.txt";
         let args = json!({
-            "path": test_file,
+            "path": "test_write_tool.txt",
             "content": "test content"
         });
 
         let result = registry.execute("write", args, &ctx).await;
         assert!(result.is_ok());
         
-        let content = std::fs::read_to_string(test_file).unwrap();
+        let content = std::fs::read_to_string(workspace.join("test_write_tool.txt")).unwrap();
         assert_eq!(content, "test content");
+
+        // Paths outside the workspace are rejected and nothing is written.
+        let outside = workspace.with_extension("outside.txt");
+        let outside_name = outside.file_name().unwrap().to_string_lossy().into_owned();
+        for path in [outside.to_string_lossy().into_owned(), format!("../{}", outside_name)] {
+            let args = json!({
+                "path": path,
+                "content": "test content"
+            });
+            let result = registry.execute("write", args, &ctx).await;
+            assert!(result.is_err(), "{} is outside the workspace", path);
+        }
+        assert!(!outside.exists());
         
-        std::fs::remove_file(test_file).ok();
+        std::fs::remove_dir_all(&workspace).ok();
     }
 
     #[tokio::test]
@@ -67,6 +96,8 @@ mod tests {
             agent: "test".to_string(),
             call_id: None,
             workspace_path: None,
+            permissions: None,
+            allowed_paths: Vec::new(),
         };
 
         let args = json!({
@@ -84,16 +115,11 @@ mod tests {
         let mut registry = ToolRegistry::new();
         register_all_tools(&mut registry);
         
-        let test_file = "/tmp/test_multiedit.txt";
-        std::fs::write(test_file, "line1\nline2\nline3").unwrap();
+        let workspace = temp_workspace();
+        let test_file = "test_multiedit.txt";
+        std::fs::write(workspace.join(test_file), "line1\nline2\nline3").unwrap();
         
-        let ctx = ToolContext {
-            session_id: "test".to_string(),
-            message_id: "test".to_string(),
-            agent: "test".to_string(),
-            call_id: None,
-            workspace_path: None,
-        };
+        let ctx = workspace_context(&workspace);
 
         let args = json!({
             "file_path": test_file,
@@ -110,9 +136,9 @@ mod tests {
         let result = registry.execute("multiedit", args, &ctx).await;
         assert!(result.is_ok());
         
-        let content = std::fs::read_to_string(test_file).unwrap();
+        let content = std::fs::read_to_string(workspace.join(test_file)).unwrap();
         assert!(content.contains("line2_modified"));
         
-        std::fs::remove_file(test_file).ok();
+        std::fs::remove_dir_all(&workspace).ok();
     }
 }